@@ -0,0 +1,231 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::Error;
+use std::io::ErrorKind;
+
+#[cfg(all(target_os = "linux", feature = "acl"))]
+use arbitrary::Unstructured;
+
+/// Extended attribute that stores the access ACL.
+pub const ACL_ACCESS_XATTR: &str = "system.posix_acl_access";
+
+/// Extended attribute that stores the default ACL of a directory.
+pub const ACL_DEFAULT_XATTR: &str = "system.posix_acl_default";
+
+/// POSIX access control lists of a file.
+///
+/// Both lists are empty when the file doesn't have an extended ACL, i.e. when the permissions
+/// are fully described by the file mode.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Acl {
+    /// Access ACL.
+    pub access: Vec<AclEntry>,
+    /// Default ACL (directories only).
+    pub default: Vec<AclEntry>,
+}
+
+impl Acl {
+    /// Is the ACL equivalent to the file mode?
+    pub fn is_empty(&self) -> bool {
+        self.access.is_empty() && self.default.is_empty()
+    }
+}
+
+/// ACL entry.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct AclEntry {
+    /// Whom the entry applies to.
+    pub tag: AclTag,
+    /// Read (4), write (2) and execute (1) permissions.
+    pub perm: u8,
+}
+
+/// Whom the ACL entry applies to.
+///
+/// The variants are ordered as they are stored in the extended attribute.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub enum AclTag {
+    /// File owner.
+    UserObj,
+    /// User with the specified id.
+    User(u32),
+    /// File group.
+    GroupObj,
+    /// Group with the specified id.
+    Group(u32),
+    /// Maximum permissions of the named users, the named groups and the file group.
+    Mask,
+    /// Everyone else.
+    Other,
+}
+
+impl Display for AclEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use AclTag::*;
+        match self.tag {
+            UserObj => f.write_str("user::")?,
+            User(id) => write!(f, "user:{id}:")?,
+            GroupObj => f.write_str("group::")?,
+            Group(id) => write!(f, "group:{id}:")?,
+            Mask => f.write_str("mask::")?,
+            Other => f.write_str("other::")?,
+        }
+        for (bit, c) in [(4, 'r'), (2, 'w'), (1, 'x')] {
+            let c = if self.perm & bit != 0 { c } else { '-' };
+            write!(f, "{c}")?;
+        }
+        Ok(())
+    }
+}
+
+const ACL_VERSION: u32 = 2;
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+/// Encode the entries in the format of [`ACL_ACCESS_XATTR`] and [`ACL_DEFAULT_XATTR`].
+///
+/// This is the format that `acl_set_file` uses. The entries are sorted.
+pub fn encode_acl(entries: &[AclEntry]) -> Vec<u8> {
+    use AclTag::*;
+    let mut entries = entries.to_vec();
+    entries.sort();
+    let mut bytes = Vec::with_capacity(4 + entries.len() * 8);
+    bytes.extend_from_slice(&ACL_VERSION.to_le_bytes());
+    for entry in entries.iter() {
+        let (tag, id) = match entry.tag {
+            UserObj => (ACL_USER_OBJ, ACL_UNDEFINED_ID),
+            User(id) => (ACL_USER, id),
+            GroupObj => (ACL_GROUP_OBJ, ACL_UNDEFINED_ID),
+            Group(id) => (ACL_GROUP, id),
+            Mask => (ACL_MASK, ACL_UNDEFINED_ID),
+            Other => (ACL_OTHER, ACL_UNDEFINED_ID),
+        };
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&(entry.perm as u16).to_le_bytes());
+        bytes.extend_from_slice(&id.to_le_bytes());
+    }
+    bytes
+}
+
+/// Decode the value of [`ACL_ACCESS_XATTR`] or [`ACL_DEFAULT_XATTR`].
+pub fn decode_acl(bytes: &[u8]) -> Result<Vec<AclEntry>, Error> {
+    use AclTag::*;
+    let invalid = || Error::new(ErrorKind::InvalidData, "invalid ACL");
+    let (version, bytes) = bytes.split_first_chunk::<4>().ok_or_else(invalid)?;
+    if u32::from_le_bytes(*version) != ACL_VERSION || bytes.len() % 8 != 0 {
+        return Err(invalid());
+    }
+    let mut entries = Vec::with_capacity(bytes.len() / 8);
+    for chunk in bytes.chunks_exact(8) {
+        let tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+        let perm = u16::from_le_bytes([chunk[2], chunk[3]]);
+        let id = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        let tag = match tag {
+            ACL_USER_OBJ => UserObj,
+            ACL_USER => User(id),
+            ACL_GROUP_OBJ => GroupObj,
+            ACL_GROUP => Group(id),
+            ACL_MASK => Mask,
+            ACL_OTHER => Other,
+            _ => return Err(invalid()),
+        };
+        entries.push(AclEntry {
+            tag,
+            perm: (perm & 0o7) as u8,
+        });
+    }
+    Ok(entries)
+}
+
+/// Generate extended access ACL that doesn't change the permission bits of the file.
+///
+/// The owner's, the mask's and the other's permissions are taken from the mode; the named users
+/// and groups are arbitrary.
+#[cfg(all(target_os = "linux", feature = "acl"))]
+pub(crate) fn arbitrary_access_acl(
+    u: &mut Unstructured<'_>,
+    mode: u32,
+) -> arbitrary::Result<Vec<AclEntry>> {
+    let mut entries = vec![
+        AclEntry {
+            tag: AclTag::UserObj,
+            perm: ((mode >> 6) & 0o7) as u8,
+        },
+        AclEntry {
+            tag: AclTag::GroupObj,
+            perm: arbitrary_perm(u)?,
+        },
+        AclEntry {
+            tag: AclTag::Mask,
+            perm: ((mode >> 3) & 0o7) as u8,
+        },
+        AclEntry {
+            tag: AclTag::Other,
+            perm: (mode & 0o7) as u8,
+        },
+    ];
+    arbitrary_named_entries(u, &mut entries, 1)?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Generate arbitrary default ACL.
+#[cfg(all(target_os = "linux", feature = "acl"))]
+pub(crate) fn arbitrary_default_acl(u: &mut Unstructured<'_>) -> arbitrary::Result<Vec<AclEntry>> {
+    let mut entries = Vec::new();
+    for tag in [AclTag::UserObj, AclTag::GroupObj, AclTag::Other] {
+        entries.push(AclEntry {
+            tag,
+            perm: arbitrary_perm(u)?,
+        });
+    }
+    if arbitrary_named_entries(u, &mut entries, 0)? {
+        entries.push(AclEntry {
+            tag: AclTag::Mask,
+            perm: arbitrary_perm(u)?,
+        });
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Returns `true` if any entries were added.
+#[cfg(all(target_os = "linux", feature = "acl"))]
+fn arbitrary_named_entries(
+    u: &mut Unstructured<'_>,
+    entries: &mut Vec<AclEntry>,
+    min: usize,
+) -> arbitrary::Result<bool> {
+    let n = u.int_in_range(min..=MAX_NAMED_ENTRIES)?;
+    for _ in 0..n {
+        let id = u.int_in_range(0..=MAX_ID)?;
+        let tag = if u.arbitrary()? {
+            AclTag::User(id)
+        } else {
+            AclTag::Group(id)
+        };
+        if entries.iter().any(|entry| entry.tag == tag) {
+            continue;
+        }
+        entries.push(AclEntry {
+            tag,
+            perm: arbitrary_perm(u)?,
+        });
+    }
+    Ok(n != 0)
+}
+
+#[cfg(all(target_os = "linux", feature = "acl"))]
+fn arbitrary_perm(u: &mut Unstructured<'_>) -> arbitrary::Result<u8> {
+    u.int_in_range(0..=7)
+}
+
+#[cfg(all(target_os = "linux", feature = "acl"))]
+const MAX_NAMED_ENTRIES: usize = 4;
+#[cfg(all(target_os = "linux", feature = "acl"))]
+const MAX_ID: u32 = 65535;
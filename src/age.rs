@@ -0,0 +1,325 @@
+#[cfg(feature = "fs")]
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::os::fd::AsFd;
+#[cfg(feature = "fs")]
+use std::os::fd::AsRawFd;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+#[cfg(feature = "fs")]
+use arbitrary::Unstructured;
+
+#[cfg(feature = "fs")]
+use crate::create_dir_all_at;
+#[cfg(feature = "fs")]
+use crate::os_str_to_c_string;
+#[cfg(feature = "fs")]
+use crate::path_to_c_string;
+#[cfg(feature = "fs")]
+use crate::set_file_modified_time;
+#[cfg(feature = "fs")]
+use crate::set_file_modified_time_at;
+#[cfg(feature = "fs")]
+use crate::Entry;
+#[cfg(feature = "fs")]
+use crate::FileType;
+#[cfg(feature = "fs")]
+use crate::PATH_MAX;
+
+/// Modification time bucket.
+///
+/// The bucket covers the ages from [`min_age`](Self::min_age) up to the minimum age of the next
+/// older bucket. The oldest bucket ends at Unix epoch.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct AgeBucket {
+    /// The entries in the bucket are older than this.
+    pub min_age: Duration,
+    /// Percentage of the entries that fall into the bucket.
+    pub percent: u8,
+}
+
+impl AgeBucket {
+    /// Create new bucket.
+    pub const fn new(min_age: Duration, percent: u8) -> Self {
+        Self { min_age, percent }
+    }
+}
+
+/// An entry whose modification time falls into an age bucket.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AgedEntry {
+    /// Path relative to the directory root.
+    pub path: PathBuf,
+    /// The bucket.
+    pub bucket: AgeBucket,
+}
+
+/// Minimum distance between the modification times and the bucket boundaries.
+///
+/// The membership doesn't change if the test runs shortly after the generation.
+pub const AGE_MARGIN: Duration = Duration::from_secs(60 * 60);
+
+/// Modification times before Unix epoch in seconds.
+///
+/// These are the times just before the epoch and around the lower limit of the signed 32-bit
+/// `time_t` (1901).
+pub const PRE_EPOCH_TIMES: [i64; 5] = [
+    -1,
+    -60 * 60 * 24,
+    i32::MIN as i64 + 1,
+    i32::MIN as i64,
+    i32::MIN as i64 - 1,
+];
+
+/// Modification times after 2038 in seconds since Unix epoch.
+///
+/// These are the times around the upper limits of the signed (2038) and unsigned (2106) 32-bit
+/// `time_t`, the 11-digit octal field of ustar (2242) and the largest time that ext4 can store
+/// (2446).
+pub const FAR_FUTURE_TIMES: [i64; 7] = [
+    i32::MAX as i64,
+    i32::MAX as i64 + 1,
+    u32::MAX as i64,
+    u32::MAX as i64 + 1,
+    (1 << 33) - 1,
+    1 << 33,
+    (1 << 34) - (1 << 31) - 1,
+];
+
+/// Assign the entries to the buckets and generate the matching modification times.
+#[cfg(feature = "fs")]
+pub(crate) fn arbitrary_ages(
+    u: &mut Unstructured<'_>,
+    buckets: &[AgeBucket],
+    entries: &mut [Entry],
+) -> arbitrary::Result<Vec<AgedEntry>> {
+    let mut buckets = buckets.to_vec();
+    buckets.sort_by_key(|bucket| bucket.min_age);
+    let now = SystemTime::now();
+    let oldest = now.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+    let mut aged_entries = Vec::new();
+    let mut mtimes = HashMap::new();
+    for entry in entries.iter_mut() {
+        if entry.mtime.is_none() || entry.file_type == FileType::HardLink {
+            continue;
+        }
+        let roll: u8 = u.int_in_range(0..=99)?;
+        let mut percent: u8 = 0;
+        let i = buckets.iter().position(|bucket| {
+            percent = percent.saturating_add(bucket.percent);
+            roll < percent
+        });
+        let (min_age, max_age) = match i {
+            Some(i) => (
+                buckets[i].min_age + AGE_MARGIN,
+                match buckets.get(i + 1) {
+                    Some(next) => next.min_age.saturating_sub(AGE_MARGIN),
+                    None => oldest,
+                },
+            ),
+            None => (
+                Duration::ZERO,
+                buckets
+                    .first()
+                    .map(|bucket| bucket.min_age.saturating_sub(AGE_MARGIN))
+                    .unwrap_or(oldest),
+            ),
+        };
+        // the buckets might be too narrow or too old
+        let min_age = min_age.min(oldest);
+        let max_age = max_age.clamp(min_age, oldest);
+        let age = Duration::from_nanos(
+            u.int_in_range(min_age.as_nanos() as u64..=max_age.as_nanos() as u64)?,
+        );
+        let t = now - age;
+        entry.mtime = Some(t);
+        mtimes.insert(entry.path.clone(), t);
+        if let Some(i) = i {
+            aged_entries.push(AgedEntry {
+                path: entry.path.clone(),
+                bucket: buckets[i],
+            });
+        }
+    }
+    // hard links share the modification time with the original file
+    for entry in entries.iter_mut() {
+        if entry.file_type != FileType::HardLink {
+            continue;
+        }
+        let Some(target) = entry.target.as_ref() else {
+            continue;
+        };
+        let Some(t) = mtimes.get(target) else {
+            continue;
+        };
+        let t = *t;
+        entry.mtime = Some(t);
+        mtimes.insert(entry.path.clone(), t);
+        if let Some(aged) = aged_entries.iter().find(|aged| &aged.path == target) {
+            let bucket = aged.bucket;
+            aged_entries.push(AgedEntry {
+                path: entry.path.clone(),
+                bucket,
+            });
+        }
+    }
+    Ok(aged_entries)
+}
+
+/// Replace the modification times of some entries with the times from `times`.
+///
+/// The files in the directory `root` are updated as well. The times that the file system can't
+/// store are skipped. The entries that got the new times are removed from `aged_entries`.
+#[cfg(feature = "fs")]
+pub(crate) fn arbitrary_extreme_times(
+    u: &mut Unstructured<'_>,
+    root: Option<&Path>,
+    times: &[i64],
+    entries: &mut [Entry],
+    aged_entries: &mut Vec<AgedEntry>,
+) -> arbitrary::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let mut mtimes = HashMap::new();
+    for entry in entries.iter_mut() {
+        if entry.mtime.is_none() || entry.file_type == FileType::HardLink {
+            continue;
+        }
+        if !u.ratio(1_u8, 4_u8)? {
+            continue;
+        }
+        let secs = *u.choose(times)?;
+        let nanos = u.int_in_range(0..=999_999_999)?;
+        let t = from_unix_time(secs, nanos);
+        if let Some(root) = root {
+            let path = root.join(&entry.path);
+            if path.as_os_str().len() >= PATH_MAX {
+                continue;
+            }
+            let c_path = path_to_c_string(path.clone()).unwrap();
+            if set_file_modified_time(&c_path, t).is_err() {
+                continue;
+            }
+            // the file system might clamp the time
+            let stored = path
+                .symlink_metadata()
+                .is_ok_and(|m| m.mtime() == secs && m.mtime_nsec() == nanos as i64);
+            if !stored {
+                if let Some(old) = entry.mtime {
+                    set_file_modified_time(&c_path, old).unwrap();
+                }
+                continue;
+            }
+        }
+        entry.mtime = Some(t);
+        mtimes.insert(entry.path.clone(), t);
+    }
+    // hard links share the modification time with the original file
+    for entry in entries.iter_mut() {
+        if entry.file_type != FileType::HardLink {
+            continue;
+        }
+        let Some(t) = entry.target.as_ref().and_then(|target| mtimes.get(target)) else {
+            continue;
+        };
+        let t = *t;
+        entry.mtime = Some(t);
+        mtimes.insert(entry.path.clone(), t);
+    }
+    aged_entries.retain(|aged| !mtimes.contains_key(&aged.path));
+    Ok(())
+}
+
+/// Seconds and nanoseconds since Unix epoch.
+///
+/// The seconds are negative and the nanoseconds are positive for the times before the epoch,
+/// i.e. the same as in `struct timespec`.
+pub(crate) fn unix_time(t: SystemTime) -> (i64, u32) {
+    match t.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+        Err(e) => {
+            let d = e.duration();
+            match d.subsec_nanos() {
+                0 => (-(d.as_secs() as i64), 0),
+                nanos => (-(d.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+/// The inverse of [`unix_time`].
+pub(crate) fn from_unix_time(secs: i64, nanos: u32) -> SystemTime {
+    if secs >= 0 {
+        SystemTime::UNIX_EPOCH + Duration::new(secs as u64, nanos)
+    } else {
+        SystemTime::UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+            + Duration::from_nanos(nanos as u64)
+    }
+}
+
+/// Convert days since Unix epoch to year, month and day in the proleptic Gregorian calendar.
+#[cfg(any(feature = "fs", feature = "zip"))]
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+/// Update the modification times of the existing files.
+#[cfg(feature = "fs")]
+pub(crate) fn set_modified_times(root: &Path, entries: &[Entry]) {
+    for entry in entries.iter() {
+        let Some(t) = entry.mtime else {
+            continue;
+        };
+        if entry.file_type == FileType::HardLink {
+            continue;
+        }
+        let path = root.join(&entry.path);
+        if path.as_os_str().len() < PATH_MAX {
+            let c_path = path_to_c_string(path).unwrap();
+            set_file_modified_time(&c_path, t).unwrap();
+        } else {
+            // descend to the parent
+            let root = File::open(root).unwrap();
+            let parent = create_dir_all_at(root.as_fd(), entry.path.parent().unwrap()).unwrap();
+            let name = os_str_to_c_string(entry.path.file_name().unwrap()).unwrap();
+            set_file_modified_time_at(parent.as_raw_fd(), &name, t).unwrap();
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "fs", feature = "zip")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates() {
+        for (days, expected) in [
+            (0, (1970, 1, 1)),
+            (-1, (1969, 12, 31)),
+            (-719_468, (0, 3, 1)),
+            (10_956, (1999, 12, 31)),
+            (11_016, (2000, 2, 29)),
+            (11_017, (2000, 3, 1)),
+            (19_417, (2023, 3, 1)),
+            (-25_508, (1900, 3, 1)),
+            (-25_509, (1900, 2, 28)),
+        ] {
+            assert_eq!(expected, civil_from_days(days), "{days}");
+        }
+    }
+}
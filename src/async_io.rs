@@ -0,0 +1,47 @@
+use std::io::Error;
+use std::path::Path;
+
+use arbitrary::Unstructured;
+use tokio::task::JoinError;
+
+use crate::list_dir_all;
+use crate::Dir;
+use crate::DirBuilder;
+use crate::FileInfo;
+
+impl DirBuilder {
+    /// Create a temporary directory with random contents without blocking the async runtime.
+    ///
+    /// The files are generated on tokio's blocking thread pool by the builder returned by
+    /// `builder`. Consumes all the remaining bytes of `u` (see [`spawn`](Self::spawn)).
+    pub async fn create_async<F>(builder: F, u: &mut Unstructured<'_>) -> arbitrary::Result<Dir>
+    where
+        F: FnOnce() -> DirBuilder + Send + 'static,
+    {
+        let data = u.bytes(u.len()).unwrap_or_default().to_vec();
+        tokio::task::spawn_blocking(move || builder().create(&mut Unstructured::new(&data)))
+            .await
+            .unwrap_or_else(resume_unwind)
+    }
+}
+
+/// Recursively list the directory (see [`list_dir_all`]) without blocking the async runtime.
+///
+/// The directory is listed on tokio's blocking thread pool.
+pub async fn list_dir_all_async<P: AsRef<Path>>(dir: P) -> Result<Vec<FileInfo>, Error> {
+    let dir = dir.as_ref().to_path_buf();
+    tokio::task::spawn_blocking(move || list_dir_all(dir))
+        .await
+        .unwrap_or_else(resume_unwind)
+}
+
+/// Propagate the panic of the blocking task.
+///
+/// The blocking tasks are never aborted, i.e. the task is cancelled only when the runtime is
+/// shutting down.
+fn resume_unwind<T>(e: JoinError) -> T {
+    match e.try_into_panic() {
+        Ok(panic) => std::panic::resume_unwind(panic),
+        Err(e) => panic!("{}", e),
+    }
+}
@@ -0,0 +1,207 @@
+//! Generate, list and compare directories from the shell.
+//!
+//! ```text
+//! random-dir generate --seed N --out DIR [--bytes N] [--manifest FILE]
+//! random-dir generate --spec FILE --out DIR [--manifest FILE]
+//! random-dir list DIR [--json]
+//! random-dir diff A B [--fail-on LEVEL]
+//! ```
+
+use std::ffi::OsString;
+use std::io::Error;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use arbitrary::Unstructured;
+use random_dir::diff_dirs;
+use random_dir::diff_dirs_with_readdir_order;
+use random_dir::list_dir_all_sampled;
+use random_dir::write_tree;
+use random_dir::write_tree_json;
+use random_dir::DirBuilder;
+use random_dir::Severity;
+use random_dir::ALL_SEVERITIES;
+
+fn main() -> ExitCode {
+    let mut args = std::env::args_os().skip(1);
+    let command = args.next().unwrap_or_default();
+    let args: Vec<OsString> = args.collect();
+    let result = match command.to_str() {
+        Some("generate") => generate(args),
+        Some("list") => list(args),
+        Some("diff") => diff(args),
+        Some("help" | "--help" | "-h") => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => Err(usage_error()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("random-dir: {}", e);
+            if e.kind() == std::io::ErrorKind::InvalidInput {
+                eprint!("{}", USAGE);
+                ExitCode::from(USAGE_EXIT_CODE)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+fn generate(args: Vec<OsString>) -> Result<ExitCode, Error> {
+    let mut seed = None;
+    let mut out = None;
+    let mut spec = None;
+    let mut manifest = None;
+    let mut num_bytes = DEFAULT_NUM_BYTES;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--seed") => seed = Some(parse_number(args.next())?),
+            Some("--out") => out = Some(PathBuf::from(value(args.next())?)),
+            Some("--bytes") => num_bytes = parse_number(args.next())? as usize,
+            Some("--spec") => spec = Some(PathBuf::from(value(args.next())?)),
+            Some("--manifest") => manifest = Some(PathBuf::from(value(args.next())?)),
+            _ => return Err(usage_error()),
+        }
+    }
+    let out = out.ok_or_else(usage_error)?;
+    let data = match (seed, spec) {
+        (Some(seed), None) => expand_seed(seed, num_bytes),
+        (None, Some(spec)) => std::fs::read(spec)?,
+        _ => return Err(usage_error()),
+    };
+    std::fs::create_dir_all(&out)?;
+    let dir = DirBuilder::new()
+        .create_in(&out, &mut Unstructured::new(&data))
+        .map_err(|e| Error::other(format!("failed to generate the tree: {}", e)))?;
+    if let Some(manifest) = manifest {
+        dir.manifest().write_to(std::fs::File::create(manifest)?)?;
+    }
+    dir.persist();
+    Ok(ExitCode::SUCCESS)
+}
+
+fn list(args: Vec<OsString>) -> Result<ExitCode, Error> {
+    let mut dir = None;
+    let mut json = false;
+    for arg in args.into_iter() {
+        match arg.to_str() {
+            Some("--json") => json = true,
+            _ if dir.is_none() => dir = Some(PathBuf::from(arg)),
+            _ => return Err(usage_error()),
+        }
+    }
+    let files = list_dir_all_sampled(dir.ok_or_else(usage_error)?)?;
+    let mut stdout = std::io::stdout().lock();
+    if json {
+        write_tree_json(&files, &mut stdout)?;
+    } else {
+        write_tree(&files, &mut stdout)?;
+    }
+    stdout.flush()?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn diff(args: Vec<OsString>) -> Result<ExitCode, Error> {
+    let mut dirs = Vec::new();
+    let mut fail_on = Severity::Metadata;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--fail-on") => fail_on = parse_severity(args.next())?,
+            _ => dirs.push(arg),
+        }
+    }
+    let [a, b]: [OsString; 2] = dirs.try_into().map_err(|_| usage_error())?;
+    // `readdir` order is only compared when the ordering differences are failures
+    let result = if fail_on == Severity::Ordering {
+        diff_dirs_with_readdir_order(a, b)
+    } else {
+        diff_dirs(a, b)
+    };
+    // distinguish the errors from the differences like diff(1) does
+    let diff = match result {
+        Ok(diff) => diff,
+        Err(e) => {
+            eprintln!("random-dir: {}", e);
+            return Ok(ExitCode::from(DIFF_ERROR_EXIT_CODE));
+        }
+    };
+    print!("{}", diff);
+    let fail_on: Vec<Severity> = ALL_SEVERITIES
+        .into_iter()
+        .filter(|severity| *severity >= fail_on)
+        .collect();
+    Ok(ExitCode::from(diff.exit_code(&fail_on) as u8))
+}
+
+/// Expand the seed into `len` pseudo-random bytes (SplitMix64).
+fn expand_seed(mut seed: u64, len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len + 8);
+    while data.len() < len {
+        seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        data.extend_from_slice(&z.to_le_bytes());
+    }
+    data.truncate(len);
+    data
+}
+
+fn value(arg: Option<OsString>) -> Result<OsString, Error> {
+    arg.ok_or_else(usage_error)
+}
+
+fn parse_number(arg: Option<OsString>) -> Result<u64, Error> {
+    value(arg)?
+        .to_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(usage_error)
+}
+
+fn parse_severity(arg: Option<OsString>) -> Result<Severity, Error> {
+    let arg = value(arg)?;
+    ALL_SEVERITIES
+        .into_iter()
+        .find(|severity| arg.to_str() == Some(severity.to_string().as_str()))
+        .ok_or_else(usage_error)
+}
+
+fn usage_error() -> Error {
+    Error::new(std::io::ErrorKind::InvalidInput, "invalid arguments")
+}
+
+const USAGE: &str = "\
+Usage:
+    random-dir generate --seed N --out DIR [--bytes N] [--manifest FILE]
+    random-dir generate --spec FILE --out DIR [--manifest FILE]
+    random-dir list DIR [--json]
+    random-dir diff A B [--fail-on LEVEL]
+
+Commands:
+    generate  Populate DIR with a random tree generated from the seed or the spec file.
+    list      Print the listing of DIR.
+    diff      Compare the directories and print the differences.
+
+Options:
+    --fail-on LEVEL  Exit with non-zero code only if there are differences of
+                     LEVEL or more severe (default: metadata). LEVEL is one of
+                     ordering (also compares readdir order), metadata, content.
+
+Exit codes of diff:
+    0    No differences of LEVEL or more severe.
+    1    The most severe difference is in ordering.
+    2    The most severe difference is in metadata.
+    3    The most severe difference is in contents.
+    125  Failed to read the directories.
+";
+
+const DEFAULT_NUM_BYTES: usize = 64 * 1024;
+const USAGE_EXIT_CODE: u8 = 2;
+const DIFF_ERROR_EXIT_CODE: u8 = 125;
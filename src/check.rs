@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::Path;
+
+use arbitrary::Unstructured;
+
+use crate::copy_dir_all;
+use crate::copy_dir_all_shuffled;
+use crate::diff_dirs;
+use crate::list_dir_all;
+use crate::CopyError;
+use crate::DirBuilder;
+use crate::DirDiff;
+use crate::FileInfo;
+use crate::WorkDir;
+use crate::ALL_SEVERITIES;
+
+/// Assert that running `tool` for the second time doesn't change the directory.
+///
+/// The tool is run twice against the copy of `dir`; the original directory is left intact.
+/// Any content or metadata change made by the second run results in a panic.
+/// Useful to test formatters, normalizers and sync tools.
+pub fn assert_idempotent<P, F>(dir: P, tool: F)
+where
+    P: AsRef<Path>,
+    F: Fn(&Path),
+{
+    let workdir = WorkDir::new().unwrap();
+    let copy = workdir.path().join("copy");
+    copy_dir_all(dir.as_ref(), &copy, false).unwrap();
+    tool(&copy);
+    let files1 = list_dir_all(&copy).unwrap();
+    tool(&copy);
+    let files2 = list_dir_all(&copy).unwrap();
+    let diff = DirDiff::new(&files1, &files2);
+    if let Err(e) = diff.check(&ALL_SEVERITIES) {
+        panic!("the second run changed the directory:\n{}", e);
+    }
+}
+
+/// Assert that the operation that ran between the two listings didn't modify anything.
+///
+/// The listings must be produced by
+/// [`list_dir_all_with_ctime`](crate::list_dir_all_with_ctime): the status change time is
+/// updated whenever the contents or the metadata change, hence even the modifications that
+/// restore the original contents and modification times are detected. Any difference results in
+/// a panic. Useful to test that archiving or scanning is non-destructive.
+pub fn assert_metadata_untouched(before: &[FileInfo], after: &[FileInfo]) {
+    assert!(
+        before
+            .iter()
+            .chain(after.iter())
+            .all(|file| file.metadata.ctime.is_some()),
+        "the listings don't include the status change times"
+    );
+    let diff = DirDiff::new(before, after);
+    if let Err(e) = diff.check(&ALL_SEVERITIES) {
+        panic!("the operation modified the directory:\n{}", e);
+    }
+}
+
+/// Assert that the copy allocates no more blocks than the original.
+///
+/// The listings must be produced by [`list_dir_all_with_blocks`](crate::list_dir_all_with_blocks).
+/// Only the regular files that exist in both listings are compared. The copy may allocate up to
+/// [`ALLOCATION_SLACK`] bytes more per file because of the differences in block sizes and
+/// preallocation. Any file that exceeds this results in a panic. Useful to test the tools that
+/// should preserve the holes in sparse files (`cp --sparse`, `tar -S`).
+pub fn assert_sparseness_preserved(original: &[FileInfo], copy: &[FileInfo]) {
+    assert!(
+        original
+            .iter()
+            .chain(copy.iter())
+            .all(|file| file.metadata.blocks.is_some()),
+        "the listings don't include the no. of allocated blocks"
+    );
+    let original: HashMap<&Path, &FileInfo> = original
+        .iter()
+        .map(|file| (file.path.as_path(), file))
+        .collect();
+    for file in copy.iter() {
+        if file.metadata.mode & libc::S_IFMT != libc::S_IFREG {
+            continue;
+        }
+        let Some(original) = original.get(file.path.as_path()) else {
+            continue;
+        };
+        let allocated = file.metadata.blocks.unwrap_or(0) * 512;
+        let expected = original.metadata.blocks.unwrap_or(0) * 512;
+        assert!(
+            allocated <= expected + ALLOCATION_SLACK,
+            "`{}` allocates {} bytes instead of {}",
+            file.path.display(),
+            allocated,
+            expected
+        );
+    }
+}
+
+/// Assert that `tool`'s output doesn't depend on the order in which the files were created.
+///
+/// The directory is copied several times, each time creating the files in a
+/// different order determined by `u`. On most file systems this also changes the order in which
+/// the directory entries are listed (`readdir` order). Then the tool is run against each copy
+/// and the outputs are compared. The original directory is left intact.
+pub fn assert_order_independent<P, F, T>(
+    u: &mut Unstructured<'_>,
+    dir: P,
+    tool: F,
+) -> arbitrary::Result<()>
+where
+    P: AsRef<Path>,
+    F: Fn(&Path) -> T,
+    T: PartialEq + Debug,
+{
+    let dir = dir.as_ref();
+    let workdir = WorkDir::new().unwrap();
+    let num_copies: usize = u.int_in_range(2..=4)?;
+    let mut first_output = None;
+    for i in 0..num_copies {
+        let copy = workdir.path().join(i.to_string());
+        match copy_dir_all_shuffled(dir, &copy, u) {
+            Ok(()) => {}
+            Err(CopyError::Arbitrary(e)) => return Err(e),
+            Err(CopyError::Io(e)) => panic!("failed to copy `{}`: {}", dir.display(), e),
+        }
+        let output = tool(&copy);
+        match first_output.as_ref() {
+            None => first_output = Some(output),
+            Some(first_output) => assert_eq!(
+                first_output, &output,
+                "the output depends on the file creation order"
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Assert that unpacking the archive produces the same directory that was packed.
+///
+/// The tree is generated by `builder`, then `pack` is called with the tree and the path of the
+/// archive, and `unpack` is called with the archive path and an empty destination directory.
+/// Any difference between the tree and the destination directory (see [`diff_dirs`]) results
+/// in a panic. The archive and the destination directory are removed afterwards.
+pub fn round_trip<P, U>(
+    u: &mut Unstructured<'_>,
+    builder: DirBuilder,
+    pack: P,
+    unpack: U,
+) -> arbitrary::Result<()>
+where
+    P: FnOnce(&Path, &Path),
+    U: FnOnce(&Path, &Path),
+{
+    let dir = builder.create(u)?;
+    let workdir = WorkDir::new().unwrap();
+    let archive = workdir.path().join("archive");
+    let unpacked = workdir.path().join("unpacked");
+    std::fs::create_dir(&unpacked).unwrap();
+    pack(dir.path(), &archive);
+    unpack(&archive, &unpacked);
+    let diff = diff_dirs(dir.path(), &unpacked).unwrap();
+    if let Err(e) = diff.check(&ALL_SEVERITIES) {
+        panic!("the unpacked directory differs from the original:\n{}", e);
+    }
+    Ok(())
+}
+
+/// The no. of bytes that the copy may allocate in excess of the original (see
+/// [`assert_sparseness_preserved`]).
+pub const ALLOCATION_SLACK: u64 = 1024 * 1024;
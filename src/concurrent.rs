@@ -0,0 +1,153 @@
+use std::io::Error;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use arbitrary::Unstructured;
+
+use crate::list_dir_all_with_hook;
+use crate::FileInfo;
+use crate::Mutation;
+use crate::MutationKinds;
+use crate::Mutator;
+
+/// How the mutations are interleaved with the scan (see [`scan_while_mutating`]).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum MutationSchedule {
+    /// The background thread applies the mutations while the scan runs.
+    ///
+    /// The interleaving depends on the thread scheduling.
+    #[default]
+    Background,
+    /// The scanning thread applies the mutations at the checkpoints chosen by `u`.
+    ///
+    /// The same data produces the same interleaving as long as the scan calls
+    /// [`Checkpoint::check`] in the same order.
+    Deterministic,
+}
+
+/// The point in the scan at which the mutations are applied with
+/// [`MutationSchedule::Deterministic`] schedule.
+///
+/// With [`MutationSchedule::Background`] schedule the checkpoints are no-op.
+pub struct Checkpoint<'a, 'b> {
+    state: Option<CheckpointState<'a, 'b>>,
+}
+
+struct CheckpointState<'a, 'b> {
+    u: &'a mut Unstructured<'b>,
+    mutator: Mutator,
+    mutations: Vec<Mutation>,
+    /// The no. of checkpoints to skip before the next mutation.
+    countdown: usize,
+    error: Option<arbitrary::Error>,
+}
+
+impl Checkpoint<'_, '_> {
+    /// Apply the mutations scheduled at this point.
+    ///
+    /// Call it before opening each file.
+    pub fn check(&mut self) {
+        let Some(state) = self.state.as_mut() else {
+            return;
+        };
+        if state.error.is_some() || state.mutations.len() >= MAX_MUTATIONS {
+            return;
+        }
+        if state.countdown != 0 {
+            state.countdown -= 1;
+            return;
+        }
+        let result = state.mutator.mutate_one(state.u).and_then(|mutation| {
+            state.mutations.extend(mutation);
+            state.u.int_in_range(0..=MAX_COUNTDOWN)
+        });
+        match result {
+            Ok(countdown) => state.countdown = countdown,
+            Err(e) => state.error = Some(e),
+        }
+    }
+}
+
+/// Run `scan` while the tree is being modified by [`mutate`](crate::mutate)-like edits.
+///
+/// Use it to test that the archiver under test handles the files that disappear or change
+/// mid-scan gracefully. Returns the result of the scan and the mutations in the order they were
+/// applied. The scan receives the path of the directory and the checkpoint that it should call
+/// before visiting each file (see [`MutationSchedule`]).
+///
+/// Panics if some mutation fails.
+pub fn scan_while_mutating<P, F, T>(
+    u: &mut Unstructured<'_>,
+    dir: P,
+    kinds: MutationKinds,
+    schedule: MutationSchedule,
+    scan: F,
+) -> arbitrary::Result<(T, Vec<Mutation>)>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&Path, &mut Checkpoint<'_, '_>) -> T,
+{
+    let dir = dir.as_ref();
+    let mut mutator = Mutator::new(dir, kinds);
+    match schedule {
+        MutationSchedule::Background => {
+            let done = AtomicBool::new(false);
+            std::thread::scope(|scope| {
+                let thread = scope.spawn(|| {
+                    let mut mutations = Vec::new();
+                    while !done.load(Ordering::Relaxed) && mutations.len() < MAX_MUTATIONS {
+                        match mutator.mutate_one(u)? {
+                            Some(mutation) => mutations.push(mutation),
+                            // nothing to edit with the chosen kind, try another one
+                            None if !mutator.is_idle() && !u.is_empty() => continue,
+                            None => break,
+                        }
+                    }
+                    Ok(mutations)
+                });
+                let output = scan(dir, &mut Checkpoint { state: None });
+                done.store(true, Ordering::Relaxed);
+                let mutations = thread.join().unwrap()?;
+                Ok((output, mutations))
+            })
+        }
+        MutationSchedule::Deterministic => {
+            let countdown = u.int_in_range(0..=MAX_COUNTDOWN)?;
+            let mut checkpoint = Checkpoint {
+                state: Some(CheckpointState {
+                    u,
+                    mutator,
+                    mutations: Vec::new(),
+                    countdown,
+                    error: None,
+                }),
+            };
+            let output = scan(dir, &mut checkpoint);
+            let state = checkpoint.state.unwrap();
+            if let Some(e) = state.error {
+                return Err(e);
+            }
+            Ok((output, state.mutations))
+        }
+    }
+}
+
+/// Run [`list_dir_all`](crate::list_dir_all) while the tree is being modified (see
+/// [`scan_while_mutating`]).
+///
+/// The listing fails if some file disappears mid-scan.
+#[allow(clippy::type_complexity)]
+pub fn list_dir_all_while_mutating<P: AsRef<Path>>(
+    u: &mut Unstructured<'_>,
+    dir: P,
+    kinds: MutationKinds,
+    schedule: MutationSchedule,
+) -> arbitrary::Result<(Result<Vec<FileInfo>, Error>, Vec<Mutation>)> {
+    scan_while_mutating(u, dir, kinds, schedule, |dir, checkpoint| {
+        list_dir_all_with_hook(dir, false, None, &mut || checkpoint.check())
+    })
+}
+
+const MAX_MUTATIONS: usize = 100;
+const MAX_COUNTDOWN: usize = 7;
@@ -0,0 +1,55 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::Error;
+use std::io::ErrorKind;
+
+use crate::FileType;
+
+/// Impossible [`DirBuilder`](crate::DirBuilder) configuration (see
+/// [`DirBuilder::build`](crate::DirBuilder::build)).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ConfigError {
+    /// No file types to choose from (see
+    /// [`DirBuilder::file_types`](crate::DirBuilder::file_types)).
+    NoFileTypes,
+    /// [`TreeShape::min_files`](crate::TreeShape::min_files) is greater than
+    /// [`TreeShape::max_files`](crate::TreeShape::max_files).
+    FileCountRange {
+        /// Minimum no. of files.
+        min: usize,
+        /// Maximum no. of files.
+        max: usize,
+    },
+    /// Only links are requested but there are no file types the links could point to.
+    NoLinkTargets {
+        /// The requested link types.
+        file_types: Vec<FileType>,
+    },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoFileTypes => write!(f, "no file types to generate"),
+            Self::FileCountRange { min, max } => write!(
+                f,
+                "the minimum no. of files ({}) is greater than the maximum ({})",
+                min, max
+            ),
+            Self::NoLinkTargets { file_types } => write!(
+                f,
+                "the links ({:?}) need at least one non-directory file type to point to, \
+                 e.g. `FileType::Regular`",
+                file_types
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for Error {
+    fn from(other: ConfigError) -> Self {
+        Error::new(ErrorKind::InvalidInput, other)
+    }
+}
@@ -0,0 +1,203 @@
+use std::fs::File;
+use std::io::Error;
+use std::io::Write;
+use std::path::Path;
+
+use arbitrary::Unstructured;
+
+/// Regular file contents generator.
+///
+/// Implement this trait to fill the generated files with domain-specific payloads
+/// (valid ELF headers, UTF-8 text, samples of your own format etc.).
+pub trait ContentGenerator {
+    /// Generate the contents of the regular file located at `path`.
+    fn generate(&mut self, u: &mut Unstructured<'_>, path: &Path) -> arbitrary::Result<Vec<u8>>;
+}
+
+impl<F> ContentGenerator for F
+where
+    F: FnMut(&mut Unstructured<'_>, &Path) -> arbitrary::Result<Vec<u8>>,
+{
+    fn generate(&mut self, u: &mut Unstructured<'_>, path: &Path) -> arbitrary::Result<Vec<u8>> {
+        self(u, path)
+    }
+}
+
+/// Generates arbitrary bytes.
+///
+/// This is the default content generator.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ArbitraryContents;
+
+impl ContentGenerator for ArbitraryContents {
+    fn generate(&mut self, u: &mut Unstructured<'_>, _path: &Path) -> arbitrary::Result<Vec<u8>> {
+        u.arbitrary()
+    }
+}
+
+/// Regular file size recipe.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SizeRecipe {
+    /// The size is determined by the content generator.
+    #[default]
+    Arbitrary,
+    /// Cycle through the specified sizes starting from an arbitrary offset.
+    ///
+    /// The contents produced by the content generator are repeated (or truncated)
+    /// to match the size.
+    Cycle(Vec<u64>),
+    /// Sample the sizes from the histogram of power-of-two buckets.
+    ///
+    /// The first element is the weight of the empty files, the element `i > 0` is the weight of
+    /// the sizes in `[2^(i-1), 2^i)`. The contents are repeated (or truncated) as in
+    /// [`Cycle`](Self::Cycle).
+    Histogram(Vec<u64>),
+}
+
+impl SizeRecipe {
+    /// Cycle through [`POWER_OF_TWO_BOUNDARY_SIZES`].
+    ///
+    /// Archive formats have header and size-field edge cases exactly at these sizes,
+    /// and uniformly distributed random sizes almost never hit them.
+    pub fn power_of_two_boundaries() -> Self {
+        Self::Cycle(POWER_OF_TWO_BOUNDARY_SIZES.into())
+    }
+
+    /// Cycle through [`BUFFER_BOUNDARY_SIZES`].
+    ///
+    /// Triggers off-by-one bugs in block-oriented readers and writers.
+    pub fn buffer_boundaries() -> Self {
+        Self::Cycle(BUFFER_BOUNDARY_SIZES.into())
+    }
+}
+
+/// Produces the sizes according to [`SizeRecipe`].
+pub(crate) enum Sizes<'a> {
+    Cycle(std::iter::Skip<std::iter::Cycle<std::iter::Copied<std::slice::Iter<'a, u64>>>>),
+    Histogram(&'a [u64]),
+}
+
+impl<'a> Sizes<'a> {
+    /// Returns `None` if the size is determined by the content generator.
+    pub(crate) fn new(
+        u: &mut Unstructured<'_>,
+        recipe: &'a SizeRecipe,
+    ) -> arbitrary::Result<Option<Self>> {
+        match recipe {
+            SizeRecipe::Cycle(sizes) if !sizes.is_empty() => {
+                let offset = u.choose_index(sizes.len())?;
+                Ok(Some(Self::Cycle(
+                    sizes.iter().copied().cycle().skip(offset),
+                )))
+            }
+            SizeRecipe::Histogram(weights) if weights.iter().any(|w| *w != 0) => {
+                Ok(Some(Self::Histogram(&weights[..weights.len().min(65)])))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub(crate) fn next(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<u64> {
+        match self {
+            Self::Cycle(sizes) => Ok(sizes.next().unwrap()),
+            Self::Histogram(weights) => match choose_weighted(u, weights)? {
+                0 => Ok(0),
+                i => {
+                    let min = 1_u64 << (i - 1);
+                    let max = min.checked_mul(2).map_or(u64::MAX, |max| max - 1);
+                    u.int_in_range(min..=max)
+                }
+            },
+        }
+    }
+}
+
+/// Returns the index of the weight chosen with the probability proportional to the weight.
+///
+/// Returns zero if all weights are zero.
+pub(crate) fn choose_weighted(
+    u: &mut Unstructured<'_>,
+    weights: &[u64],
+) -> arbitrary::Result<usize> {
+    let total = weights
+        .iter()
+        .fold(0_u64, |total, w| total.saturating_add(*w));
+    if total == 0 {
+        return Ok(0);
+    }
+    let mut x = u.int_in_range(0..=total - 1)?;
+    for (i, w) in weights.iter().enumerate() {
+        match x.checked_sub(*w) {
+            Some(y) => x = y,
+            None => return Ok(i),
+        }
+    }
+    Ok(weights.len() - 1)
+}
+
+/// Sizes near powers of two.
+///
+/// Files larger than [`MAX_DENSE_FILE_SIZE`] are created sparse.
+/// Note that [`list_dir_all`](crate::list_dir_all) reads the whole file into memory, use
+/// [`list_dir_all_sampled`](crate::list_dir_all_sampled) for such files.
+pub const POWER_OF_TWO_BOUNDARY_SIZES: [u64; 13] = [
+    0,
+    1,
+    511,
+    512,
+    513,
+    4095,
+    4096,
+    65535,
+    65536,
+    (1 << 20) - 1,
+    (1 << 20) + 1,
+    (1 << 32) - 1,
+    (1 << 32) + 1,
+];
+
+/// Sizes one byte below, at and one byte above common buffer sizes.
+///
+/// Includes the multiples of the 512-byte tar block, the tar record (10 KiB), the page size and
+/// the typical I/O buffer sizes.
+pub const BUFFER_BOUNDARY_SIZES: [u64; 27] = [
+    511, 512, 513, 1023, 1024, 1025, 1535, 1536, 1537, 4095, 4096, 4097, 8191, 8192, 8193, 10239,
+    10240, 10241, 16383, 16384, 16385, 65535, 65536, 65537, 131071, 131072, 131073,
+];
+
+/// Files larger than this size are created sparse: only the first block of the contents is
+/// written.
+pub const MAX_DENSE_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+pub(crate) fn write_contents(
+    file: &mut File,
+    pattern: &[u8],
+    size: u64,
+    sparse: bool,
+) -> Result<(), Error> {
+    const BLOCK_SIZE: u64 = 4096;
+    const CHUNK_SIZE: usize = 1024 * 1024;
+    let dense_size = if sparse && size > MAX_DENSE_FILE_SIZE {
+        BLOCK_SIZE
+    } else {
+        size
+    };
+    if !pattern.is_empty() {
+        // keep the pattern continuous across the chunks
+        let chunk_size = (CHUNK_SIZE / pattern.len()).max(1) * pattern.len();
+        let chunk: Vec<u8> = pattern
+            .iter()
+            .copied()
+            .cycle()
+            .take(chunk_size.min(dense_size as usize))
+            .collect();
+        let mut written = 0;
+        while written < dense_size {
+            let n = chunk.len().min((dense_size - written) as usize);
+            file.write_all(&chunk[..n])?;
+            written += n as u64;
+        }
+    }
+    file.set_len(size)?;
+    Ok(())
+}
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::fs::create_dir;
+use std::fs::hard_link;
+use std::fs::read_link;
+use std::fs::File;
+use std::fs::Permissions;
+use std::io::Error;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::lchown;
+use std::os::unix::fs::symlink;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use arbitrary::Unstructured;
+use walkdir::WalkDir;
+
+use crate::bind_socket;
+#[cfg(target_os = "linux")]
+use crate::bind_socket_at;
+use crate::chown_at;
+use crate::create_dir_all_at;
+use crate::create_file_at;
+use crate::fchmod_at;
+use crate::from_unix_time;
+use crate::link_at;
+use crate::mkdir_at;
+use crate::mkfifo;
+use crate::mkfifo_at;
+use crate::mknod;
+use crate::mknod_at;
+use crate::open_dir_at;
+use crate::open_file_at;
+use crate::os_str_to_c_string;
+use crate::path_to_c_string;
+use crate::read_dir_at;
+use crate::read_link_at;
+#[cfg(target_os = "linux")]
+use crate::reflink;
+use crate::set_file_modified_time;
+use crate::set_file_modified_time_at;
+use crate::stat_at;
+use crate::symlink_at;
+
+/// Copy the tree faithfully: file types, modes, modification times, hard link topology and
+/// (when running as root) ownership.
+///
+/// The files are copied relative to the directory descriptors, i.e. the trees deeper than
+/// [`PATH_MAX`](crate::PATH_MAX) are copied as well. The regular files share the extents with
+/// the originals if `reflinks` is `true`. The destination directory must not exist.
+pub(crate) fn copy_dir_all(src: &Path, dst: &Path, reflinks: bool) -> Result<(), Error> {
+    create_dir(dst)?;
+    copy_dir_into(src, dst, reflinks)
+}
+
+/// Same as [`copy_dir_all`] but the destination directory must exist and be empty.
+pub(crate) fn copy_dir_into(src: &Path, dst: &Path, reflinks: bool) -> Result<(), Error> {
+    let is_root = unsafe { libc::geteuid() } == 0;
+    let src_dir = File::open(src)?;
+    let metadata = src_dir.metadata()?;
+    let dst_dir = File::open(dst)?;
+    let mut inodes = HashMap::new();
+    copy_dir_at(
+        src_dir.as_fd(),
+        dst_dir.as_fd(),
+        dst,
+        &mut Context {
+            dst_root: dst_dir.as_fd(),
+            prefix: PathBuf::new(),
+            inodes: &mut inodes,
+            is_root,
+            reflinks,
+        },
+    )?;
+    if is_root {
+        lchown(dst, Some(metadata.uid()), Some(metadata.gid()))?;
+    }
+    std::fs::set_permissions(dst, Permissions::from_mode(metadata.mode() & 0o7777))?;
+    let c_path = path_to_c_string(dst.to_path_buf())?;
+    set_file_modified_time(&c_path, metadata.modified()?)?;
+    Ok(())
+}
+
+struct Context<'a> {
+    dst_root: BorrowedFd<'a>,
+    /// The path of the current directory relative to the root.
+    prefix: PathBuf,
+    /// The first copy of each multiply-linked inode relative to the root.
+    inodes: &'a mut HashMap<(u64, u64), PathBuf>,
+    is_root: bool,
+    reflinks: bool,
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn copy_dir_at(
+    src: BorrowedFd<'_>,
+    dst: BorrowedFd<'_>,
+    dst_path: &Path,
+    context: &mut Context<'_>,
+) -> Result<(), Error> {
+    let mut names: Vec<CString> = read_dir_at(src)?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    names.sort_unstable();
+    for name in names.into_iter() {
+        let stat = stat_at(src, &name)?;
+        let file_name = OsStr::from_bytes(name.to_bytes());
+        let format = stat.st_mode & libc::S_IFMT;
+        if format == libc::S_IFDIR {
+            mkdir_at(dst, &name, 0o700)?;
+            let src_dir = open_dir_at(src, &name)?;
+            let dst_dir = open_dir_at(dst, &name)?;
+            context.prefix.push(file_name);
+            copy_dir_at(
+                src_dir.as_fd(),
+                dst_dir.as_fd(),
+                &dst_path.join(file_name),
+                context,
+            )?;
+            context.prefix.pop();
+            // children modify parent's modification time, hence the metadata is set afterwards
+            set_metadata_at(dst, &name, &stat, context.is_root)?;
+            continue;
+        }
+        if stat.st_nlink > 1 {
+            let key = (stat.st_dev as u64, stat.st_ino as u64);
+            if let Some(original) = context.inodes.get(&key) {
+                let original_dir =
+                    create_dir_all_at(context.dst_root, original.parent().unwrap_or(original))?;
+                let original_name = os_str_to_c_string(original.file_name().unwrap_or_default())?;
+                link_at(original_dir.as_fd(), &original_name, dst, &name)?;
+                continue;
+            }
+            context.inodes.insert(key, context.prefix.join(file_name));
+        }
+        match format {
+            libc::S_IFREG => {
+                let mut src_file = open_file_at(src, &name)?;
+                let mut dst_file = create_file_at(dst, &name, 0o600)?;
+                if context.reflinks {
+                    reflink_file(&src_file, &dst_file)?;
+                } else {
+                    std::io::copy(&mut src_file, &mut dst_file)?;
+                }
+            }
+            libc::S_IFLNK => {
+                let target = read_link_at(src, &name)?;
+                symlink_at(&path_to_c_string(target)?, dst, &name)?;
+            }
+            libc::S_IFIFO => mkfifo_at(dst, &name, 0o600)?,
+            #[cfg(target_os = "linux")]
+            libc::S_IFSOCK => bind_socket_at(dst, file_name)?,
+            #[cfg(not(target_os = "linux"))]
+            libc::S_IFSOCK => bind_socket(&dst_path.join(file_name))?,
+            libc::S_IFBLK | libc::S_IFCHR => mknod_at(dst, &name, stat.st_mode, stat.st_rdev)?,
+            _ => continue,
+        }
+        set_metadata_at(dst, &name, &stat, context.is_root)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn reflink_file(src: &File, dst: &File) -> Result<(), Error> {
+    reflink(src.as_fd(), dst.as_fd())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink_file(_src: &File, _dst: &File) -> Result<(), Error> {
+    Err(Error::new(
+        std::io::ErrorKind::Unsupported,
+        "reflinks are supported only on Linux",
+    ))
+}
+
+fn set_metadata_at(
+    dirfd: BorrowedFd<'_>,
+    name: &CStr,
+    stat: &libc::stat,
+    is_root: bool,
+) -> Result<(), Error> {
+    if is_root {
+        chown_at(dirfd, name, stat.st_uid, stat.st_gid)?;
+    }
+    if stat.st_mode & libc::S_IFMT != libc::S_IFLNK {
+        fchmod_at(dirfd, name, stat.st_mode & 0o7777)?;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    let mtime = from_unix_time(stat.st_mtime as i64, stat.st_mtime_nsec as u32);
+    set_file_modified_time_at(dirfd.as_raw_fd(), name, mtime)?;
+    Ok(())
+}
+
+/// Copy the tree creating the files in the order determined by `u`.
+///
+/// The parent directories are always created before their children.
+pub(crate) fn copy_dir_all_shuffled(
+    src: &Path,
+    dst: &Path,
+    u: &mut Unstructured<'_>,
+) -> Result<(), CopyError> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    for entry in WalkDir::new(src).into_iter() {
+        let entry = entry.map_err(Error::from)?;
+        if entry.path() == src {
+            continue;
+        }
+        if entry.file_type().is_dir() {
+            dirs.push(entry.into_path());
+        } else {
+            files.push(entry.into_path());
+        }
+    }
+    shuffle(u, &mut dirs[..])?;
+    shuffle(u, &mut files[..])?;
+    // add missing parents in front of their children
+    let mut paths = vec![src.to_path_buf()];
+    let mut added = HashSet::new();
+    for dir in dirs.into_iter() {
+        let mut parents = Vec::new();
+        let mut parent = dir.as_path();
+        while parent != src && added.insert(parent.to_path_buf()) {
+            parents.push(parent.to_path_buf());
+            parent = parent.parent().unwrap_or(src);
+        }
+        paths.extend(parents.into_iter().rev());
+    }
+    paths.extend(files);
+    copy_paths(src, dst, paths)?;
+    Ok(())
+}
+
+/// Either I/O error or not enough unstructured data.
+#[derive(Debug)]
+pub(crate) enum CopyError {
+    Io(Error),
+    Arbitrary(arbitrary::Error),
+}
+
+impl From<Error> for CopyError {
+    fn from(other: Error) -> Self {
+        Self::Io(other)
+    }
+}
+
+impl From<arbitrary::Error> for CopyError {
+    fn from(other: arbitrary::Error) -> Self {
+        Self::Arbitrary(other)
+    }
+}
+
+fn shuffle<T>(u: &mut Unstructured<'_>, items: &mut [T]) -> arbitrary::Result<()> {
+    for i in (1..items.len()).rev() {
+        let j = u.choose_index(i + 1)?;
+        items.swap(i, j);
+    }
+    Ok(())
+}
+
+/// Copy the paths in the specified order. Parents should precede their children.
+fn copy_paths(src: &Path, dst: &Path, paths: Vec<PathBuf>) -> Result<(), Error> {
+    let is_root = unsafe { libc::geteuid() } == 0;
+    let mut inodes: HashMap<(u64, u64), PathBuf> = HashMap::new();
+    let mut dirs = Vec::new();
+    for src_path in paths.into_iter() {
+        let relative_path = src_path.strip_prefix(src).map_err(Error::other)?;
+        let path = dst.join(relative_path);
+        let metadata = src_path.symlink_metadata()?;
+        let file_type = metadata.file_type();
+        if file_type.is_dir() {
+            create_dir(&path)?;
+            dirs.push((path, metadata));
+            continue;
+        }
+        if metadata.nlink() > 1 {
+            let key = (metadata.dev(), metadata.ino());
+            if let Some(original) = inodes.get(&key) {
+                hard_link(original, &path)?;
+                continue;
+            }
+            inodes.insert(key, path.clone());
+        }
+        let c_path = path_to_c_string(path.clone())?;
+        if file_type.is_file() {
+            std::fs::copy(&src_path, &path)?;
+        } else if file_type.is_symlink() {
+            symlink(read_link(&src_path)?, &path)?;
+        } else if file_type.is_fifo() {
+            mkfifo(&c_path, 0o600)?;
+        } else if file_type.is_socket() {
+            bind_socket(&path)?;
+        } else if file_type.is_block_device() || file_type.is_char_device() {
+            mknod(
+                &c_path,
+                metadata.mode() as libc::mode_t,
+                metadata.rdev() as libc::dev_t,
+            )?;
+        }
+        if is_root {
+            lchown(&path, Some(metadata.uid()), Some(metadata.gid()))?;
+        }
+        if !file_type.is_symlink() {
+            std::fs::set_permissions(&path, Permissions::from_mode(metadata.mode() & 0o7777))?;
+        }
+        set_file_modified_time(
+            &c_path,
+            metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        )?;
+    }
+    // children modify parent's modification time, hence the deepest directories go first
+    dirs.sort_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+    for (path, metadata) in dirs.into_iter() {
+        if is_root {
+            lchown(&path, Some(metadata.uid()), Some(metadata.gid()))?;
+        }
+        std::fs::set_permissions(&path, Permissions::from_mode(metadata.mode() & 0o7777))?;
+        let c_path = path_to_c_string(path)?;
+        set_file_modified_time(&c_path, metadata.modified()?)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use std::fs::create_dir_all;
+use std::io::Error;
+use std::path::Path;
+use std::path::PathBuf;
+
+use arbitrary::Unstructured;
+
+use crate::Digest;
+use crate::Dir;
+use crate::DirBuilder;
+
+/// The bytes of the spec that were consumed by the generator.
+///
+/// The spec is the unstructured data that the tree is generated from. The generator consumes the
+/// bytes from the front (the values) and from the end (the lengths, see
+/// [`Unstructured::arbitrary_len`]); the bytes in the middle are never read.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConsumedSpec {
+    bytes: Vec<u8>,
+    front_len: usize,
+    back_len: usize,
+}
+
+impl ConsumedSpec {
+    /// Measure how many bytes of `spec` were consumed by `u`.
+    ///
+    /// If all the bytes were consumed they are counted as consumed from the front. Panics if `u`
+    /// wasn't created from `spec`.
+    pub fn measure(spec: &[u8], u: &Unstructured<'_>) -> Self {
+        let start = spec.as_ptr() as usize;
+        if u.is_empty() {
+            // the remaining empty slice might not point into `spec`
+            return Self {
+                bytes: spec.to_vec(),
+                front_len: spec.len(),
+                back_len: 0,
+            };
+        }
+        let remaining = u.peek_bytes(0).unwrap_or_default().as_ptr() as usize;
+        assert!(
+            start <= remaining && remaining + u.len() <= start + spec.len(),
+            "`u` wasn't created from `spec`"
+        );
+        let front_len = remaining - start;
+        let back_len = spec.len() - front_len - u.len();
+        let mut bytes = spec.to_vec();
+        bytes[front_len..spec.len() - back_len].fill(0);
+        Self {
+            bytes,
+            front_len,
+            back_len,
+        }
+    }
+
+    /// The consumed bytes with the unconsumed middle zeroed out.
+    ///
+    /// The middle is kept because the lengths taken from the end are bounded by the no. of
+    /// remaining bytes, i.e. the bytes reproduce the same tree. Note that the modification times
+    /// are sampled relative to the current time, i.e. the same bytes might produce different
+    /// times on different days.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..]
+    }
+
+    /// No. of bytes consumed from the front.
+    pub fn front_len(&self) -> usize {
+        self.front_len
+    }
+
+    /// No. of bytes consumed from the end.
+    pub fn back_len(&self) -> usize {
+        self.back_len
+    }
+
+    /// The length of the original spec.
+    pub fn spec_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Write [`bytes`](Self::bytes) to the fuzzer corpus directory creating the directory if
+    /// needed.
+    ///
+    /// The file is named after the digest of the bytes (see [`Digest::of`]), i.e. writing the
+    /// same spec twice produces one file. Returns the path of the file.
+    pub fn write_to_corpus<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, Error> {
+        let dir = dir.as_ref();
+        create_dir_all(dir)?;
+        let path = dir.join(Digest::of(&self.bytes).to_string());
+        std::fs::write(&path, &self.bytes)?;
+        Ok(path)
+    }
+}
+
+impl DirBuilder {
+    /// Create the directory from `spec` and record which bytes were consumed.
+    ///
+    /// Write the consumed bytes to the corpus directory when the test fails (see
+    /// [`ConsumedSpec::write_to_corpus`]).
+    pub fn create_recorded(self, spec: &[u8]) -> arbitrary::Result<(Dir, ConsumedSpec)> {
+        let mut u = Unstructured::new(spec);
+        let dir = self.create(&mut u)?;
+        let consumed = ConsumedSpec::measure(spec, &u);
+        Ok((dir, consumed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+
+    use arbtest::arbtest;
+
+    use super::*;
+
+    #[test]
+    fn consumed_bytes_reproduce_the_tree() {
+        arbtest(|u| {
+            // the generator doesn't read the padding in the middle
+            let front = u.bytes(u.len() / 2)?.to_vec();
+            let padding = vec![u8::MAX; u.int_in_range(0..=4096)?];
+            let back = u.bytes(u.len())?;
+            let spec = &[&front[..], &padding[..], back].concat()[..];
+            let builder = || {
+                let start = UNIX_EPOCH + Duration::from_secs(1 << 30);
+                DirBuilder::new()
+                    .mtime_range(start..=start + Duration::from_secs(1 << 30))
+                    // takes the lengths from the end
+                    .content_generator(|u: &mut Unstructured<'_>, _: &Path| {
+                        let len = u.arbitrary_len::<u8>()?;
+                        Ok(u.bytes(len)?.to_vec())
+                    })
+            };
+            let (dir, consumed) = builder().create_recorded(spec)?;
+            assert_eq!(spec.len(), consumed.bytes().len());
+            let other = builder()
+                .create(&mut Unstructured::new(consumed.bytes()))
+                .unwrap();
+            assert_eq!(dir.entries(), other.entries());
+            Ok(())
+        });
+    }
+}
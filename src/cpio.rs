@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::split_device_number;
+use crate::unix_time;
+use crate::DirSpec;
+use crate::Entry;
+use crate::FileType;
+
+impl DirSpec {
+    /// Write the tree as cpio archive in "new ASCII" (newc) format.
+    ///
+    /// Hard links share the inode with the original file, the contents are stored with the last
+    /// link as GNU cpio does. Fails if some file is larger than 4 GiB or if some modification time is
+    /// before the epoch or after 2106.
+    pub fn write_cpio<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let entries = self.entries();
+        // assign the same inode to all the links to the file
+        let mut inodes: HashMap<&Path, usize> = HashMap::new();
+        let mut link_counts: Vec<u32> = Vec::with_capacity(entries.len());
+        let mut last_links: Vec<usize> = Vec::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            let original = match entry.file_type {
+                FileType::HardLink => entry
+                    .target
+                    .as_deref()
+                    .and_then(|target| inodes.get(target))
+                    .copied(),
+                _ => None,
+            };
+            let ino = match original {
+                Some(ino) => {
+                    link_counts[ino] += 1;
+                    last_links[ino] = i;
+                    ino
+                }
+                None => {
+                    link_counts.push(1);
+                    last_links.push(i);
+                    link_counts.len() - 1
+                }
+            };
+            inodes.insert(entry.path.as_path(), ino);
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            let ino = inodes[entry.path.as_path()];
+            let original = match entry.file_type {
+                FileType::HardLink => entry
+                    .target
+                    .as_deref()
+                    .and_then(|target| entries.iter().find(|e| e.path == target))
+                    .unwrap_or(entry),
+                _ => entry,
+            };
+            let data: &[u8] = if last_links[ino] != i {
+                &[]
+            } else {
+                match original.file_type {
+                    FileType::Regular => self.contents(&original.path).unwrap_or_default(),
+                    FileType::Symlink => original
+                        .target
+                        .as_deref()
+                        .map(|target| target.as_os_str().as_bytes())
+                        .unwrap_or_default(),
+                    _ => &[],
+                }
+            };
+            let size: u32 = data.len().try_into().map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("`{}` is too large for cpio", entry.path.display()),
+                )
+            })?;
+            let nlink = match original.file_type {
+                FileType::Directory => 2,
+                _ => link_counts[ino],
+            };
+            let (rdev_major, rdev_minor) = split_device_number(original.rdev);
+            let mtime = match original.mtime {
+                Some(t) => u32::try_from(unix_time(t).0).map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "the modification time of `{}` is out of range for cpio",
+                            original.path.display()
+                        ),
+                    )
+                })?,
+                None => 0,
+            };
+            let (uid, gid) = original.owner.unwrap_or((0, 0));
+            let header = [
+                ino as u32 + 1,
+                mode(original),
+                uid,
+                gid,
+                nlink,
+                mtime,
+                size,
+                0,
+                0,
+                rdev_major,
+                rdev_minor,
+                entry.path.as_os_str().len() as u32 + 1,
+                0,
+            ];
+            write_record(
+                &mut writer,
+                &header,
+                entry.path.as_os_str().as_bytes(),
+                data,
+            )?;
+        }
+        let trailer = [0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, TRAILER.len() as u32 + 1, 0];
+        write_record(&mut writer, &trailer, TRAILER, &[])?;
+        Ok(())
+    }
+}
+
+fn write_record<W: Write>(
+    mut writer: W,
+    header: &[u32; 13],
+    name: &[u8],
+    data: &[u8],
+) -> Result<(), Error> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + name.len() + data.len() + 8);
+    buf.extend_from_slice(MAGIC);
+    for field in header.iter() {
+        buf.extend_from_slice(format!("{:08X}", field).as_bytes());
+    }
+    buf.extend_from_slice(name);
+    buf.push(0);
+    pad(&mut buf);
+    buf.extend_from_slice(data);
+    pad(&mut buf);
+    writer.write_all(&buf)
+}
+
+/// Pad to 4-byte boundary.
+fn pad(buf: &mut Vec<u8>) {
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+// `mode_t` is 16-bit on MacOS
+#[allow(clippy::unnecessary_cast)]
+fn mode(entry: &Entry) -> u32 {
+    let format = match entry.file_type {
+        FileType::Regular | FileType::HardLink => libc::S_IFREG,
+        FileType::Directory => libc::S_IFDIR,
+        FileType::Fifo => libc::S_IFIFO,
+        FileType::Socket => libc::S_IFSOCK,
+        FileType::BlockDevice => libc::S_IFBLK,
+        FileType::CharDevice | FileType::Whiteout => libc::S_IFCHR,
+        FileType::Symlink => libc::S_IFLNK,
+    };
+    format as u32 | (entry.mode & 0o7777)
+}
+
+const MAGIC: &[u8] = b"070701";
+const HEADER_LEN: usize = 110;
+const TRAILER: &[u8] = b"TRAILER!!!";
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+
+    use arbtest::arbtest;
+
+    use super::*;
+    use crate::DirBuilder;
+
+    #[test]
+    fn out_of_range_times_are_rejected() {
+        arbtest(|u| {
+            let end = UNIX_EPOCH - Duration::from_secs(1);
+            let spec = DirBuilder::new()
+                .mtime_range(end - Duration::from_secs(1 << 30)..=end)
+                .create_spec(u)?;
+            let result = spec.write_cpio(std::io::sink());
+            if spec.entries().iter().any(|e| e.mtime.is_some()) {
+                assert_eq!(ErrorKind::InvalidInput, result.unwrap_err().kind());
+            } else {
+                result.unwrap();
+            }
+            Ok(())
+        });
+    }
+}
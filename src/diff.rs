@@ -0,0 +1,764 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::Error;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+#[cfg(feature = "fs")]
+use crate::list_dir_all;
+#[cfg(feature = "fs")]
+use crate::list_dir_all_sampled;
+#[cfg(feature = "fs")]
+use crate::list_dir_all_with_readdir_order;
+use crate::Acl;
+#[cfg(feature = "fs")]
+use crate::ReaddirOrder;
+
+/// How severe the difference is.
+///
+/// The levels are ordered from the least to the most severe.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum Severity {
+    /// The same entries are listed in a different order.
+    Ordering,
+    /// Only metadata differs (mode, mtime etc.).
+    Metadata,
+    /// Contents differ or the entry is missing.
+    Content,
+}
+
+/// All severity levels.
+pub const ALL_SEVERITIES: [Severity; 3] =
+    [Severity::Ordering, Severity::Metadata, Severity::Content];
+
+impl Severity {
+    /// Machine-readable exit code.
+    ///
+    /// More severe levels have larger codes, zero means no differences.
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Severity::Ordering => 1,
+            Severity::Metadata => 2,
+            Severity::Content => 3,
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Severity::Ordering => "ordering",
+            Severity::Metadata => "metadata",
+            Severity::Content => "content",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Metadata field.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum MetadataField {
+    /// Containing device number.
+    Dev,
+    /// Inode.
+    Ino,
+    /// File mode.
+    Mode,
+    /// Owner's user id.
+    Uid,
+    /// Owner's group id.
+    Gid,
+    /// No. of hard links.
+    Nlink,
+    /// Device number of the file itself.
+    Rdev,
+    /// Last modification time.
+    Mtime,
+    /// File size in bytes.
+    FileSize,
+    /// POSIX access control lists.
+    Acl,
+    /// SELinux security context.
+    SelinuxContext,
+    /// Inode flags.
+    InodeFlags,
+    /// Birth time.
+    Btime,
+    /// Last status change time.
+    Ctime,
+    /// Whether the extents are shared with other files.
+    SharedExtents,
+    /// No. of allocated blocks.
+    Blocks,
+}
+
+impl MetadataField {
+    /// Returns the fields that differ.
+    pub fn diff(a: &Metadata, b: &Metadata) -> Vec<MetadataField> {
+        use MetadataField::*;
+        let mut fields = Vec::new();
+        if a.dev != b.dev {
+            fields.push(Dev);
+        }
+        if a.ino != b.ino {
+            fields.push(Ino);
+        }
+        if a.mode != b.mode {
+            fields.push(Mode);
+        }
+        if a.uid != b.uid {
+            fields.push(Uid);
+        }
+        if a.gid != b.gid {
+            fields.push(Gid);
+        }
+        if a.nlink != b.nlink {
+            fields.push(Nlink);
+        }
+        if a.rdev != b.rdev {
+            fields.push(Rdev);
+        }
+        if a.mtime != b.mtime {
+            fields.push(Mtime);
+        }
+        if a.file_size != b.file_size {
+            fields.push(FileSize);
+        }
+        if a.acl != b.acl {
+            fields.push(Acl);
+        }
+        if a.selinux_context != b.selinux_context {
+            fields.push(SelinuxContext);
+        }
+        if a.inode_flags != b.inode_flags {
+            fields.push(InodeFlags);
+        }
+        if a.btime != b.btime {
+            fields.push(Btime);
+        }
+        if a.ctime != b.ctime {
+            fields.push(Ctime);
+        }
+        if a.shared_extents != b.shared_extents {
+            fields.push(SharedExtents);
+        }
+        if a.blocks != b.blocks {
+            fields.push(Blocks);
+        }
+        fields
+    }
+}
+
+impl Display for MetadataField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use MetadataField::*;
+        let s = match self {
+            Dev => "dev",
+            Ino => "ino",
+            Mode => "mode",
+            Uid => "uid",
+            Gid => "gid",
+            Nlink => "nlink",
+            Rdev => "rdev",
+            Mtime => "mtime",
+            FileSize => "size",
+            Acl => "acl",
+            SelinuxContext => "selinux",
+            InodeFlags => "flags",
+            Btime => "btime",
+            Ctime => "ctime",
+            SharedExtents => "shared",
+            Blocks => "blocks",
+        };
+        f.write_str(s)
+    }
+}
+
+/// All metadata fields.
+pub const ALL_METADATA_FIELDS: [MetadataField; 16] = {
+    use MetadataField::*;
+    [
+        Dev,
+        Ino,
+        Mode,
+        Uid,
+        Gid,
+        Nlink,
+        Rdev,
+        Mtime,
+        FileSize,
+        Acl,
+        SelinuxContext,
+        InodeFlags,
+        Btime,
+        Ctime,
+        SharedExtents,
+        Blocks,
+    ]
+};
+
+/// Set of metadata fields that are kept by [`Metadata::masked`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct MetadataMask(u16);
+
+impl MetadataMask {
+    /// The mask that keeps all the fields.
+    pub const ALL: Self = Self::new(&ALL_METADATA_FIELDS);
+
+    /// The mask that keeps only the specified fields.
+    pub const fn new(fields: &[MetadataField]) -> Self {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < fields.len() {
+            bits |= 1 << fields[i] as u16;
+            i += 1;
+        }
+        Self(bits)
+    }
+
+    /// Keep the field.
+    pub const fn with(self, field: MetadataField) -> Self {
+        Self(self.0 | 1 << field as u16)
+    }
+
+    /// Zero out the field.
+    pub const fn without(self, field: MetadataField) -> Self {
+        Self(self.0 & !(1 << field as u16))
+    }
+
+    /// Is the field kept?
+    pub const fn contains(self, field: MetadataField) -> bool {
+        self.0 & (1 << field as u16) != 0
+    }
+}
+
+impl FromIterator<MetadataField> for MetadataMask {
+    fn from_iter<I: IntoIterator<Item = MetadataField>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::default(), Self::with)
+    }
+}
+
+/// Difference kind.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DiffKind {
+    /// The entry exists only in the second listing.
+    Added,
+    /// The entry exists only in the first listing.
+    Removed,
+    /// File contents differ.
+    Content,
+    /// Metadata fields differ.
+    Metadata(Vec<MetadataField>),
+    /// The entry is listed in a different position or the entries of the directory are returned
+    /// by `readdir` in a different order (see [`diff_dirs_with_readdir_order`]).
+    Ordering,
+}
+
+impl DiffKind {
+    /// Difference severity.
+    pub fn severity(&self) -> Severity {
+        match self {
+            DiffKind::Added | DiffKind::Removed | DiffKind::Content => Severity::Content,
+            DiffKind::Metadata(..) => Severity::Metadata,
+            DiffKind::Ordering => Severity::Ordering,
+        }
+    }
+}
+
+/// Single difference between two listings.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Difference {
+    /// Path relative to the directory root.
+    pub path: PathBuf,
+    /// What is different.
+    pub kind: DiffKind,
+}
+
+impl Difference {
+    /// Difference severity.
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.severity())?;
+        match &self.kind {
+            DiffKind::Added => write!(f, "added")?,
+            DiffKind::Removed => write!(f, "removed")?,
+            DiffKind::Content => write!(f, "contents differ")?,
+            DiffKind::Metadata(fields) => {
+                write!(f, "metadata differs (")?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", field)?;
+                }
+                write!(f, ")")?;
+            }
+            DiffKind::Ordering => write!(f, "listed in a different order")?,
+        }
+        write!(f, ": `{}`", self.path.display())
+    }
+}
+
+/// Differences between two directories.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DirDiff {
+    differences: Vec<Difference>,
+}
+
+impl DirDiff {
+    /// Compare two listings.
+    ///
+    /// Ordering differences are reported only for the entries that are present in both listings.
+    pub fn new(a: &[FileInfo], b: &[FileInfo]) -> Self {
+        let mut differences = Vec::new();
+        let b_index: HashMap<&Path, usize> = b
+            .iter()
+            .enumerate()
+            .map(|(i, file)| (file.path.as_path(), i))
+            .collect();
+        let mut common = Vec::new();
+        for file_a in a.iter() {
+            let Some(i) = b_index.get(file_a.path.as_path()) else {
+                differences.push(Difference {
+                    path: file_a.path.clone(),
+                    kind: DiffKind::Removed,
+                });
+                continue;
+            };
+            let file_b = &b[*i];
+            common.push(*i);
+            if file_a.contents != file_b.contents {
+                differences.push(Difference {
+                    path: file_a.path.clone(),
+                    kind: DiffKind::Content,
+                });
+            }
+            let fields = MetadataField::diff(&file_a.metadata, &file_b.metadata);
+            if !fields.is_empty() {
+                differences.push(Difference {
+                    path: file_a.path.clone(),
+                    kind: DiffKind::Metadata(fields),
+                });
+            }
+        }
+        let a_paths: HashSet<&Path> = a.iter().map(|file| file.path.as_path()).collect();
+        for file_b in b.iter() {
+            if !a_paths.contains(file_b.path.as_path()) {
+                differences.push(Difference {
+                    path: file_b.path.clone(),
+                    kind: DiffKind::Added,
+                });
+            }
+        }
+        let mut sorted = common.clone();
+        sorted.sort_unstable();
+        for (expected, actual) in sorted.iter().zip(common.iter()) {
+            if expected != actual {
+                differences.push(Difference {
+                    path: b[*actual].path.clone(),
+                    kind: DiffKind::Ordering,
+                });
+            }
+        }
+        Self { differences }
+    }
+
+    /// Add the ordering differences for the directories whose entries are returned by `readdir`
+    /// in a different order (see [`ReaddirOrder::differing_dirs`]).
+    #[cfg(feature = "fs")]
+    pub fn with_readdir_order(mut self, a: &ReaddirOrder, b: &ReaddirOrder) -> Self {
+        for dir in a.differing_dirs(b) {
+            self.differences.push(Difference {
+                path: dir.to_path_buf(),
+                kind: DiffKind::Ordering,
+            });
+        }
+        self
+    }
+
+    /// Drop the ordering differences and the metadata fields that are not in `fields`, and sort
+    /// the remaining differences by path.
+    ///
+    /// Useful to compare the differences reported by external tools that track only some
+    /// metadata fields.
+    pub fn restricted_to(&self, fields: &[MetadataField]) -> Self {
+        let mut differences: Vec<Difference> = self
+            .differences
+            .iter()
+            .filter_map(|difference| {
+                let kind = match &difference.kind {
+                    DiffKind::Ordering => return None,
+                    DiffKind::Metadata(other) => {
+                        let other: Vec<MetadataField> = other
+                            .iter()
+                            .copied()
+                            .filter(|field| fields.contains(field))
+                            .collect();
+                        if other.is_empty() {
+                            return None;
+                        }
+                        DiffKind::Metadata(other)
+                    }
+                    kind => kind.clone(),
+                };
+                Some(Difference {
+                    path: difference.path.clone(),
+                    kind,
+                })
+            })
+            .collect();
+        differences.sort_by(|a, b| {
+            a.path
+                .cmp(&b.path)
+                .then_with(|| b.severity().cmp(&a.severity()))
+        });
+        Self { differences }
+    }
+
+    /// All differences.
+    pub fn differences(&self) -> &[Difference] {
+        &self.differences[..]
+    }
+
+    /// Returns `true` if there are no differences.
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    /// The most severe level among all differences.
+    pub fn max_severity(&self) -> Option<Severity> {
+        self.differences.iter().map(Difference::severity).max()
+    }
+
+    /// Differences with the severity level from `fail_on` list.
+    pub fn failures<'a>(
+        &'a self,
+        fail_on: &'a [Severity],
+    ) -> impl Iterator<Item = &'a Difference> + 'a {
+        self.differences
+            .iter()
+            .filter(move |d| fail_on.contains(&d.severity()))
+    }
+
+    /// Differences with the severity level not from `fail_on` list.
+    pub fn warnings<'a>(
+        &'a self,
+        fail_on: &'a [Severity],
+    ) -> impl Iterator<Item = &'a Difference> + 'a {
+        self.differences
+            .iter()
+            .filter(move |d| !fail_on.contains(&d.severity()))
+    }
+
+    /// Exit code of the most severe failure or zero if there are no failures.
+    ///
+    /// Only the severity levels from `fail_on` list are considered failures.
+    pub fn exit_code(&self, fail_on: &[Severity]) -> i32 {
+        self.failures(fail_on)
+            .map(Difference::severity)
+            .max()
+            .map(Severity::exit_code)
+            .unwrap_or(0)
+    }
+
+    /// Returns an error listing all failures.
+    ///
+    /// Only the severity levels from `fail_on` list are considered failures.
+    pub fn check(&self, fail_on: &[Severity]) -> Result<(), Error> {
+        let mut message = String::new();
+        for difference in self.failures(fail_on) {
+            if !message.is_empty() {
+                message.push('\n');
+            }
+            message.push_str(&difference.to_string());
+        }
+        if !message.is_empty() {
+            return Err(Error::other(message));
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<Difference>> for DirDiff {
+    fn from(differences: Vec<Difference>) -> Self {
+        Self { differences }
+    }
+}
+
+impl Display for DirDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for difference in self.differences.iter() {
+            writeln!(f, "{}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+/// Recursively list and compare two directories.
+///
+/// Inodes are remapped as in [`list_dir_all`], device numbers of the containing file system are
+/// ignored.
+#[cfg(feature = "fs")]
+pub fn diff_dirs<P1: AsRef<Path>, P2: AsRef<Path>>(a: P1, b: P2) -> Result<DirDiff, Error> {
+    let mut files_a = list_dir_all(a)?;
+    let mut files_b = list_dir_all(b)?;
+    for file in files_a.iter_mut().chain(files_b.iter_mut()) {
+        file.metadata.dev = 0;
+    }
+    Ok(DirDiff::new(&files_a, &files_b))
+}
+
+/// Same as [`diff_dirs`] but also reports the directories whose entries are returned by `readdir`
+/// in a different order (see [`DirDiff::with_readdir_order`]).
+///
+/// The listings themselves are sorted, i.e. this is the only way to get
+/// [`Severity::Ordering`] differences for the directories on disk. The order depends on the file
+/// system, hence compare only the directories on the same file system.
+#[cfg(feature = "fs")]
+pub fn diff_dirs_with_readdir_order<P1: AsRef<Path>, P2: AsRef<Path>>(
+    a: P1,
+    b: P2,
+) -> Result<DirDiff, Error> {
+    let (mut files_a, order_a) = list_dir_all_with_readdir_order(a)?;
+    let (mut files_b, order_b) = list_dir_all_with_readdir_order(b)?;
+    for file in files_a.iter_mut().chain(files_b.iter_mut()) {
+        file.metadata.dev = 0;
+    }
+    Ok(DirDiff::new(&files_a, &files_b).with_readdir_order(&order_a, &order_b))
+}
+
+/// Same as [`diff_dirs`] but compares the sampled contents of large files (see
+/// [`list_dir_all_sampled`]).
+#[cfg(feature = "fs")]
+pub fn diff_dirs_sampled<P1: AsRef<Path>, P2: AsRef<Path>>(a: P1, b: P2) -> Result<DirDiff, Error> {
+    let mut files_a = list_dir_all_sampled(a)?;
+    let mut files_b = list_dir_all_sampled(b)?;
+    for file in files_a.iter_mut().chain(files_b.iter_mut()) {
+        file.metadata.dev = 0;
+    }
+    Ok(DirDiff::new(&files_a, &files_b))
+}
+
+/// File's path, metadata and contents.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct FileInfo {
+    /// Path.
+    pub path: PathBuf,
+    /// Metadata.
+    pub metadata: Metadata,
+    /// File contents.
+    pub contents: Vec<u8>,
+}
+
+impl FileInfo {
+    /// Zero out the metadata fields that are not in the mask (see [`Metadata::masked`]).
+    pub fn masked(&self, mask: MetadataMask) -> Self {
+        Self {
+            path: self.path.clone(),
+            metadata: self.metadata.masked(mask),
+            contents: self.contents.clone(),
+        }
+    }
+}
+
+/// File's metadata.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Metadata {
+    /// Containing device number.
+    pub dev: u64,
+    /// Inode.
+    pub ino: u64,
+    /// File mode.
+    pub mode: u32,
+    /// Owner's user id.
+    pub uid: u32,
+    /// Owner's group id.
+    pub gid: u32,
+    /// No. of hard links.
+    pub nlink: u32,
+    /// Device number of the file itself.
+    pub rdev: u64,
+    /// Last modification time in seconds since Unix epoch, negative before the epoch.
+    pub mtime: i64,
+    /// File size in bytes.
+    pub file_size: u64,
+    /// POSIX access control lists.
+    ///
+    /// Listed only with `acl` feature.
+    pub acl: Acl,
+    /// SELinux security context without the terminating NUL or empty if the file isn't labeled.
+    ///
+    /// Listed only with `selinux` feature.
+    pub selinux_context: Vec<u8>,
+    /// Inode flags.
+    ///
+    /// On Linux only [`FS_IMMUTABLE_FL`](crate::FS_IMMUTABLE_FL) and
+    /// [`FS_APPEND_FL`](crate::FS_APPEND_FL) of regular files and directories are listed and
+    /// only with `chattr` feature. On MacOS and FreeBSD only [`UF_NODUMP`](crate::UF_NODUMP) and
+    /// [`UF_HIDDEN`](crate::UF_HIDDEN) are listed (`st_flags`).
+    pub inode_flags: u32,
+    /// Birth (creation) time in seconds since Unix epoch or `None` if it wasn't listed or is not
+    /// supported by the file system.
+    ///
+    /// Listed only by [`list_dir_all_with_btime`](crate::list_dir_all_with_btime): the
+    /// copies of the files are always born later.
+    pub btime: Option<i64>,
+    /// Last status change time with nanosecond precision or `None` if it wasn't listed.
+    ///
+    /// Listed only by [`list_dir_all_with_ctime`](crate::list_dir_all_with_ctime): the time
+    /// changes whenever the contents or the metadata change.
+    pub ctime: Option<SystemTime>,
+    /// Whether the extents of the regular file are shared with other files (reflinks) or `None`
+    /// if it wasn't listed.
+    ///
+    /// Listed only by
+    /// [`list_dir_all_with_shared_extents`](crate::list_dir_all_with_shared_extents).
+    pub shared_extents: Option<bool>,
+    /// No. of 512-byte blocks allocated to the file or `None` if it wasn't listed.
+    ///
+    /// Listed only by [`list_dir_all_with_blocks`](crate::list_dir_all_with_blocks): the
+    /// allocation depends on the file system.
+    pub blocks: Option<u64>,
+}
+
+impl Metadata {
+    /// Zero out the fields that are not in the mask.
+    ///
+    /// Use it to compare only the fields that the format under test preserves, e.g. zip
+    /// archives don't store the owner and the device numbers.
+    pub fn masked(&self, mask: MetadataMask) -> Self {
+        use MetadataField::*;
+        let keep = |field| mask.contains(field);
+        Self {
+            dev: if keep(Dev) { self.dev } else { 0 },
+            ino: if keep(Ino) { self.ino } else { 0 },
+            mode: if keep(Mode) { self.mode } else { 0 },
+            uid: if keep(Uid) { self.uid } else { 0 },
+            gid: if keep(Gid) { self.gid } else { 0 },
+            nlink: if keep(Nlink) { self.nlink } else { 0 },
+            rdev: if keep(Rdev) { self.rdev } else { 0 },
+            mtime: if keep(Mtime) { self.mtime } else { 0 },
+            file_size: if keep(FileSize) { self.file_size } else { 0 },
+            acl: if keep(Acl) {
+                self.acl.clone()
+            } else {
+                Default::default()
+            },
+            selinux_context: if keep(SelinuxContext) {
+                self.selinux_context.clone()
+            } else {
+                Default::default()
+            },
+            inode_flags: if keep(InodeFlags) {
+                self.inode_flags
+            } else {
+                0
+            },
+            btime: if keep(Btime) { self.btime } else { None },
+            ctime: if keep(Ctime) { self.ctime } else { None },
+            shared_extents: if keep(SharedExtents) {
+                self.shared_extents
+            } else {
+                None
+            },
+            blocks: if keep(Blocks) { self.blocks } else { None },
+        }
+    }
+
+    /// Convert the metadata obtained via `fstatat`.
+    #[cfg(feature = "fs")]
+    #[allow(clippy::unnecessary_cast)]
+    pub(crate) fn from_stat(stat: &libc::stat) -> Self {
+        Self {
+            dev: stat.st_dev as u64,
+            ino: stat.st_ino as u64,
+            mode: stat.st_mode as u32,
+            uid: stat.st_uid,
+            gid: stat.st_gid,
+            nlink: stat.st_nlink as u32,
+            rdev: stat.st_rdev as u64,
+            mtime: stat.st_mtime as i64,
+            file_size: stat.st_size as u64,
+            acl: Default::default(),
+            selinux_context: Default::default(),
+            #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+            inode_flags: stat.st_flags & (crate::UF_NODUMP | crate::UF_HIDDEN),
+            #[cfg(not(any(target_os = "macos", target_os = "freebsd")))]
+            inode_flags: 0,
+            btime: None,
+            ctime: None,
+            shared_extents: None,
+            blocks: None,
+        }
+    }
+}
+
+impl TryFrom<&std::fs::Metadata> for Metadata {
+    type Error = Error;
+    fn try_from(other: &std::fs::Metadata) -> Result<Self, Error> {
+        #[cfg(target_os = "freebsd")]
+        use std::os::freebsd::fs::MetadataExt as _;
+        #[cfg(target_os = "macos")]
+        use std::os::macos::fs::MetadataExt as _;
+        use std::os::unix::fs::MetadataExt;
+        Ok(Self {
+            dev: other.dev(),
+            ino: other.ino(),
+            mode: other.mode(),
+            uid: other.uid(),
+            gid: other.gid(),
+            nlink: other.nlink() as u32,
+            rdev: other.rdev(),
+            mtime: other.mtime(),
+            file_size: other.size(),
+            acl: Default::default(),
+            selinux_context: Default::default(),
+            #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+            inode_flags: other.st_flags() & (crate::UF_NODUMP | crate::UF_HIDDEN),
+            #[cfg(not(any(target_os = "macos", target_os = "freebsd")))]
+            inode_flags: 0,
+            btime: None,
+            ctime: None,
+            shared_extents: None,
+            blocks: None,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readdir_ordering_differences() {
+        let order = |dirs: &[(&str, &[&str])]| ReaddirOrder {
+            dirs: dirs
+                .iter()
+                .map(|(dir, names)| (dir.into(), names.iter().map(Into::into).collect()))
+                .collect(),
+        };
+        let a = order(&[("", &["x", "y", "d"]), ("d", &["a", "b", "c"])]);
+        let b = order(&[("", &["x", "d", "y", "z"]), ("d", &["a", "b"])]);
+        let diff = DirDiff::default().with_readdir_order(&a, &b);
+        assert_eq!(
+            vec![Difference {
+                path: "".into(),
+                kind: DiffKind::Ordering,
+            }],
+            diff.differences
+        );
+        assert_eq!(Severity::Ordering, diff.differences[0].severity());
+        assert!(DirDiff::default()
+            .with_readdir_order(&a, &a)
+            .differences
+            .is_empty());
+    }
+}
@@ -1,22 +1,37 @@
 use std::collections::HashMap;
-use std::ffi::CString;
+use std::collections::HashSet;
+#[cfg(all(
+    target_os = "linux",
+    any(feature = "acl", feature = "selinux", feature = "chattr")
+))]
+use std::ffi::CStr;
+use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::fs::create_dir_all;
 use std::fs::hard_link;
-use std::fs::read_link;
+use std::fs::rename;
 use std::fs::File;
+use std::fs::OpenOptions;
 use std::fs::Permissions;
 use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
 use std::io::Write;
+use std::ops::RangeInclusive;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::ffi::OsStringExt;
 use std::os::unix::fs::symlink;
 use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::FileExt;
+use std::os::unix::fs::FileTypeExt;
 use std::os::unix::fs::PermissionsExt;
-use std::os::unix::net::UnixDatagram;
 use std::path::Path;
 use std::path::PathBuf;
 use std::path::MAIN_SEPARATOR_STR;
+use std::thread::JoinHandle;
 use std::time::Duration;
 use std::time::SystemTime;
 
@@ -28,40 +43,379 @@ use normalize_path::NormalizePath;
 use tempfile::TempDir;
 use walkdir::WalkDir;
 
+#[cfg(all(target_os = "linux", feature = "acl"))]
+use crate::arbitrary_access_acl;
+use crate::arbitrary_ages;
+#[cfg(all(target_os = "linux", feature = "acl"))]
+use crate::arbitrary_default_acl;
+use crate::arbitrary_extreme_times;
+use crate::bind_socket;
+#[cfg(target_os = "linux")]
+use crate::bind_socket_at;
+use crate::btime_at;
+#[cfg(target_os = "linux")]
+use crate::chown_in_user_namespace;
+use crate::copy_dir_all;
+use crate::copy_dir_into;
+use crate::create_dir_all_at;
+use crate::create_file_at;
+use crate::create_limit_entries;
+#[cfg(all(target_os = "linux", feature = "acl"))]
+use crate::decode_acl;
+use crate::default_hash_algorithm;
+use crate::delete_dir_all;
+use crate::delete_entries;
+#[cfg(all(target_os = "linux", feature = "acl"))]
+use crate::encode_acl;
+use crate::fchmod;
+use crate::fchmod_at;
+use crate::from_unix_time;
+#[cfg(any(
+    all(target_os = "linux", feature = "chattr"),
+    target_os = "macos",
+    target_os = "freebsd"
+))]
+use crate::get_inode_flags;
+#[cfg(all(target_os = "linux", any(feature = "acl", feature = "selinux")))]
+use crate::get_xattr;
+#[cfg(target_os = "linux")]
+use crate::has_shared_extents;
+use crate::link_at;
+#[cfg(target_os = "linux")]
+use crate::link_unnamed_at;
+use crate::mkdir_at;
 use crate::mkfifo;
+use crate::mkfifo_at;
 use crate::mknod;
+use crate::mknod_at;
+use crate::open_dir_at;
+use crate::open_file_at;
+use crate::open_parent_at;
+#[cfg(target_os = "linux")]
+use crate::open_tmpfile_at;
+use crate::os_str_to_c_string;
 use crate::path_to_c_string;
+use crate::read_dir_at;
+use crate::read_link_at;
+#[cfg(target_os = "linux")]
+use crate::restore_owners_in_user_namespace;
+#[cfg(target_os = "linux")]
+use crate::restrict_to;
 use crate::set_file_modified_time;
+use crate::set_file_modified_time_at;
+#[cfg(any(
+    all(target_os = "linux", feature = "chattr"),
+    target_os = "macos",
+    target_os = "freebsd"
+))]
+use crate::set_inode_flags;
+use crate::set_modified_times;
+#[cfg(target_os = "linux")]
+use crate::set_xattr;
+use crate::stat_at;
+use crate::stat_path_at;
+use crate::symlink_at;
+use crate::unix_time;
+#[cfg(target_os = "linux")]
+use crate::unlink_at;
+use crate::write_contents;
+#[cfg(all(target_os = "linux", feature = "acl"))]
+use crate::Acl;
+use crate::AgeBucket;
+use crate::AgedEntry;
+use crate::ArbitraryContents;
+use crate::ArbitraryNames;
+use crate::CharsetNames;
+use crate::CollapsedName;
+use crate::CollidingNames;
+use crate::ConfigError;
+use crate::ContentGenerator;
+use crate::CraftedEntry;
+use crate::DeleteReport;
+use crate::DirSpec;
+use crate::Downgrade;
+use crate::Entry;
+use crate::ExpectedAction;
+use crate::ExtensionNames;
+use crate::FifoWriter;
+use crate::FileInfo;
+use crate::FileType;
+#[cfg(all(target_os = "linux", feature = "fuse"))]
+use crate::FuseMount;
+use crate::HiddenNames;
+use crate::HostileNameCategory;
+use crate::HostileNames;
+use crate::Manifest;
+use crate::Metadata;
+use crate::NameCharset;
+use crate::NameEncoding;
+use crate::NameGenerator;
+use crate::Preset;
+use crate::PresetNames;
+use crate::PrintableNames;
+use crate::ReaddirOrder;
+use crate::SafetyGuard;
+use crate::ShapedPaths;
+use crate::SizeRecipe;
+use crate::Sizes;
+use crate::SpecialNames;
+use crate::TreeProfile;
+use crate::TreeShape;
+#[cfg(all(target_os = "linux", feature = "fuse"))]
+use crate::Vfs;
+use crate::WorkQueue;
+#[cfg(all(target_os = "linux", feature = "acl"))]
+use crate::ACL_ACCESS_XATTR;
+#[cfg(all(target_os = "linux", feature = "acl"))]
+use crate::ACL_DEFAULT_XATTR;
+use crate::ALL_FILE_TYPES;
+use crate::FAR_FUTURE_TIMES;
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+use crate::FS_APPEND_FL;
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+use crate::FS_IMMUTABLE_FL;
+use crate::MAX_DENSE_FILE_SIZE;
+use crate::MAX_FIFO_CONTENTS_LEN;
+use crate::NAME_MAX;
+use crate::PATH_MAX;
+use crate::PORTABLE_FILE_TYPES;
+use crate::POWER_OF_TWO_BOUNDARY_SIZES;
+use crate::PRE_EPOCH_TIMES;
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+use crate::UF_HIDDEN;
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+use crate::UF_NODUMP;
 
 /// [`Dir`] configuration.
 pub struct DirBuilder {
-    printable_names: bool,
     file_types: Vec<FileType>,
+    require_all_types: bool,
+    content_generator: Box<dyn ContentGenerator>,
+    name_generator: Box<dyn NameGenerator>,
+    shape: Option<TreeShape>,
+    size_recipe: SizeRecipe,
+    max_total_bytes: Option<u64>,
+    empty_contents: bool,
+    max_name_len: usize,
+    max_path_len: Option<usize>,
+    stress_modes: Vec<StressMode>,
+    max_links_per_file: Option<u64>,
+    sparse_huge_files: bool,
+    min_empty_dirs: usize,
+    name_encoding: NameEncoding,
+    device_numbers: Vec<(u32, u32)>,
+    escaping_symlinks: bool,
+    age_buckets: Vec<AgeBucket>,
+    pre_epoch_times: bool,
+    far_future_times: bool,
+    mtime_range: Option<RangeInclusive<SystemTime>>,
+    policy: Option<Box<Policy>>,
+    #[cfg(target_os = "linux")]
+    user_namespace: bool,
+    #[cfg(target_os = "linux")]
+    landlock: bool,
+    #[cfg(target_os = "linux")]
+    opaque_dirs: bool,
+    #[cfg(target_os = "linux")]
+    unnamed_files: bool,
+    #[cfg(target_os = "linux")]
+    file_capabilities: bool,
+    #[cfg(all(target_os = "linux", feature = "acl"))]
+    acls: bool,
+    #[cfg(all(target_os = "linux", feature = "selinux"))]
+    selinux_contexts: Vec<String>,
+    #[cfg(any(
+        all(target_os = "linux", feature = "chattr"),
+        target_os = "macos",
+        target_os = "freebsd"
+    ))]
+    inode_flags: bool,
+    fifo_writers: bool,
+    keep_on_panic: bool,
+    cleanup: Cleanup,
+    strict: bool,
+    temp_root: Option<PathBuf>,
+    prefix: Option<OsString>,
 }
 
 impl DirBuilder {
     /// Create new directory builder with default parameters.
     pub fn new() -> Self {
         Self {
+            file_types: default_file_types(),
+            require_all_types: false,
+            content_generator: Box::new(ArbitraryContents),
             #[cfg(not(target_os = "macos"))]
-            printable_names: false,
-            #[cfg(target_os = "macos")]
-            printable_names: true,
-            #[cfg(not(target_os = "macos"))]
-            file_types: ALL_FILE_TYPES.into(),
+            name_generator: Box::new(ArbitraryNames),
             #[cfg(target_os = "macos")]
-            file_types: {
-                use FileType::*;
-                [Regular, Directory, Fifo, Socket, Symlink, HardLink].into()
-            },
+            name_generator: Box::new(PrintableNames::default()),
+            shape: None,
+            size_recipe: Default::default(),
+            max_total_bytes: None,
+            empty_contents: false,
+            max_name_len: NAME_MAX,
+            max_path_len: None,
+            stress_modes: Vec::new(),
+            max_links_per_file: None,
+            sparse_huge_files: true,
+            min_empty_dirs: 0,
+            name_encoding: Default::default(),
+            device_numbers: Vec::new(),
+            escaping_symlinks: false,
+            age_buckets: Vec::new(),
+            pre_epoch_times: false,
+            far_future_times: false,
+            mtime_range: None,
+            policy: None,
+            #[cfg(target_os = "linux")]
+            user_namespace: false,
+            #[cfg(target_os = "linux")]
+            landlock: false,
+            #[cfg(target_os = "linux")]
+            opaque_dirs: false,
+            #[cfg(target_os = "linux")]
+            unnamed_files: false,
+            #[cfg(target_os = "linux")]
+            file_capabilities: false,
+            #[cfg(all(target_os = "linux", feature = "acl"))]
+            acls: false,
+            #[cfg(all(target_os = "linux", feature = "selinux"))]
+            selinux_contexts: Vec::new(),
+            #[cfg(any(
+                all(target_os = "linux", feature = "chattr"),
+                target_os = "macos",
+                target_os = "freebsd"
+            ))]
+            inode_flags: false,
+            fifo_writers: false,
+            keep_on_panic: std::env::var_os(KEEP_ENV).is_some_and(|value| value == "1"),
+            cleanup: Default::default(),
+            strict: false,
+            temp_root: None,
+            prefix: None,
         }
     }
 
     /// Generate files with printable names, i.e. names consisting only from printable characters.
     ///
     /// Useful to test CLI applications.
-    pub fn printable_names(mut self, value: bool) -> Self {
-        self.printable_names = value;
+    pub fn printable_names(self, value: bool) -> Self {
+        if value {
+            self.name_generator(PrintableNames::default())
+        } else {
+            self.name_generator(ArbitraryNames)
+        }
+    }
+
+    /// Generate names that differ only in case or in Unicode normalization (see
+    /// [`CollidingNames`]).
+    ///
+    /// The names that the file system collapses are recorded in [`Dir::collapsed_names`]. `false`
+    /// leaves the current name generator intact.
+    pub fn colliding_names(self, value: bool) -> Self {
+        if value {
+            self.name_generator(CollidingNames::default())
+        } else {
+            self
+        }
+    }
+
+    /// Generate names that are hard to quote and escape (see [`HostileNames`]).
+    ///
+    /// Useful to test CLI applications. `false` leaves the current name generator intact.
+    pub fn hostile_names(self, value: bool) -> Self {
+        if value {
+            self.name_generator(HostileNames::default())
+        } else {
+            self
+        }
+    }
+
+    /// Generate hostile names only from the specified categories.
+    pub fn hostile_name_categories<I>(self, categories: I) -> Self
+    where
+        I: IntoIterator<Item = HostileNameCategory>,
+    {
+        self.name_generator(HostileNames::new(categories))
+    }
+
+    /// Generate valid UTF-8 names from the specified character set.
+    pub fn name_charset(self, charset: NameCharset) -> Self {
+        self.name_generator(CharsetNames::new(charset))
+    }
+
+    /// Which generator to use for file names?
+    ///
+    /// By default the names consist of arbitrary bytes (printable names on MacOS).
+    pub fn name_generator<G>(mut self, generator: G) -> Self
+    where
+        G: NameGenerator + 'static,
+    {
+        self.name_generator = Box::new(generator);
+        self
+    }
+
+    /// Control the directory structure: no. of files, fanout and depth (see [`TreeShape`]).
+    ///
+    /// By default the structure is determined by the generated names.
+    pub fn shape(mut self, shape: TreeShape) -> Self {
+        self.shape = Some(shape);
+        self
+    }
+
+    /// Generate the trees that resemble the specified profile (see [`TreeProfile`]).
+    ///
+    /// Sets the tree shape, the size recipe and appends the extensions to the names produced by
+    /// the current name generator, i.e. call this method after
+    /// [`name_generator`](Self::name_generator).
+    pub fn profile(mut self, profile: &TreeProfile) -> Self {
+        let names = std::mem::replace(&mut self.name_generator, Box::new(ArbitraryNames));
+        self.name_generator = Box::new(ExtensionNames::new(names, profile));
+        self.shape(profile.shape())
+            .size_recipe(profile.size_recipe())
+    }
+
+    /// Append the extensions chosen from the list to the names produced by the current name
+    /// generator, i.e. call this method after [`name_generator`](Self::name_generator).
+    ///
+    /// The extensions are specified without the leading dot; the empty extension means no
+    /// extension. Useful to fuzz the tools that branch on the extension, e.g. auto-detect the
+    /// compression.
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        let extensions = extensions.into_iter().map(Into::into).collect();
+        let names = std::mem::replace(&mut self.name_generator, Box::new(ArbitraryNames));
+        self.name_generator = Box::new(ExtensionNames::uniform(names, extensions));
+        self
+    }
+
+    /// Make `percent` percent of the names hidden, i.e. prepend `.` to the last component.
+    ///
+    /// Wraps the current name generator, i.e. call this method after
+    /// [`name_generator`](Self::name_generator). Useful to test the tools with include/exclude
+    /// rules around hidden files.
+    pub fn hidden_names(mut self, percent: u8) -> Self {
+        let names = std::mem::replace(&mut self.name_generator, Box::new(ArbitraryNames));
+        self.name_generator = Box::new(HiddenNames::new(names, percent));
+        self
+    }
+
+    /// Replace the last component of `percent` percent of the names with the names chosen from
+    /// the list, e.g. [`SPECIAL_NAMES`](crate::SPECIAL_NAMES).
+    ///
+    /// Wraps the current name generator as [`hidden_names`](Self::hidden_names) does. The file
+    /// types are chosen as usual, e.g. `.gitignore` might be a directory.
+    pub fn special_names<I, S>(mut self, names: I, percent: u8) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
+    {
+        let special = names.into_iter().map(Into::into).collect();
+        let names = std::mem::replace(&mut self.name_generator, Box::new(ArbitraryNames));
+        self.name_generator = Box::new(SpecialNames::new(names, special, percent));
         self
     }
 
@@ -76,142 +430,2782 @@ impl DirBuilder {
         self
     }
 
-    /// Create a temprary directory with random contents.
-    pub fn create(self, u: &mut Unstructured<'_>) -> arbitrary::Result<Dir> {
+    /// Generate at least one file of each of the [`file_types`](Self::file_types).
+    ///
+    /// More files are generated if needed. The types are still chosen from the spec, i.e. the
+    /// guarantee holds only if the spec has enough bytes. The file
+    /// types that are unsupported by the file system are downgraded as usual (see
+    /// [`Manifest::downgrades`]).
+    pub fn require_all_types(mut self, value: bool) -> Self {
+        self.require_all_types = value;
+        self
+    }
+
+    /// Generate only the file types that are valid on any file system: regular files,
+    /// directories, symbolic and hard links.
+    pub fn portable(self) -> Self {
+        self.file_types(PORTABLE_FILE_TYPES)
+    }
+
+    /// Generate only the trees that are representable on the preset's backend: file types,
+    /// names (see [`PresetNames`]) and path lengths.
+    ///
+    /// Compare such trees only by [`Preset::metadata_fields`].
+    pub fn preset(self, preset: Preset) -> Self {
+        self.file_types(preset.file_types.iter().copied())
+            .max_name_len(preset.max_name_len)
+            .max_path_len(preset.max_path_len)
+            .name_generator(PresetNames::new(preset))
+    }
+
+    /// Apply the bundled configuration (see [`Coverage`]).
+    ///
+    /// Only the settings listed in the variant's docs are changed, the subsequent calls override
+    /// them.
+    pub fn coverage(self, coverage: Coverage) -> Self {
+        match coverage {
+            Coverage::Minimal => self
+                .portable()
+                .printable_names(true)
+                .shape(TreeShape {
+                    min_files: 1,
+                    max_files: 8,
+                    depth_weights: vec![2, 1],
+                    ..Default::default()
+                })
+                .max_total_bytes(64 * 1024),
+            Coverage::Typical => self
+                .file_types({
+                    use FileType::*;
+                    [Regular, Directory, Fifo, Symlink, HardLink]
+                })
+                .printable_names(true)
+                .shape(TreeShape {
+                    min_files: 10,
+                    max_files: 200,
+                    depth_weights: vec![4, 8, 6, 3, 1],
+                    ..Default::default()
+                })
+                .min_empty_dirs(1)
+                .max_total_bytes(16 * 1024 * 1024),
+            Coverage::Adversarial => self
+                .file_types(default_file_types())
+                .hostile_names(true)
+                .shape(TreeShape {
+                    min_files: 10,
+                    max_files: 100,
+                    depth_weights: vec![2, 4, 2, 1],
+                    ..Default::default()
+                })
+                .size_recipe(SizeRecipe::Cycle(
+                    POWER_OF_TWO_BOUNDARY_SIZES
+                        .into_iter()
+                        .filter(|size| *size <= MAX_ADVERSARIAL_FILE_SIZE)
+                        .collect(),
+                ))
+                .stress(StressMode::HeaderLimits)
+                .min_empty_dirs(1)
+                .allow_escaping_symlinks(true),
+        }
+    }
+
+    /// Generate only the file types that can be created by the current process in the
+    /// temporary directory (see [`probe::supported_file_types`](crate::probe::supported_file_types)).
+    ///
+    /// [`StressMode::HugeFiles`] is disabled if the file system doesn't support sparse files (see
+    /// [`probe::fs_features`](crate::probe::fs_features)).
+    ///
+    /// Call this method after [`tempdir_in`](Self::tempdir_in) to probe the right file system.
+    pub fn detect_capabilities(mut self) -> Self {
+        let dir = match self.temp_root.as_ref() {
+            Some(path) => path.clone(),
+            None => std::env::temp_dir(),
+        };
+        if let Ok(supported) = crate::probe::supported_file_types(&dir) {
+            self.file_types
+                .retain(|file_type| supported.contains(file_type));
+            if self.file_types.is_empty() {
+                self.file_types.push(FileType::Regular);
+            }
+        }
+        if let Ok(features) = crate::probe::fs_features(&dir) {
+            if !features.sparse_files {
+                self.stress_modes
+                    .retain(|mode| *mode != StressMode::HugeFiles);
+            }
+        }
+        self
+    }
+
+    /// Which generator to use for regular files' contents?
+    ///
+    /// By default the contents are arbitrary bytes.
+    pub fn content_generator<G>(mut self, generator: G) -> Self
+    where
+        G: ContentGenerator + 'static,
+    {
+        self.content_generator = Box::new(generator);
+        self
+    }
+
+    /// How to choose regular files' sizes?
+    ///
+    /// By default the size is determined by the content generator.
+    pub fn size_recipe(mut self, recipe: SizeRecipe) -> Self {
+        self.size_recipe = recipe;
+        self
+    }
+
+    /// Maximum total size of the regular files in bytes.
+    ///
+    /// The file that exceeds the budget is truncated, the subsequent files are empty.
+    /// The budget includes the huge files (see [`StressMode::HugeFiles`]) and is not shared by
+    /// the hard links. Prevents a pathological input from filling up the disk. Unlimited by
+    /// default.
+    pub fn max_total_bytes(mut self, value: u64) -> Self {
+        self.max_total_bytes = Some(value);
+        self
+    }
+
+    /// Make every regular file zero-length.
+    ///
+    /// Speeds up the fuzz targets that only care about the metadata and the structure of the
+    /// tree. The huge files (see [`StressMode::HugeFiles`]) are empty as well. Disabled by
+    /// default.
+    pub fn empty_contents(mut self, value: bool) -> Self {
+        self.empty_contents = value;
+        self
+    }
+
+    /// Maximum length of a path component in bytes.
+    ///
+    /// Longer components are truncated. By default the limit is [`NAME_MAX`].
+    pub fn max_name_len(mut self, value: usize) -> Self {
+        self.max_name_len = value.max(1);
+        self
+    }
+
+    /// Maximum length of a path relative to the directory root in bytes.
+    ///
+    /// Files with longer paths are not generated. By default the limit is [`PATH_MAX`]
+    /// and twice as much in [`StressMode::LongPaths`] mode.
+    pub fn max_path_len(mut self, value: usize) -> Self {
+        self.max_path_len = Some(value);
+        self
+    }
+
+    /// Enable the specified stress mode.
+    pub fn stress(mut self, mode: StressMode) -> Self {
+        if !self.stress_modes.contains(&mode) {
+            self.stress_modes.push(mode);
+        }
+        self
+    }
+
+    /// Maximum number of hard links to a single file.
+    ///
+    /// By default the number is unlimited and 64 in [`StressMode::ManyHardLinks`] mode.
+    pub fn max_links_per_file(mut self, value: u64) -> Self {
+        self.max_links_per_file = Some(value.max(1));
+        self
+    }
+
+    /// Generate files just above 4 GiB and 8 GiB?
+    ///
+    /// Shorthand for enabling or disabling [`StressMode::HugeFiles`]. The sizes are recorded in
+    /// the [manifest](Dir::manifest), i.e. the test can compare the sizes without reading the
+    /// contents back. Disabled by default.
+    pub fn huge_files(mut self, value: bool) -> Self {
+        if value {
+            self = self.stress(StressMode::HugeFiles);
+        } else {
+            self.stress_modes
+                .retain(|mode| *mode != StressMode::HugeFiles);
+        }
+        self
+    }
+
+    /// Create huge files sparse?
+    ///
+    /// Only the first block is written for sparse files. Writing the contents in full takes much
+    /// longer but is useful to test the writers that skip the holes. By default huge files are
+    /// sparse.
+    pub fn sparse_huge_files(mut self, value: bool) -> Self {
+        self.sparse_huge_files = value;
+        self
+    }
+
+    /// Minimum number of empty directories in the tree.
+    ///
+    /// Useful to test the formats that must explicitly encode directory entries (zip, cpio).
+    /// The directories are placed at arbitrary depth, possibly as a chain of nested directories.
+    pub fn min_empty_dirs(mut self, value: usize) -> Self {
+        self.min_empty_dirs = value;
+        self
+    }
+
+    /// File name encoding for zip archives.
+    ///
+    /// The encoding is carried in the manifest (see [`Dir::manifest`]). By default UTF-8 is used.
+    pub fn name_encoding(mut self, encoding: NameEncoding) -> Self {
+        self.name_encoding = encoding;
+        self
+    }
+
+    /// Restrict block and character device numbers to the specified `(major, minor)` pairs.
+    ///
+    /// By default arbitrary device numbers are generated. Creating device files requires
+    /// privileges, the devices are never opened.
+    pub fn device_numbers<I: IntoIterator<Item = (u32, u32)>>(mut self, allowlist: I) -> Self {
+        self.device_numbers = allowlist.into_iter().collect();
+        self
+    }
+
+    /// Generate symbolic links whose targets point outside the directory?
+    ///
+    /// The targets are either absolute system paths (e.g. `/etc/hostname`) or relative paths
+    /// with enough `..` components (e.g. `../../outside`). The links are never followed by the
+    /// generator, the targets are stored as is. Such links are recorded in
+    /// [`Dir::escaping_symlinks`]. Use it to test that the extractor refuses to follow them.
+    pub fn allow_escaping_symlinks(mut self, value: bool) -> Self {
+        self.escaping_symlinks = value;
+        self
+    }
+
+    /// Distribute the modification times between the specified age buckets.
+    ///
+    /// E.g. 40% of the entries older than 30 days and 10% older than a year. The ages are
+    /// counted from the generation time and are at least [`AGE_MARGIN`](crate::AGE_MARGIN) away
+    /// from the bucket boundaries. The remaining entries are younger than any bucket, symbolic
+    /// links are left intact. The membership is recorded in [`Dir::aged_entries`]. Useful to test
+    /// cleanup and retention tools.
+    pub fn age_buckets<I: IntoIterator<Item = AgeBucket>>(mut self, buckets: I) -> Self {
+        self.age_buckets = buckets.into_iter().collect();
+        self
+    }
+
+    /// Generate the modification times within the range.
+    ///
+    /// By default the times are between Unix epoch and one day after the generation time. A
+    /// narrow range clusters the timestamps (e.g. all within one hour) to test the archivers that
+    /// delta-encode them, an empty range (`t..=t`) gives all entries the same time. The age
+    /// buckets and the extreme times take precedence (see [`DirBuilder::age_buckets`] and
+    /// [`DirBuilder::pre_epoch_times`]).
+    pub fn mtime_range(mut self, range: RangeInclusive<SystemTime>) -> Self {
+        self.mtime_range = Some(range);
+        self
+    }
+
+    /// Set the modification times of some entries to the times before 1970.
+    ///
+    /// The times are chosen from [`PRE_EPOCH_TIMES`](crate::PRE_EPOCH_TIMES). The times that the
+    /// file system can't store are skipped. Useful to test how the archivers handle negative time
+    /// fields. Disabled by default.
+    pub fn pre_epoch_times(mut self, value: bool) -> Self {
+        self.pre_epoch_times = value;
+        self
+    }
+
+    /// Set the modification times of some entries to the times after 2038.
+    ///
+    /// The times are chosen from [`FAR_FUTURE_TIMES`](crate::FAR_FUTURE_TIMES), i.e. they overflow
+    /// 32-bit time fields. The times that the file system can't store are skipped. Disabled by
+    /// default.
+    pub fn far_future_times(mut self, value: bool) -> Self {
+        self.far_future_times = value;
+        self
+    }
+
+    /// Tag each entry with the action that the policy under test is expected to take.
+    ///
+    /// The closure is called for every entry after the tree is generated and returns the
+    /// action (e.g. "keep", "delete" or "archive") or `None` if the entry should not be tagged.
+    /// The actions are recorded in [`Dir::expected_actions`], turning random trees into labeled
+    /// datasets for policy engines.
+    pub fn policy<F, S>(mut self, policy: F) -> Self
+    where
+        F: Fn(&Entry) -> Option<S> + 'static,
+        S: Into<String>,
+    {
+        self.policy = Some(Box::new(move |entry| policy(entry).map(Into::into)));
+        self
+    }
+
+    /// Change the owners of the generated files to arbitrary ids from inside a new user
+    /// namespace.
+    ///
+    /// The ownership is changed by a child process that is uid 0 in the namespace, which works
+    /// without privileges. Besides the caller's own ids the subordinate ids from `/etc/subuid`
+    /// and `/etc/subgid` are mapped when `newuidmap` and `newgidmap` are available; all ids are
+    /// mapped when running as root. The owners are recorded in [`Entry::owner`].
+    /// Directories are not chowned so that the tree can still be deleted. When the user
+    /// namespaces are disabled or restricted the owners are left intact.
+    #[cfg(target_os = "linux")]
+    pub fn user_namespace(mut self, value: bool) -> Self {
+        self.user_namespace = value;
+        self
+    }
+
+    /// Mark arbitrary directories as overlayfs opaque directories.
+    ///
+    /// Sets `trusted.overlay.opaque` extended attribute to `y` which hides the contents of the
+    /// same directory in the lower layers. Requires `CAP_SYS_ADMIN`, otherwise no directories are
+    /// marked. The attributes are recorded in [`Entry::xattrs`]. Use together with
+    /// [`FileType::Whiteout`] to test OCI layer tarballs and overlayfs snapshots.
+    #[cfg(target_os = "linux")]
+    pub fn opaque_dirs(mut self, value: bool) -> Self {
+        self.opaque_dirs = value;
+        self
+    }
+
+    /// Recreate arbitrary regular files as unnamed files (`O_TMPFILE`) and link them into the tree
+    /// in arbitrary order.
+    ///
+    /// The inodes are allocated in a different order than the directory entries are created.
+    /// Useful to test the archivers that are sensitive to the inode order, e.g. squashfs
+    /// builders. No files are recreated if the file system doesn't support unnamed files.
+    #[cfg(target_os = "linux")]
+    pub fn unnamed_files(mut self, value: bool) -> Self {
+        self.unnamed_files = value;
+        self
+    }
+
+    /// Set arbitrary file capabilities on the regular files.
+    ///
+    /// Sets `security.capability` extended attribute to a valid version 2 blob with arbitrary
+    /// permitted and inheritable sets and the effective flag. Requires `CAP_SETFCAP`, otherwise no
+    /// capabilities are set. The attributes are recorded in [`Entry::xattrs`]. Use it to test
+    /// the archivers and package builders that must preserve the capabilities.
+    #[cfg(target_os = "linux")]
+    pub fn file_capabilities(mut self, value: bool) -> Self {
+        self.file_capabilities = value;
+        self
+    }
+
+    /// Attach arbitrary POSIX ACLs to the regular files and directories.
+    ///
+    /// Sets extended access ACLs that keep the permission bits intact, and default ACLs for
+    /// the directories. The ACLs are set after all the files are created and are recorded in
+    /// [`Entry::xattrs`] as [`ACL_ACCESS_XATTR`](crate::ACL_ACCESS_XATTR) and
+    /// [`ACL_DEFAULT_XATTR`](crate::ACL_DEFAULT_XATTR) in the format of `acl_set_file`; the
+    /// listings capture them in [`Metadata::acl`]. No ACLs are set if the file system doesn't
+    /// support them.
+    #[cfg(all(target_os = "linux", feature = "acl"))]
+    pub fn acls(mut self, value: bool) -> Self {
+        self.acls = value;
+        self
+    }
+
+    /// Label arbitrary files with the SELinux contexts chosen from the list.
+    ///
+    /// Sets `security.selinux` extended attribute to the NUL-terminated context (as `setfilecon`
+    /// does). When SELinux is enabled the contexts must be valid and relabeling requires the
+    /// permission, otherwise no files are labeled. The attributes are recorded in
+    /// [`Entry::xattrs`], the listings capture the contexts in [`Metadata::selinux_context`].
+    #[cfg(all(target_os = "linux", feature = "selinux"))]
+    pub fn selinux_contexts<I, S>(mut self, contexts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.selinux_contexts = contexts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set arbitrary inode flags on the regular files and directories.
+    ///
+    /// On Linux sets [`FS_IMMUTABLE_FL`](crate::FS_IMMUTABLE_FL) and
+    /// [`FS_APPEND_FL`](crate::FS_APPEND_FL) (as `chattr +i` and `chattr +a` do), which requires
+    /// `CAP_LINUX_IMMUTABLE`, otherwise no flags are set; the flags are cleared before the
+    /// directory is deleted. On MacOS and FreeBSD sets [`UF_NODUMP`](crate::UF_NODUMP) and
+    /// [`UF_HIDDEN`](crate::UF_HIDDEN) user flags (as `chflags` does). The flags are set after
+    /// all the other changes and are recorded in [`Entry::inode_flags`], the listings capture
+    /// them in [`Metadata::inode_flags`].
+    #[cfg(any(
+        all(target_os = "linux", feature = "chattr"),
+        target_os = "macos",
+        target_os = "freebsd"
+    ))]
+    pub fn inode_flags(mut self, value: bool) -> Self {
+        self.inode_flags = value;
+        self
+    }
+
+    /// Restrict the file system access to the generated directory using Landlock before creating
+    /// anything.
+    ///
+    /// This is a safety net for the code that modifies the tree, and a fixture for testing the
+    /// tools under the same restrictions. The restriction can't be lifted and applies to the
+    /// calling thread and the threads and processes it spawns afterwards, hence use a dedicated
+    /// thread. Does nothing if the kernel doesn't support Landlock. Incompatible with
+    /// [`user_namespace`](Self::user_namespace) and
+    /// [`create_isolated`](Self::create_isolated).
+    #[cfg(target_os = "linux")]
+    pub fn landlock(mut self, value: bool) -> Self {
+        self.landlock = value;
+        self
+    }
+
+    /// Feed each named pipe from a background thread?
+    ///
+    /// The thread waits for a reader, writes random bytes (see [`Dir::fifo_contents`]) once and
+    /// closes the pipe, then restores the pipe's modification time. Without the writers the
+    /// readers that open the pipes block forever. The pipes that the owner can't write don't get
+    /// the writers. The remaining writers are stopped on drop.
+    pub fn fifo_writers(mut self, value: bool) -> Self {
+        self.fifo_writers = value;
+        self
+    }
+
+    /// Keep the directory when the test panics?
+    ///
+    /// When enabled the directory is not deleted on drop if the current thread is panicking;
+    /// its path is printed to the standard error for post-mortem inspection.
+    /// By default the directory is kept only if `RANDOM_DIR_KEEP` environment variable
+    /// equals `1`.
+    pub fn keep_on_panic(mut self, value: bool) -> Self {
+        self.keep_on_panic = value;
+        self
+    }
+
+    /// How to delete the directory on drop.
+    ///
+    /// The directories populated via [`create_in`](Self::create_in) are never deleted.
+    pub fn cleanup(mut self, cleanup: Cleanup) -> Self {
+        self.cleanup = cleanup;
+        self
+    }
+
+    /// Fail instead of silently skipping or substituting the entries.
+    ///
+    /// By default the empty, too long and aliased paths are skipped or truncated, the file types
+    /// that can't be created are replaced with the other types (see [`Dir::downgrades`]) and the
+    /// file system might alter the modes (e.g. via umask). In strict mode any such fallback
+    /// rejects the input with [`arbitrary::Error::IncorrectFormat`] and the temporary directory
+    /// is deleted, i.e. the generated tree either matches the manifest exactly or is not
+    /// generated at all.
+    pub fn strict(mut self, value: bool) -> Self {
+        self.strict = value;
+        self
+    }
+
+    /// Create the temporary directory inside `path`.
+    ///
+    /// Useful to generate the files on a file system with the features under test
+    /// (e.g. mounted with `user_xattr` or `noatime`). By default the system's temporary
+    /// directory is used.
+    pub fn tempdir_in<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.temp_root = Some(path.into());
+        self
+    }
+
+    /// Temporary directory name prefix.
+    ///
+    /// Makes the generated directories easy to identify.
+    pub fn prefix<S: Into<OsString>>(mut self, prefix: S) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Check that the configuration is possible to generate.
+    ///
+    /// [`create`](Self::create) and the other methods don't validate the configuration and
+    /// either fail with an opaque [`arbitrary::Error`] or silently fall back to regular files;
+    /// call this method first to get a descriptive error.
+    pub fn build(self) -> Result<Self, ConfigError> {
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Same as [`build`](Self::build) but doesn't consume the builder.
+    pub fn validate(&self) -> Result<(), ConfigError> {
         use FileType::*;
-        let dir = TempDir::new().unwrap();
-        let mut files = Vec::new();
-        let num_files: usize = u.int_in_range(0..=10)?;
-        for _ in 0..num_files {
-            let path: CString = if self.printable_names {
-                let len: usize = u.int_in_range(1..=10)?;
-                let mut string = String::with_capacity(len);
-                for _ in 0..len {
-                    string.push(u.int_in_range(b'a'..=b'z')? as char);
-                }
-                CString::new(string).unwrap()
-            } else {
-                u.arbitrary()?
-            };
-            if path.as_bytes().is_empty() {
-                // do not allow empty paths
-                continue;
+        if self.file_types.is_empty() {
+            return Err(ConfigError::NoFileTypes);
+        }
+        if let Some(shape) = self.shape.as_ref() {
+            if shape.min_files > shape.max_files {
+                return Err(ConfigError::FileCountRange {
+                    min: shape.min_files,
+                    max: shape.max_files,
+                });
             }
-            let path: OsString = OsString::from_vec(path.into_bytes());
-            let path: PathBuf = path.into();
-            let path = match path.strip_prefix(MAIN_SEPARATOR_STR) {
-                Ok(path) => path,
-                Err(_) => path.as_path(),
+        }
+        // links point to the files of the other types, directories are never linked
+        if self
+            .file_types
+            .iter()
+            .all(|kind| matches!(kind, HardLink | Symlink | Directory))
+        {
+            let links: Vec<FileType> = [HardLink, Symlink]
+                .into_iter()
+                .filter(|kind| self.file_types.contains(kind))
+                .collect();
+            if !links.is_empty() {
+                return Err(ConfigError::NoLinkTargets { file_types: links });
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a temprary directory with random contents.
+    pub fn create(mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<Dir> {
+        self.create_temporary(u)
+    }
+
+    /// Create two temporary directories with random contents (see [`TreeRelation`]).
+    ///
+    /// Useful to fuzz the tools that take two trees as inputs, e.g. diff and sync tools.
+    pub fn create_pair(
+        self,
+        relation: TreeRelation,
+        u: &mut Unstructured<'_>,
+    ) -> arbitrary::Result<(Dir, Dir)> {
+        let mut dirs = self.create_many(2, relation, u)?;
+        let second = dirs.pop().unwrap();
+        let first = dirs.pop().unwrap();
+        Ok((first, second))
+    }
+
+    /// Create `n` temporary directories with random contents (see [`TreeRelation`]).
+    pub fn create_many(
+        mut self,
+        n: usize,
+        relation: TreeRelation,
+        u: &mut Unstructured<'_>,
+    ) -> arbitrary::Result<Vec<Dir>> {
+        let mut dirs = Vec::with_capacity(n);
+        match relation {
+            TreeRelation::Independent => {
+                for i in 0..n {
+                    // each tree gets an equal share of the remaining bytes
+                    let len = u.len() / (n - i);
+                    let mut chunk = Unstructured::new(u.bytes(len)?);
+                    dirs.push(self.create_temporary(&mut chunk)?);
+                }
+            }
+            TreeRelation::Identical if n != 0 => {
+                let original = self.create_temporary(u)?;
+                for _ in 1..n {
+                    let copy = match self.copy_of(&original) {
+                        Ok(copy) => copy,
+                        Err(e) => panic!("failed to copy `{}`: {}", original.path().display(), e),
+                    };
+                    dirs.push(copy);
+                }
+                dirs.insert(0, original);
+            }
+            TreeRelation::Identical => {}
+        }
+        Ok(dirs)
+    }
+
+    fn create_temporary(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<Dir> {
+        let dir = self.temp_dir().unwrap();
+        let (manifest, fifo_writers) = match self.populate(dir.path(), u).and_then(|manifest| {
+            let fifo_writers = self.spawn_fifo_writers(dir.path(), &manifest, u)?;
+            Ok((manifest, fifo_writers))
+        }) {
+            Ok(result) => result,
+            Err(e) => {
+                // the generated directories might be read-only
+                let _ = delete_dir_all(&dir.keep(), true);
+                return Err(e);
+            }
+        };
+        Ok(Dir {
+            dir: Some(DirRoot::Temporary(dir)),
+            keep_on_panic: self.keep_on_panic,
+            cleanup: self.cleanup,
+            manifest,
+            fifo_writers,
+        })
+    }
+
+    fn temp_dir(&self) -> Result<TempDir, Error> {
+        let mut builder = tempfile::Builder::new();
+        if let Some(prefix) = self.prefix.as_ref() {
+            builder.prefix(prefix);
+        }
+        match self.temp_root.as_ref() {
+            Some(path) => builder.tempdir_in(path),
+            None => builder.tempdir(),
+        }
+    }
+
+    /// Copy the tree to a new temporary directory (see [`TreeRelation::Identical`]).
+    fn copy_of(&self, original: &Dir) -> Result<Dir, Error> {
+        let dir = self.temp_dir()?;
+        copy_dir_into(original.path(), dir.path(), false)?;
+        let mut manifest = original.manifest.clone();
+        restore_metadata(dir.path(), &mut manifest.entries);
+        Ok(Dir {
+            dir: Some(DirRoot::Temporary(dir)),
+            keep_on_panic: self.keep_on_panic,
+            cleanup: self.cleanup,
+            manifest,
+            fifo_writers: Vec::new(),
+        })
+    }
+
+    /// Create a temporary directory with random contents on a background thread.
+    ///
+    /// The builder is returned by `builder` on the background thread, i.e. the generators don't
+    /// have to be `Send`. Consumes all the remaining bytes of `u`; the tree is the same as the
+    /// one created by [`create`](Self::create) from these bytes. Prepare the other state of the
+    /// test while the files are being generated and then call [`DirHandle::wait`].
+    pub fn spawn<F>(builder: F, u: &mut Unstructured<'_>) -> DirHandle
+    where
+        F: FnOnce() -> DirBuilder + Send + 'static,
+    {
+        let data = u.bytes(u.len()).unwrap_or_default().to_vec();
+        let thread = std::thread::spawn(move || builder().create(&mut Unstructured::new(&data)));
+        DirHandle { thread }
+    }
+
+    /// Populate the existing directory with random contents.
+    ///
+    /// Useful to generate the files on a specific file system (xfs, tmpfs, loop-mounted image
+    /// etc.). The directory is not deleted on drop. The existing files are left intact.
+    pub fn create_in<P: AsRef<Path>>(
+        mut self,
+        path: P,
+        u: &mut Unstructured<'_>,
+    ) -> arbitrary::Result<Dir> {
+        let path = path.as_ref();
+        let manifest = self.populate(path, u)?;
+        let fifo_writers = self.spawn_fifo_writers(path, &manifest, u)?;
+        Ok(Dir {
+            dir: Some(DirRoot::Existing(path.to_path_buf())),
+            keep_on_panic: self.keep_on_panic,
+            cleanup: self.cleanup,
+            manifest,
+            fifo_writers,
+        })
+    }
+
+    fn spawn_fifo_writers(
+        &self,
+        root: &Path,
+        manifest: &Manifest,
+        u: &mut Unstructured<'_>,
+    ) -> arbitrary::Result<Vec<FifoWriter>> {
+        let mut fifo_writers = Vec::new();
+        if !self.fifo_writers {
+            return Ok(fifo_writers);
+        }
+        for entry in manifest.entries.iter() {
+            let c_path = path_to_c_string(root.join(&entry.path)).unwrap();
+            if entry.file_type != FileType::Fifo
+                || unsafe { libc::access(c_path.as_ptr(), libc::W_OK) } != 0
+            {
+                // the writer can't open the pipe
+                continue;
+            }
+            let mut contents: Vec<u8> = u.arbitrary()?;
+            contents.truncate(MAX_FIFO_CONTENTS_LEN);
+            fifo_writers.push(FifoWriter::spawn(
+                root,
+                entry.path.clone(),
+                contents,
+                entry.mtime,
+            ));
+        }
+        Ok(fifo_writers)
+    }
+
+    /// Generate the tree in memory without touching the file system.
+    ///
+    /// The file types, names, contents, device numbers, links limit, name encoding, age buckets,
+    /// policy, strict mode (see [`DirBuilder::strict`]) and [`StressMode::LongPaths`] are
+    /// respected, the other settings only apply to the files created on disk. The sizes are
+    /// determined by the content generator.
+    pub fn create_spec(mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<DirSpec> {
+        use FileType::*;
+        let long_paths = self.stress_modes.contains(&StressMode::LongPaths);
+        let max_links = self.max_links_per_file.unwrap_or(u64::MAX);
+        let max_path_len = match self.max_path_len {
+            Some(max_path_len) => max_path_len,
+            None if long_paths => 2 * PATH_MAX,
+            None => PATH_MAX,
+        };
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut contents = HashMap::new();
+        let mut dirs: HashSet<PathBuf> = HashSet::new();
+        // all entries except directories
+        let mut files: Vec<PathBuf> = Vec::new();
+        // the number of links to the original files
+        let mut link_counts: HashMap<PathBuf, u64> = HashMap::new();
+        let mut escaping_symlinks = Vec::new();
+        let mut shape = self.shape.clone().map(ShapedPaths::new);
+        let num_files: usize = match shape.as_ref() {
+            Some(shape) => shape.num_files(u)?,
+            None => u.int_in_range(0..=10)?,
+        };
+        let mut created_types = Vec::new();
+        for attempt in 0.. {
+            if !self.needs_more_files(attempt, num_files, &created_types) {
+                break;
+            }
+            let path: PathBuf = match shape.as_mut() {
+                Some(shape) => {
+                    shape.generate(u, self.name_generator.as_mut(), self.max_name_len)?
+                }
+                None => self.name_generator.generate(u)?,
+            }
+            .into();
+            let path = match path.strip_prefix(MAIN_SEPARATOR_STR) {
+                Ok(path) => path,
+                Err(_) => path.as_path(),
+            };
+            let path = if long_paths && !path.as_os_str().is_empty() {
+                long_path(u, path.as_os_str(), self.max_name_len, max_path_len)?
+            } else {
+                let max_name_len = self.max_name_len;
+                if path
+                    .components()
+                    .any(|c| c.as_os_str().len() > max_name_len)
+                {
+                    self.fallback()?;
+                }
+                truncate_components(path, max_name_len)
+            };
+            let path = path.normalize();
+            if path.as_os_str().is_empty()
+                || path == Path::new(".")
+                || path.as_os_str().len() > max_path_len
+                || dirs.contains(&path)
+                || path.ancestors().any(|p| files.iter().any(|file| file == p))
+            {
+                // the path aliased some existing file or directory
+                self.fallback()?;
+                continue;
+            }
+            let parents: Vec<&Path> = path.ancestors().skip(1).collect();
+            for parent in parents.into_iter().rev() {
+                if parent.as_os_str().is_empty() || dirs.contains(parent) {
+                    continue;
+                }
+                let mode = u.int_in_range(0..=0o777)? | 0o500;
+                let t = arbitrary_mtime(u, self.mtime_range.as_ref())?;
+                entries.push(new_entry(parent, Directory, mode, t));
+                dirs.insert(parent.to_path_buf());
+            }
+            let mut kind: FileType = self.choose_file_type(u, &created_types)?;
+            let escaping = kind == Symlink && self.escaping_symlinks && u.arbitrary()?;
+            if matches!(kind, HardLink | Symlink) && files.is_empty() && !escaping {
+                self.fallback()?;
+                kind = Regular;
+            }
+            if kind == Symlink
+                && !escaping
+                && !files
+                    .iter()
+                    .any(|original| symlink_target(&path, original).as_os_str().len() < PATH_MAX)
+            {
+                // the targets don't fit into `PATH_MAX`
+                self.fallback()?;
+                kind = Regular;
+            }
+            let mut original = None;
+            if kind == HardLink {
+                // never link directories and respect the links limit
+                let candidates: Vec<&PathBuf> = files
+                    .iter()
+                    .filter(|file| link_counts.get(*file).is_some_and(|n| *n < max_links))
+                    .collect();
+                if candidates.is_empty() {
+                    self.fallback()?;
+                    kind = Regular;
+                } else {
+                    original = Some((*u.choose(&candidates[..])?).clone());
+                }
+            }
+            let t = arbitrary_mtime(u, self.mtime_range.as_ref())?;
+            let entry = match kind {
+                Regular => {
+                    let mode = u.int_in_range(0..=0o777)? | 0o400;
+                    let mut data = self.content_generator.generate(u, &path)?;
+                    if self.empty_contents {
+                        data.clear();
+                    }
+                    let size = data.len() as u64;
+                    contents.insert(path.clone(), data);
+                    Entry {
+                        size,
+                        ..new_entry(&path, kind, mode, t)
+                    }
+                }
+                Directory => {
+                    let mode = u.int_in_range(0..=0o777)? | 0o500;
+                    new_entry(&path, kind, mode, t)
+                }
+                Fifo | Socket => {
+                    let mode = u.int_in_range(0..=0o777)? | 0o400;
+                    new_entry(&path, kind, mode, t)
+                }
+                BlockDevice | CharDevice | Whiteout => {
+                    let dev = if kind == Whiteout {
+                        device_number(0, 0)
+                    } else {
+                        let (major, minor) = arbitrary_device_number(u, &self.device_numbers)?;
+                        device_number(major, minor)
+                    };
+                    let mode = u.int_in_range(0o400..=0o777)?;
+                    Entry {
+                        rdev: dev as u64,
+                        ..new_entry(&path, kind, mode, t)
+                    }
+                }
+                Symlink if escaping => {
+                    escaping_symlinks.push(path.clone());
+                    Entry {
+                        target: Some(escaping_target(u, &path)?),
+                        ..new_entry(&path, kind, 0o777, t)
+                    }
+                }
+                Symlink => {
+                    let targets: Vec<PathBuf> = files
+                        .iter()
+                        .map(|original| symlink_target(&path, original))
+                        .filter(|target| target.as_os_str().len() < PATH_MAX)
+                        .collect();
+                    let target = u.choose(&targets[..])?.clone();
+                    Entry {
+                        target: Some(target),
+                        ..new_entry(&path, kind, 0o777, t)
+                    }
+                }
+                HardLink => {
+                    let original = original.unwrap();
+                    *link_counts.get_mut(&original).unwrap() += 1;
+                    hard_link_entry(&entries, &path, &original)
+                }
+            };
+            created_types.push(kind);
+            if kind == Directory {
+                dirs.insert(path);
+            } else {
+                if !matches!(kind, HardLink | Symlink) {
+                    link_counts.insert(path.clone(), 1);
+                }
+                files.push(path);
+            }
+            entries.push(entry);
+        }
+        let mut aged_entries = if self.age_buckets.is_empty() {
+            Vec::new()
+        } else {
+            arbitrary_ages(u, &self.age_buckets, &mut entries)?
+        };
+        let extreme_times = self.extreme_times();
+        if !extreme_times.is_empty() {
+            arbitrary_extreme_times(u, None, &extreme_times, &mut entries, &mut aged_entries)?;
+        }
+        let manifest = Manifest {
+            expected_actions: self.expected_actions(&entries),
+            entries,
+            aged_entries,
+            name_encoding: self.name_encoding,
+            escaping_symlinks,
+            ..Default::default()
+        };
+        Ok(DirSpec::new(manifest, contents))
+    }
+
+    fn populate(&mut self, root: &Path, u: &mut Unstructured<'_>) -> arbitrary::Result<Manifest> {
+        use FileType::*;
+        #[cfg(target_os = "linux")]
+        if self.landlock {
+            restrict_to(root).unwrap();
+        }
+        // do not touch the directories that existed before
+        let existing_dirs = list_existing_dirs(root);
+        let mut files: Vec<PathBuf> = Vec::new();
+        let mut long_files = Vec::new();
+        let mut dirs = Vec::new();
+        let mut entries = Vec::new();
+        let mut downgrades = Vec::new();
+        let mut collapsed_names = Vec::new();
+        let mut escaping_symlinks = Vec::new();
+        let long_paths = self.stress_modes.contains(&StressMode::LongPaths);
+        let many_hard_links = self.stress_modes.contains(&StressMode::ManyHardLinks);
+        let max_links = match self.max_links_per_file {
+            Some(max_links) => max_links,
+            None if many_hard_links => 64,
+            None => u64::MAX,
+        };
+        let max_path_len = match self.max_path_len {
+            Some(max_path_len) => max_path_len,
+            None if long_paths => 2 * PATH_MAX,
+            None => PATH_MAX,
+        };
+        let mut sizes = Sizes::new(u, &self.size_recipe)?;
+        let mut budget = if self.empty_contents {
+            Budget::new(Some(0))
+        } else {
+            Budget::new(self.max_total_bytes)
+        };
+        let mut queue = WorkQueue::new();
+        let mut shape = self.shape.clone().map(ShapedPaths::new);
+        let num_files: usize = match shape.as_ref() {
+            Some(shape) => shape.num_files(u)?,
+            None => u.int_in_range(0..=10)?,
+        };
+        let mut created_types = Vec::new();
+        for attempt in 0.. {
+            if !self.needs_more_files(attempt, num_files, &created_types) {
+                break;
+            }
+            let path: OsString = match shape.as_mut() {
+                Some(shape) => {
+                    shape.generate(u, self.name_generator.as_mut(), self.max_name_len)?
+                }
+                None => self.name_generator.generate(u)?,
+            };
+            if path.is_empty() {
+                // do not allow empty paths
+                self.fallback()?;
+                continue;
+            }
+            let path: PathBuf = path.into();
+            let path = match path.strip_prefix(MAIN_SEPARATOR_STR) {
+                Ok(path) => path,
+                Err(_) => path.as_path(),
+            };
+            let path = if long_paths {
+                long_path(u, path.as_os_str(), self.max_name_len, max_path_len)?
+            } else {
+                let max_name_len = self.max_name_len;
+                if path
+                    .components()
+                    .any(|c| c.as_os_str().len() > max_name_len)
+                {
+                    self.fallback()?;
+                }
+                truncate_components(path, max_name_len)
+            };
+            let path = root.join(path).normalize();
+            if path.symlink_metadata().is_ok() || long_files.contains(&path) {
+                // the path aliased some existing file or directory
+                if let Some(existing) = find_alias(&path) {
+                    collapsed_names.push(CollapsedName {
+                        path: path.strip_prefix(root).unwrap().to_path_buf(),
+                        existing: existing.strip_prefix(root).unwrap().to_path_buf(),
+                    });
+                }
+                self.fallback()?;
+                continue;
+            }
+            let relative_path = path.strip_prefix(root).unwrap();
+            if relative_path.as_os_str().is_empty()
+                || relative_path.as_os_str().len() > max_path_len
+            {
+                self.fallback()?;
+                continue;
+            }
+            let mut kind: FileType = self.choose_file_type(u, &created_types)?;
+            // such paths are created relative to the parent directory's descriptor
+            let long = path.as_os_str().len() >= PATH_MAX;
+            if !long && create_dir_all(path.parent().unwrap()).is_err() {
+                // the parent path aliased some existing file
+                self.fallback()?;
+                continue;
+            }
+            let escaping = kind == Symlink && self.escaping_symlinks && u.arbitrary()?;
+            if matches!(kind, FileType::HardLink | FileType::Symlink)
+                && files.is_empty()
+                && (!long || long_files.is_empty())
+                && !escaping
+            {
+                self.fallback()?;
+                kind = Regular;
+            }
+            let mut original = None;
+            if kind == HardLink {
+                // never link directories and respect the links limit
+                let candidates: Vec<&PathBuf> = files
+                    .iter()
+                    .filter(|file| {
+                        max_links == u64::MAX || link_count(file).is_some_and(|n| n < max_links)
+                    })
+                    .chain(long_files.iter().filter(|file| {
+                        // only long files can link to long files
+                        long && (max_links == u64::MAX
+                            || long_link_count(root, file).is_some_and(|n| n < max_links))
+                    }))
+                    .collect();
+                if candidates.is_empty() {
+                    self.fallback()?;
+                    kind = Regular;
+                } else {
+                    original = Some((*u.choose(&candidates[..])?).clone());
+                }
+            }
+            if long && kind == Symlink && escaping {
+                original = Some(escaping_target(u, relative_path)?);
+            } else if long && kind == Symlink {
+                // the targets are relative, the absolute ones don't fit into `PATH_MAX`
+                let targets: Vec<PathBuf> = files
+                    .iter()
+                    .chain(long_files.iter())
+                    .map(|file| symlink_target(relative_path, file.strip_prefix(root).unwrap()))
+                    .filter(|target| target.as_os_str().len() < PATH_MAX)
+                    .collect();
+                if targets.is_empty() {
+                    self.fallback()?;
+                    kind = Regular;
+                } else {
+                    original = Some(u.choose(&targets[..])?.clone());
+                }
+            }
+            let requested_kind = kind;
+            if !long && kind == Socket && bind_socket(&path).is_err() {
+                // the path doesn't fit into `sockaddr_un`
+                kind = Fifo;
+            }
+            let t = arbitrary_mtime(u, self.mtime_range.as_ref())?;
+            let mut requested_rdev = 0;
+            let entry = match kind {
+                _ if long => {
+                    let original = original.as_deref();
+                    let result = create_long(
+                        self.content_generator.as_mut(),
+                        &self.device_numbers,
+                        u,
+                        root,
+                        relative_path,
+                        kind,
+                        original,
+                        t,
+                        &entries,
+                        &mut budget,
+                    )?;
+                    let Some((entry, rdev)) = result else {
+                        // the path aliased some existing file
+                        self.fallback()?;
+                        continue;
+                    };
+                    kind = entry.file_type;
+                    requested_rdev = rdev;
+                    entry
+                }
+                Regular => {
+                    let mode = u.int_in_range(0..=0o777)? | 0o400;
+                    let mut contents = self.content_generator.generate(u, relative_path)?;
+                    // create the file now, write the contents later
+                    File::create(&path).unwrap();
+                    let recipe_size = match sizes.as_mut() {
+                        Some(sizes) => Some(budget.take(sizes.next(u)?)),
+                        None => None,
+                    };
+                    let size = match recipe_size {
+                        Some(size) => size,
+                        None => {
+                            contents.truncate(budget.take(contents.len() as u64) as usize);
+                            contents.len() as u64
+                        }
+                    };
+                    let file_path = path.clone();
+                    queue.push(move || {
+                        let mut file = OpenOptions::new().write(true).open(&file_path)?;
+                        match recipe_size {
+                            Some(size) => write_contents(&mut file, &contents, size, true)?,
+                            None => file.write_all(&contents)?,
+                        }
+                        file.set_permissions(Permissions::from_mode(mode))?;
+                        file.set_modified(t)
+                    });
+                    Entry {
+                        path: relative_path.to_path_buf(),
+                        file_type: kind,
+                        mode,
+                        mtime: Some(t),
+                        target: None,
+                        size,
+                        rdev: 0,
+                        owner: None,
+                        xattrs: Vec::new(),
+                        inode_flags: 0,
+                    }
+                }
+                Directory => {
+                    let mode = u.int_in_range(0..=0o777)? | 0o500;
+                    std::fs::DirBuilder::new()
+                        .mode(mode)
+                        .recursive(true)
+                        .create(&path)
+                        .unwrap();
+                    // bypass umask
+                    std::fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+                    let c_path = path_to_c_string(path.clone()).unwrap();
+                    set_file_modified_time(&c_path, t).unwrap();
+                    new_entry(relative_path, kind, mode, t)
+                }
+                Fifo => {
+                    let mode = u.int_in_range(0..=0o777)? | 0o400;
+                    let c_path = path_to_c_string(path.clone()).unwrap();
+                    mkfifo(&c_path, mode).unwrap();
+                    // bypass umask
+                    std::fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+                    set_file_modified_time(&c_path, t).unwrap();
+                    new_entry(relative_path, kind, mode, t)
+                }
+                Socket => {
+                    // the socket is bound above
+                    let c_path = path_to_c_string(path.clone()).unwrap();
+                    set_file_modified_time(&c_path, t).unwrap();
+                    Entry::from_file(root, relative_path.to_path_buf(), kind, None).unwrap()
+                }
+                BlockDevice | CharDevice | Whiteout => {
+                    let dev = if kind == Whiteout {
+                        device_number(0, 0)
+                    } else {
+                        let (major, minor) = arbitrary_device_number(u, &self.device_numbers)?;
+                        device_number(major, minor)
+                    };
+                    let mode = u.int_in_range(0o400..=0o777)?;
+                    let format = if kind == BlockDevice {
+                        libc::S_IFBLK
+                    } else {
+                        libc::S_IFCHR
+                    };
+                    let c_path = path_to_c_string(path.clone()).unwrap();
+                    let rdev = match mknod(&c_path, format | mode as libc::mode_t, dev) {
+                        Ok(()) => dev as u64,
+                        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                            // unprivileged, substitute named pipe
+                            kind = Fifo;
+                            requested_rdev = dev as u64;
+                            mkfifo(&c_path, mode as libc::mode_t).unwrap();
+                            0
+                        }
+                        Err(e) => panic!("failed to create `{}`: {}", path.display(), e),
+                    };
+                    // bypass umask
+                    std::fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+                    set_file_modified_time(&c_path, t).unwrap();
+                    Entry {
+                        rdev,
+                        ..new_entry(relative_path, kind, mode, t)
+                    }
+                }
+                Symlink if escaping => {
+                    let target = escaping_target(u, relative_path)?;
+                    symlink(&target, &path).unwrap();
+                    Entry {
+                        target: Some(target),
+                        ..new_entry(relative_path, kind, 0o777, t)
+                    }
+                }
+                Symlink => {
+                    let original = u.choose(&files[..]).unwrap();
+                    let target =
+                        symlink_target(relative_path, original.strip_prefix(root).unwrap());
+                    symlink(&target, &path).unwrap();
+                    Entry {
+                        path: relative_path.to_path_buf(),
+                        file_type: kind,
+                        mode: 0o777,
+                        mtime: None,
+                        target: Some(target),
+                        size: 0,
+                        rdev: 0,
+                        owner: None,
+                        xattrs: Vec::new(),
+                        inode_flags: 0,
+                    }
+                }
+                HardLink => {
+                    let original = original.as_ref().unwrap();
+                    assert!(
+                        hard_link(original, &path).is_ok(),
+                        "original = `{}`, path = `{}`",
+                        original.display(),
+                        path.display()
+                    );
+                    let original = original.strip_prefix(root).unwrap();
+                    hard_link_entry(&entries, relative_path, original)
+                }
+            };
+            if escaping && kind == Symlink {
+                escaping_symlinks.push(relative_path.to_path_buf());
+            }
+            created_types.push(requested_kind);
+            if kind != requested_kind {
+                self.fallback()?;
+                downgrades.push(Downgrade {
+                    path: relative_path.to_path_buf(),
+                    requested: requested_kind,
+                    actual: kind,
+                    rdev: requested_rdev,
+                });
+            }
+            entries.push(entry);
+            if long {
+                if kind != FileType::Directory {
+                    long_files.push(path.clone());
+                }
+            } else if kind != FileType::Directory {
+                files.push(path.clone());
+            } else {
+                dirs.push(path.clone());
+            }
+        }
+        queue.run().unwrap();
+        #[cfg(target_os = "linux")]
+        if self.unnamed_files {
+            relink_unnamed_files(u, root, &entries)?;
+        }
+        self.create_empty_dirs(u, root, &mut dirs, &mut entries)?;
+        if many_hard_links {
+            create_link_clusters(
+                u,
+                self.name_generator.as_mut(),
+                root,
+                &mut files,
+                &mut entries,
+                max_links,
+                self.max_name_len,
+            )?;
+        }
+        if self.stress_modes.contains(&StressMode::HugeFiles) {
+            self.create_huge_files(u, root, &mut files, &mut entries, &mut budget)?;
+        }
+        let crafted_entries = if self.stress_modes.contains(&StressMode::HeaderLimits) {
+            create_limit_entries(u, self.name_generator.as_mut(), root, &mut entries)?
+        } else {
+            Vec::new()
+        };
+        record_parents(
+            u,
+            root,
+            &existing_dirs,
+            self.mtime_range.as_ref(),
+            &mut entries,
+        )?;
+        let mut aged_entries = if self.age_buckets.is_empty() {
+            Vec::new()
+        } else {
+            let aged_entries = arbitrary_ages(u, &self.age_buckets, &mut entries)?;
+            set_modified_times(root, &entries);
+            aged_entries
+        };
+        let extreme_times = self.extreme_times();
+        if !extreme_times.is_empty() {
+            arbitrary_extreme_times(
+                u,
+                Some(root),
+                &extreme_times,
+                &mut entries,
+                &mut aged_entries,
+            )?;
+        }
+        // the owner might change in the user namespace
+        set_dir_modified_times(root, &entries);
+        #[cfg(target_os = "linux")]
+        if self.opaque_dirs {
+            set_opaque(u, root, &mut entries)?;
+        }
+        #[cfg(all(target_os = "linux", feature = "acl"))]
+        if self.acls {
+            set_acls(u, root, &mut entries)?;
+        }
+        #[cfg(all(target_os = "linux", feature = "selinux"))]
+        if !self.selinux_contexts.is_empty() {
+            set_selinux_contexts(u, root, &self.selinux_contexts, &mut entries)?;
+        }
+        #[cfg(target_os = "linux")]
+        if self.user_namespace {
+            chown_in_user_namespace(u, root, &mut entries)?;
+        }
+        // changing the owner clears the capabilities
+        #[cfg(target_os = "linux")]
+        if self.file_capabilities {
+            set_file_capabilities(u, root, &mut entries)?;
+        }
+        if self.strict
+            && (count_empty_dirs(root) < self.min_empty_dirs
+                || !entries.iter().all(|entry| matches_entry(root, entry)))
+        {
+            return Err(arbitrary::Error::IncorrectFormat);
+        }
+        // the flags prevent any further modifications
+        #[cfg(any(
+            all(target_os = "linux", feature = "chattr"),
+            target_os = "macos",
+            target_os = "freebsd"
+        ))]
+        if self.inode_flags {
+            set_arbitrary_inode_flags(u, root, &mut entries)?;
+        }
+        // hard links share the metadata with the original file
+        sync_hard_links(&mut entries);
+        Ok(Manifest {
+            expected_actions: self.expected_actions(&entries),
+            entries,
+            crafted_entries,
+            downgrades,
+            aged_entries,
+            name_encoding: self.name_encoding,
+            collapsed_names,
+            escaping_symlinks,
+        })
+    }
+
+    /// Generate one more file?
+    ///
+    /// Makes extra attempts to create the missing file types (see
+    /// [`DirBuilder::require_all_types`]).
+    fn needs_more_files(
+        &self,
+        attempt: usize,
+        num_files: usize,
+        created_types: &[FileType],
+    ) -> bool {
+        if attempt < num_files {
+            return true;
+        }
+        self.require_all_types
+            && attempt < num_files + MAX_EXTRA_ATTEMPTS
+            && self
+                .file_types
+                .iter()
+                .any(|kind| !created_types.contains(kind))
+    }
+
+    /// Choose the type of the next file.
+    ///
+    /// When [`DirBuilder::require_all_types`] is set, the types that were not created yet are
+    /// chosen first; the links are postponed until there are files to point to.
+    fn choose_file_type(
+        &self,
+        u: &mut Unstructured<'_>,
+        created_types: &[FileType],
+    ) -> arbitrary::Result<FileType> {
+        use FileType::*;
+        if self.require_all_types {
+            let missing: Vec<FileType> = self
+                .file_types
+                .iter()
+                .copied()
+                .filter(|kind| !created_types.contains(kind))
+                .collect();
+            let targets: Vec<FileType> = missing
+                .iter()
+                .copied()
+                .filter(|kind| !matches!(kind, HardLink | Symlink))
+                .collect();
+            let candidates = if targets.is_empty() {
+                &missing[..]
+            } else {
+                &targets[..]
+            };
+            if !candidates.is_empty() {
+                return Ok(*u.choose(candidates)?);
+            }
+        }
+        Ok(*u.choose(&self.file_types[..])?)
+    }
+
+    /// The times enabled by [`DirBuilder::pre_epoch_times`] and [`DirBuilder::far_future_times`].
+    fn extreme_times(&self) -> Vec<i64> {
+        let mut times = Vec::new();
+        if self.pre_epoch_times {
+            times.extend(PRE_EPOCH_TIMES);
+        }
+        if self.far_future_times {
+            times.extend(FAR_FUTURE_TIMES);
+        }
+        times
+    }
+
+    /// Reject the input in strict mode (see [`DirBuilder::strict`]).
+    fn fallback(&self) -> arbitrary::Result<()> {
+        if self.strict {
+            return Err(arbitrary::Error::IncorrectFormat);
+        }
+        Ok(())
+    }
+
+    fn expected_actions(&self, entries: &[Entry]) -> Vec<ExpectedAction> {
+        let Some(policy) = self.policy.as_ref() else {
+            return Vec::new();
+        };
+        entries
+            .iter()
+            .filter_map(|entry| {
+                policy(entry).map(|action| ExpectedAction {
+                    path: entry.path.clone(),
+                    action,
+                })
+            })
+            .collect()
+    }
+}
+
+type Policy = dyn Fn(&Entry) -> Option<String>;
+
+/// How the trees generated by [`DirBuilder::create_pair`] and [`DirBuilder::create_many`] are
+/// related.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum TreeRelation {
+    /// Each tree is generated from its own equal share of the unstructured data.
+    #[default]
+    Independent,
+    /// The same tree in different temporary directories.
+    ///
+    /// The first tree is generated, the others are its copies (see [`Dir::clone_to`]). The
+    /// extended attributes, the owners and the inode flags from the manifest are set on the
+    /// copies as well; the ones that can't be set are removed from the copies' manifests. Only
+    /// the first tree has the named pipe writers (see [`DirBuilder::fifo_writers`]).
+    Identical,
+}
+
+/// Drop-time deletion strategy (see [`DirBuilder::cleanup`]).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Cleanup {
+    /// Delete the directory recursively in the current thread.
+    ///
+    /// The read-only directories are made writable before deleting their contents.
+    #[default]
+    Remove,
+    /// Rename the directory and delete it in a background thread.
+    ///
+    /// Dropping the directory doesn't skew the benchmarks. The files are left behind if the
+    /// process exits before the thread finishes.
+    Background,
+    /// Do not delete the directory, print its path to the standard error instead.
+    Leave,
+}
+
+/// Bundled [`DirBuilder`] configuration (see [`DirBuilder::coverage`]).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Coverage {
+    /// A few small files and directories with printable names.
+    ///
+    /// Sets [portable](DirBuilder::portable) file types, printable names, up to 8 files at most
+    /// two levels deep and 64 KiB total size.
+    Minimal,
+    /// Realistic mixed tree with printable names.
+    ///
+    /// Sets portable file types and named pipes, printable names, 10–200 files up to five levels
+    /// deep, at least one empty directory and 16 MiB total size.
+    Typical,
+    /// Hostile tree that exercises the edge cases.
+    ///
+    /// Sets all file types, [hostile names](HostileNames), 10–100 files up to four levels deep,
+    /// [power-of-two boundary](POWER_OF_TWO_BOUNDARY_SIZES) sizes up to 1 MiB,
+    /// [`StressMode::HeaderLimits`], at least one empty directory and [escaping
+    /// symlinks](DirBuilder::allow_escaping_symlinks).
+    /// [`StressMode::HugeFiles`] is not enabled because the listings hold the contents in memory.
+    Adversarial,
+}
+
+/// Stress mode.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StressMode {
+    /// Generate path components near [`DirBuilder::max_name_len`] and paths near
+    /// [`DirBuilder::max_path_len`].
+    ///
+    /// Paths longer than [`PATH_MAX`] are created via `*at` system calls relative to the parent
+    /// directory's descriptor. Sockets with such paths are bound via `/proc/self/fd` on Linux and
+    /// are substituted with named pipes on other platforms.
+    /// Useful to test tar's 100/155-byte name splitting and GNU long name extensions.
+    LongPaths,
+    /// Generate clusters of many hard links to a single file scattered across directories.
+    ///
+    /// Produces high `nlink` counts to stress inode-tracking in archivers.
+    /// The number of links is limited by [`DirBuilder::max_links_per_file`] and each cluster has
+    /// at most 256 links.
+    ManyHardLinks,
+    /// Generate regular files larger than 4 GiB and 8 GiB.
+    ///
+    /// These sizes overflow 32-bit size fields and old ustar size field.
+    /// The files are sparse by default (see [`DirBuilder::sparse_huge_files`]).
+    HugeFiles,
+    /// Generate entries that hit archiver header field limits.
+    ///
+    /// These are paths of exactly 100, 155 and 255 bytes (ustar split points),
+    /// files with many hard links and very long symlink targets.
+    /// Each crafted entry is annotated with the targeted limit (see [`Dir::crafted_entries`]).
+    HeaderLimits,
+}
+
+fn default_file_types() -> Vec<FileType> {
+    #[cfg(not(target_os = "macos"))]
+    {
+        ALL_FILE_TYPES.into()
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use FileType::*;
+        [Regular, Directory, Fifo, Socket, Symlink, HardLink].into()
+    }
+}
+
+/// Maximum file size in [`Coverage::Adversarial`] configuration.
+const MAX_ADVERSARIAL_FILE_SIZE: u64 = (1 << 20) + 1;
+
+/// Arbitrary modification time within `range` or between Unix epoch and one day after the current
+/// time if the range is not specified.
+fn arbitrary_mtime(
+    u: &mut Unstructured<'_>,
+    range: Option<&RangeInclusive<SystemTime>>,
+) -> arbitrary::Result<SystemTime> {
+    if let Some(range) = range {
+        let to_nanos = |t: SystemTime| {
+            let (secs, nanos) = unix_time(t);
+            secs as i128 * 1_000_000_000 + nanos as i128
+        };
+        let start = to_nanos(*range.start());
+        let end = to_nanos(*range.end()).max(start);
+        let t = u.int_in_range(start..=end)?;
+        return Ok(from_unix_time(
+            t.div_euclid(1_000_000_000) as i64,
+            t.rem_euclid(1_000_000_000) as u32,
+        ));
+    }
+    let t = SystemTime::now() + Duration::from_secs(60 * 60 * 24);
+    let dt = t.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+    Ok(SystemTime::UNIX_EPOCH
+        + Duration::new(
+            u.int_in_range(0..=dt.as_secs())?,
+            u.int_in_range(0..=999_999_999)?,
+        ))
+}
+
+/// Replace arbitrary regular files with the unnamed copies and link them in in arbitrary order
+/// (see [`DirBuilder::unnamed_files`]).
+///
+/// The files with hard links and extended attributes are left intact.
+#[cfg(target_os = "linux")]
+fn relink_unnamed_files(
+    u: &mut Unstructured<'_>,
+    root: &Path,
+    entries: &[Entry],
+) -> arbitrary::Result<()> {
+    let targets: HashSet<&Path> = entries
+        .iter()
+        .filter(|entry| entry.file_type == FileType::HardLink)
+        .filter_map(|entry| entry.target.as_deref())
+        .collect();
+    let root = File::open(root).unwrap();
+    let mut unnamed = Vec::new();
+    for entry in entries.iter() {
+        if entry.file_type != FileType::Regular
+            || entry.size > MAX_DENSE_FILE_SIZE
+            || !entry.xattrs.is_empty()
+            || targets.contains(entry.path.as_path())
+            || !u.arbitrary()?
+        {
+            continue;
+        }
+        let Some(name) = entry.path.file_name() else {
+            continue;
+        };
+        let parent =
+            create_dir_all_at(root.as_fd(), entry.path.parent().unwrap_or(Path::new(""))).unwrap();
+        let name = os_str_to_c_string(name).unwrap();
+        let Ok(mut file) = open_tmpfile_at(parent.as_fd(), entry.mode as libc::mode_t) else {
+            // unsupported by the file system
+            break;
+        };
+        std::io::copy(&mut open_file_at(parent.as_fd(), &name).unwrap(), &mut file).unwrap();
+        fchmod(file.as_fd(), entry.mode as libc::mode_t).unwrap();
+        if let Some(t) = entry.mtime {
+            file.set_modified(t).unwrap();
+        }
+        unnamed.push((parent, name, file));
+    }
+    while !unnamed.is_empty() {
+        let (parent, name, file) = unnamed.swap_remove(u.choose_index(unnamed.len())?);
+        unlink_at(parent.as_fd(), &name, 0).unwrap();
+        link_unnamed_at(file.as_fd(), parent.as_fd(), &name).unwrap();
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_opaque(
+    u: &mut Unstructured<'_>,
+    root: &Path,
+    entries: &mut [Entry],
+) -> arbitrary::Result<()> {
+    const NAME: &str = "trusted.overlay.opaque";
+    for entry in entries.iter_mut() {
+        if entry.file_type != FileType::Directory || !u.arbitrary()? {
+            continue;
+        }
+        let path = root.join(&entry.path);
+        if path.as_os_str().len() >= PATH_MAX {
+            continue;
+        }
+        let c_path = path_to_c_string(path).unwrap();
+        let c_name = os_str_to_c_string(OsStr::new(NAME)).unwrap();
+        if set_xattr(&c_path, &c_name, b"y").is_err() {
+            // unprivileged or unsupported by the file system
+            return Ok(());
+        }
+        entry.xattrs.push((NAME.into(), b"y".to_vec()));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_file_capabilities(
+    u: &mut Unstructured<'_>,
+    root: &Path,
+    entries: &mut [Entry],
+) -> arbitrary::Result<()> {
+    for entry in entries.iter_mut() {
+        if entry.file_type != FileType::Regular || !u.arbitrary()? {
+            continue;
+        }
+        let path = root.join(&entry.path);
+        if path.as_os_str().len() >= PATH_MAX {
+            continue;
+        }
+        let c_path = path_to_c_string(path).unwrap();
+        let c_name = os_str_to_c_string(OsStr::new(CAPABILITY_XATTR)).unwrap();
+        let value = arbitrary_file_capabilities(u)?;
+        if set_xattr(&c_path, &c_name, &value).is_err() {
+            // unprivileged or unsupported by the file system
+            return Ok(());
+        }
+        entry.xattrs.push((CAPABILITY_XATTR.into(), value));
+    }
+    Ok(())
+}
+
+/// Generate `vfs_cap_data` structure (revision 2).
+#[cfg(target_os = "linux")]
+fn arbitrary_file_capabilities(u: &mut Unstructured<'_>) -> arbitrary::Result<Vec<u8>> {
+    const VFS_CAP_REVISION_2: u32 = 0x0200_0000;
+    const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x0000_0001;
+    // CAP_CHECKPOINT_RESTORE
+    const CAP_LAST_CAP: u32 = 40;
+    let mut arbitrary_set = || -> arbitrary::Result<u64> {
+        let set: u64 = u.arbitrary()?;
+        Ok(set & ((1_u64 << (CAP_LAST_CAP + 1)) - 1))
+    };
+    let permitted = arbitrary_set()?;
+    let inheritable = arbitrary_set()?;
+    let mut magic = VFS_CAP_REVISION_2;
+    if u.arbitrary()? {
+        magic |= VFS_CAP_FLAGS_EFFECTIVE;
+    }
+    let mut value = Vec::with_capacity(20);
+    value.extend_from_slice(&magic.to_le_bytes());
+    for i in 0..2 {
+        value.extend_from_slice(&((permitted >> (32 * i)) as u32).to_le_bytes());
+        value.extend_from_slice(&((inheritable >> (32 * i)) as u32).to_le_bytes());
+    }
+    Ok(value)
+}
+
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+const ARBITRARY_INODE_FLAGS: [u32; 3] = [
+    FS_IMMUTABLE_FL,
+    FS_APPEND_FL,
+    FS_IMMUTABLE_FL | FS_APPEND_FL,
+];
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+const ARBITRARY_INODE_FLAGS: [u32; 3] = [UF_NODUMP, UF_HIDDEN, UF_NODUMP | UF_HIDDEN];
+
+#[cfg(any(
+    all(target_os = "linux", feature = "chattr"),
+    target_os = "macos",
+    target_os = "freebsd"
+))]
+fn set_arbitrary_inode_flags(
+    u: &mut Unstructured<'_>,
+    root: &Path,
+    entries: &mut [Entry],
+) -> arbitrary::Result<()> {
+    for entry in entries.iter_mut() {
+        if !matches!(entry.file_type, FileType::Regular | FileType::Directory) || !u.arbitrary()? {
+            continue;
+        }
+        let path = root.join(&entry.path);
+        if path.as_os_str().len() >= PATH_MAX {
+            continue;
+        }
+        let c_path = path_to_c_string(path).unwrap();
+        let flags = *u.choose(&ARBITRARY_INODE_FLAGS)?;
+        let Ok(old_flags) = get_inode_flags(&c_path) else {
+            continue;
+        };
+        if set_inode_flags(&c_path, old_flags | flags).is_err() {
+            // unprivileged or unsupported by the file system
+            return Ok(());
+        }
+        entry.inode_flags = flags;
+    }
+    Ok(())
+}
+
+/// Set the metadata from the manifest that the copy of the tree doesn't preserve: extended
+/// attributes, owners (when unprivileged) and inode flags.
+///
+/// The metadata that can't be set is removed from the entries.
+fn restore_metadata(root: &Path, entries: &mut [Entry]) {
+    #[cfg(target_os = "linux")]
+    {
+        let is_capability = |name: &OsStr| name == CAPABILITY_XATTR;
+        restore_xattrs(root, entries, |name| !is_capability(name));
+        if unsafe { libc::geteuid() } != 0 {
+            restore_owners_in_user_namespace(root, entries);
+        }
+        // changing the owner clears the capabilities
+        restore_xattrs(root, entries, is_capability);
+    }
+    // the flags prevent any further modifications
+    #[cfg(any(
+        all(target_os = "linux", feature = "chattr"),
+        target_os = "macos",
+        target_os = "freebsd"
+    ))]
+    for entry in entries.iter_mut() {
+        if entry.inode_flags == 0 || entry.file_type == FileType::HardLink {
+            continue;
+        }
+        let restored = path_to_c_string(root.join(&entry.path)).and_then(|c_path| {
+            let flags = get_inode_flags(&c_path)?;
+            set_inode_flags(&c_path, flags | entry.inode_flags)
+        });
+        if restored.is_err() {
+            entry.inode_flags = 0;
+        }
+    }
+    sync_hard_links(entries);
+}
+
+/// Copy the owners, the extended attributes and the inode flags of the original files to their
+/// hard links.
+fn sync_hard_links(entries: &mut [Entry]) {
+    for i in 0..entries.len() {
+        if entries[i].file_type != FileType::HardLink {
+            continue;
+        }
+        let Some(original) = entries
+            .iter()
+            .find(|entry| Some(&entry.path) == entries[i].target.as_ref())
+        else {
+            continue;
+        };
+        let (owner, xattrs, inode_flags) = (
+            original.owner,
+            original.xattrs.clone(),
+            original.inode_flags,
+        );
+        let entry = &mut entries[i];
+        entry.owner = owner;
+        entry.xattrs = xattrs;
+        entry.inode_flags = inode_flags;
+    }
+}
+
+/// Set the extended attributes accepted by `filter`, the ones that can't be set are removed
+/// from the entries.
+#[cfg(target_os = "linux")]
+fn restore_xattrs<F: Fn(&OsStr) -> bool>(root: &Path, entries: &mut [Entry], filter: F) {
+    for entry in entries.iter_mut() {
+        if entry.file_type == FileType::HardLink {
+            continue;
+        }
+        let c_path = path_to_c_string(root.join(&entry.path)).ok();
+        entry.xattrs.retain(|(name, value)| {
+            if !filter(name) {
+                return true;
+            }
+            let (Some(c_path), Ok(c_name)) = (c_path.as_ref(), os_str_to_c_string(name)) else {
+                return false;
+            };
+            set_xattr(c_path, &c_name, value).is_ok()
+        });
+    }
+}
+
+#[cfg(target_os = "linux")]
+const CAPABILITY_XATTR: &str = "security.capability";
+
+/// Clear the flags that prevent the deletion.
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+fn clear_inode_flags(root: &Path, entries: &[Entry]) {
+    for entry in entries.iter() {
+        if entry.inode_flags == 0 {
+            continue;
+        }
+        let Ok(c_path) = path_to_c_string(root.join(&entry.path)) else {
+            continue;
+        };
+        if let Ok(flags) = get_inode_flags(&c_path) {
+            let _ = set_inode_flags(&c_path, flags & !(FS_IMMUTABLE_FL | FS_APPEND_FL));
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "selinux"))]
+const SELINUX_XATTR: &str = "security.selinux";
+
+#[cfg(all(target_os = "linux", feature = "selinux"))]
+fn set_selinux_contexts(
+    u: &mut Unstructured<'_>,
+    root: &Path,
+    contexts: &[String],
+    entries: &mut [Entry],
+) -> arbitrary::Result<()> {
+    let c_name = os_str_to_c_string(OsStr::new(SELINUX_XATTR)).unwrap();
+    for entry in entries.iter_mut() {
+        // hard links share the context with the original file
+        if entry.file_type == FileType::HardLink || !u.arbitrary()? {
+            continue;
+        }
+        let path = root.join(&entry.path);
+        if path.as_os_str().len() >= PATH_MAX {
+            continue;
+        }
+        let c_path = path_to_c_string(path).unwrap();
+        let mut value = u.choose(contexts)?.as_bytes().to_vec();
+        value.push(0);
+        if set_xattr(&c_path, &c_name, &value).is_err() {
+            // invalid context, not permitted or unsupported by the file system
+            return Ok(());
+        }
+        entry.xattrs.push((SELINUX_XATTR.into(), value));
+    }
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "acl"))]
+fn set_acls(u: &mut Unstructured<'_>, root: &Path, entries: &mut [Entry]) -> arbitrary::Result<()> {
+    for entry in entries.iter_mut() {
+        if !matches!(entry.file_type, FileType::Regular | FileType::Directory) || !u.arbitrary()? {
+            continue;
+        }
+        let path = root.join(&entry.path);
+        if path.as_os_str().len() >= PATH_MAX {
+            continue;
+        }
+        let c_path = path_to_c_string(path).unwrap();
+        let mut xattrs = vec![(
+            ACL_ACCESS_XATTR,
+            encode_acl(&arbitrary_access_acl(u, entry.mode)?),
+        )];
+        if entry.file_type == FileType::Directory && u.arbitrary()? {
+            xattrs.push((ACL_DEFAULT_XATTR, encode_acl(&arbitrary_default_acl(u)?)));
+        }
+        for (name, value) in xattrs.into_iter() {
+            let c_name = os_str_to_c_string(OsStr::new(name)).unwrap();
+            if set_xattr(&c_path, &c_name, &value).is_err() {
+                // unsupported by the file system
+                return Ok(());
+            }
+            entry.xattrs.push((name.into(), value));
+        }
+    }
+    Ok(())
+}
+
+fn arbitrary_device_number(
+    u: &mut Unstructured<'_>,
+    allowlist: &[(u32, u32)],
+) -> arbitrary::Result<(u32, u32)> {
+    if !allowlist.is_empty() {
+        return Ok(*u.choose(allowlist)?);
+    }
+    Ok((
+        u.int_in_range(0..=MAX_DEVICE_MAJOR)?,
+        u.int_in_range(0..=MAX_DEVICE_MINOR)?,
+    ))
+}
+
+#[allow(unused_unsafe)]
+fn device_number(major: u32, minor: u32) -> dev_t {
+    unsafe { makedev(major as _, minor as _) }
+}
+
+/// Create the file with the path that is longer than `PATH_MAX`.
+///
+/// The file is created via `*at` system calls relative to the parent directory's descriptor.
+/// `original` is the relative target of the symbolic link or the original file of the hard link.
+/// The sockets are
+/// substituted with named pipes if they can't be bound, and the devices are substituted with
+/// named pipes without privileges. Returns the entry and the requested device number of the
+/// substituted device, or `None` if the path aliased some existing file.
+#[allow(clippy::too_many_arguments)]
+fn create_long(
+    content_generator: &mut dyn ContentGenerator,
+    device_numbers: &[(u32, u32)],
+    u: &mut Unstructured<'_>,
+    root: &Path,
+    relative_path: &Path,
+    kind: FileType,
+    original: Option<&Path>,
+    t: SystemTime,
+    entries: &[Entry],
+    budget: &mut Budget,
+) -> arbitrary::Result<Option<(Entry, u64)>> {
+    use FileType::*;
+    let root_dir = File::open(root).unwrap();
+    let Ok(parent) = create_dir_all_at(root_dir.as_fd(), relative_path.parent().unwrap()) else {
+        return Ok(None);
+    };
+    let file_name = relative_path.file_name().unwrap();
+    let name = os_str_to_c_string(file_name).unwrap();
+    let mut requested_rdev = 0;
+    let entry = match kind {
+        Directory => {
+            let mode = u.int_in_range(0..=0o777)? | 0o500;
+            if mkdir_at(parent.as_fd(), &name, mode).is_err() {
+                return Ok(None);
+            }
+            let dir = open_dir_at(parent.as_fd(), &name).unwrap();
+            // bypass umask
+            fchmod(dir.as_fd(), mode).unwrap();
+            set_file_modified_time_at(parent.as_raw_fd(), &name, t).unwrap();
+            new_entry(relative_path, kind, mode, t)
+        }
+        Regular => {
+            let mode = u.int_in_range(0..=0o777)? | 0o400;
+            let mut contents = content_generator.generate(u, relative_path)?;
+            let Ok(mut file) = create_file_at(parent.as_fd(), &name, 0o600) else {
+                return Ok(None);
+            };
+            contents.truncate(budget.take(contents.len() as u64) as usize);
+            file.write_all(&contents).unwrap();
+            file.set_permissions(Permissions::from_mode(mode)).unwrap();
+            file.set_modified(t).unwrap();
+            Entry {
+                size: contents.len() as u64,
+                ..new_entry(relative_path, kind, mode, t)
+            }
+        }
+        Fifo | Socket => {
+            let mode = u.int_in_range(0..=0o777)? | 0o400;
+            #[cfg(target_os = "linux")]
+            let bound = kind == Socket && bind_socket_at(parent.as_fd(), file_name).is_ok();
+            #[cfg(not(target_os = "linux"))]
+            let bound = false;
+            if !bound && mkfifo_at(parent.as_fd(), &name, mode).is_err() {
+                return Ok(None);
+            }
+            // bypass umask
+            fchmod_at(parent.as_fd(), &name, mode).unwrap();
+            set_file_modified_time_at(parent.as_raw_fd(), &name, t).unwrap();
+            let kind = if bound { Socket } else { Fifo };
+            new_entry(relative_path, kind, mode, t)
+        }
+        BlockDevice | CharDevice | Whiteout => {
+            let dev = if kind == Whiteout {
+                device_number(0, 0)
+            } else {
+                let (major, minor) = arbitrary_device_number(u, device_numbers)?;
+                device_number(major, minor)
+            };
+            let mode = u.int_in_range(0o400..=0o777)?;
+            let format = if kind == BlockDevice {
+                libc::S_IFBLK
+            } else {
+                libc::S_IFCHR
+            };
+            let (kind, rdev) =
+                match mknod_at(parent.as_fd(), &name, format | mode as libc::mode_t, dev) {
+                    Ok(()) => (kind, dev as u64),
+                    Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                        // unprivileged, substitute named pipe
+                        requested_rdev = dev as u64;
+                        if mkfifo_at(parent.as_fd(), &name, mode as libc::mode_t).is_err() {
+                            return Ok(None);
+                        }
+                        (Fifo, 0)
+                    }
+                    Err(_) => return Ok(None),
+                };
+            // bypass umask
+            fchmod_at(parent.as_fd(), &name, mode as libc::mode_t).unwrap();
+            set_file_modified_time_at(parent.as_raw_fd(), &name, t).unwrap();
+            Entry {
+                rdev,
+                ..new_entry(relative_path, kind, mode, t)
+            }
+        }
+        Symlink => {
+            let original = original.unwrap();
+            let target = path_to_c_string(original.to_path_buf()).unwrap();
+            if symlink_at(&target, parent.as_fd(), &name).is_err() {
+                return Ok(None);
+            }
+            Entry {
+                path: relative_path.to_path_buf(),
+                file_type: kind,
+                mode: 0o777,
+                mtime: None,
+                target: Some(original.to_path_buf()),
+                size: 0,
+                rdev: 0,
+                owner: None,
+                xattrs: Vec::new(),
+                inode_flags: 0,
+            }
+        }
+        HardLink => {
+            let original = original.unwrap().strip_prefix(root).unwrap();
+            // the original might be long as well
+            let original_parent =
+                create_dir_all_at(root_dir.as_fd(), original.parent().unwrap()).unwrap();
+            let original_name = os_str_to_c_string(original.file_name().unwrap()).unwrap();
+            if link_at(
+                original_parent.as_fd(),
+                &original_name,
+                parent.as_fd(),
+                &name,
+            )
+            .is_err()
+            {
+                return Ok(None);
+            }
+            hard_link_entry(entries, relative_path, original)
+        }
+    };
+    Ok(Some((entry, requested_rdev)))
+}
+
+/// The target of the symbolic link `link` to `original` relative to the link's parent directory.
+///
+/// The target ascends to the root unless the resulting path is longer than `PATH_MAX`, otherwise
+/// it ascends only to the common ancestor.
+fn symlink_target(link: &Path, original: &Path) -> PathBuf {
+    let dir = link.parent().unwrap();
+    let mut target: PathBuf = dir.components().map(|_| "..").collect();
+    target.push(original);
+    if target.as_os_str().len() < PATH_MAX {
+        return target;
+    }
+    let common = dir
+        .components()
+        .zip(original.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut target: PathBuf = dir.components().skip(common).map(|_| "..").collect();
+    target.extend(original.components().skip(common));
+    target
+}
+
+/// Check that the file on disk has the file type and the mode from the manifest.
+fn matches_entry(root: &Path, entry: &Entry) -> bool {
+    use FileType::*;
+    let path = root.join(&entry.path);
+    if path.as_os_str().len() >= PATH_MAX {
+        // created via relative descent
+        return true;
+    }
+    let Ok(metadata) = path.symlink_metadata() else {
+        return false;
+    };
+    let file_type = metadata.file_type();
+    let type_matches = match entry.file_type {
+        Regular => file_type.is_file(),
+        Directory => file_type.is_dir(),
+        Fifo => file_type.is_fifo(),
+        Socket => file_type.is_socket(),
+        BlockDevice => file_type.is_block_device(),
+        CharDevice | Whiteout => file_type.is_char_device(),
+        Symlink => return file_type.is_symlink(),
+        // the original might be of any type
+        HardLink => true,
+    };
+    type_matches && metadata.permissions().mode() & 0o7777 == entry.mode & 0o7777
+}
+
+/// List the directories relative to `root`.
+fn list_existing_dirs(root: &Path) -> HashSet<PathBuf> {
+    WalkDir::new(root)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_dir())
+        .filter_map(|entry| Some(entry.path().strip_prefix(root).ok()?.to_path_buf()))
+        .collect()
+}
+
+/// Add the entries for the parent directories that were created implicitly and set their random
+/// modes (see [`set_dir_modified_times`] for the modification times).
+///
+/// The parents precede their children in the resulting list.
+fn record_parents(
+    u: &mut Unstructured<'_>,
+    root: &Path,
+    existing_dirs: &HashSet<PathBuf>,
+    mtime_range: Option<&RangeInclusive<SystemTime>>,
+    entries: &mut Vec<Entry>,
+) -> arbitrary::Result<()> {
+    let mut known: HashSet<PathBuf> = entries.iter().map(|entry| entry.path.clone()).collect();
+    let mut new_entries = Vec::with_capacity(entries.len());
+    let mut parents = Vec::new();
+    for entry in entries.iter() {
+        let ancestors: Vec<&Path> = entry.path.ancestors().skip(1).collect();
+        for parent in ancestors.into_iter().rev() {
+            if parent.as_os_str().is_empty()
+                || existing_dirs.contains(parent)
+                || !known.insert(parent.to_path_buf())
+            {
+                continue;
+            }
+            let mode = u.int_in_range(0..=0o777)? | 0o500;
+            let t = arbitrary_mtime(u, mtime_range)?;
+            parents.push(new_entries.len());
+            new_entries.push(new_entry(parent, FileType::Directory, mode, t));
+        }
+        new_entries.push(entry.clone());
+    }
+    *entries = new_entries;
+    for i in parents.into_iter() {
+        let entry = &entries[i];
+        let path = root.join(&entry.path);
+        if path.as_os_str().len() < PATH_MAX {
+            std::fs::set_permissions(&path, Permissions::from_mode(entry.mode)).unwrap();
+        } else {
+            let root = File::open(root).unwrap();
+            let dir = create_dir_all_at(root.as_fd(), &entry.path).unwrap();
+            fchmod(dir.as_fd(), entry.mode as libc::mode_t).unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Set the modification times of the directories bottom-up.
+///
+/// Creating the children updates the modification time of the parent, hence this should be the
+/// last pass that changes the contents of the tree.
+fn set_dir_modified_times(root: &Path, entries: &[Entry]) {
+    for entry in entries.iter().rev() {
+        let (FileType::Directory, Some(t)) = (entry.file_type, entry.mtime) else {
+            continue;
+        };
+        let path = root.join(&entry.path);
+        if path.as_os_str().len() < PATH_MAX {
+            set_file_modified_time(&path_to_c_string(path).unwrap(), t).unwrap();
+        } else {
+            // descend to the parent
+            let root = File::open(root).unwrap();
+            let parent = create_dir_all_at(root.as_fd(), entry.path.parent().unwrap()).unwrap();
+            let name = os_str_to_c_string(entry.path.file_name().unwrap()).unwrap();
+            set_file_modified_time_at(parent.as_raw_fd(), &name, t).unwrap();
+        }
+    }
+}
+
+fn new_entry(path: &Path, file_type: FileType, mode: u32, t: SystemTime) -> Entry {
+    Entry {
+        path: path.to_path_buf(),
+        file_type,
+        mode,
+        mtime: Some(t),
+        target: None,
+        size: 0,
+        rdev: 0,
+        owner: None,
+        xattrs: Vec::new(),
+        inode_flags: 0,
+    }
+}
+
+/// Returns the listed path of the existing file if the file system resolved the path to
+/// a different name, i.e. the names differ only in case or normalization.
+fn find_alias(path: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+    let name = path.file_name()?;
+    let parent = path.parent()?;
+    let metadata = path.symlink_metadata().ok()?;
+    let mut alias = None;
+    for entry in std::fs::read_dir(parent).ok()? {
+        let entry = entry.ok()?;
+        if entry.file_name() == name {
+            return None;
+        }
+        let Ok(other) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        if other.dev() == metadata.dev() && other.ino() == metadata.ino() {
+            alias = Some(entry.path());
+        }
+    }
+    alias
+}
+
+/// Hard links share the metadata with the original file.
+fn hard_link_entry(entries: &[Entry], path: &Path, original: &Path) -> Entry {
+    let original_entry = entries.iter().find(|entry| entry.path == original);
+    Entry {
+        path: path.to_path_buf(),
+        file_type: FileType::HardLink,
+        mode: original_entry.map(|entry| entry.mode).unwrap_or(0),
+        mtime: original_entry.and_then(|entry| entry.mtime),
+        target: Some(original.to_path_buf()),
+        size: original_entry.map(|entry| entry.size).unwrap_or(0),
+        rdev: original_entry.map(|entry| entry.rdev).unwrap_or(0),
+        owner: original_entry.and_then(|entry| entry.owner),
+        xattrs: original_entry
+            .map(|entry| entry.xattrs.clone())
+            .unwrap_or_default(),
+        inode_flags: original_entry
+            .map(|entry| entry.inode_flags)
+            .unwrap_or_default(),
+    }
+}
+
+/// Create many hard links to a few regular files.
+fn create_link_clusters(
+    u: &mut Unstructured<'_>,
+    name_generator: &mut dyn NameGenerator,
+    root: &Path,
+    files: &mut Vec<PathBuf>,
+    entries: &mut Vec<Entry>,
+    max_links: u64,
+    max_name_len: usize,
+) -> arbitrary::Result<()> {
+    let regular_files: Vec<PathBuf> = files
+        .iter()
+        .filter(|file| file.symlink_metadata().is_ok_and(|m| m.is_file()))
+        .cloned()
+        .collect();
+    if regular_files.is_empty() {
+        return Ok(());
+    }
+    let mut dirs: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|file| file.parent().map(Path::to_path_buf))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    let num_clusters = u.int_in_range(1..=regular_files.len().min(3))?;
+    for _ in 0..num_clusters {
+        let original = u.choose(&regular_files[..])?;
+        let max_cluster_len = max_links.min(MAX_LINK_CLUSTER_LEN);
+        let num_links = u.int_in_range(max_cluster_len / 2..=max_cluster_len)?;
+        let num_links = num_links.saturating_sub(link_count(original).unwrap_or(max_links));
+        for i in 0..num_links {
+            let dir = u.choose(&dirs[..])?;
+            let name: PathBuf = name_generator.generate(u)?.into();
+            let name = match name.file_name() {
+                Some(name) => OsStr::from_bytes(truncate_name(name.as_bytes(), max_name_len)),
+                None => OsStr::new("link"),
+            };
+            let mut path = dir.join(name);
+            if path.symlink_metadata().is_ok() {
+                path = dir.join(format!("link{}", i));
+                if path.symlink_metadata().is_ok() {
+                    continue;
+                }
+            }
+            if hard_link(original, &path).is_err() {
+                continue;
+            }
+            entries.push(hard_link_entry(
+                entries,
+                path.strip_prefix(root).unwrap(),
+                original.strip_prefix(root).unwrap(),
+            ));
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the number of hard links or `None` if the file is a directory.
+fn link_count(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = path.symlink_metadata().ok()?;
+    if metadata.is_dir() {
+        return None;
+    }
+    Some(metadata.nlink())
+}
+
+/// Generate the target of the symbolic link `link` that points outside the directory root.
+fn escaping_target(u: &mut Unstructured<'_>, link: &Path) -> arbitrary::Result<PathBuf> {
+    if u.arbitrary()? {
+        return Ok(PathBuf::from(*u.choose(&ESCAPING_TARGETS[..])?));
+    }
+    // ascend above the root
+    let depth = link.components().count() - 1;
+    let num_parents = u.int_in_range(depth + 1..=depth + 3)?;
+    let mut target: PathBuf = (0..num_parents).map(|_| "..").collect();
+    if u.arbitrary()? {
+        target.push("outside");
+    }
+    Ok(target)
+}
+
+/// Get the no. of hard links of the file with the path that is longer than `PATH_MAX`.
+fn long_link_count(root: &Path, path: &Path) -> Option<u64> {
+    let relative_path = path.strip_prefix(root).ok()?;
+    let root = File::open(root).ok()?;
+    let parent = create_dir_all_at(root.as_fd(), relative_path.parent()?).ok()?;
+    let name = os_str_to_c_string(relative_path.file_name()?).ok()?;
+    let stat = stat_at(parent.as_fd(), &name).ok()?;
+    if stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.st_nlink as u64)
+}
+
+/// Generate long path from the characters of `name`.
+fn long_path(
+    u: &mut Unstructured<'_>,
+    name: &OsStr,
+    max_name_len: usize,
+    max_path_len: usize,
+) -> arbitrary::Result<PathBuf> {
+    let mut chars: Vec<u8> = name
+        .as_bytes()
+        .iter()
+        .copied()
+        .filter(|b| *b != b'/')
+        .collect();
+    if chars.is_empty() {
+        chars.push(b'x');
+    }
+    let target_len = u.int_in_range(max_path_len.saturating_sub(max_name_len)..=max_path_len)?;
+    let mut path = PathBuf::new();
+    let mut len = 0;
+    loop {
+        let name_len = u.int_in_range(max_name_len.saturating_sub(8).max(1)..=max_name_len)?;
+        let separator_len = if len == 0 { 0 } else { 1 };
+        if len + separator_len + name_len > target_len {
+            break;
+        }
+        let component: Vec<u8> = chars.iter().copied().cycle().take(name_len * 2).collect();
+        let component = truncate_name(&component, name_len);
+        len += separator_len + component.len();
+        path.push(OsStr::from_bytes(component));
+        if u.is_empty() {
+            break;
+        }
+    }
+    if path.as_os_str().is_empty() {
+        // the limits are too strict
+        path.push(OsStr::from_bytes(truncate_name(&chars, max_name_len)));
+    }
+    Ok(path)
+}
+
+/// Truncate each path component to `max_len` bytes.
+fn truncate_components(path: &Path, max_len: usize) -> PathBuf {
+    path.components()
+        .map(|component| {
+            OsStr::from_bytes(truncate_name(component.as_os_str().as_bytes(), max_len))
+        })
+        .collect()
+}
+
+/// Truncate the name to `max_len` bytes preserving UTF-8 character boundaries.
+fn truncate_name(name: &[u8], max_len: usize) -> &[u8] {
+    if name.len() <= max_len {
+        return name;
+    }
+    match std::str::from_utf8(name) {
+        Ok(string) => {
+            let mut i = max_len;
+            while !string.is_char_boundary(i) {
+                i -= 1;
+            }
+            &name[..i]
+        }
+        Err(_) => &name[..max_len],
+    }
+}
+
+impl DirBuilder {
+    fn create_huge_files(
+        &mut self,
+        u: &mut Unstructured<'_>,
+        root: &Path,
+        files: &mut Vec<PathBuf>,
+        entries: &mut Vec<Entry>,
+        budget: &mut Budget,
+    ) -> arbitrary::Result<()> {
+        for (i, limit) in HUGE_FILE_SIZE_LIMITS.into_iter().enumerate() {
+            let name: PathBuf = self.name_generator.generate(u)?.into();
+            let name = match name.file_name() {
+                Some(name) => OsStr::from_bytes(truncate_name(name.as_bytes(), self.max_name_len)),
+                None => OsStr::new("huge"),
             };
-            let path = dir.path().join(path).normalize();
-            if path.is_dir() || files.contains(&path) {
-                // the path aliased some existing directory
-                continue;
+            let mut path = root.join(name);
+            if path.symlink_metadata().is_ok() {
+                path = root.join(format!("huge{}", i));
+                if path.symlink_metadata().is_ok() {
+                    continue;
+                }
             }
-            create_dir_all(path.parent().unwrap()).unwrap();
-            let mut kind: FileType = *u.choose(&self.file_types[..])?;
-            if matches!(kind, FileType::HardLink | FileType::Symlink) && files.is_empty() {
-                kind = Regular;
+            let size = budget.take(limit + u.int_in_range(1..=4096)?);
+            let mode = u.int_in_range(0..=0o777)? | 0o400;
+            let t = arbitrary_mtime(u, self.mtime_range.as_ref())?;
+            let contents = self
+                .content_generator
+                .generate(u, path.strip_prefix(root).unwrap())?;
+            let mut file = File::create(&path).unwrap();
+            write_contents(&mut file, &contents, size, self.sparse_huge_files).unwrap();
+            file.set_permissions(Permissions::from_mode(mode)).unwrap();
+            file.set_modified(t).unwrap();
+            entries.push(Entry {
+                size,
+                ..new_entry(path.strip_prefix(root).unwrap(), FileType::Regular, mode, t)
+            });
+            files.push(path);
+        }
+        Ok(())
+    }
+}
+
+impl DirBuilder {
+    fn create_empty_dirs(
+        &mut self,
+        u: &mut Unstructured<'_>,
+        root: &Path,
+        dirs: &mut Vec<PathBuf>,
+        entries: &mut Vec<Entry>,
+    ) -> arbitrary::Result<()> {
+        let mut i = 0;
+        // nested directories might make their parents non-empty, hence the recount
+        for _ in 0..self.min_empty_dirs * 4 {
+            if count_empty_dirs(root) >= self.min_empty_dirs {
+                break;
             }
-            let t = {
-                let t = SystemTime::now() + Duration::from_secs(60 * 60 * 24);
-                let dt = t.duration_since(SystemTime::UNIX_EPOCH).unwrap();
-                SystemTime::UNIX_EPOCH
-                    + Duration::new(
-                        u.int_in_range(0..=dt.as_secs())?,
-                        u.int_in_range(0..=999_999_999)?,
-                    )
+            let mut path = if dirs.is_empty() || u.arbitrary()? {
+                root.to_path_buf()
+            } else {
+                u.choose(&dirs[..])?.clone()
             };
-            match kind {
-                Regular => {
-                    let mode = u.int_in_range(0..=0o777)? | 0o400;
-                    let contents: Vec<u8> = u.arbitrary()?;
-                    let mut file = File::create(&path).unwrap();
-                    file.write_all(&contents).unwrap();
-                    file.set_permissions(Permissions::from_mode(mode)).unwrap();
-                    file.set_modified(t).unwrap();
-                }
-                Directory => {
-                    let mode = u.int_in_range(0..=0o777)? | 0o500;
-                    std::fs::DirBuilder::new()
-                        .mode(mode)
-                        .recursive(true)
-                        .create(&path)
-                        .unwrap();
-                    let path = path_to_c_string(path.clone()).unwrap();
-                    set_file_modified_time(&path, t).unwrap();
-                }
-                Fifo => {
-                    let mode = u.int_in_range(0..=0o777)? | 0o400;
-                    let path = path_to_c_string(path.clone()).unwrap();
-                    mkfifo(&path, mode).unwrap();
-                    set_file_modified_time(&path, t).unwrap();
-                }
-                Socket => {
-                    UnixDatagram::bind(&path).unwrap();
-                    let path = path_to_c_string(path.clone()).unwrap();
-                    set_file_modified_time(&path, t).unwrap();
-                }
-                #[allow(unused_unsafe)]
-                BlockDevice => {
-                    // dev loop
-                    let dev = unsafe { makedev(7, 0) };
-                    let mode = u.int_in_range(0o400..=0o777)?;
-                    let path = path_to_c_string(path.clone()).unwrap();
-                    mknod(&path, mode, dev).unwrap();
-                    set_file_modified_time(&path, t).unwrap();
-                }
-                CharDevice => {
-                    let dev = arbitrary_char_dev();
-                    let mode = u.int_in_range(0o400..=0o777)?;
-                    let path = path_to_c_string(path.clone()).unwrap();
-                    mknod(&path, mode, dev).unwrap();
-                    set_file_modified_time(&path, t).unwrap();
-                }
-                Symlink => {
-                    let original = u.choose(&files[..]).unwrap();
-                    symlink(original, &path).unwrap();
-                }
-                HardLink => {
-                    let original = u.choose(&files[..]).unwrap();
-                    assert!(
-                        hard_link(original, &path).is_ok(),
-                        "original = `{}`, path = `{}`",
-                        original.display(),
-                        path.display()
-                    );
+            let depth: usize = u.int_in_range(1..=4)?;
+            for _ in 0..depth {
+                let name: PathBuf = self.name_generator.generate(u)?.into();
+                match name.file_name() {
+                    Some(name) => {
+                        path.push(OsStr::from_bytes(truncate_name(
+                            name.as_bytes(),
+                            self.max_name_len,
+                        )));
+                    }
+                    None => path.push(format!("empty{}", i)),
                 }
+                i += 1;
             }
-            if kind != FileType::Directory {
-                files.push(path.clone());
+            if path.symlink_metadata().is_ok() {
+                path.push(format!("empty{}", i));
+                i += 1;
+            }
+            if path.as_os_str().len() >= PATH_MAX
+                || path.symlink_metadata().is_ok()
+                || create_dir_all(&path).is_err()
+            {
+                continue;
             }
+            let mode = u.int_in_range(0..=0o777)? | 0o500;
+            let t = arbitrary_mtime(u, self.mtime_range.as_ref())?;
+            std::fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+            let c_path = path_to_c_string(path.clone()).unwrap();
+            set_file_modified_time(&c_path, t).unwrap();
+            let relative_path = path.strip_prefix(root).unwrap();
+            entries.push(new_entry(relative_path, FileType::Directory, mode, t));
+            dirs.push(path);
+        }
+        Ok(())
+    }
+}
+
+fn count_empty_dirs(root: &Path) -> usize {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path() != root && entry.file_type().is_dir())
+        .filter(|entry| {
+            std::fs::read_dir(entry.path()).is_ok_and(|mut entries| entries.next().is_none())
+        })
+        .count()
+}
+
+/// The remaining no. of bytes that can be written (see [`DirBuilder::max_total_bytes`]).
+struct Budget {
+    remaining: u64,
+}
+
+impl Budget {
+    fn new(max_total_bytes: Option<u64>) -> Self {
+        Self {
+            remaining: max_total_bytes.unwrap_or(u64::MAX),
         }
-        Ok(Dir { dir })
+    }
+
+    /// Returns the size truncated to the remaining budget.
+    fn take(&mut self, size: u64) -> u64 {
+        let size = size.min(self.remaining);
+        self.remaining -= size;
+        size
     }
 }
 
+/// Size limits crossed by [`StressMode::HugeFiles`]: 32-bit size field and ustar size field.
+const HUGE_FILE_SIZE_LIMITS: [u64; 2] = [1 << 32, 1 << 33];
+
 impl Default for DirBuilder {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Environment variable that enables [`DirBuilder::keep_on_panic`] by default.
+pub const KEEP_ENV: &str = "RANDOM_DIR_KEEP";
+
 /// Directory with randomly generated contents.
 ///
 /// Automatically Deleted on drop.
 pub struct Dir {
-    dir: TempDir,
+    // `None` only after the directory was moved out
+    dir: Option<DirRoot>,
+    keep_on_panic: bool,
+    cleanup: Cleanup,
+    manifest: Manifest,
+    fifo_writers: Vec<FifoWriter>,
 }
 
 impl Dir {
     /// Get directory path.
     pub fn path(&self) -> &Path {
-        self.dir.path()
+        match self.dir.as_ref().unwrap() {
+            DirRoot::Temporary(dir) => dir.path(),
+            DirRoot::Existing(path) => path.as_path(),
+        }
+    }
+
+    /// Generated entries.
+    ///
+    /// Use this manifest to assert against the intended tree rather than re-scanning the file
+    /// system. The intermediate directories that were created implicitly are not included.
+    pub fn entries(&self) -> &[Entry] {
+        &self.manifest.entries[..]
+    }
+
+    /// Get the manifest describing the intended tree.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Entries crafted to hit archiver limits (see [`StressMode::HeaderLimits`]).
+    pub fn crafted_entries(&self) -> &[CraftedEntry] {
+        &self.manifest.crafted_entries[..]
+    }
+
+    /// Entries that were created with a different file type than requested.
+    pub fn downgrades(&self) -> &[Downgrade] {
+        &self.manifest.downgrades[..]
+    }
+
+    /// Generated names that the file system collapsed into the existing ones.
+    pub fn collapsed_names(&self) -> &[CollapsedName] {
+        &self.manifest.collapsed_names[..]
+    }
+
+    /// The paths of the symbolic links whose targets point outside the directory (see
+    /// [`DirBuilder::allow_escaping_symlinks`]).
+    pub fn escaping_symlinks(&self) -> &[PathBuf] {
+        &self.manifest.escaping_symlinks[..]
+    }
+
+    /// The bytes that the background thread writes to the named pipe (see
+    /// [`DirBuilder::fifo_writers`]).
+    ///
+    /// Returns `None` if the pipe doesn't have a writer.
+    pub fn fifo_contents<P: AsRef<Path>>(&self, path: P) -> Option<&[u8]> {
+        let path = path.as_ref();
+        self.fifo_writers
+            .iter()
+            .find(|writer| writer.path() == path)
+            .map(FifoWriter::contents)
+    }
+
+    /// Entries whose modification times fall into the age buckets (see
+    /// [`DirBuilder::age_buckets`]).
+    pub fn aged_entries(&self) -> &[AgedEntry] {
+        &self.manifest.aged_entries[..]
+    }
+
+    /// Actions that the policy under test is expected to take (see [`DirBuilder::policy`]).
+    pub fn expected_actions(&self) -> &[ExpectedAction] {
+        &self.manifest.expected_actions[..]
+    }
+
+    /// Get the guard that refuses to modify the files outside this directory.
+    pub fn safety_guard(&self) -> Result<SafetyGuard, Error> {
+        SafetyGuard::new(self.path())
+    }
+
+    /// Copy the tree to `path` faithfully: file types including devices and named pipes, modes,
+    /// modification times, hard link topology and (when running as root) ownership.
+    ///
+    /// The destination directory must not exist. Useful to apply the operation under test to
+    /// the copy and compare the result with the original.
+    pub fn clone_to<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        copy_dir_all(self.path(), path.as_ref(), false)
+    }
+
+    /// Same as [`clone_to`](Self::clone_to) but the regular files share the extents with the
+    /// originals (via `FICLONE`).
+    ///
+    /// Fails if the file system doesn't support reflinks (see
+    /// [`FsFeatures::reflinks`](crate::probe::FsFeatures::reflinks)) or `path` is on another file
+    /// system. Supported only on Linux. Use it together with
+    /// [`list_dir_all_with_shared_extents`] to test the tools that preserve or break the sharing.
+    pub fn clone_to_with_reflinks<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        copy_dir_all(self.path(), path.as_ref(), true)
+    }
+
+    /// Do not delete the directory on drop.
+    ///
+    /// Returns the directory path.
+    pub fn persist(mut self) -> PathBuf {
+        match self.dir.take().unwrap() {
+            DirRoot::Temporary(dir) => dir.keep(),
+            DirRoot::Existing(path) => path,
+        }
+    }
+
+    /// Change how the directory is deleted on drop (see [`DirBuilder::cleanup`]).
+    pub fn set_cleanup(&mut self, cleanup: Cleanup) {
+        self.cleanup = cleanup;
+    }
+
+    /// Delete the tree and measure how long it takes.
+    ///
+    /// The files are removed depth-first via `*at` system calls, read-only directories are made
+    /// writable on demand. Use the report to compare the teardown cost of different stress modes.
+    /// For the directories populated via [`DirBuilder::create_in`] only the generated entries are
+    /// removed. The paths are checked by the [`safety_guard`](Self::safety_guard) first.
+    pub fn delete_benchmark(mut self) -> Result<DeleteReport, Error> {
+        #[cfg(all(target_os = "linux", feature = "chattr"))]
+        clear_inode_flags(self.path(), &self.manifest.entries);
+        let guard = self.safety_guard()?;
+        match self.dir.take().unwrap() {
+            DirRoot::Temporary(dir) => delete_temporary_dir(&dir.keep()),
+            DirRoot::Existing(path) => delete_entries(&path, &self.manifest.entries, &guard),
+        }
+    }
+
+    /// Serve the tree via FUSE at `mountpoint` (see [`Vfs::mount_fuse`]).
+    ///
+    /// The tree is read from the directory. The downgraded entries (see
+    /// [`downgrades`](Self::downgrades)) are served with the requested file type and device
+    /// number, i.e. the mounted tree contains the device files even if the directory was
+    /// generated without privileges.
+    #[cfg(all(target_os = "linux", feature = "fuse"))]
+    pub fn mount_fuse<P: AsRef<Path>>(&self, mountpoint: P) -> Result<FuseMount, Error> {
+        let mut vfs = Vfs::from_dir(self.path())?;
+        for downgrade in self.downgrades() {
+            vfs.set_file_type(&downgrade.path, downgrade.requested, downgrade.rdev)?;
+        }
+        vfs.mount_fuse(mountpoint)
     }
 
     /// Transform into inner representation.
-    pub fn into_inner(self) -> TempDir {
-        self.dir
+    ///
+    /// # Panics
+    ///
+    /// Panics if the directory was created via [`DirBuilder::create_in`].
+    pub fn into_inner(mut self) -> TempDir {
+        match self.dir.take().unwrap() {
+            DirRoot::Temporary(dir) => dir,
+            DirRoot::Existing(path) => panic!("`{}` is not a temporary directory", path.display()),
+        }
+    }
+}
+
+/// Directory that is being generated on a background thread (see [`DirBuilder::spawn`]).
+pub struct DirHandle {
+    thread: JoinHandle<arbitrary::Result<Dir>>,
+}
+
+impl DirHandle {
+    /// Wait for the generation to finish.
+    ///
+    /// Panics if the generating thread panicked.
+    pub fn wait(self) -> arbitrary::Result<Dir> {
+        self.thread
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+
+    /// Returns `true` if the generation has finished.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+}
+
+enum DirRoot {
+    Temporary(TempDir),
+    Existing(PathBuf),
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        // the writers need the pipes to stop
+        self.fifo_writers.clear();
+        if self.keep_on_panic && std::thread::panicking() {
+            if let Some(DirRoot::Temporary(dir)) = self.dir.take() {
+                let path = dir.keep();
+                eprintln!("random-dir: keeping `{}`", path.display());
+            }
+        }
+        let Some(DirRoot::Temporary(dir)) = self.dir.take() else {
+            return;
+        };
+        #[cfg(all(target_os = "linux", feature = "chattr"))]
+        if self.cleanup != Cleanup::Leave {
+            clear_inode_flags(dir.path(), &self.manifest.entries);
+        }
+        match self.cleanup {
+            Cleanup::Remove => {
+                // the generated directories might be read-only
+                let _ = delete_temporary_dir(&dir.keep());
+            }
+            Cleanup::Background => {
+                let path = move_to_trash(dir.keep());
+                std::thread::spawn(move || {
+                    let _ = delete_temporary_dir(&path);
+                });
+            }
+            Cleanup::Leave => {
+                let path = dir.keep();
+                eprintln!("random-dir: leaving `{}`", path.display());
+            }
+        }
+    }
+}
+
+/// Delete the temporary directory unless the safety guard refuses, e.g. if the directory was
+/// replaced with a symlink.
+fn delete_temporary_dir(path: &Path) -> Result<DeleteReport, Error> {
+    SafetyGuard::new(path)?.check(path)?;
+    delete_dir_all(path, true)
+}
+
+/// Rename the directory to a hidden sibling to free the original path immediately.
+///
+/// Returns the new path or the original path if the directory can't be renamed.
+fn move_to_trash(path: PathBuf) -> PathBuf {
+    let Some(name) = path.file_name() else {
+        return path;
+    };
+    let mut trash_name = OsString::from(".");
+    trash_name.push(name);
+    trash_name.push(".trash");
+    let trash = path.with_file_name(trash_name);
+    match rename(&path, &trash) {
+        Ok(()) => trash,
+        Err(_) => path,
     }
 }
 
@@ -221,74 +3215,66 @@ impl<'a> Arbitrary<'a> for Dir {
     }
 }
 
-/// File type.
-#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Copy)]
-pub enum FileType {
-    /// Regular file.
-    Regular,
-    /// A directory.
-    Directory,
-    /// Named pipe.
-    Fifo,
-    /// UNIX socket.
-    Socket,
-    /// Block device.
-    BlockDevice,
-    /// Character device.
-    CharDevice,
-    /// Symbolic link.
-    Symlink,
-    /// Hard link.
-    HardLink,
-}
-
-/// All file types supported by the platform.
-pub const ALL_FILE_TYPES: [FileType; 8] = {
-    use FileType::*;
-    [
-        Regular,
-        Directory,
-        Fifo,
-        Socket,
-        BlockDevice,
-        CharDevice,
-        Symlink,
-        HardLink,
-    ]
-};
-
 /// Recursively list specified directory.
 ///
 /// This function always returns the same entries in the same order for the same directory.
 /// It also remaps inodes to make listings of the two directories conataining the same files
 /// consistent.
 ///
+/// The directory is opened once and then traversed via `openat`/`fstatat` relative to the
+/// directory descriptors, i.e. the trees deeper than [`PATH_MAX`] are listed as well. If the
+/// directory itself is a symbolic link, the directory it points to is listed; the symbolic links
+/// inside the directory are never followed.
+///
 /// The intended usage is to compare the contents (files and metadata) of the two directories.
 pub fn list_dir_all<P: AsRef<Path>>(dir: P) -> Result<Vec<FileInfo>, Error> {
-    let dir = dir.as_ref();
+    list_dir_all_with_hook(dir.as_ref(), false, None, &mut || {})
+}
+
+/// Same as [`list_dir_all`] but also records the order in which `readdir` returned the entries
+/// of each directory.
+///
+/// Use it to test that the tool under test preserves the directory entry order or sorts the
+/// entries deliberately (see [`ReaddirOrder`]).
+pub fn list_dir_all_with_readdir_order<P: AsRef<Path>>(
+    dir: P,
+) -> Result<(Vec<FileInfo>, ReaddirOrder), Error> {
+    let mut order = ReaddirOrder::default();
+    let files = list_dir_all_with_hook(dir.as_ref(), false, Some(&mut order), &mut || {})?;
+    Ok((files, order))
+}
+
+/// Same as [`list_dir_all`] but the regular files larger than [`MAX_DENSE_FILE_SIZE`] are not
+/// read in full.
+///
+/// The contents of such files are replaced with the digest of the blocks at the evenly spaced
+/// offsets including the first and the last block (see [`default_hash_algorithm`]). Together with
+/// the file size this keeps the round-trip tests of multi-gigabyte files (see
+/// [`DirBuilder::huge_files`]) feasible. Compare only the listings that were obtained with this
+/// function: the sampled contents never match the full ones.
+pub fn list_dir_all_sampled<P: AsRef<Path>>(dir: P) -> Result<Vec<FileInfo>, Error> {
+    list_dir_all_with_hook(dir.as_ref(), true, None, &mut || {})
+}
+
+/// Same as [`list_dir_all`] but calls `hook` before listing each file, optionally samples the
+/// contents of large files (see [`list_dir_all_sampled`]) and records `readdir` order.
+pub(crate) fn list_dir_all_with_hook(
+    dir: &Path,
+    sampled: bool,
+    order: Option<&mut ReaddirOrder>,
+    hook: &mut dyn FnMut(),
+) -> Result<Vec<FileInfo>, Error> {
+    let root = File::open(dir)?;
     let mut files = Vec::new();
-    for entry in WalkDir::new(dir).into_iter() {
-        let entry = entry?;
-        if entry.path() == dir {
-            continue;
-        }
-        let metadata = entry.path().symlink_metadata()?;
-        let contents = if metadata.is_file() {
-            std::fs::read(entry.path()).unwrap()
-        } else if metadata.is_symlink() {
-            let target = read_link(entry.path()).unwrap();
-            target.as_os_str().as_bytes().to_vec()
-        } else {
-            Vec::new()
-        };
-        let path = entry.path().strip_prefix(dir).map_err(Error::other)?;
-        let metadata: Metadata = (&metadata).try_into()?;
-        files.push(FileInfo {
-            path: path.to_path_buf(),
-            metadata,
-            contents,
-        });
-    }
+    list_dir_at(
+        dir,
+        root.as_fd(),
+        Path::new(""),
+        sampled,
+        order,
+        hook,
+        &mut files,
+    )?;
     files.sort_by(|a, b| a.path.cmp(&b.path));
     // remap inodes
     use std::collections::hash_map::Entry::*;
@@ -310,67 +3296,416 @@ pub fn list_dir_all<P: AsRef<Path>>(dir: P) -> Result<Vec<FileInfo>, Error> {
     Ok(files)
 }
 
-/// File's path, metadata and contents.
-#[derive(PartialEq, Eq, Debug, Clone)]
-pub struct FileInfo {
-    /// Path.
-    pub path: PathBuf,
-    /// Metadata.
-    pub metadata: Metadata,
-    /// File contents.
-    pub contents: Vec<u8>,
-}
-
-/// File's metadata.
-#[derive(PartialEq, Eq, Clone, Debug)]
-pub struct Metadata {
-    /// Containing device number.
-    pub dev: u64,
-    /// Inode.
-    pub ino: u64,
-    /// File mode.
-    pub mode: u32,
-    /// Owner's user id.
-    pub uid: u32,
-    /// Owner's group id.
-    pub gid: u32,
-    /// No. of hard links.
-    pub nlink: u32,
-    /// Device number of the file itself.
-    pub rdev: u64,
-    /// Last modification time.
-    pub mtime: u64,
-    /// File size in bytes.
-    pub file_size: u64,
-}
-
-impl TryFrom<&std::fs::Metadata> for Metadata {
-    type Error = Error;
-    fn try_from(other: &std::fs::Metadata) -> Result<Self, Error> {
-        use std::os::unix::fs::MetadataExt;
-        Ok(Self {
-            dev: other.dev(),
-            ino: other.ino(),
-            mode: other.mode(),
-            uid: other.uid(),
-            gid: other.gid(),
-            nlink: other.nlink() as u32,
-            rdev: other.rdev(),
-            mtime: other.mtime() as u64,
-            file_size: other.size(),
-        })
+/// List the directory `prefix` relative to `dirfd` recursively.
+#[cfg_attr(
+    not(all(
+        target_os = "linux",
+        any(feature = "acl", feature = "selinux", feature = "chattr")
+    )),
+    allow(clippy::only_used_in_recursion)
+)]
+fn list_dir_at(
+    root: &Path,
+    dirfd: BorrowedFd<'_>,
+    prefix: &Path,
+    sampled: bool,
+    mut order: Option<&mut ReaddirOrder>,
+    hook: &mut dyn FnMut(),
+    files: &mut Vec<FileInfo>,
+) -> Result<(), Error> {
+    let mut names = read_dir_at(dirfd)?;
+    if let Some(order) = order.as_deref_mut() {
+        order.dirs.insert(
+            prefix.to_path_buf(),
+            names
+                .iter()
+                .map(|(name, _)| OsStr::from_bytes(name.to_bytes()).to_os_string())
+                .collect(),
+        );
+    }
+    names.sort();
+    for (name, _) in names.into_iter() {
+        hook();
+        let path = prefix.join(OsStr::from_bytes(name.to_bytes()));
+        let stat = stat_at(dirfd, &name)?;
+        let file_type = stat.st_mode & libc::S_IFMT;
+        let contents = match file_type {
+            libc::S_IFREG if sampled && stat.st_size as u64 > MAX_DENSE_FILE_SIZE => {
+                read_samples(&open_file_at(dirfd, &name)?, stat.st_size as u64)?
+            }
+            libc::S_IFREG => {
+                let mut contents = Vec::new();
+                open_file_at(dirfd, &name)?.read_to_end(&mut contents)?;
+                contents
+            }
+            libc::S_IFLNK => read_link_at(dirfd, &name)?.into_os_string().into_vec(),
+            _ => Vec::new(),
+        };
+        let metadata = Metadata::from_stat(&stat);
+        #[cfg(all(
+            target_os = "linux",
+            any(feature = "acl", feature = "selinux", feature = "chattr")
+        ))]
+        let xattr_path = xattr_path(root, dirfd, &path, &name);
+        #[cfg(all(target_os = "linux", feature = "acl"))]
+        let metadata = Metadata {
+            acl: read_acl(&xattr_path)?,
+            ..metadata
+        };
+        #[cfg(all(target_os = "linux", feature = "selinux"))]
+        let metadata = Metadata {
+            selinux_context: read_selinux_context(&xattr_path)?,
+            ..metadata
+        };
+        #[cfg(all(target_os = "linux", feature = "chattr"))]
+        let metadata = Metadata {
+            inode_flags: read_inode_flags(&xattr_path)?,
+            ..metadata
+        };
+        files.push(FileInfo {
+            path: path.clone(),
+            metadata,
+            contents,
+        });
+        if file_type == libc::S_IFDIR {
+            let dir = open_dir_at(dirfd, &name)?;
+            list_dir_at(
+                root,
+                dir.as_fd(),
+                &path,
+                sampled,
+                order.as_deref_mut(),
+                hook,
+                files,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Digest of [`NUM_CONTENT_SAMPLES`] blocks spread evenly across the file.
+fn read_samples(file: &File, size: u64) -> Result<Vec<u8>, Error> {
+    let mut hasher = default_hash_algorithm().hasher();
+    let mut block = [0_u8; CONTENT_SAMPLE_LEN];
+    let last_offset = size.saturating_sub(CONTENT_SAMPLE_LEN as u64);
+    for i in 0..NUM_CONTENT_SAMPLES {
+        let offset = (last_offset as u128 * i as u128 / (NUM_CONTENT_SAMPLES - 1) as u128) as u64;
+        file.read_exact_at(&mut block, offset)?;
+        hasher.update(&block);
+    }
+    Ok(hasher.finalize().0)
+}
+
+/// The path of the file that is suitable for path-based system calls.
+///
+/// The paths longer than `PATH_MAX` are accessed via `/proc/self/fd`.
+#[cfg(all(
+    target_os = "linux",
+    any(feature = "acl", feature = "selinux", feature = "chattr")
+))]
+fn xattr_path(root: &Path, dirfd: BorrowedFd<'_>, path: &Path, name: &CStr) -> PathBuf {
+    let path = root.join(path);
+    if path.as_os_str().len() < PATH_MAX {
+        return path;
+    }
+    Path::new("/proc/self/fd")
+        .join(dirfd.as_raw_fd().to_string())
+        .join(OsStr::from_bytes(name.to_bytes()))
+}
+
+#[cfg(all(target_os = "linux", feature = "acl"))]
+pub(crate) fn read_acl(path: &Path) -> Result<Acl, Error> {
+    let c_path = path_to_c_string(path.to_path_buf())?;
+    let mut acl = Acl::default();
+    for (name, entries) in [
+        (ACL_ACCESS_XATTR, &mut acl.access),
+        (ACL_DEFAULT_XATTR, &mut acl.default),
+    ] {
+        let c_name = os_str_to_c_string(OsStr::new(name))?;
+        if let Some(value) = get_xattr(&c_path, &c_name)? {
+            *entries = decode_acl(&value)?;
+        }
+    }
+    Ok(acl)
+}
+
+#[cfg(all(target_os = "linux", feature = "selinux"))]
+pub(crate) fn read_selinux_context(path: &Path) -> Result<Vec<u8>, Error> {
+    let c_path = path_to_c_string(path.to_path_buf())?;
+    let c_name = os_str_to_c_string(OsStr::new(SELINUX_XATTR))?;
+    let mut context = get_xattr(&c_path, &c_name)?.unwrap_or_default();
+    if context.last() == Some(&0) {
+        context.pop();
+    }
+    Ok(context)
+}
+
+/// Returns zero for the files other than regular files and directories.
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+pub(crate) fn read_inode_flags(path: &Path) -> Result<u32, Error> {
+    let metadata = path.symlink_metadata()?;
+    if !metadata.is_file() && !metadata.is_dir() {
+        return Ok(0);
+    }
+    let c_path = path_to_c_string(path.to_path_buf())?;
+    Ok(get_inode_flags(&c_path)? & (FS_IMMUTABLE_FL | FS_APPEND_FL))
+}
+
+/// Same as [`list_dir_all`] but temporarily grants the owner read access to the files and
+/// directories (and search access to the directories) that deny it.
+///
+/// The original permissions are restored afterwards and are reported in the listing. Useful to
+/// list the trees after the tool under test has changed the permissions. Fails if the
+/// permissions can't be changed, i.e. the files are owned by another user.
+pub fn list_dir_all_with_access<P: AsRef<Path>>(dir: P) -> Result<Vec<FileInfo>, Error> {
+    let dir = dir.as_ref();
+    let mut granted = Vec::new();
+    let result = grant_access(dir, &mut granted).and_then(|_| list_dir_all(dir));
+    // children first, the parents might deny the search access
+    let mut restored = Ok(());
+    for (path, mode) in granted.iter().rev() {
+        let r = std::fs::set_permissions(path, Permissions::from_mode(*mode));
+        restored = restored.and(r);
+    }
+    let mut files = result?;
+    restored?;
+    let modes: HashMap<&Path, u32> = granted
+        .iter()
+        .filter_map(|(path, mode)| Some((path.strip_prefix(dir).ok()?, *mode)))
+        .collect();
+    for file in files.iter_mut() {
+        if let Some(mode) = modes.get(file.path.as_path()) {
+            file.metadata.mode = (file.metadata.mode & !0o7777) | mode;
+        }
     }
+    Ok(files)
+}
+
+/// Same as [`list_dir_all`] but also lists the birth times (see [`Metadata::btime`]).
+///
+/// The times are obtained via `statx` on Linux and `st_birthtime` on MacOS and BSD. Use it to
+/// test the formats that preserve the creation times, e.g. pax archives and NTFS extra fields in
+/// zip archives.
+pub fn list_dir_all_with_btime<P: AsRef<Path>>(dir: P) -> Result<Vec<FileInfo>, Error> {
+    let dir = dir.as_ref();
+    let root = File::open(dir)?;
+    let mut files = list_dir_all(dir)?;
+    for file in files.iter_mut() {
+        let (parent, name) = open_parent_at(root.as_fd(), &file.path)?;
+        file.metadata.btime = btime_at(parent.as_fd(), &name)?;
+    }
+    Ok(files)
+}
+
+/// Same as [`list_dir_all`] but also lists the status change times (see [`Metadata::ctime`]).
+///
+/// Use it together with [`assert_metadata_untouched`](crate::assert_metadata_untouched).
+pub fn list_dir_all_with_ctime<P: AsRef<Path>>(dir: P) -> Result<Vec<FileInfo>, Error> {
+    let dir = dir.as_ref();
+    let root = File::open(dir)?;
+    let mut files = list_dir_all(dir)?;
+    for file in files.iter_mut() {
+        let stat = stat_path_at(root.as_fd(), &file.path)?;
+        #[allow(clippy::unnecessary_cast)]
+        let ctime = from_unix_time(stat.st_ctime as i64, stat.st_ctime_nsec as u32);
+        file.metadata.ctime = Some(ctime);
+    }
+    Ok(files)
+}
+
+/// Same as [`list_dir_all`] but also lists whether the regular files share the extents with other
+/// files (see [`Metadata::shared_extents`]).
+///
+/// The extents are obtained via `FIEMAP` on Linux, on other platforms nothing is listed.
+pub fn list_dir_all_with_shared_extents<P: AsRef<Path>>(dir: P) -> Result<Vec<FileInfo>, Error> {
+    let dir = dir.as_ref();
+    let mut files = list_dir_all(dir)?;
+    #[cfg(target_os = "linux")]
+    {
+        let root = File::open(dir)?;
+        for file in files.iter_mut() {
+            if file.metadata.mode & libc::S_IFMT != libc::S_IFREG {
+                continue;
+            }
+            let (parent, name) = open_parent_at(root.as_fd(), &file.path)?;
+            let f = open_file_at(parent.as_fd(), &name)?;
+            if !f.metadata()?.is_file() {
+                // replaced after the listing
+                continue;
+            }
+            file.metadata.shared_extents = Some(has_shared_extents(f.as_fd())?);
+        }
+    }
+    Ok(files)
+}
+
+/// Same as [`list_dir_all`] but also lists the no. of allocated blocks (see
+/// [`Metadata::blocks`]).
+///
+/// Use it together with [`assert_sparseness_preserved`](crate::assert_sparseness_preserved).
+pub fn list_dir_all_with_blocks<P: AsRef<Path>>(dir: P) -> Result<Vec<FileInfo>, Error> {
+    let dir = dir.as_ref();
+    let root = File::open(dir)?;
+    let mut files = list_dir_all(dir)?;
+    for file in files.iter_mut() {
+        let stat = stat_path_at(root.as_fd(), &file.path)?;
+        file.metadata.blocks = Some(stat.st_blocks as u64);
+    }
+    Ok(files)
+}
+
+/// Add the missing permissions and record the original ones.
+fn grant_access(path: &Path, granted: &mut Vec<(PathBuf, u32)>) -> Result<(), Error> {
+    let metadata = path.symlink_metadata()?;
+    let mode = metadata.permissions().mode() & 0o7777;
+    let required = if metadata.is_dir() {
+        0o500
+    } else if metadata.is_file() {
+        0o400
+    } else {
+        return Ok(());
+    };
+    if mode & required != required {
+        std::fs::set_permissions(path, Permissions::from_mode(mode | required))?;
+        granted.push((path.to_path_buf(), mode));
+    }
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            grant_access(&entry?.path(), granted)?;
+        }
+    }
+    Ok(())
 }
 
+/// The maximum no. of hard links in a cluster (see [`StressMode::ManyHardLinks`]).
+const MAX_LINK_CLUSTER_LEN: u64 = 256;
+
+/// The limit for [`DirBuilder::require_all_types`] in case the spec is exhausted.
+const MAX_EXTRA_ATTEMPTS: usize = 64;
+
+/// No. of blocks read by [`list_dir_all_sampled`] from each large file.
+const NUM_CONTENT_SAMPLES: usize = 64;
+
+/// The size of each block read by [`list_dir_all_sampled`].
+const CONTENT_SAMPLE_LEN: usize = 4096;
+
+/// Absolute targets of the escaping symbolic links.
+const ESCAPING_TARGETS: [&str; 6] = [
+    "/",
+    "/etc",
+    "/etc/hostname",
+    "/etc/passwd",
+    "/tmp",
+    "/dev/null",
+];
+
+/// The kernel stores 12-bit major and 20-bit minor device numbers.
+#[cfg(not(target_os = "macos"))]
+const MAX_DEVICE_MAJOR: u32 = (1 << 12) - 1;
+#[cfg(not(target_os = "macos"))]
+const MAX_DEVICE_MINOR: u32 = (1 << 20) - 1;
+
+/// The kernel stores 8-bit major and 24-bit minor device numbers.
+#[cfg(target_os = "macos")]
+const MAX_DEVICE_MAJOR: u32 = (1 << 8) - 1;
+#[cfg(target_os = "macos")]
+const MAX_DEVICE_MINOR: u32 = (1 << 24) - 1;
+
 #[allow(unused_unsafe)]
 #[cfg(target_os = "linux")]
-fn arbitrary_char_dev() -> dev_t {
+pub(crate) fn arbitrary_char_dev() -> dev_t {
     // /dev/null
     makedev(1, 3)
 }
 
 #[cfg(target_os = "macos")]
-fn arbitrary_char_dev() -> dev_t {
+pub(crate) fn arbitrary_char_dev() -> dev_t {
     // /dev/null
     unsafe { makedev(3, 2) }
 }
+
+#[cfg(test)]
+mod tests {
+    use arbtest::arbtest;
+
+    use super::*;
+    use crate::diff_dirs;
+
+    #[test]
+    fn identical_trees_have_no_differences() {
+        arbtest(|u| {
+            let (first, second) = DirBuilder::new().create_pair(TreeRelation::Identical, u)?;
+            assert_eq!(first.entries(), second.entries());
+            let diff = diff_dirs(first.path(), second.path()).unwrap();
+            assert!(diff.is_empty(), "{}", diff);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn disabling_names_keeps_the_generator() {
+        arbtest(|u| {
+            let spec = DirBuilder::new()
+                .printable_names(true)
+                .colliding_names(false)
+                .hostile_names(false)
+                .create_spec(u)?;
+            for entry in spec.entries() {
+                let path = entry.path.as_os_str().as_bytes();
+                assert!(
+                    path.iter().all(|b| b.is_ascii_lowercase() || *b == b'/'),
+                    "{}",
+                    entry.path.display()
+                );
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn delete_benchmark_keeps_existing_files() {
+        arbtest(|u| {
+            let workdir = TempDir::new().unwrap();
+            std::fs::create_dir(workdir.path().join("existing")).unwrap();
+            std::fs::write(workdir.path().join("existing/keep"), "keep").unwrap();
+            let dir = DirBuilder::new().create_in(workdir.path(), u)?;
+            dir.delete_benchmark().unwrap();
+            let files = list_dir_all(workdir.path()).unwrap();
+            let paths: Vec<&Path> = files.iter().map(|file| file.path.as_path()).collect();
+            assert_eq!(
+                vec![Path::new("existing"), Path::new("existing/keep")],
+                paths
+            );
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn drop_does_not_follow_replaced_root() {
+        let workdir = TempDir::new().unwrap();
+        let victim = workdir.path().join("victim");
+        std::fs::create_dir(&victim).unwrap();
+        std::fs::write(victim.join("keep"), "keep").unwrap();
+        for cleanup in [Cleanup::Remove, Cleanup::Background] {
+            let mut dir = DirBuilder::new()
+                .create(&mut Unstructured::new(&[]))
+                .unwrap();
+            dir.set_cleanup(cleanup);
+            let path = dir.path().to_path_buf();
+            rename(&path, workdir.path().join("moved")).unwrap();
+            std::os::unix::fs::symlink(&victim, &path).unwrap();
+            drop(dir);
+            // wait for the background thread
+            std::thread::sleep(Duration::from_millis(100));
+            assert!(victim.join("keep").exists(), "{:?}", cleanup);
+            // the background cleanup renames the symlink
+            let mut trash_name = OsString::from(".");
+            trash_name.push(path.file_name().unwrap());
+            trash_name.push(".trash");
+            std::fs::remove_file(&path)
+                .or_else(|_| std::fs::remove_file(path.with_file_name(trash_name)))
+                .unwrap();
+            delete_dir_all(&workdir.path().join("moved"), true).unwrap();
+        }
+    }
+}
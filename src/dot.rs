@@ -0,0 +1,123 @@
+use std::collections::BTreeSet;
+use std::io::Error;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::Entry;
+use crate::FileType;
+use crate::Manifest;
+
+impl Manifest {
+    /// Write the graph of hard links and symbolic links in Graphviz DOT format.
+    ///
+    /// Only the links and their targets are included. Hard links point to the original files
+    /// with solid edges, symbolic links point to the resolved targets with dashed edges. The
+    /// targets that are missing from the manifest are drawn in red, the targets outside the
+    /// directory (absolute or escaping via `..`) are drawn as plain text. Render the graph with
+    /// `dot -Tsvg` to debug inode-tracking bugs.
+    pub fn write_link_graph<W: Write>(&self, writer: W) -> Result<(), Error> {
+        write_link_graph(&self.entries, writer)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl crate::Dir {
+    /// Write the graph of hard links and symbolic links (see [`Manifest::write_link_graph`]).
+    pub fn write_link_graph<W: Write>(&self, writer: W) -> Result<(), Error> {
+        self.manifest().write_link_graph(writer)
+    }
+}
+
+fn write_link_graph<W: Write>(entries: &[Entry], mut writer: W) -> Result<(), Error> {
+    let paths: BTreeSet<&Path> = entries.iter().map(|entry| entry.path.as_path()).collect();
+    let mut nodes = BTreeSet::new();
+    let mut missing = BTreeSet::new();
+    let mut external = BTreeSet::new();
+    let mut edges = Vec::new();
+    for entry in entries.iter() {
+        let Some(target) = entry.target.as_deref() else {
+            continue;
+        };
+        let (resolved, style) = match entry.file_type {
+            FileType::HardLink => (Some(target.to_path_buf()), "solid"),
+            FileType::Symlink => (resolve(&entry.path, target), "dashed"),
+            _ => continue,
+        };
+        let target = match resolved {
+            Some(target) => {
+                // the empty path is the root
+                if !target.as_os_str().is_empty() && !paths.contains(target.as_path()) {
+                    missing.insert(target.clone());
+                }
+                nodes.insert(target.clone());
+                target
+            }
+            None => {
+                let label = target_label(target);
+                external.insert(label.clone());
+                label
+            }
+        };
+        nodes.insert(entry.path.clone());
+        edges.push((entry.path.clone(), target, style));
+    }
+    writeln!(writer, "digraph links {{")?;
+    writeln!(writer, "    node [shape=box];")?;
+    for node in nodes.iter() {
+        if missing.contains(node) {
+            writeln!(writer, "    {} [color=red];", quote(node))?;
+        } else {
+            writeln!(writer, "    {};", quote(node))?;
+        }
+    }
+    for node in external.iter() {
+        writeln!(writer, "    {} [shape=plaintext];", quote(node))?;
+    }
+    for (from, to, style) in edges.iter() {
+        writeln!(
+            writer,
+            "    {} -> {} [style={}];",
+            quote(from),
+            quote(to),
+            style
+        )?;
+    }
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+/// Resolve the symbolic link target relative to the directory root lexically.
+///
+/// Returns `None` if the target is absolute or escapes the root.
+fn resolve(link: &Path, target: &Path) -> Option<PathBuf> {
+    let mut resolved = PathBuf::new();
+    for component in link.parent()?.components().chain(target.components()) {
+        match component {
+            Component::Normal(name) => resolved.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(..) => return None,
+        }
+    }
+    Some(resolved)
+}
+
+/// Distinguish the external targets from the paths inside the directory.
+fn target_label(target: &Path) -> PathBuf {
+    let mut label = PathBuf::from("target: ");
+    label.as_mut_os_string().push(target);
+    label
+}
+
+fn quote(path: &Path) -> String {
+    let path = path.as_os_str().as_bytes();
+    let path = if path.is_empty() { &b"."[..] } else { path };
+    format!("\"{}\"", path.escape_ascii())
+}
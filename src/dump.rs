@@ -0,0 +1,228 @@
+use std::io::Error;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+
+use crate::civil_from_days;
+use crate::list_dir_all_sampled;
+use crate::split_device_number;
+use crate::Dir;
+use crate::FileInfo;
+
+impl Dir {
+    /// Print the tree as `ls -l`-like text, one file per line (see [`write_tree`]).
+    ///
+    /// Use it to understand failing test cases at a glance.
+    pub fn dump_tree<W: Write>(&self, writer: W) -> Result<(), Error> {
+        write_tree(&list_dir_all_sampled(self.path())?, writer)
+    }
+
+    /// Print the tree as JSON array of objects, one object per file (see [`write_tree_json`]).
+    pub fn dump_tree_json<W: Write>(&self, writer: W) -> Result<(), Error> {
+        write_tree_json(&list_dir_all_sampled(self.path())?, writer)
+    }
+}
+
+/// Print the listing as `ls -l`-like text, one file per line.
+///
+/// Each line contains the file type, the permission bits in octal, the owner, the no. of hard
+/// links, the size (or the device number), the modification time in UTC, the path and the
+/// symbolic link target. Non-printable bytes of the paths are escaped.
+pub fn write_tree<W: Write>(files: &[FileInfo], mut writer: W) -> Result<(), Error> {
+    for file in files.iter() {
+        let metadata = &file.metadata;
+        let size = match metadata.mode & libc::S_IFMT {
+            libc::S_IFBLK | libc::S_IFCHR => {
+                let (major, minor) = split_device_number(metadata.rdev);
+                format!("{},{}", major, minor)
+            }
+            _ => metadata.file_size.to_string(),
+        };
+        write!(
+            writer,
+            "{} {:04o} {}:{} {:>3} {:>12} {} {}",
+            file_type_char(metadata.mode),
+            metadata.mode & 0o7777,
+            metadata.uid,
+            metadata.gid,
+            metadata.nlink,
+            size,
+            format_utc(metadata.mtime),
+            file.path.as_os_str().as_bytes().escape_ascii(),
+        )?;
+        if let Some(target) = symlink_target(file) {
+            write!(writer, " -> {}", target.escape_ascii())?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Print the listing as JSON array of objects, one object per file.
+///
+/// The objects have the same fields as the lines of [`write_tree`]; the modification time is in
+/// seconds since Unix epoch. The paths and the targets that are not valid UTF-8 are printed with
+/// the invalid bytes replaced by `U+FFFD`, and their raw bytes are printed as an array of
+/// numbers in the separate `path_bytes` and `target_bytes` fields.
+pub fn write_tree_json<W: Write>(files: &[FileInfo], mut writer: W) -> Result<(), Error> {
+    writeln!(writer, "[")?;
+    for (i, file) in files.iter().enumerate() {
+        let metadata = &file.metadata;
+        write!(
+            writer,
+            "  {{{}, \"type\": \"{}\", \"mode\": \"{:04o}\", \"uid\": {}, \
+                 \"gid\": {}, \"nlink\": {}, \"size\": {}, \"rdev\": {}, \"mtime\": {}",
+            json_path("path", file.path.as_os_str().as_bytes()),
+            file_type_name(metadata.mode),
+            metadata.mode & 0o7777,
+            metadata.uid,
+            metadata.gid,
+            metadata.nlink,
+            metadata.file_size,
+            metadata.rdev,
+            metadata.mtime,
+        )?;
+        if let Some(target) = symlink_target(file) {
+            write!(writer, ", {}", json_path("target", target))?;
+        }
+        let comma = if i + 1 == files.len() { "" } else { "," };
+        writeln!(writer, "}}{}", comma)?;
+    }
+    writeln!(writer, "]")?;
+    Ok(())
+}
+
+fn symlink_target(file: &FileInfo) -> Option<&[u8]> {
+    match file.metadata.mode & libc::S_IFMT {
+        libc::S_IFLNK => Some(&file.contents[..]),
+        _ => None,
+    }
+}
+
+fn file_type_char(mode: u32) -> char {
+    match mode & libc::S_IFMT {
+        libc::S_IFDIR => 'd',
+        libc::S_IFLNK => 'l',
+        libc::S_IFIFO => 'p',
+        libc::S_IFSOCK => 's',
+        libc::S_IFBLK => 'b',
+        libc::S_IFCHR => 'c',
+        _ => '-',
+    }
+}
+
+fn file_type_name(mode: u32) -> &'static str {
+    match mode & libc::S_IFMT {
+        libc::S_IFDIR => "directory",
+        libc::S_IFLNK => "symlink",
+        libc::S_IFIFO => "fifo",
+        libc::S_IFSOCK => "socket",
+        libc::S_IFBLK => "block-device",
+        libc::S_IFCHR => "char-device",
+        _ => "regular",
+    }
+}
+
+/// Format the time as `YYYY-MM-DD hh:mm:ss` in UTC.
+fn format_utc(secs: i64) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECONDS_PER_DAY));
+    let secs = secs.rem_euclid(SECONDS_PER_DAY);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Format the path as `"key": "..."` field followed by `"key_bytes": [...]` field if the path is
+/// not valid UTF-8.
+fn json_path(key: &str, bytes: &[u8]) -> String {
+    let mut s = format!("\"{}\": {}", key, json_string(bytes));
+    if std::str::from_utf8(bytes).is_err() {
+        s.push_str(&format!(", \"{}_bytes\": [", key));
+        for (i, byte) in bytes.iter().enumerate() {
+            if i != 0 {
+                s.push_str(", ");
+            }
+            s.push_str(&byte.to_string());
+        }
+        s.push(']');
+    }
+    s
+}
+
+fn json_string(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() + 2);
+    s.push('"');
+    for ch in String::from_utf8_lossy(bytes).chars() {
+        match ch {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            ch if (ch as u32) < 0x20 || ch == '\u{7f}' => {
+                s.push_str(&format!("\\u{:04x}", ch as u32));
+            }
+            ch => s.push(ch),
+        }
+    }
+    s.push('"');
+    s
+}
+
+const SECONDS_PER_DAY: i64 = 60 * 60 * 24;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_strings() {
+        for (bytes, expected) in [
+            (&b"abc"[..], r#""abc""#),
+            ("ü".as_bytes(), r#""ü""#),
+            (b"a\"b", r#""a\"b""#),
+            (b"\n\x01", r#""\n\u0001""#),
+            (b"a\\b", r#""a\\b""#),
+            (b"\\x80", r#""\\x80""#),
+            (b"\x80", "\"\u{fffd}\""),
+            (b"a\xffb", "\"a\u{fffd}b\""),
+        ] {
+            assert_eq!(expected, json_string(bytes), "{}", bytes.escape_ascii());
+        }
+    }
+
+    #[test]
+    fn json_paths() {
+        for (bytes, expected) in [
+            (&b"a\\b"[..], r#""path": "a\\b""#),
+            (
+                b"a\xffb",
+                "\"path\": \"a\u{fffd}b\", \"path_bytes\": [97, 255, 98]",
+            ),
+        ] {
+            assert_eq!(
+                expected,
+                json_path("path", bytes),
+                "{}",
+                bytes.escape_ascii()
+            );
+        }
+    }
+
+    #[test]
+    fn utc_times() {
+        for (secs, expected) in [
+            (0, "1970-01-01 00:00:00"),
+            (-1, "1969-12-31 23:59:59"),
+            (951_782_400, "2000-02-29 00:00:00"),
+            (4_102_444_799, "2099-12-31 23:59:59"),
+        ] {
+            assert_eq!(expected, format_utc(secs), "{secs}");
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt::Write;
+
+/// File name encoding used when exporting the tree to zip archives.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum NameEncoding {
+    /// Store the names as is and set the UTF-8 flag (general purpose bit 11) for valid non-ASCII
+    /// UTF-8 names.
+    #[default]
+    Utf8,
+    /// Map the names to IBM code page 437.
+    ///
+    /// Unmappable characters are escaped as `%uXXXX` (or `%UXXXXXXXX`), invalid UTF-8 bytes
+    /// are escaped as `%XX` and the percent sign itself is escaped as `%25`.
+    Cp437,
+}
+
+/// File name encoded for a zip archive.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EncodedName {
+    /// Encoded name.
+    pub bytes: Vec<u8>,
+    /// Whether the UTF-8 flag should be set.
+    pub utf8_flag: bool,
+    /// Whether any characters were escaped.
+    pub escaped: bool,
+}
+
+impl NameEncoding {
+    /// Encode the name.
+    pub fn encode(self, name: &[u8]) -> EncodedName {
+        match self {
+            NameEncoding::Utf8 => EncodedName {
+                bytes: name.to_vec(),
+                utf8_flag: !name.is_ascii() && std::str::from_utf8(name).is_ok(),
+                escaped: false,
+            },
+            NameEncoding::Cp437 => encode_cp437(name),
+        }
+    }
+
+    /// Decode the name encoded via [`encode`](Self::encode).
+    ///
+    /// Returns `None` if the encoded name is malformed.
+    pub fn decode(self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            NameEncoding::Utf8 => Some(bytes.to_vec()),
+            NameEncoding::Cp437 => decode_cp437(bytes),
+        }
+    }
+}
+
+fn encode_cp437(name: &[u8]) -> EncodedName {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut escaped = false;
+    let mut escape = |bytes: &mut Vec<u8>, s: String| {
+        bytes.extend(s.as_bytes());
+        escaped = true;
+    };
+    for chunk in name.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '%' {
+                escape(&mut bytes, "%25".into());
+            } else if c.is_ascii() {
+                bytes.push(c as u8);
+            } else if let Some(i) = CP437_HIGH.iter().position(|x| *x == c) {
+                bytes.push(0x80 + i as u8);
+            } else if (c as u32) <= 0xffff {
+                escape(&mut bytes, format!("%u{:04X}", c as u32));
+            } else {
+                escape(&mut bytes, format!("%U{:08X}", c as u32));
+            }
+        }
+        let mut s = String::new();
+        for b in chunk.invalid() {
+            write!(&mut s, "%{:02X}", b).unwrap();
+        }
+        if !s.is_empty() {
+            escape(&mut bytes, s);
+        }
+    }
+    EncodedName {
+        bytes,
+        utf8_flag: false,
+        escaped,
+    }
+}
+
+fn decode_cp437(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut name = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b == b'%' {
+            let (len, is_char) = match bytes.get(i + 1) {
+                Some(b'u') => (4, true),
+                Some(b'U') => (8, true),
+                _ => (2, false),
+            };
+            let start = if is_char { i + 2 } else { i + 1 };
+            let hex = std::str::from_utf8(bytes.get(start..start + len)?).ok()?;
+            let code = u32::from_str_radix(hex, 16).ok()?;
+            if is_char {
+                let c = char::from_u32(code)?;
+                let mut buf = [0_u8; 4];
+                name.extend(c.encode_utf8(&mut buf).as_bytes());
+            } else {
+                name.push(code as u8);
+            }
+            i = start + len;
+        } else {
+            if b.is_ascii() {
+                name.push(b);
+            } else {
+                let c = CP437_HIGH[(b - 0x80) as usize];
+                let mut buf = [0_u8; 4];
+                name.extend(c.encode_utf8(&mut buf).as_bytes());
+            }
+            i += 1;
+        }
+    }
+    Some(name)
+}
+
+/// Characters 0x80-0xFF of IBM code page 437.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}', //
+];
@@ -0,0 +1,222 @@
+use std::ffi::OsString;
+#[cfg(feature = "fs")]
+use std::io::Error;
+use std::os::unix::ffi::OsStrExt;
+#[cfg(feature = "fs")]
+use std::os::unix::fs::MetadataExt;
+#[cfg(feature = "fs")]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use arbitrary::Arbitrary;
+
+use crate::AgedEntry;
+use crate::CraftedEntry;
+use crate::EncodedName;
+use crate::NameEncoding;
+
+/// Generated file system entry.
+///
+/// Describes the intended tree as opposed to the listing of the actual tree
+/// (see [`list_dir_all`](crate::list_dir_all)).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Entry {
+    /// Path relative to the directory root.
+    pub path: PathBuf,
+    /// File type.
+    pub file_type: FileType,
+    /// Permission bits.
+    pub mode: u32,
+    /// Last modification time or `None` if it was not set explicitly.
+    pub mtime: Option<SystemTime>,
+    /// Symbolic link target or hard link's original path relative to the directory root.
+    pub target: Option<PathBuf>,
+    /// File size in bytes (regular files only).
+    pub size: u64,
+    /// Device number (block and character devices only).
+    pub rdev: u64,
+    /// Owner's user and group ids or `None` if the ownership was not changed explicitly.
+    pub owner: Option<(u32, u32)>,
+    /// Extended attributes that were set explicitly.
+    pub xattrs: Vec<(OsString, Vec<u8>)>,
+    /// Inode flags that were set explicitly.
+    ///
+    /// [`FS_IMMUTABLE_FL`] and [`FS_APPEND_FL`] on Linux, [`UF_NODUMP`] and [`UF_HIDDEN`] on
+    /// MacOS and FreeBSD.
+    pub inode_flags: u32,
+}
+
+/// The file can't be modified, deleted or renamed, and no hard links to it can be created.
+pub const FS_IMMUTABLE_FL: u32 = 0x10;
+
+/// The file can only be opened in append mode for writing and can't be deleted or renamed.
+pub const FS_APPEND_FL: u32 = 0x20;
+
+/// Do not dump the file (MacOS and FreeBSD).
+pub const UF_NODUMP: u32 = 0x1;
+
+/// The file is hidden in the GUI (MacOS and FreeBSD).
+pub const UF_HIDDEN: u32 = 0x8000;
+
+#[cfg(feature = "fs")]
+impl Entry {
+    /// Create new entry from the metadata of the existing file.
+    pub(crate) fn from_file(
+        root: &Path,
+        path: PathBuf,
+        file_type: FileType,
+        target: Option<PathBuf>,
+    ) -> Result<Self, Error> {
+        let metadata = root.join(&path).symlink_metadata()?;
+        let mtime = match file_type {
+            FileType::Symlink => None,
+            _ => Some(metadata.modified()?),
+        };
+        let rdev = if matches!(
+            file_type,
+            FileType::BlockDevice | FileType::CharDevice | FileType::Whiteout
+        ) {
+            metadata.rdev()
+        } else {
+            0
+        };
+        let size = if metadata.is_file() {
+            metadata.len()
+        } else {
+            0
+        };
+        Ok(Self {
+            path,
+            file_type,
+            mode: metadata.permissions().mode() & 0o7777,
+            mtime,
+            target,
+            size,
+            rdev,
+            owner: None,
+            xattrs: Vec::new(),
+            inode_flags: 0,
+        })
+    }
+}
+
+/// Generated tree description.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Manifest {
+    /// Generated entries.
+    pub entries: Vec<Entry>,
+    /// Entries crafted to hit archiver limits.
+    pub crafted_entries: Vec<CraftedEntry>,
+    /// Entries that were created with a different file type than requested.
+    pub downgrades: Vec<Downgrade>,
+    /// Entries whose modification times fall into the age buckets.
+    pub aged_entries: Vec<AgedEntry>,
+    /// Actions that the policy under test is expected to take.
+    pub expected_actions: Vec<ExpectedAction>,
+    /// Intended file name encoding for zip archives.
+    pub name_encoding: NameEncoding,
+    /// Generated names that the file system collapsed into the existing ones.
+    pub collapsed_names: Vec<CollapsedName>,
+    /// The paths of the symbolic links whose targets point outside the directory.
+    pub escaping_symlinks: Vec<PathBuf>,
+}
+
+impl Manifest {
+    /// Encode entry's path using the intended file name encoding.
+    pub fn encoded_name(&self, entry: &Entry) -> EncodedName {
+        self.name_encoding.encode(entry.path.as_os_str().as_bytes())
+    }
+}
+
+/// File type substitution.
+///
+/// Device files are replaced with named pipes when `mknod` is not permitted, and sockets are
+/// replaced with named pipes when the path doesn't fit into `sockaddr_un`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Downgrade {
+    /// Path relative to the directory root.
+    pub path: PathBuf,
+    /// Requested file type.
+    pub requested: FileType,
+    /// Actual file type.
+    pub actual: FileType,
+    /// Requested device number (block and character devices only).
+    pub rdev: u64,
+}
+
+/// Generated name that the file system treated as the name of an existing file.
+///
+/// Case-insensitive and normalization-insensitive file systems collapse the names that differ
+/// only in case or in Unicode normalization (see [`CollidingNames`](crate::CollidingNames)).
+/// No entry is created for such names.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CollapsedName {
+    /// Generated path relative to the directory root.
+    pub path: PathBuf,
+    /// The path of the existing file as listed by the file system.
+    pub existing: PathBuf,
+}
+
+/// The action that the policy under test is expected to take on the entry.
+///
+/// Computed by the policy closure at generation time (see [`DirBuilder::policy`]).
+///
+/// [`DirBuilder::policy`]: crate::DirBuilder::policy
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExpectedAction {
+    /// Path relative to the directory root.
+    pub path: PathBuf,
+    /// The action, e.g. "keep", "delete" or "archive".
+    pub action: String,
+}
+
+/// File type.
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FileType {
+    /// Regular file.
+    Regular,
+    /// A directory.
+    Directory,
+    /// Named pipe.
+    Fifo,
+    /// UNIX socket.
+    ///
+    /// Replaced with a named pipe when the path doesn't fit into `sockaddr_un`.
+    Socket,
+    /// Block device.
+    BlockDevice,
+    /// Character device.
+    CharDevice,
+    /// Symbolic link.
+    Symlink,
+    /// Hard link.
+    HardLink,
+    /// Overlayfs whiteout, i.e. character device with device number 0/0.
+    ///
+    /// Marks the files deleted in the upper layer. Not included in [`ALL_FILE_TYPES`].
+    Whiteout,
+}
+
+/// All file types supported by the platform.
+pub const ALL_FILE_TYPES: [FileType; 8] = {
+    use FileType::*;
+    [
+        Regular,
+        Directory,
+        Fifo,
+        Socket,
+        BlockDevice,
+        CharDevice,
+        Symlink,
+        HardLink,
+    ]
+};
+
+/// File types that are valid on any file system.
+pub const PORTABLE_FILE_TYPES: [FileType; 4] = {
+    use FileType::*;
+    [Regular, Directory, Symlink, HardLink]
+};
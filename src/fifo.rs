@@ -0,0 +1,103 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::SystemTime;
+
+use crate::path_to_c_string;
+use crate::set_file_modified_time;
+
+/// Background thread that writes the contents to the named pipe once and closes it.
+///
+/// The thread blocks until some reader opens the pipe. The pipe's modification time is restored
+/// after the contents are written.
+pub(crate) struct FifoWriter {
+    /// Path relative to the directory root.
+    path: PathBuf,
+    absolute_path: PathBuf,
+    contents: Arc<Vec<u8>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FifoWriter {
+    pub(crate) fn spawn(
+        root: &Path,
+        path: PathBuf,
+        contents: Vec<u8>,
+        mtime: Option<SystemTime>,
+    ) -> Self {
+        let absolute_path = root.join(&path);
+        let contents = Arc::new(contents);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let path = absolute_path.clone();
+            let contents = contents.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                // blocks until there is a reader
+                let Ok(mut file) = OpenOptions::new().write(true).open(&path) else {
+                    return;
+                };
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                // the contents fit into the pipe buffer, i.e. the write never blocks
+                let _ = file.write_all(&contents);
+                drop(file);
+                if let (Some(t), Ok(c_path)) = (mtime, path_to_c_string(path)) {
+                    let _ = set_file_modified_time(&c_path, t);
+                }
+            })
+        };
+        Self {
+            path,
+            absolute_path,
+            contents,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    pub(crate) fn contents(&self) -> &[u8] {
+        &self.contents[..]
+    }
+}
+
+impl Drop for FifoWriter {
+    fn drop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.stop.store(true, Ordering::Relaxed);
+        // unblock the writer
+        let _reader = if thread.is_finished() {
+            None
+        } else {
+            match OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.absolute_path)
+            {
+                Ok(reader) => Some(reader),
+                // the pipe was removed or can't be opened, the thread is left blocked
+                Err(_) => return,
+            }
+        };
+        let _ = thread.join();
+    }
+}
+
+/// Maximum size of the contents written to the named pipe.
+///
+/// Equals the minimum pipe buffer size on Linux.
+pub(crate) const MAX_FIFO_CONTENTS_LEN: usize = 4096;
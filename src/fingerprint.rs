@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::Error;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::default_hash_algorithm;
+use crate::encode_acl;
+#[cfg(all(target_os = "linux", feature = "acl"))]
+use crate::read_acl;
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+use crate::read_inode_flags;
+#[cfg(all(target_os = "linux", feature = "selinux"))]
+use crate::read_selinux_context;
+use crate::Digest;
+use crate::Dir;
+use crate::HashAlgorithm;
+use crate::Metadata;
+
+impl Dir {
+    /// Compute the digest of the current state of the tree (see [`tree_digest`]).
+    pub fn fingerprint(&self) -> Result<Digest, Error> {
+        tree_digest(self.path())
+    }
+}
+
+/// Compute the digest of the paths, the metadata and the contents of the files in `dir` using
+/// [`default_hash_algorithm`].
+///
+/// The digest is computed bottom-up: the digest of the directory covers the names, the metadata
+/// and the digests of its children sorted by name, the digest of the regular file covers its
+/// contents and the digest of the symbolic link covers its target. All [`Metadata`] fields except
+/// the device and inode numbers, the birth and the status change times are included, the
+/// metadata of `dir` itself is not. The contents are streamed, i.e. neither the contents nor the
+/// listing are kept in memory.
+///
+/// Use it to check that the tree is unchanged after some operation.
+pub fn tree_digest<P: AsRef<Path>>(dir: P) -> Result<Digest, Error> {
+    tree_digest_with(dir, default_hash_algorithm().as_ref())
+}
+
+/// Same as [`tree_digest`] but uses the specified hash algorithm.
+pub fn tree_digest_with<P: AsRef<Path>>(
+    dir: P,
+    algorithm: &dyn HashAlgorithm,
+) -> Result<Digest, Error> {
+    dir_digest(dir.as_ref(), algorithm)
+}
+
+fn dir_digest(dir: &Path, algorithm: &dyn HashAlgorithm) -> Result<Digest, Error> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    let mut hasher = algorithm.hasher();
+    for entry in entries.into_iter() {
+        let path = entry.path();
+        let metadata = path.symlink_metadata()?;
+        let digest = if metadata.is_dir() {
+            dir_digest(&path, algorithm)?
+        } else if metadata.is_file() {
+            file_digest(&path, algorithm)?
+        } else if metadata.is_symlink() {
+            algorithm.digest(std::fs::read_link(&path)?.as_os_str().as_bytes())
+        } else {
+            algorithm.digest(&[])
+        };
+        let name = entry.file_name();
+        hasher.update(&(name.len() as u64).to_le_bytes());
+        hasher.update(name.as_bytes());
+        let metadata: Metadata = (&metadata).try_into()?;
+        #[cfg(all(target_os = "linux", feature = "acl"))]
+        let metadata = Metadata {
+            acl: read_acl(&path)?,
+            ..metadata
+        };
+        #[cfg(all(target_os = "linux", feature = "selinux"))]
+        let metadata = Metadata {
+            selinux_context: read_selinux_context(&path)?,
+            ..metadata
+        };
+        #[cfg(all(target_os = "linux", feature = "chattr"))]
+        let metadata = Metadata {
+            inode_flags: read_inode_flags(&path)?,
+            ..metadata
+        };
+        for field in [
+            metadata.mode as u64,
+            metadata.uid as u64,
+            metadata.gid as u64,
+            metadata.nlink as u64,
+            metadata.rdev,
+            metadata.mtime as u64,
+            metadata.file_size,
+            metadata.inode_flags as u64,
+        ] {
+            hasher.update(&field.to_le_bytes());
+        }
+        for acl in [&metadata.acl.access, &metadata.acl.default] {
+            let acl = encode_acl(acl);
+            hasher.update(&(acl.len() as u64).to_le_bytes());
+            hasher.update(&acl);
+        }
+        hasher.update(&(metadata.selinux_context.len() as u64).to_le_bytes());
+        hasher.update(&metadata.selinux_context);
+        hasher.update(&(digest.0.len() as u64).to_le_bytes());
+        hasher.update(&digest.0);
+    }
+    Ok(hasher.finalize())
+}
+
+fn file_digest(path: &Path, algorithm: &dyn HashAlgorithm) -> Result<Digest, Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = algorithm.hasher();
+    let mut buf = vec![0_u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize())
+}
@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::io::Error;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use fuser::BackgroundSession;
+use fuser::FileAttr;
+use fuser::Filesystem;
+use fuser::MountOption;
+use fuser::ReplyAttr;
+use fuser::ReplyData;
+use fuser::ReplyDirectory;
+use fuser::ReplyEntry;
+use fuser::Request;
+
+use crate::from_unix_time;
+use crate::FileInfo;
+use crate::Vfs;
+
+/// The tree mounted via FUSE (see [`Vfs::mount_fuse`]).
+///
+/// Unmounted on drop.
+pub struct FuseMount {
+    // `None` only after the file system was unmounted
+    session: Option<BackgroundSession>,
+    mountpoint: PathBuf,
+}
+
+impl FuseMount {
+    /// Get the mount point.
+    pub fn path(&self) -> &Path {
+        self.mountpoint.as_path()
+    }
+
+    /// Unmount the file system and wait for the session thread to finish.
+    pub fn unmount(mut self) -> Result<(), Error> {
+        self.session.take().unwrap().join();
+        Ok(())
+    }
+}
+
+impl Drop for FuseMount {
+    fn drop(&mut self) {
+        // unmounts the file system without waiting for the session thread
+        drop(self.session.take());
+    }
+}
+
+impl Vfs {
+    /// Serve the tree via FUSE at `mountpoint`.
+    ///
+    /// The file system is read-only and is served from the snapshot of the tree, i.e. the
+    /// subsequent changes are not visible. Any file type, device number, mode and owner are served
+    /// as is without privileges. The device files are listed but can't be opened (`nodev`), the
+    /// permission bits are not enforced, i.e. the mounting user can read any file.
+    ///
+    /// Mounting requires either root privileges or `fusermount3` binary.
+    pub fn mount_fuse<P: AsRef<Path>>(&self, mountpoint: P) -> Result<FuseMount, Error> {
+        let mountpoint = mountpoint.as_ref();
+        let options = [
+            MountOption::FSName("random-dir".into()),
+            MountOption::RO,
+            MountOption::NoDev,
+            MountOption::NoSuid,
+            MountOption::NoExec,
+        ];
+        let session = fuser::spawn_mount2(FuseTree::new(self.list()), mountpoint, &options)?;
+        Ok(FuseMount {
+            session: Some(session),
+            mountpoint: mountpoint.to_path_buf(),
+        })
+    }
+}
+
+/// Read-only snapshot of [`Vfs`] indexed by FUSE inodes.
+struct FuseTree {
+    /// Indexed by inode minus one.
+    attrs: Vec<FileAttr>,
+    /// File contents or symlink targets indexed by inode minus one.
+    data: Vec<Vec<u8>>,
+    dirs: HashMap<u64, FuseDir>,
+}
+
+struct FuseDir {
+    parent: u64,
+    children: BTreeMap<OsString, u64>,
+}
+
+impl FuseTree {
+    fn new(files: Vec<FileInfo>) -> Self {
+        let mut attrs = vec![FileAttr {
+            nlink: 2,
+            ..file_attr(ROOT_INO, libc::S_IFDIR | 0o755, SystemTime::UNIX_EPOCH)
+        }];
+        let mut data = vec![Vec::new()];
+        let mut dirs = HashMap::new();
+        dirs.insert(
+            ROOT_INO,
+            FuseDir {
+                parent: ROOT_INO,
+                children: BTreeMap::new(),
+            },
+        );
+        let mut dir_inodes: HashMap<PathBuf, u64> = HashMap::new();
+        for file in files.into_iter() {
+            // the listing's inodes start from zero
+            let ino = file.metadata.ino + 2;
+            let parent = match file.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => dir_inodes[parent],
+                _ => ROOT_INO,
+            };
+            let Some(name) = file.path.file_name() else {
+                continue;
+            };
+            dirs.get_mut(&parent)
+                .unwrap()
+                .children
+                .insert(name.to_os_string(), ino);
+            let index = (ino - 1) as usize;
+            if index < attrs.len() {
+                // hard link
+                continue;
+            }
+            let metadata = &file.metadata;
+            let mtime = from_unix_time(metadata.mtime, 0);
+            let mut attr = file_attr(ino, metadata.mode, mtime);
+            attr.size = metadata.file_size;
+            attr.blocks = metadata.file_size.div_ceil(512);
+            attr.nlink = metadata.nlink;
+            attr.uid = metadata.uid;
+            attr.gid = metadata.gid;
+            attr.rdev = encode_device_number(metadata.rdev);
+            if attr.kind == fuser::FileType::Directory {
+                attrs[0].nlink += u32::from(parent == ROOT_INO);
+                dir_inodes.insert(file.path.clone(), ino);
+                dirs.insert(
+                    ino,
+                    FuseDir {
+                        parent,
+                        children: BTreeMap::new(),
+                    },
+                );
+            }
+            attrs.push(attr);
+            data.push(file.contents);
+        }
+        Self { attrs, data, dirs }
+    }
+
+    fn attr(&self, ino: u64) -> Option<&FileAttr> {
+        self.attrs.get((ino as usize).checked_sub(1)?)
+    }
+
+    fn data(&self, ino: u64) -> Option<&[u8]> {
+        self.data
+            .get((ino as usize).checked_sub(1)?)
+            .map(|data| &data[..])
+    }
+}
+
+impl Filesystem for FuseTree {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let Some(dir) = self.dirs.get(&parent) else {
+            return reply.error(libc::ENOTDIR);
+        };
+        match dir.children.get(name) {
+            Some(ino) => reply.entry(&TTL, &self.attrs[(*ino - 1) as usize], 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        match self.attr(ino) {
+            Some(attr) if attr.kind == fuser::FileType::Symlink => {
+                reply.data(self.data(ino).unwrap())
+            }
+            Some(_) => reply.error(libc::EINVAL),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        match self.attr(ino) {
+            Some(attr) if attr.kind == fuser::FileType::RegularFile => {
+                let data = self.data(ino).unwrap();
+                let start = data.len().min(offset.max(0) as usize);
+                let end = data.len().min(start + size as usize);
+                reply.data(&data[start..end])
+            }
+            Some(attr) if attr.kind == fuser::FileType::Directory => reply.error(libc::EISDIR),
+            Some(_) => reply.error(libc::EINVAL),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(dir) = self.dirs.get(&ino) else {
+            return reply.error(libc::ENOTDIR);
+        };
+        let entries = [(OsStr::new("."), ino), (OsStr::new(".."), dir.parent)]
+            .into_iter()
+            .chain(
+                dir.children
+                    .iter()
+                    .map(|(name, ino)| (name.as_os_str(), *ino)),
+            );
+        for (i, (name, ino)) in entries.enumerate().skip(offset.max(0) as usize) {
+            let kind = self.attrs[(ino - 1) as usize].kind;
+            // the offset of the next entry
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok()
+    }
+}
+
+fn file_attr(ino: u64, mode: u32, mtime: SystemTime) -> FileAttr {
+    let kind = match mode & libc::S_IFMT {
+        libc::S_IFDIR => fuser::FileType::Directory,
+        libc::S_IFLNK => fuser::FileType::Symlink,
+        libc::S_IFIFO => fuser::FileType::NamedPipe,
+        libc::S_IFSOCK => fuser::FileType::Socket,
+        libc::S_IFBLK => fuser::FileType::BlockDevice,
+        libc::S_IFCHR => fuser::FileType::CharDevice,
+        _ => fuser::FileType::RegularFile,
+    };
+    FileAttr {
+        ino,
+        size: 0,
+        blocks: 0,
+        atime: mtime,
+        mtime,
+        ctime: mtime,
+        crtime: mtime,
+        kind,
+        perm: (mode & 0o7777) as u16,
+        nlink: 1,
+        uid: 0,
+        gid: 0,
+        rdev: 0,
+        blksize: 4096,
+        flags: 0,
+    }
+}
+
+/// Encode the device number in the format that the kernel expects in FUSE replies.
+#[allow(unused_unsafe)]
+fn encode_device_number(rdev: u64) -> u32 {
+    let rdev = rdev as libc::dev_t;
+    let (major, minor) = unsafe { (libc::major(rdev), libc::minor(rdev)) };
+    (minor & 0xff) | (major << 8) | ((minor & !0xff) << 12)
+}
+
+const ROOT_INO: u64 = 1;
+
+/// How long the kernel caches the attributes and the lookups.
+///
+/// The tree never changes.
+const TTL: Duration = Duration::from_secs(3600);
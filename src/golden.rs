@@ -0,0 +1,266 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fs::create_dir_all;
+use std::fs::File;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use arbitrary::Unstructured;
+
+use crate::all_compressions;
+use crate::default_hash_algorithm;
+use crate::Compression;
+use crate::Digest;
+use crate::DirBuilder;
+use crate::HashAlgorithm;
+use crate::Manifest;
+
+/// Regression corpus that maps the digests of the specs to the digests of the expected tool
+/// output.
+///
+/// The spec is the unstructured data that the tree is generated from. Only the specs and
+/// the digests are stored, the trees are regenerated on every run. Each entry is stored as
+/// `<spec-digest>.spec` (spec bytes), `<spec-digest>.manifest` (see [`Manifest::write_to`]) and
+/// `<spec-digest>.expected` (hexadecimal digest of the tool output prefixed with the algorithm
+/// name) files. The spec and the manifest files have an additional extension if compressed.
+pub struct GoldenCorpus {
+    dir: PathBuf,
+    algorithm: Box<dyn HashAlgorithm>,
+    compression: Compression,
+}
+
+impl GoldenCorpus {
+    /// Open the corpus stored in `dir` creating the directory if needed.
+    pub fn open<P: Into<PathBuf>>(dir: P) -> Result<Self, Error> {
+        let dir = dir.into();
+        create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            algorithm: default_hash_algorithm(),
+            compression: Default::default(),
+        })
+    }
+
+    /// Compress the specs and the manifests of the newly recorded entries.
+    ///
+    /// The existing entries are read regardless of their compression.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Use the specified hash algorithm instead of [`default_hash_algorithm`].
+    ///
+    /// Changing the algorithm invalidates the existing entries.
+    pub fn hash_algorithm<H: HashAlgorithm + 'static>(mut self, algorithm: H) -> Self {
+        self.algorithm = Box::new(algorithm);
+        self
+    }
+
+    /// Corpus directory.
+    pub fn path(&self) -> &Path {
+        self.dir.as_path()
+    }
+
+    /// Generate the tree from `spec`, run the tool and store the digest of its output as the
+    /// expected one.
+    ///
+    /// Returns the digest of the spec.
+    pub fn record<B, F>(&self, spec: &[u8], builder: B, tool: F) -> Result<Digest, Error>
+    where
+        B: FnOnce() -> DirBuilder,
+        F: FnOnce(&Path) -> Vec<u8>,
+    {
+        let (output, manifest) = run(spec, builder, tool)?;
+        let spec_digest = self.algorithm.digest(spec);
+        let file = File::create(self.spec_path(&spec_digest, self.compression))?;
+        self.compression.writer(file)?.write_all(spec)?;
+        let file = File::create(self.manifest_path(&spec_digest, self.compression))?;
+        manifest.write_to(self.compression.writer(file)?)?;
+        std::fs::write(
+            self.expected_path(&spec_digest),
+            format!(
+                "{}:{}",
+                self.algorithm.name(),
+                self.algorithm.digest(&output)
+            ),
+        )?;
+        Ok(spec_digest)
+    }
+
+    /// Regenerate all the trees, run the tool and compare the digests of its output to the
+    /// expected ones.
+    pub fn check<B, F>(&self, builder: B, tool: F) -> Result<GoldenReport, Error>
+    where
+        B: Fn() -> DirBuilder,
+        F: Fn(&Path) -> Vec<u8>,
+    {
+        let mut report = GoldenReport::default();
+        for spec_digest in self.specs()? {
+            let spec = self.spec(&spec_digest)?;
+            let expected = self.read_expected(&spec_digest)?;
+            let (output, _) = run(&spec, &builder, &tool)?;
+            let actual = self.algorithm.digest(&output);
+            if actual == expected {
+                report.passed.push(spec_digest);
+            } else {
+                report.drifted.push(Drift {
+                    spec: spec_digest,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// Digests of all the specs in the corpus in sorted order.
+    pub fn specs(&self) -> Result<Vec<Digest>, Error> {
+        let mut specs = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let digest = all_compressions().into_iter().find_map(|compression| {
+                let stem = name.strip_suffix(compression.extension())?;
+                Digest::from_hex(stem.strip_suffix(".spec")?)
+            });
+            if let Some(digest) = digest {
+                specs.push(digest);
+            }
+        }
+        specs.sort();
+        specs.dedup();
+        Ok(specs)
+    }
+
+    /// Read the spec with the specified digest.
+    pub fn spec(&self, digest: &Digest) -> Result<Vec<u8>, Error> {
+        let mut spec = Vec::new();
+        self.open_entry(digest, Self::spec_path)?
+            .read_to_end(&mut spec)?;
+        Ok(spec)
+    }
+
+    /// Read the manifest of the tree generated from the spec with the specified digest.
+    pub fn manifest(&self, digest: &Digest) -> Result<Manifest, Error> {
+        Manifest::read_from(self.open_entry(digest, Self::manifest_path)?)
+    }
+
+    fn open_entry<F>(&self, digest: &Digest, path: F) -> Result<Box<dyn Read>, Error>
+    where
+        F: Fn(&Self, &Digest, Compression) -> PathBuf,
+    {
+        for compression in all_compressions() {
+            match File::open(path(self, digest, compression)) {
+                Ok(file) => return compression.reader(file),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "`{}` not found",
+                path(self, digest, Compression::None).display()
+            ),
+        ))
+    }
+
+    fn read_expected(&self, spec_digest: &Digest) -> Result<Digest, Error> {
+        let path = self.expected_path(spec_digest);
+        let contents = std::fs::read_to_string(&path)?;
+        let (name, hex) = contents
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| invalid_data(format!("no algorithm name in `{}`", path.display())))?;
+        if name != self.algorithm.name() {
+            return Err(invalid_data(format!(
+                "`{}` was recorded with `{}`, but the corpus uses `{}`",
+                path.display(),
+                name,
+                self.algorithm.name()
+            )));
+        }
+        Digest::from_hex(hex)
+            .ok_or_else(|| invalid_data(format!("invalid digest in `{}`", path.display())))
+    }
+
+    fn spec_path(&self, digest: &Digest, compression: Compression) -> PathBuf {
+        self.dir
+            .join(format!("{}.spec{}", digest, compression.extension()))
+    }
+
+    fn manifest_path(&self, digest: &Digest, compression: Compression) -> PathBuf {
+        self.dir
+            .join(format!("{}.manifest{}", digest, compression.extension()))
+    }
+
+    fn expected_path(&self, digest: &Digest) -> PathBuf {
+        self.dir.join(format!("{}.expected", digest))
+    }
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn run<B, F>(spec: &[u8], builder: B, tool: F) -> Result<(Vec<u8>, Manifest), Error>
+where
+    B: FnOnce() -> DirBuilder,
+    F: FnOnce(&Path) -> Vec<u8>,
+{
+    let mut u = Unstructured::new(spec);
+    let dir = builder().create(&mut u).map_err(Error::other)?;
+    Ok((tool(dir.path()), dir.manifest().clone()))
+}
+
+/// The result of [`GoldenCorpus::check`].
+#[derive(Debug, Default)]
+pub struct GoldenReport {
+    /// The digests of the specs for which the output matches.
+    pub passed: Vec<Digest>,
+    /// The specs for which the output doesn't match.
+    pub drifted: Vec<Drift>,
+}
+
+impl GoldenReport {
+    /// Returns `true` if there is no drift.
+    pub fn is_ok(&self) -> bool {
+        self.drifted.is_empty()
+    }
+}
+
+impl Display for GoldenReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for drift in self.drifted.iter() {
+            writeln!(
+                f,
+                "spec {}: expected {}, actual {}",
+                drift.spec, drift.expected, drift.actual
+            )?;
+        }
+        write!(
+            f,
+            "{} passed, {} drifted",
+            self.passed.len(),
+            self.drifted.len()
+        )
+    }
+}
+
+/// Tool output drift.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Drift {
+    /// Spec digest.
+    pub spec: Digest,
+    /// Expected output digest.
+    pub expected: Digest,
+    /// Actual output digest.
+    pub actual: Digest,
+}
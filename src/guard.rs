@@ -0,0 +1,160 @@
+use std::fs::Permissions;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Refuses to delete or change permissions of the files outside of the whitelisted root.
+///
+/// The paths are canonicalized before the check; the last path component is not followed,
+/// i.e. the guard protects the symlink itself, not its target.
+///
+/// The guard protects from catastrophic mistakes like deleting the files in the wrong directory.
+/// It checks every modification made by [`mutate`](crate::mutate) and
+/// [`scan_while_mutating`](crate::scan_while_mutating), and the deletions made by
+/// [`Dir::delete_benchmark`] and on drop (see [`Cleanup`]).
+///
+/// [`Dir::delete_benchmark`]: crate::Dir::delete_benchmark
+/// [`Cleanup`]: crate::Cleanup
+#[derive(Debug, Clone)]
+pub struct SafetyGuard {
+    root: Option<PathBuf>,
+}
+
+impl SafetyGuard {
+    /// Create new guard that allows modifications only inside `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, Error> {
+        Ok(Self {
+            root: Some(root.as_ref().canonicalize()?),
+        })
+    }
+
+    /// Create new guard that allows any modifications.
+    pub fn disabled() -> Self {
+        Self { root: None }
+    }
+
+    /// Whitelisted root (canonical path) or `None` if the guard is disabled.
+    pub fn root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Returns an error if `path` is outside the whitelisted root.
+    pub fn check<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let Some(root) = self.root.as_ref() else {
+            return Ok(());
+        };
+        let path = path.as_ref();
+        let canonical_path = canonicalize_parent(path)?;
+        if !canonical_path.starts_with(root) {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "refusing to modify `{}`: the path is outside `{}`",
+                    path.display(),
+                    root.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Remove the file after checking its path.
+    pub fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        self.check(path)?;
+        std::fs::remove_file(path)
+    }
+
+    /// Remove the directory recursively after checking its path.
+    pub fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        self.check(path)?;
+        std::fs::remove_dir_all(path)
+    }
+
+    /// Change file permissions after checking its path.
+    pub fn set_permissions<P: AsRef<Path>>(
+        &self,
+        path: P,
+        permissions: Permissions,
+    ) -> Result<(), Error> {
+        let path = path.as_ref();
+        self.check(path)?;
+        std::fs::set_permissions(path, permissions)
+    }
+}
+
+fn canonicalize_parent(path: &Path) -> Result<PathBuf, Error> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            Ok(parent.canonicalize()?.join(file_name))
+        }
+        _ => path.canonicalize(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn check() {
+        let workdir = TempDir::new().unwrap();
+        let workdir = workdir.path();
+        std::fs::create_dir_all(workdir.join("root/sub")).unwrap();
+        std::fs::create_dir(workdir.join("outside")).unwrap();
+        std::fs::write(workdir.join("root/sub/file"), "").unwrap();
+        std::fs::write(workdir.join("outside/secret"), "").unwrap();
+        symlink("root", workdir.join("link")).unwrap();
+        symlink("../outside", workdir.join("root/escape")).unwrap();
+        symlink("sub", workdir.join("root/inner")).unwrap();
+        // the root is a symlink
+        let guard = SafetyGuard::new(workdir.join("link")).unwrap();
+        assert_eq!(
+            Some(workdir.join("root").canonicalize().unwrap().as_path()),
+            guard.root()
+        );
+        for (path, allowed) in [
+            ("root", true),
+            ("root/sub", true),
+            ("root/sub/file", true),
+            ("root/sub/new", true),
+            ("link/sub/file", true),
+            ("root/inner/file", true),
+            // the symlinks themselves are protected, not their targets
+            ("root/escape", true),
+            ("link", false),
+            ("root/escape/secret", false),
+            ("outside/secret", false),
+            // `..` escapes
+            ("root/sub/..", true),
+            ("root/..", false),
+            ("root/../outside", false),
+            ("root/sub/../../outside/secret", false),
+            ("link/../outside/secret", false),
+        ] {
+            let result = guard.check(workdir.join(path));
+            assert_eq!(allowed, result.is_ok(), "{}: {:?}", path, result);
+            if let Err(e) = result {
+                assert_eq!(ErrorKind::PermissionDenied, e.kind(), "{}", path);
+            }
+        }
+        assert!(SafetyGuard::disabled()
+            .check(workdir.join("outside/secret"))
+            .is_ok());
+        assert!(guard
+            .remove_file(workdir.join("root/escape/secret"))
+            .is_err());
+        assert!(workdir.join("outside/secret").exists());
+    }
+}
@@ -0,0 +1,272 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// Hash digest.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, PartialOrd, Ord)]
+pub struct Digest(pub Vec<u8>);
+
+impl Digest {
+    /// Compute the digest of `data` using [`default_hash_algorithm`].
+    pub fn of(data: &[u8]) -> Self {
+        default_hash_algorithm().digest(data)
+    }
+
+    /// Parse hexadecimal representation.
+    pub fn from_hex(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s.len() % 2 != 0 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self(bytes))
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for b in self.0.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hash algorithm.
+///
+/// Implement this trait to match the digests produced by external systems.
+pub trait HashAlgorithm {
+    /// Algorithm name, e.g. `sha256`.
+    ///
+    /// The name is stored alongside the digests to detect algorithm mismatch.
+    fn name(&self) -> &str;
+
+    /// Create new incremental hasher.
+    fn hasher(&self) -> Box<dyn DigestHasher>;
+
+    /// Compute the digest of `data`.
+    fn digest(&self, data: &[u8]) -> Digest {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// Incremental hasher.
+pub trait DigestHasher {
+    /// Feed `data` to the hasher.
+    fn update(&mut self, data: &[u8]);
+
+    /// Compute the digest of all the data fed so far.
+    fn finalize(self: Box<Self>) -> Digest;
+}
+
+/// The default hash algorithm.
+///
+/// This is [`Blake3`] when `blake3` feature is enabled and [`Fnv1a64`] otherwise.
+pub fn default_hash_algorithm() -> Box<dyn HashAlgorithm> {
+    #[cfg(feature = "blake3")]
+    {
+        Box::new(Blake3)
+    }
+    #[cfg(not(feature = "blake3"))]
+    {
+        Box::new(Fnv1a64)
+    }
+}
+
+/// BLAKE3 hash algorithm.
+#[cfg(feature = "blake3")]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Blake3;
+
+#[cfg(feature = "blake3")]
+impl HashAlgorithm for Blake3 {
+    fn name(&self) -> &str {
+        "blake3"
+    }
+
+    fn hasher(&self) -> Box<dyn DigestHasher> {
+        Box::new(blake3::Hasher::new())
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl DigestHasher for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Digest {
+        Digest(blake3::Hasher::finalize(&self).as_bytes().to_vec())
+    }
+}
+
+/// SHA-256 hash algorithm.
+#[cfg(feature = "sha2")]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Sha256;
+
+#[cfg(feature = "sha2")]
+impl HashAlgorithm for Sha256 {
+    fn name(&self) -> &str {
+        "sha256"
+    }
+
+    fn hasher(&self) -> Box<dyn DigestHasher> {
+        use sha2::Digest as _;
+        Box::new(sha2::Sha256::new())
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl DigestHasher for sha2::Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Digest {
+        Digest(sha2::Digest::finalize(*self).to_vec())
+    }
+}
+
+/// 64-bit XXH3 hash algorithm.
+///
+/// This is a non-cryptographic hash.
+#[cfg(feature = "xxh3")]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Xxh3;
+
+#[cfg(feature = "xxh3")]
+impl HashAlgorithm for Xxh3 {
+    fn name(&self) -> &str {
+        "xxh3"
+    }
+
+    fn hasher(&self) -> Box<dyn DigestHasher> {
+        Box::new(xxhash_rust::xxh3::Xxh3::new())
+    }
+}
+
+#[cfg(feature = "xxh3")]
+impl DigestHasher for xxhash_rust::xxh3::Xxh3 {
+    fn update(&mut self, data: &[u8]) {
+        xxhash_rust::xxh3::Xxh3::update(self, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Digest {
+        Digest(self.digest().to_be_bytes().to_vec())
+    }
+}
+
+/// 64-bit FNV-1a hash algorithm.
+///
+/// This is a non-cryptographic hash that doesn't require any dependencies.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Fnv1a64;
+
+impl HashAlgorithm for Fnv1a64 {
+    fn name(&self) -> &str {
+        "fnv1a64"
+    }
+
+    fn hasher(&self) -> Box<dyn DigestHasher> {
+        Box::new(Fnv1a64Hasher(FNV_OFFSET_BASIS))
+    }
+}
+
+struct Fnv1a64Hasher(u64);
+
+impl DigestHasher for Fnv1a64Hasher {
+    fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finalize(self: Box<Self>) -> Digest {
+        Digest(self.0.to_be_bytes().to_vec())
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+#[cfg(test)]
+mod tests {
+    use arbtest::arbtest;
+
+    use super::*;
+
+    #[test]
+    fn known_answers() {
+        let algorithms: Vec<(Box<dyn HashAlgorithm>, &[u8], &str)> = vec![
+            (Box::new(Fnv1a64), b"", "cbf29ce484222325"),
+            (Box::new(Fnv1a64), b"a", "af63dc4c8601ec8c"),
+            (Box::new(Fnv1a64), b"foobar", "85944171f73967e8"),
+            #[cfg(feature = "blake3")]
+            (
+                Box::new(Blake3),
+                b"",
+                "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+            ),
+            #[cfg(feature = "blake3")]
+            (
+                Box::new(Blake3),
+                b"abc",
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ),
+            #[cfg(feature = "sha2")]
+            (
+                Box::new(Sha256),
+                b"",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            #[cfg(feature = "sha2")]
+            (
+                Box::new(Sha256),
+                b"abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            #[cfg(feature = "xxh3")]
+            (Box::new(Xxh3), b"", "2d06800538d394c2"),
+        ];
+        for (algorithm, data, expected) in algorithms {
+            let digest = algorithm.digest(data);
+            assert_eq!(expected, digest.to_string(), "{}", algorithm.name());
+            assert_eq!(Some(digest), Digest::from_hex(expected));
+        }
+    }
+
+    #[test]
+    fn incremental_digest() {
+        arbtest(|u| {
+            let data: Vec<u8> = u.arbitrary()?;
+            let mid = u.int_in_range(0..=data.len())?;
+            let mut hasher = default_hash_algorithm().hasher();
+            hasher.update(&data[..mid]);
+            hasher.update(&data[mid..]);
+            assert_eq!(Digest::of(&data), hasher.finalize());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        arbtest(|u| {
+            let digest = Digest(u.arbitrary()?);
+            assert_eq!(Some(digest.clone()), Digest::from_hex(&digest.to_string()));
+            assert_eq!(
+                Some(digest.clone()),
+                Digest::from_hex(&digest.to_string().to_uppercase())
+            );
+            Ok(())
+        });
+        for invalid in ["0", "abc", "zz", "0g", "+1", "é0"] {
+            assert_eq!(None, Digest::from_hex(invalid), "{:?}", invalid);
+        }
+    }
+}
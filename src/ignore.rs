@@ -0,0 +1,311 @@
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::io::Error;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+
+use arbitrary::Unstructured;
+
+use crate::Entry;
+use crate::FileType;
+
+/// Include/exclude glob patterns in `.gitignore` format and the oracle that tells which paths
+/// they ignore.
+///
+/// Generate the rules for the tree via [`arbitrary`](Self::arbitrary) (or
+/// [`DirBuilder::create_with_ignore_rules`](crate::DirBuilder::create_with_ignore_rules)) and
+/// compare the paths that the file-walking library under test skips with
+/// [`ignored_paths`](Self::ignored_paths).
+///
+/// Supported syntax: `*` matches any sequence of bytes except `/`, `?` matches any byte except
+/// `/`, `**` matches any sequence of path components, `\` escapes the next byte, `!` negates
+/// the rule, the trailing `/` matches only directories, the leading `/` (or any `/` except the
+/// trailing one) anchors the pattern to the root, otherwise the pattern matches the file name
+/// at any depth. Character classes are not supported.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IgnoreRules {
+    /// The rules in the order of precedence: the last matching rule wins.
+    pub rules: Vec<IgnoreRule>,
+}
+
+/// A line of [`IgnoreRules`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreRule {
+    /// Glob pattern without the leading `!` and the trailing `/`.
+    pub pattern: OsString,
+    /// Re-include the matching paths.
+    pub negated: bool,
+    /// Match only directories.
+    pub dir_only: bool,
+}
+
+impl IgnoreRules {
+    /// Generate the rules from the paths of the entries, i.e. most of the rules match some of
+    /// the entries.
+    ///
+    /// The patterns are literal names and paths, extensions (`*.ext`), prefixes (`abc*`),
+    /// single-byte wildcards (`a?c`) and recursive wildcards (`a/**`, `**/c`).
+    pub fn arbitrary(u: &mut Unstructured<'_>, entries: &[Entry]) -> arbitrary::Result<Self> {
+        let mut rules = Vec::new();
+        if entries.is_empty() {
+            return Ok(Self { rules });
+        }
+        let num_rules = u.int_in_range(1..=MAX_RULES)?;
+        for _ in 0..num_rules {
+            let entry = u.choose(entries)?;
+            let components: Vec<&OsStr> = entry.path.iter().collect();
+            let i = u.choose_index(components.len())?;
+            let is_dir = i + 1 != components.len() || entry.file_type == FileType::Directory;
+            let name = components[i].as_bytes();
+            let mut pattern = Vec::new();
+            match u.int_in_range(0..=5)? {
+                0 => pattern.extend(escape(name)),
+                1 => {
+                    for component in components[..=i].iter() {
+                        pattern.push(b'/');
+                        pattern.extend(escape(component.as_bytes()));
+                    }
+                }
+                2 => match name.iter().rposition(|c| *c == b'.') {
+                    Some(j) if j != 0 => {
+                        pattern.push(b'*');
+                        pattern.extend(escape(&name[j..]));
+                    }
+                    _ => pattern.extend(escape(name)),
+                },
+                3 => {
+                    let len = u.int_in_range(0..=name.len())?;
+                    pattern.extend(escape(&name[..len]));
+                    pattern.push(b'*');
+                }
+                4 => {
+                    let j = u.choose_index(name.len())?;
+                    pattern.extend(escape(&name[..j]));
+                    pattern.push(b'?');
+                    pattern.extend(escape(&name[j + 1..]));
+                }
+                _ if u.arbitrary()? => {
+                    pattern.extend(b"**/");
+                    pattern.extend(escape(name));
+                }
+                _ => {
+                    for component in components[..=i].iter() {
+                        pattern.extend(escape(component.as_bytes()));
+                        pattern.push(b'/');
+                    }
+                    pattern.extend(b"**");
+                }
+            }
+            rules.push(IgnoreRule {
+                pattern: OsString::from_vec(pattern),
+                negated: u.ratio(1, 3)?,
+                dir_only: is_dir && u.ratio(1, 4)?,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// Is the path relative to the root ignored?
+    ///
+    /// The path is ignored if the last matching rule is not negated or if any of its parent
+    /// directories is ignored, i.e. the files can't be re-included in an ignored directory.
+    pub fn is_ignored<P: AsRef<Path>>(&self, path: P, is_dir: bool) -> bool {
+        let path = path.as_ref();
+        let ancestors: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .collect();
+        ancestors
+            .into_iter()
+            .rev()
+            .any(|parent| self.matches(parent, true))
+            || self.matches(path, is_dir)
+    }
+
+    /// The paths of the entries that are ignored (see [`is_ignored`](Self::is_ignored)).
+    pub fn ignored_paths<'a>(&self, entries: &'a [Entry]) -> Vec<&'a Path> {
+        entries
+            .iter()
+            .filter(|entry| self.is_ignored(&entry.path, entry.file_type == FileType::Directory))
+            .map(|entry| entry.path.as_path())
+            .collect()
+    }
+
+    /// Write the rules one per line in `.gitignore` format.
+    ///
+    /// The rules are separated by newlines, i.e. the names with newlines can't be represented.
+    /// Git also strips the trailing carriage return from each line.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        for rule in self.rules.iter() {
+            if rule.negated {
+                writer.write_all(b"!")?;
+            }
+            writer.write_all(rule.pattern.as_bytes())?;
+            if rule.dir_only {
+                writer.write_all(b"/")?;
+            }
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Does the last matching rule ignore the path (without checking the parents)?
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        let path = path.as_os_str().as_bytes();
+        let name = &path[path.iter().rposition(|c| *c == b'/').map_or(0, |i| i + 1)..];
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                if rule.dir_only && !is_dir {
+                    return false;
+                }
+                let pattern = rule.pattern.as_bytes();
+                match pattern.strip_prefix(b"/") {
+                    Some(pattern) => glob_match(pattern, path),
+                    None if pattern.contains(&b'/') => glob_match(pattern, path),
+                    None => glob_match(pattern, name),
+                }
+            })
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl crate::DirBuilder {
+    /// Create the directory and generate the ignore rules for it (see
+    /// [`IgnoreRules::arbitrary`]).
+    pub fn create_with_ignore_rules(
+        self,
+        u: &mut Unstructured<'_>,
+    ) -> arbitrary::Result<(crate::Dir, IgnoreRules)> {
+        let dir = self.create(u)?;
+        let rules = IgnoreRules::arbitrary(u, dir.entries())?;
+        Ok((dir, rules))
+    }
+}
+
+/// Match the whole `path` against the glob `pattern`.
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => (0..=path.len())
+            .filter(|i| *i == 0 || path[i - 1] == b'/')
+            .any(|i| glob_match(rest, &path[i..])),
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob_match(rest, &path[i..])),
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|i| *i == 0 || path[i - 1] != b'/')
+            .any(|i| glob_match(rest, &path[i..])),
+        [b'?', rest @ ..] => !path.is_empty() && path[0] != b'/' && glob_match(rest, &path[1..]),
+        [b'\\', c, rest @ ..] | [c, rest @ ..] => {
+            !path.is_empty() && path[0] == *c && glob_match(rest, &path[1..])
+        }
+    }
+}
+
+/// Escape the glob metacharacters and the bytes that have special meaning in `.gitignore`.
+fn escape(name: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(name.len());
+    for (i, c) in name.iter().enumerate() {
+        let special = match c {
+            b'*' | b'?' | b'[' | b'\\' => true,
+            b'!' | b'#' => i == 0,
+            // trailing spaces are stripped
+            b' ' => name[i..].iter().all(|c| *c == b' '),
+            _ => false,
+        };
+        if special {
+            escaped.push(b'\\');
+        }
+        escaped.push(*c);
+    }
+    escaped
+}
+
+const MAX_RULES: usize = 8;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_oracle() {
+        for (lines, path, is_dir, expected) in [
+            // `**`
+            (&["**/foo"][..], "foo", false, true),
+            (&["**/foo"], "a/b/foo", false, true),
+            (&["**/foo"], "afoo", false, false),
+            (&["a/**"], "a", true, false),
+            (&["a/**"], "a/b", false, true),
+            (&["a/**"], "a/b/c", false, true),
+            (&["a/**/b"], "a/b", false, true),
+            (&["a/**/b"], "a/x/y/b", false, true),
+            (&["a/**/b"], "a/xb", false, false),
+            // `!`
+            (&["*.log", "!keep.log"], "a.log", false, true),
+            (&["*.log", "!keep.log"], "keep.log", false, false),
+            (&["*.log", "!keep.log"], "d/keep.log", false, false),
+            (&["!keep.log", "*.log"], "keep.log", false, true),
+            (&["build/", "!build/x"], "build/x", false, true),
+            // trailing `/`
+            (&["tmp/"], "tmp", true, true),
+            (&["tmp/"], "a/tmp", true, true),
+            (&["tmp/"], "tmp", false, false),
+            (&["tmp/"], "tmp/x", false, true),
+            // anchored
+            (&["/foo"], "foo", false, true),
+            (&["/foo"], "a/foo", false, false),
+            (&["a/b"], "a/b", false, true),
+            (&["a/b"], "x/a/b", false, false),
+            (&["/*.c"], "x.c", false, true),
+            (&["/*.c"], "d/x.c", false, false),
+            // wildcards and escapes
+            (&["*.c"], "d/x.c", false, true),
+            (&["a?c"], "abc", false, true),
+            (&["a?c"], "a/c", false, false),
+            (&["\\*"], "*", false, true),
+            (&["\\*"], "a", false, false),
+        ] {
+            let rules = IgnoreRules {
+                rules: lines.iter().map(|line| parse(line)).collect(),
+            };
+            assert_eq!(
+                expected,
+                rules.is_ignored(path, is_dir),
+                "{lines:?} {path:?} {is_dir}"
+            );
+        }
+    }
+
+    #[test]
+    fn write_to() {
+        let rules = IgnoreRules {
+            rules: ["a", "!b/", "/c/**"]
+                .iter()
+                .map(|line| parse(line))
+                .collect(),
+        };
+        let mut buf = Vec::new();
+        rules.write_to(&mut buf).unwrap();
+        assert_eq!(b"a\n!b/\n/c/**\n", &buf[..]);
+    }
+
+    fn parse(line: &str) -> IgnoreRule {
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        IgnoreRule {
+            pattern: line.into(),
+            negated,
+            dir_only,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Error;
+use std::marker::PhantomData;
+use std::os::fd::AsRawFd;
+use std::os::fd::OwnedFd;
+use std::path::Path;
+use std::path::PathBuf;
+
+use arbitrary::Unstructured;
+
+use crate::path_to_c_string;
+use crate::Dir;
+use crate::DirBuilder;
+
+impl DirBuilder {
+    /// Populate a fresh tmpfs mounted at `mount_point` inside a new private mount namespace.
+    ///
+    /// The mount is visible only to the calling thread and the threads it spawns. The host's
+    /// directory that serves as the mount point (e.g. `/mnt`) is left intact.
+    /// Requires `CAP_SYS_ADMIN`.
+    /// The returned handle unmounts the file system and restores the original mount namespace
+    /// on drop; it must be dropped on the same thread.
+    pub fn create_isolated<P: Into<PathBuf>>(
+        self,
+        mount_point: P,
+        u: &mut Unstructured<'_>,
+    ) -> Result<IsolatedDir, Error> {
+        let mount_point = mount_point.into();
+        let namespace: OwnedFd = File::open("/proc/thread-self/ns/mnt")?.into();
+        let cwd: OwnedFd = File::open(".")?.into();
+        check(unsafe { libc::unshare(libc::CLONE_NEWNS) })?;
+        let mut isolated = IsolatedDir {
+            dir: None,
+            mount_point,
+            mounted: false,
+            namespace: Some(namespace),
+            cwd,
+            _not_send: PhantomData,
+        };
+        // do not propagate the new mounts to the host
+        mount(None, "/".as_ref(), None, libc::MS_REC | libc::MS_PRIVATE)?;
+        mount(
+            Some("tmpfs"),
+            &isolated.mount_point,
+            Some("tmpfs"),
+            libc::MS_NOSUID | libc::MS_NODEV,
+        )?;
+        isolated.mounted = true;
+        let dir = self
+            .create_in(&isolated.mount_point, u)
+            .map_err(Error::other)?;
+        isolated.dir = Some(dir);
+        Ok(isolated)
+    }
+}
+
+/// Directory generated on a tmpfs in a private mount namespace.
+///
+/// Created via [`DirBuilder::create_isolated`]. Unmounted on drop.
+pub struct IsolatedDir {
+    // `None` only if the population failed
+    dir: Option<Dir>,
+    mount_point: PathBuf,
+    mounted: bool,
+    // `None` after the teardown
+    namespace: Option<OwnedFd>,
+    cwd: OwnedFd,
+    // namespaces are per-thread
+    _not_send: PhantomData<*const ()>,
+}
+
+impl IsolatedDir {
+    /// Get directory path, i.e. the mount point.
+    pub fn path(&self) -> &Path {
+        self.mount_point.as_path()
+    }
+
+    /// Get the generated directory.
+    pub fn dir(&self) -> &Dir {
+        self.dir.as_ref().unwrap()
+    }
+
+    /// Unmount the file system and restore the original mount namespace.
+    ///
+    /// Use this method instead of dropping the handle to handle the errors.
+    pub fn teardown(mut self) -> Result<(), Error> {
+        self.do_teardown()
+    }
+
+    fn do_teardown(&mut self) -> Result<(), Error> {
+        self.dir = None;
+        if self.mounted {
+            let path = path_to_c_string(self.mount_point.clone())?;
+            check(unsafe { libc::umount2(path.as_ptr(), libc::MNT_DETACH) })?;
+            self.mounted = false;
+        }
+        let Some(namespace) = self.namespace.take() else {
+            return Ok(());
+        };
+        check(unsafe { libc::setns(namespace.as_raw_fd(), libc::CLONE_NEWNS) })?;
+        // `setns` changes the current working directory to the root
+        check(unsafe { libc::fchdir(self.cwd.as_raw_fd()) })?;
+        Ok(())
+    }
+}
+
+impl Drop for IsolatedDir {
+    fn drop(&mut self) {
+        if let Err(e) = self.do_teardown() {
+            eprintln!(
+                "random-dir: failed to tear down `{}`: {}",
+                self.mount_point.display(),
+                e
+            );
+        }
+    }
+}
+
+fn mount(
+    source: Option<&str>,
+    target: &Path,
+    fstype: Option<&str>,
+    flags: libc::c_ulong,
+) -> Result<(), Error> {
+    let source = source.map(CString::new).transpose()?;
+    let target = path_to_c_string(target.to_path_buf())?;
+    let fstype = fstype.map(CString::new).transpose()?;
+    check(unsafe {
+        libc::mount(
+            source.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            target.as_ptr(),
+            fstype.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            flags,
+            std::ptr::null(),
+        )
+    })
+}
+
+fn check(ret: libc::c_int) -> Result<(), Error> {
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+
+use crate::DiffKind;
+use crate::Difference;
+use crate::DirDiff;
+use crate::MetadataField;
+
+/// Metadata fields reported by `rsync --itemize-changes`.
+pub const RSYNC_ITEMIZED_FIELDS: [MetadataField; 5] = [
+    MetadataField::Mode,
+    MetadataField::Uid,
+    MetadataField::Gid,
+    MetadataField::Mtime,
+    MetadataField::FileSize,
+];
+
+impl DirDiff {
+    /// Convert `rsync --itemize-changes` output into the differences between the source and
+    /// the destination directory before the transfer.
+    ///
+    /// The lines that are not itemized changes (file lists headers, transfer statistics etc.)
+    /// are ignored as well as the destination root directory itself (`./`).
+    /// Transferred files are reported as having different contents.
+    /// Compare the result with the actual differences restricted to [`RSYNC_ITEMIZED_FIELDS`]
+    /// (see [`DirDiff::restricted_to`]).
+    pub fn from_rsync_itemized(output: &str) -> Self {
+        let mut differences = Vec::new();
+        for line in output.lines() {
+            if let Some(path) = line.strip_prefix("*deleting") {
+                differences.push(Difference {
+                    path: item_path(path.trim_start()),
+                    kind: DiffKind::Removed,
+                });
+                continue;
+            }
+            let Some((flags, path)) = line.split_at_checked(ITEM_FLAGS_LEN) else {
+                continue;
+            };
+            let flags = flags.as_bytes();
+            if !b"<>ch.".contains(&flags[0]) || !b"fdLDS".contains(&flags[1]) {
+                continue;
+            }
+            let Some(path) = path.strip_prefix(' ') else {
+                continue;
+            };
+            let path = item_path(path);
+            if path.as_os_str() == "." {
+                continue;
+            }
+            let attributes = &flags[2..];
+            if attributes.iter().all(|b| *b == b'+') {
+                differences.push(Difference {
+                    path,
+                    kind: DiffKind::Added,
+                });
+                continue;
+            }
+            if matches!(flags[0], b'<' | b'>') && flags[1] == b'f' || attributes[0] == b'c' {
+                differences.push(Difference {
+                    path: path.clone(),
+                    kind: DiffKind::Content,
+                });
+            }
+            // the same order as in `MetadataField::diff`
+            let mut fields = Vec::new();
+            if attributes[3] == b'p' {
+                fields.push(MetadataField::Mode);
+            }
+            if attributes[4] == b'o' {
+                fields.push(MetadataField::Uid);
+            }
+            if attributes[5] == b'g' {
+                fields.push(MetadataField::Gid);
+            }
+            if attributes[2] == b't' || attributes[2] == b'T' {
+                fields.push(MetadataField::Mtime);
+            }
+            if attributes[1] == b's' {
+                fields.push(MetadataField::FileSize);
+            }
+            if !fields.is_empty() {
+                differences.push(Difference {
+                    path,
+                    kind: DiffKind::Metadata(fields),
+                });
+            }
+        }
+        differences.into()
+    }
+}
+
+/// Strip the trailing slash of the directories and the link targets, and unescape `\#ooo`
+/// octal escapes.
+fn item_path(path: &str) -> PathBuf {
+    let path = match path.split_once(" -> ").or_else(|| path.split_once(" => ")) {
+        Some((path, _target)) => path,
+        None => path,
+    };
+    let path = path.strip_suffix('/').unwrap_or(path);
+    if path.is_empty() {
+        return ".".into();
+    }
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((b, tail)) = rest.split_first() {
+        let escaped = match tail {
+            [b'#', digits @ ..] if *b == b'\\' && digits.len() >= 3 => {
+                std::str::from_utf8(&digits[..3])
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &tail[4..];
+            }
+            None => {
+                bytes.push(*b);
+                rest = tail;
+            }
+        }
+    }
+    OsString::from_vec(bytes).into()
+}
+
+/// `YXcstpoguax`
+const ITEM_FLAGS_LEN: usize = 11;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rsync_itemized() {
+        use MetadataField::*;
+        let content = || DiffKind::Content;
+        let metadata = DiffKind::Metadata;
+        for (line, expected) in [
+            (
+                "*deleting   old/file",
+                vec![("old/file", DiffKind::Removed)],
+            ),
+            ("*deleting   old/dir/", vec![("old/dir", DiffKind::Removed)]),
+            ("cd+++++++++ new/", vec![("new", DiffKind::Added)]),
+            (">f+++++++++ new/file", vec![("new/file", DiffKind::Added)]),
+            ("cL+++++++++ a -> b", vec![("a", DiffKind::Added)]),
+            ("hf+++++++++ b => a", vec![("b", DiffKind::Added)]),
+            ("cS+++++++++ socket", vec![("socket", DiffKind::Added)]),
+            (
+                ">f.st...... file",
+                vec![
+                    ("file", content()),
+                    ("file", metadata(vec![Mtime, FileSize])),
+                ],
+            ),
+            (
+                ">f....og... file",
+                vec![("file", content()), ("file", metadata(vec![Uid, Gid]))],
+            ),
+            (">f......... file", vec![("file", content())]),
+            (".d..t...... dir/", vec![("dir", metadata(vec![Mtime]))]),
+            (".f..T...... file", vec![("file", metadata(vec![Mtime]))]),
+            (".f...p..... file", vec![("file", metadata(vec![Mode]))]),
+            (".L..t...... a -> b", vec![("a", metadata(vec![Mtime]))]),
+            (
+                "cLc.t...... a -> c",
+                vec![("a", content()), ("a", metadata(vec![Mtime]))],
+            ),
+            ("cd+++++++++ a\\#012b/", vec![("a\nb", DiffKind::Added)]),
+            ("cd+++++++++ \\#303\\#244", vec![("ä", DiffKind::Added)]),
+            ("cd+++++++++ a\\#xyz", vec![("a\\#xyz", DiffKind::Added)]),
+            ("cd+++++++++ a\\#01", vec![("a\\#01", DiffKind::Added)]),
+            (".d..t...... ./", vec![]),
+            ("sending incremental file list", vec![]),
+            (
+                "sent 100 bytes  received 20 bytes  240.00 bytes/sec",
+                vec![],
+            ),
+            ("", vec![]),
+            (">f+++++++++", vec![]),
+        ] {
+            let expected: Vec<Difference> = expected
+                .into_iter()
+                .map(|(path, kind)| Difference {
+                    path: path.into(),
+                    kind,
+                })
+                .collect();
+            let actual = DirDiff::from_rsync_itemized(line);
+            assert_eq!(expected, actual.differences(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn multiple_lines() {
+        let output = "sending incremental file list\n\
+                      .d..t...... ./\n\
+                      *deleting   a\n\
+                      >f+++++++++ b\n\
+                      \n\
+                      sent 1 bytes  received 2 bytes\n";
+        let paths: Vec<PathBuf> = DirDiff::from_rsync_itemized(output)
+            .differences()
+            .iter()
+            .map(|difference| difference.path.clone())
+            .collect();
+        assert_eq!(vec![PathBuf::from("a"), PathBuf::from("b")], paths);
+    }
+}
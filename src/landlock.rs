@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::Error;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::path::Path;
+
+/// Restrict the calling thread's file system access to `root` using Landlock.
+///
+/// Removing `root` itself is also allowed. Does nothing if the kernel doesn't support Landlock.
+pub(crate) fn restrict_to(root: &Path) -> Result<(), Error> {
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0_usize,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 0 {
+        let error = Error::last_os_error();
+        return match error.raw_os_error() {
+            Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP) => Ok(()),
+            _ => Err(error),
+        };
+    }
+    let handled_access_fs = handled_access_fs(abi);
+    let attr = RulesetAttr { handled_access_fs };
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0_u32,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    let ruleset = unsafe { OwnedFd::from_raw_fd(ret as libc::c_int) };
+    add_path_rule(&ruleset, root, handled_access_fs)?;
+    if let Some(parent) = root.parent() {
+        add_path_rule(&ruleset, parent, LANDLOCK_ACCESS_FS_REMOVE_DIR)?;
+    }
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0_u32) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn add_path_rule(ruleset: &OwnedFd, path: &Path, allowed_access: u64) -> Result<(), Error> {
+    let file = File::open(path)?;
+    let attr = PathBeneathAttr {
+        allowed_access,
+        parent_fd: file.as_raw_fd(),
+    };
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0_u32,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// All file system access rights supported by the specified ABI version.
+fn handled_access_fs(abi: libc::c_long) -> u64 {
+    let mut access = (LANDLOCK_ACCESS_FS_MAKE_SYM << 1) - 1;
+    if abi >= 2 {
+        access |= LANDLOCK_ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        access |= LANDLOCK_ACCESS_FS_TRUNCATE;
+    }
+    if abi >= 5 {
+        access |= LANDLOCK_ACCESS_FS_IOCTL_DEV;
+    }
+    access
+}
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+const LANDLOCK_ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+const LANDLOCK_ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+const LANDLOCK_ACCESS_FS_REFER: u64 = 1 << 13;
+const LANDLOCK_ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+const LANDLOCK_ACCESS_FS_IOCTL_DEV: u64 = 1 << 15;
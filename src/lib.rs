@@ -1,7 +1,178 @@
-#![doc = include_str!("../README.md")]
+#![cfg_attr(feature = "fs", doc = include_str!("../README.md"))]
+#![cfg_attr(
+    not(feature = "fs"),
+    doc = "Manifests, in-memory trees and archive writers without the file system support."
+)]
 
+mod acl;
+mod age;
+#[cfg(feature = "async")]
+mod async_io;
+#[cfg(feature = "fs")]
+mod check;
+#[cfg(feature = "fs")]
+mod concurrent;
+#[cfg(feature = "fs")]
+mod config_error;
+#[cfg(feature = "fs")]
+mod content;
+#[cfg(feature = "fs")]
+mod copy;
+#[cfg(feature = "fs")]
+mod corpus;
+#[cfg(feature = "cpio")]
+mod cpio;
+mod diff;
+#[cfg(feature = "fs")]
 mod dir;
+mod dot;
+#[cfg(feature = "fs")]
+mod dump;
+mod encoding;
+mod entry;
+#[cfg(feature = "fs")]
+mod fifo;
+#[cfg(feature = "fs")]
+mod fingerprint;
+#[cfg(all(target_os = "linux", feature = "fuse"))]
+mod fuse;
+#[cfg(feature = "fs")]
+mod golden;
+#[cfg(feature = "fs")]
+mod guard;
+mod hash;
+mod ignore;
+#[cfg(all(target_os = "linux", feature = "fs"))]
+mod isolated;
+mod itemize;
+#[cfg(all(target_os = "linux", feature = "fs"))]
+mod landlock;
+mod limits;
+#[cfg(feature = "fs")]
 mod mk;
+#[cfg(feature = "fs")]
+mod mutate;
+#[cfg(feature = "fs")]
+mod name;
+#[cfg(feature = "fs")]
+mod overlap;
+mod preset;
+#[cfg(feature = "fs")]
+pub mod probe;
+mod profile;
+#[cfg(feature = "fs")]
+mod queue;
+#[cfg(feature = "fs")]
+mod readdir_order;
+#[cfg(feature = "fs")]
+mod scenario;
+#[cfg(feature = "fs")]
+mod shape;
+#[cfg(feature = "fs")]
+mod space;
+mod spec;
+#[cfg(feature = "fs")]
+mod split;
+mod stats;
+mod store;
+#[cfg(feature = "tar")]
+mod tar;
+#[cfg(feature = "fs")]
+mod teardown;
+mod traversal;
+#[cfg(feature = "fs")]
+mod tree_profile;
+#[cfg(all(target_os = "linux", feature = "fs"))]
+mod userns;
+mod vfs;
+#[cfg(feature = "zip")]
+mod zip;
 
+pub use self::acl::*;
+pub use self::age::*;
+#[cfg(feature = "async")]
+pub use self::async_io::*;
+#[cfg(feature = "fs")]
+pub use self::check::*;
+#[cfg(feature = "fs")]
+pub use self::concurrent::*;
+#[cfg(feature = "fs")]
+pub use self::config_error::*;
+#[cfg(feature = "fs")]
+pub use self::content::*;
+#[cfg(feature = "fs")]
+pub(crate) use self::copy::*;
+#[cfg(feature = "fs")]
+pub use self::corpus::*;
+pub use self::diff::*;
+#[cfg(feature = "fs")]
+pub(crate) use self::dir::arbitrary_char_dev;
+#[cfg(feature = "fs")]
+pub(crate) use self::dir::list_dir_all_with_hook;
+#[cfg(all(target_os = "linux", feature = "acl"))]
+pub(crate) use self::dir::read_acl;
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+pub(crate) use self::dir::read_inode_flags;
+#[cfg(all(target_os = "linux", feature = "selinux"))]
+pub(crate) use self::dir::read_selinux_context;
+#[cfg(feature = "fs")]
 pub use self::dir::*;
+#[cfg(feature = "fs")]
+pub use self::dump::*;
+pub use self::encoding::*;
+pub use self::entry::*;
+#[cfg(feature = "fs")]
+pub(crate) use self::fifo::*;
+#[cfg(feature = "fs")]
+pub use self::fingerprint::*;
+#[cfg(all(target_os = "linux", feature = "fuse"))]
+pub use self::fuse::*;
+#[cfg(feature = "fs")]
+pub use self::golden::*;
+#[cfg(feature = "fs")]
+pub use self::guard::*;
+pub use self::hash::*;
+pub use self::ignore::*;
+#[cfg(all(target_os = "linux", feature = "fs"))]
+pub use self::isolated::*;
+pub use self::itemize::*;
+#[cfg(all(target_os = "linux", feature = "fs"))]
+pub(crate) use self::landlock::*;
+pub use self::limits::*;
+#[cfg(feature = "fs")]
 pub(crate) use self::mk::*;
+#[cfg(feature = "fs")]
+pub use self::mutate::*;
+#[cfg(feature = "fs")]
+pub use self::name::*;
+#[cfg(feature = "fs")]
+pub use self::overlap::*;
+pub use self::preset::*;
+pub use self::profile::*;
+#[cfg(feature = "fs")]
+pub(crate) use self::queue::*;
+#[cfg(feature = "fs")]
+pub use self::readdir_order::*;
+#[cfg(feature = "fs")]
+pub use self::scenario::*;
+#[cfg(feature = "fs")]
+pub use self::shape::*;
+#[cfg(feature = "fs")]
+pub use self::space::*;
+#[cfg(any(feature = "tar", feature = "cpio"))]
+pub(crate) use self::spec::split_device_number;
+pub use self::spec::*;
+#[cfg(feature = "fs")]
+pub use self::split::*;
+pub use self::stats::*;
+pub use self::store::*;
+#[cfg(feature = "fs")]
+pub use self::teardown::*;
+pub use self::traversal::*;
+#[cfg(feature = "fs")]
+pub use self::tree_profile::*;
+#[cfg(all(target_os = "linux", feature = "fs"))]
+pub(crate) use self::userns::*;
+pub use self::vfs::*;
+#[cfg(feature = "zip")]
+pub use self::zip::*;
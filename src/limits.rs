@@ -0,0 +1,201 @@
+#[cfg(feature = "fs")]
+use std::ffi::OsStr;
+#[cfg(feature = "fs")]
+use std::fs::hard_link;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(feature = "fs")]
+use std::os::unix::fs::symlink;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(feature = "fs")]
+use arbitrary::Unstructured;
+
+#[cfg(feature = "fs")]
+use crate::Entry;
+#[cfg(feature = "fs")]
+use crate::FileType;
+#[cfg(feature = "fs")]
+use crate::NameGenerator;
+
+/// Archiver limit targeted by a crafted entry.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Limit {
+    /// Relative path is exactly this many bytes long.
+    PathLength(usize),
+    /// Regular file has exactly this many hard links.
+    LinkCount(u64),
+    /// Symbolic link target is exactly this many bytes long.
+    SymlinkTargetLength(usize),
+}
+
+/// An entry crafted to hit some archiver limit.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CraftedEntry {
+    /// Path relative to the directory root.
+    pub path: PathBuf,
+    /// Targeted limit.
+    pub limit: Limit,
+}
+
+/// Ustar name field length.
+pub const USTAR_NAME_LEN: usize = 100;
+
+/// Ustar prefix field length.
+pub const USTAR_PREFIX_LEN: usize = 155;
+
+/// Maximum file name length in bytes.
+pub const NAME_MAX: usize = 255;
+
+/// Maximum path length in bytes including the terminating NUL byte.
+#[cfg(not(target_os = "macos"))]
+pub const PATH_MAX: usize = 4096;
+
+/// Maximum path length in bytes including the terminating NUL byte.
+#[cfg(target_os = "macos")]
+pub const PATH_MAX: usize = 1024;
+
+/// Path lengths targeted by [`StressMode::HeaderLimits`](crate::StressMode::HeaderLimits):
+/// ustar name and prefix field lengths and the maximum file name length.
+pub const LIMIT_PATH_LENGTHS: [usize; 3] = [USTAR_NAME_LEN, USTAR_PREFIX_LEN, 255];
+
+/// Hard link counts targeted by [`StressMode::HeaderLimits`](crate::StressMode::HeaderLimits):
+/// just above 8-bit counter.
+pub const LIMIT_LINK_COUNTS: [u64; 1] = [256];
+
+/// Symlink target lengths targeted by
+/// [`StressMode::HeaderLimits`](crate::StressMode::HeaderLimits): ustar link name field length,
+/// one byte past it and the longest possible target.
+pub const LIMIT_SYMLINK_TARGET_LENGTHS: [usize; 3] =
+    [USTAR_NAME_LEN, USTAR_NAME_LEN + 1, PATH_MAX - 1];
+
+#[cfg(feature = "fs")]
+pub(crate) fn create_limit_entries(
+    u: &mut Unstructured<'_>,
+    name_generator: &mut dyn NameGenerator,
+    root: &Path,
+    manifest: &mut Vec<Entry>,
+) -> arbitrary::Result<Vec<CraftedEntry>> {
+    let mut chars: Vec<u8> = name_generator
+        .generate(u)?
+        .as_bytes()
+        .iter()
+        .copied()
+        .filter(|b| *b != b'/' && *b != 0)
+        .collect();
+    if chars.is_empty() {
+        chars.push(b'x');
+    }
+    let mut entries = Vec::new();
+    for len in LIMIT_PATH_LENGTHS {
+        let Some(path) = create_unique(root, &mut chars, len, |path| File::create(path).is_ok())
+        else {
+            continue;
+        };
+        manifest.push(Entry::from_file(root, path.clone(), FileType::Regular, None).unwrap());
+        entries.push(CraftedEntry {
+            path,
+            limit: Limit::PathLength(len),
+        });
+    }
+    for num_links in LIMIT_LINK_COUNTS {
+        let Some(path) = create_unique(root, &mut chars, 8, |path| File::create(path).is_ok())
+        else {
+            continue;
+        };
+        manifest.push(Entry::from_file(root, path.clone(), FileType::Regular, None).unwrap());
+        let original = root.join(&path);
+        let mut n = 1;
+        let mut i = 0;
+        while n < num_links && i < 2 * num_links {
+            let mut link = path.clone().into_os_string();
+            link.push(format!(".{}", i));
+            if hard_link(&original, root.join(&link)).is_ok() {
+                let entry =
+                    Entry::from_file(root, link.into(), FileType::HardLink, Some(path.clone()));
+                manifest.push(entry.unwrap());
+                n += 1;
+            }
+            i += 1;
+        }
+        if n == num_links {
+            entries.push(CraftedEntry {
+                path,
+                limit: Limit::LinkCount(num_links),
+            });
+        }
+    }
+    for target_len in LIMIT_SYMLINK_TARGET_LENGTHS {
+        let target = fill_path(&chars, target_len);
+        let Some(path) = create_unique(root, &mut chars, 8, |path| symlink(&target, path).is_ok())
+        else {
+            continue;
+        };
+        let entry = Entry::from_file(root, path.clone(), FileType::Symlink, Some(target));
+        manifest.push(entry.unwrap());
+        entries.push(CraftedEntry {
+            path,
+            limit: Limit::SymlinkTargetLength(target_len),
+        });
+    }
+    Ok(entries)
+}
+
+/// Try to create a file with the path of the specified length varying the first character.
+#[cfg(feature = "fs")]
+fn create_unique<F>(root: &Path, chars: &mut [u8], len: usize, mut create: F) -> Option<PathBuf>
+where
+    F: FnMut(&Path) -> bool,
+{
+    for c in b'a'..=b'z' {
+        chars[0] = c;
+        let path = fill_path(chars, len);
+        let absolute_path = root.join(&path);
+        if absolute_path.symlink_metadata().is_ok() {
+            continue;
+        }
+        if let Some(parent) = absolute_path.parent() {
+            if std::fs::create_dir_all(parent).is_err() {
+                continue;
+            }
+        }
+        if create(&absolute_path) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Fill the relative path of exactly `len` bytes with `chars`.
+///
+/// Components are at most [`USTAR_NAME_LEN`] bytes long.
+#[cfg(feature = "fs")]
+fn fill_path(chars: &[u8], len: usize) -> PathBuf {
+    let mut path = Vec::with_capacity(len);
+    let mut chars = chars.iter().copied().cycle();
+    let mut remaining = len;
+    while remaining > 0 {
+        if !path.is_empty() {
+            path.push(b'/');
+            remaining -= 1;
+        }
+        let component_len = if remaining <= USTAR_NAME_LEN {
+            remaining
+        } else {
+            // leave room for the separator and at least one character
+            USTAR_NAME_LEN.min(remaining - 2)
+        };
+        let mut component: Vec<u8> = chars.by_ref().take(component_len).collect();
+        if component.iter().all(|b| *b == b'.') {
+            // do not allow special names
+            component[0] = b'_';
+        }
+        path.extend(component);
+        remaining -= component_len;
+    }
+    OsStr::from_bytes(&path).into()
+}
@@ -1,7 +1,20 @@
 use std::ffi::CStr;
 use std::ffi::CString;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs::File;
 use std::io::Error;
+use std::io::ErrorKind;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::ffi::OsStringExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::Component;
+use std::path::Path;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
@@ -11,6 +24,9 @@ use libc::AT_FDCWD;
 use libc::AT_SYMLINK_NOFOLLOW;
 use libc::UTIME_OMIT;
 
+use crate::unix_time;
+use crate::PATH_MAX;
+
 pub fn mkfifo(path: &CStr, mode: mode_t) -> Result<(), Error> {
     let ret = unsafe { libc::mkfifo(path.as_ptr(), mode) };
     if ret < 0 {
@@ -27,22 +43,232 @@ pub fn mknod(path: &CStr, mode: mode_t, dev: dev_t) -> Result<(), Error> {
     Ok(())
 }
 
-pub fn set_file_modified_time(path: &CStr, t: SystemTime) -> Result<(), Error> {
-    let Ok(d) = t.duration_since(SystemTime::UNIX_EPOCH) else {
+pub fn mkfifo_at(dirfd: BorrowedFd<'_>, name: &CStr, mode: mode_t) -> Result<(), Error> {
+    let ret = unsafe { libc::mkfifoat(dirfd.as_raw_fd(), name.as_ptr(), mode) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn mknod_at(dirfd: BorrowedFd<'_>, name: &CStr, mode: mode_t, dev: dev_t) -> Result<(), Error> {
+    let ret = unsafe { libc::mknodat(dirfd.as_raw_fd(), name.as_ptr(), mode, dev) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Create symbolic link `name` relative to `dirfd` that points to `target`.
+pub fn symlink_at(target: &CStr, dirfd: BorrowedFd<'_>, name: &CStr) -> Result<(), Error> {
+    let ret = unsafe { libc::symlinkat(target.as_ptr(), dirfd.as_raw_fd(), name.as_ptr()) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Create hard link `name` relative to `dirfd` to the file `original` relative to `original_dirfd`.
+pub fn link_at(
+    original_dirfd: BorrowedFd<'_>,
+    original: &CStr,
+    dirfd: BorrowedFd<'_>,
+    name: &CStr,
+) -> Result<(), Error> {
+    let ret = unsafe {
+        libc::linkat(
+            original_dirfd.as_raw_fd(),
+            original.as_ptr(),
+            dirfd.as_raw_fd(),
+            name.as_ptr(),
+            0,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The size of `sockaddr_un::sun_path` including the terminating NUL byte.
+#[cfg(not(target_os = "macos"))]
+pub const SUN_PATH_LEN: usize = 108;
+
+/// The size of `sockaddr_un::sun_path` including the terminating NUL byte.
+#[cfg(target_os = "macos")]
+pub const SUN_PATH_LEN: usize = 104;
+
+/// Bind UNIX socket to `path`.
+///
+/// On Linux the paths that don't fit into `sockaddr_un` are bound relative to the parent
+/// directory via `/proc/self/fd`.
+pub fn bind_socket(path: &Path) -> Result<(), Error> {
+    if path.as_os_str().len() < SUN_PATH_LEN {
+        UnixDatagram::bind(path)?;
         return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+        let parent = File::open(parent)?;
+        return bind_socket_at(parent.as_fd(), name);
+    }
+    Err(Error::new(
+        ErrorKind::InvalidInput,
+        format!("socket path `{}` is too long", path.display()),
+    ))
+}
+
+/// Bind UNIX socket to `name` relative to `dirfd` via `/proc/self/fd`.
+///
+/// Works for paths longer than `PATH_MAX`.
+#[cfg(target_os = "linux")]
+pub fn bind_socket_at(dirfd: BorrowedFd<'_>, name: &OsStr) -> Result<(), Error> {
+    let fd_path = Path::new("/proc/self/fd")
+        .join(dirfd.as_raw_fd().to_string())
+        .join(name);
+    if fd_path.as_os_str().len() >= SUN_PATH_LEN {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("socket name `{}` is too long", Path::new(name).display()),
+        ));
+    }
+    UnixDatagram::bind(fd_path)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_xattr(path: &CStr, name: &CStr, value: &[u8]) -> Result<(), Error> {
+    let ret = unsafe {
+        libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr().cast(),
+            value.len(),
+            0,
+        )
     };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns `None` if the attribute doesn't exist or is not supported.
+#[cfg(all(target_os = "linux", any(feature = "acl", feature = "selinux")))]
+pub fn get_xattr(path: &CStr, name: &CStr) -> Result<Option<Vec<u8>>, Error> {
+    loop {
+        let len = unsafe { libc::lgetxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        if len < 0 {
+            let error = Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::ENODATA) | Some(libc::EOPNOTSUPP) => Ok(None),
+                _ => Err(error),
+            };
+        }
+        let mut value = vec![0_u8; len as usize];
+        let ret = unsafe {
+            libc::lgetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if ret < 0 {
+            let error = Error::last_os_error();
+            match error.raw_os_error() {
+                // the attribute has grown
+                Some(libc::ERANGE) => continue,
+                Some(libc::ENODATA) | Some(libc::EOPNOTSUPP) => return Ok(None),
+                _ => return Err(error),
+            }
+        }
+        value.truncate(ret as usize);
+        return Ok(Some(value));
+    }
+}
+
+/// Returns zero if the file system doesn't support the flags.
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+pub fn get_inode_flags(path: &CStr) -> Result<u32, Error> {
+    let file = open_for_ioctl(path)?;
+    let mut flags: libc::c_int = 0;
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) };
+    if ret < 0 {
+        let error = Error::last_os_error();
+        return match error.raw_os_error() {
+            Some(libc::ENOTTY) | Some(libc::EOPNOTSUPP) => Ok(0),
+            _ => Err(error),
+        };
+    }
+    Ok(flags as u32)
+}
+
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+pub fn set_inode_flags(path: &CStr, flags: u32) -> Result<(), Error> {
+    let file = open_for_ioctl(path)?;
+    let flags = flags as libc::c_int;
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "chattr"))]
+fn open_for_ioctl(path: &CStr) -> Result<OwnedFd, Error> {
+    let fd = unsafe {
+        libc::open(
+            path.as_ptr(),
+            libc::O_RDONLY | libc::O_NONBLOCK | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub fn get_inode_flags(path: &CStr) -> Result<u32, Error> {
+    let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+    let ret = unsafe { libc::lstat(path.as_ptr(), &mut stat) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(stat.st_flags as u32)
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub fn set_inode_flags(path: &CStr, flags: u32) -> Result<(), Error> {
+    let ret = unsafe { libc::lchflags(path.as_ptr(), flags as _) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn set_file_modified_time(path: &CStr, t: SystemTime) -> Result<(), Error> {
+    set_file_modified_time_at(AT_FDCWD, path, t)
+}
+
+pub fn set_file_modified_time_at(
+    dirfd: libc::c_int,
+    path: &CStr,
+    t: SystemTime,
+) -> Result<(), Error> {
+    let (secs, nanos) = unix_time(t);
     let times = [
         libc::timespec {
             tv_sec: 0,
             tv_nsec: UTIME_OMIT,
         },
         libc::timespec {
-            tv_sec: d.as_secs() as libc::time_t,
-            tv_nsec: d.subsec_nanos() as libc::c_long,
+            tv_sec: secs as libc::time_t,
+            tv_nsec: nanos as libc::c_long,
         },
     ];
-    let ret =
-        unsafe { libc::utimensat(AT_FDCWD, path.as_ptr(), times.as_ptr(), AT_SYMLINK_NOFOLLOW) };
+    let ret = unsafe { libc::utimensat(dirfd, path.as_ptr(), times.as_ptr(), AT_SYMLINK_NOFOLLOW) };
     if ret < 0 {
         return Err(Error::last_os_error());
     }
@@ -52,3 +278,414 @@ pub fn set_file_modified_time(path: &CStr, t: SystemTime) -> Result<(), Error> {
 pub fn path_to_c_string(path: PathBuf) -> Result<CString, Error> {
     Ok(CString::new(path.into_os_string().into_vec())?)
 }
+
+pub fn os_str_to_c_string(s: &OsStr) -> Result<CString, Error> {
+    Ok(CString::new(s.as_bytes())?)
+}
+
+/// Open the directory `path` relative to `dirfd` creating it if needed.
+///
+/// Works for paths longer than `PATH_MAX`.
+pub fn create_dir_all_at(dirfd: BorrowedFd<'_>, path: &Path) -> Result<OwnedFd, Error> {
+    let mut fd = dirfd.try_clone_to_owned()?;
+    for component in path.components() {
+        let name = match component {
+            Component::Normal(name) => name,
+            Component::CurDir => continue,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("unsupported path component in `{}`", path.display()),
+                ))
+            }
+        };
+        let name = os_str_to_c_string(name)?;
+        let ret = unsafe { libc::mkdirat(fd.as_raw_fd(), name.as_ptr(), 0o755) };
+        if ret < 0 {
+            let error = Error::last_os_error();
+            if error.kind() != ErrorKind::AlreadyExists {
+                return Err(error);
+            }
+        }
+        fd = open_dir_at(fd.as_fd(), &name)?;
+    }
+    Ok(fd)
+}
+
+pub fn open_dir_at(dirfd: BorrowedFd<'_>, name: &CStr) -> Result<OwnedFd, Error> {
+    let ret = unsafe {
+        libc::openat(
+            dirfd.as_raw_fd(),
+            name.as_ptr(),
+            libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC | libc::O_RDONLY,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(ret) })
+}
+
+pub fn create_file_at(dirfd: BorrowedFd<'_>, name: &CStr, mode: mode_t) -> Result<File, Error> {
+    let ret = unsafe {
+        libc::openat(
+            dirfd.as_raw_fd(),
+            name.as_ptr(),
+            libc::O_CREAT | libc::O_EXCL | libc::O_WRONLY | libc::O_CLOEXEC | libc::O_NOFOLLOW,
+            mode as libc::c_uint,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(ret) })
+}
+
+/// Open the file for reading without following symlinks and without blocking on named pipes.
+pub fn open_file_at(dirfd: BorrowedFd<'_>, name: &CStr) -> Result<File, Error> {
+    let ret = unsafe {
+        libc::openat(
+            dirfd.as_raw_fd(),
+            name.as_ptr(),
+            libc::O_RDONLY | libc::O_CLOEXEC | libc::O_NOFOLLOW | libc::O_NONBLOCK,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(ret) })
+}
+
+pub fn read_link_at(dirfd: BorrowedFd<'_>, name: &CStr) -> Result<PathBuf, Error> {
+    let mut buf = vec![0_u8; PATH_MAX];
+    let ret = unsafe {
+        libc::readlinkat(
+            dirfd.as_raw_fd(),
+            name.as_ptr(),
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    buf.truncate(ret as usize);
+    Ok(PathBuf::from(OsString::from_vec(buf)))
+}
+
+pub fn mkdir_at(dirfd: BorrowedFd<'_>, name: &CStr, mode: mode_t) -> Result<(), Error> {
+    let ret = unsafe { libc::mkdirat(dirfd.as_raw_fd(), name.as_ptr(), mode) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn unlink_at(dirfd: BorrowedFd<'_>, name: &CStr, flags: libc::c_int) -> Result<(), Error> {
+    let ret = unsafe { libc::unlinkat(dirfd.as_raw_fd(), name.as_ptr(), flags) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn statvfs(path: &CStr) -> Result<libc::statvfs, Error> {
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::statvfs(path.as_ptr(), &mut stat) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(stat)
+}
+
+pub fn fchmod(fd: BorrowedFd<'_>, mode: mode_t) -> Result<(), Error> {
+    let ret = unsafe { libc::fchmod(fd.as_raw_fd(), mode) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn fchmod_at(dirfd: BorrowedFd<'_>, name: &CStr, mode: mode_t) -> Result<(), Error> {
+    let ret = unsafe { libc::fchmodat(dirfd.as_raw_fd(), name.as_ptr(), mode, 0) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Change the owner of `name` relative to `dirfd` (not following symlinks).
+pub fn chown_at(
+    dirfd: BorrowedFd<'_>,
+    name: &CStr,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+) -> Result<(), Error> {
+    let ret = unsafe {
+        libc::fchownat(
+            dirfd.as_raw_fd(),
+            name.as_ptr(),
+            uid,
+            gid,
+            AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Create an unnamed regular file in the directory (`O_TMPFILE`).
+///
+/// The file is removed on close unless it is linked in via [`link_unnamed_at`].
+#[cfg(target_os = "linux")]
+pub fn open_tmpfile_at(dirfd: BorrowedFd<'_>, mode: mode_t) -> Result<File, Error> {
+    let ret = unsafe {
+        libc::openat(
+            dirfd.as_raw_fd(),
+            c".".as_ptr(),
+            libc::O_TMPFILE | libc::O_RDWR | libc::O_CLOEXEC,
+            mode as libc::c_uint,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(ret) })
+}
+
+/// Give the unnamed file a name.
+///
+/// Links `/proc/self/fd/<fd>` instead of using `AT_EMPTY_PATH` that requires
+/// `CAP_DAC_READ_SEARCH`.
+#[cfg(target_os = "linux")]
+pub fn link_unnamed_at(
+    fd: BorrowedFd<'_>,
+    dirfd: BorrowedFd<'_>,
+    name: &CStr,
+) -> Result<(), Error> {
+    let original = CString::new(format!("/proc/self/fd/{}", fd.as_raw_fd()))?;
+    let ret = unsafe {
+        libc::linkat(
+            AT_FDCWD,
+            original.as_ptr(),
+            dirfd.as_raw_fd(),
+            name.as_ptr(),
+            libc::AT_SYMLINK_FOLLOW,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Share the extents of `src` with `dst` (copy-on-write clone).
+///
+/// Fails if the file system doesn't support reflinks or the files are on different file systems.
+#[cfg(target_os = "linux")]
+pub fn reflink(src: BorrowedFd<'_>, dst: BorrowedFd<'_>) -> Result<(), Error> {
+    let ret = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns `true` if some extents of the file are shared with other files (via `FIEMAP`).
+#[cfg(target_os = "linux")]
+pub fn has_shared_extents(fd: BorrowedFd<'_>) -> Result<bool, Error> {
+    const FS_IOC_FIEMAP: libc::c_ulong = 0xc020660b;
+    const FIEMAP_EXTENT_LAST: u32 = 0x1;
+    const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+    const NUM_EXTENTS: usize = 32;
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Extent {
+        logical: u64,
+        physical: u64,
+        length: u64,
+        reserved64: [u64; 2],
+        flags: u32,
+        reserved: [u32; 3],
+    }
+    #[repr(C)]
+    struct Fiemap {
+        start: u64,
+        length: u64,
+        flags: u32,
+        mapped_extents: u32,
+        extent_count: u32,
+        reserved: u32,
+        extents: [Extent; NUM_EXTENTS],
+    }
+    let mut start = 0;
+    loop {
+        let mut fiemap: Fiemap = unsafe { std::mem::zeroed() };
+        fiemap.start = start;
+        fiemap.length = u64::MAX - start;
+        fiemap.extent_count = NUM_EXTENTS as u32;
+        let ret = unsafe { libc::ioctl(fd.as_raw_fd(), FS_IOC_FIEMAP as _, &mut fiemap) };
+        if ret < 0 {
+            return Err(Error::last_os_error());
+        }
+        let extents = &fiemap.extents[..(fiemap.mapped_extents as usize).min(NUM_EXTENTS)];
+        if extents
+            .iter()
+            .any(|extent| extent.flags & FIEMAP_EXTENT_SHARED != 0)
+        {
+            return Ok(true);
+        }
+        match extents.last() {
+            Some(extent) if extent.flags & FIEMAP_EXTENT_LAST == 0 => {
+                start = extent.logical + extent.length;
+            }
+            _ => return Ok(false),
+        }
+    }
+}
+
+/// Get the metadata of `path` relative to `dirfd` (not following symlinks).
+///
+/// Works for paths longer than `PATH_MAX`.
+pub fn stat_path_at(dirfd: BorrowedFd<'_>, path: &Path) -> Result<libc::stat, Error> {
+    let (parent, name) = open_parent_at(dirfd, path)?;
+    stat_at(parent.as_fd(), &name)
+}
+
+/// Open the parent directory of `path` relative to `dirfd` without following symlinks.
+///
+/// Returns the parent directory and the last component of the path (`.` for an empty path).
+/// Works for paths longer than `PATH_MAX`.
+pub fn open_parent_at(dirfd: BorrowedFd<'_>, path: &Path) -> Result<(OwnedFd, CString), Error> {
+    let mut fd = dirfd.try_clone_to_owned()?;
+    let mut components = path.components().peekable();
+    while let Some(component) = components.next() {
+        let name = match component {
+            Component::Normal(name) => os_str_to_c_string(name)?,
+            Component::CurDir => continue,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("unsupported path component in `{}`", path.display()),
+                ))
+            }
+        };
+        if components.peek().is_none() {
+            return Ok((fd, name));
+        }
+        fd = open_dir_at(fd.as_fd(), &name)?;
+    }
+    Ok((fd, c".".into()))
+}
+
+/// Get the birth time of `name` relative to `dirfd` (not following symlinks).
+///
+/// Returns `None` if the file system doesn't record birth times.
+#[cfg(target_os = "linux")]
+pub fn btime_at(dirfd: BorrowedFd<'_>, name: &CStr) -> Result<Option<i64>, Error> {
+    let mut statx: libc::statx = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        libc::statx(
+            dirfd.as_raw_fd(),
+            name.as_ptr(),
+            AT_SYMLINK_NOFOLLOW,
+            libc::STATX_BTIME,
+            &mut statx,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    if statx.stx_mask & libc::STATX_BTIME == 0 {
+        return Ok(None);
+    }
+    Ok(Some(statx.stx_btime.tv_sec))
+}
+
+/// Get the birth time of `name` relative to `dirfd` (not following symlinks).
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub fn btime_at(dirfd: BorrowedFd<'_>, name: &CStr) -> Result<Option<i64>, Error> {
+    let stat = stat_at(dirfd, name)?;
+    Ok(Some(stat.st_birthtime as i64))
+}
+
+/// Birth times are not supported on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn btime_at(_dirfd: BorrowedFd<'_>, _name: &CStr) -> Result<Option<i64>, Error> {
+    Ok(None)
+}
+
+/// Returns `true` if `name` relative to `dirfd` is a directory (not following symlinks).
+pub fn is_dir_at(dirfd: BorrowedFd<'_>, name: &CStr) -> Result<bool, Error> {
+    let stat = stat_at(dirfd, name)?;
+    Ok(stat.st_mode & libc::S_IFMT == libc::S_IFDIR)
+}
+
+/// Get the metadata of `name` relative to `dirfd` (not following symlinks).
+pub fn stat_at(dirfd: BorrowedFd<'_>, name: &CStr) -> Result<libc::stat, Error> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        libc::fstatat(
+            dirfd.as_raw_fd(),
+            name.as_ptr(),
+            &mut stat,
+            AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(stat)
+}
+
+/// List the directory without following symlinks.
+///
+/// Returns the names and the file types (`None` if the file system doesn't report them).
+/// The special entries `.` and `..` are omitted.
+pub fn read_dir_at(dirfd: BorrowedFd<'_>) -> Result<Vec<(CString, Option<bool>)>, Error> {
+    let fd = dirfd.try_clone_to_owned()?;
+    let dir = unsafe { libc::fdopendir(fd.as_raw_fd()) };
+    if dir.is_null() {
+        return Err(Error::last_os_error());
+    }
+    // the descriptor is now owned by `dir`
+    std::mem::forget(fd);
+    // the duplicate shares the offset with the original descriptor
+    unsafe { libc::rewinddir(dir) };
+    let mut entries = Vec::new();
+    let result = loop {
+        unsafe { *errno() = 0 };
+        let entry = unsafe { libc::readdir(dir) };
+        if entry.is_null() {
+            let errno = unsafe { *errno() };
+            if errno != 0 {
+                break Err(Error::from_raw_os_error(errno));
+            }
+            break Ok(());
+        }
+        let entry = unsafe { &*entry };
+        let name = unsafe { CStr::from_ptr(entry.d_name.as_ptr()) };
+        if name.to_bytes() == b"." || name.to_bytes() == b".." {
+            continue;
+        }
+        let is_dir = match entry.d_type {
+            libc::DT_UNKNOWN => None,
+            d_type => Some(d_type == libc::DT_DIR),
+        };
+        entries.push((name.to_owned(), is_dir));
+    };
+    unsafe { libc::closedir(dir) };
+    result.map(|_| entries)
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn errno() -> *mut libc::c_int {
+    libc::__errno_location()
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn errno() -> *mut libc::c_int {
+    libc::__error()
+}
@@ -0,0 +1,516 @@
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::fs::OpenOptions;
+use std::fs::Permissions;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use arbitrary::Unstructured;
+use walkdir::WalkDir;
+
+use crate::path_to_c_string;
+use crate::set_file_modified_time;
+use crate::DiffKind;
+use crate::Difference;
+use crate::MetadataField;
+use crate::SafetyGuard;
+
+/// Random edit of an existing tree.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum MutationKind {
+    /// Change the modification time.
+    Touch,
+    /// Flip a byte in a regular file preserving its modification time.
+    FlipByte,
+    /// Rename a file.
+    Rename,
+    /// Delete a file.
+    Delete,
+    /// Add a regular file.
+    AddFile,
+    /// Change the permission bits.
+    Chmod,
+}
+
+/// All mutation kinds.
+pub const ALL_MUTATION_KINDS: [MutationKind; 6] = {
+    use MutationKind::*;
+    [Touch, FlipByte, Rename, Delete, AddFile, Chmod]
+};
+
+/// Set of mutation kinds that [`mutate`] chooses from.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct MutationKinds(u8);
+
+impl MutationKinds {
+    /// All mutation kinds.
+    pub const ALL: Self = Self::new(&ALL_MUTATION_KINDS);
+
+    /// Only the specified mutation kinds.
+    pub const fn new(kinds: &[MutationKind]) -> Self {
+        let mut bits = 0;
+        let mut i = 0;
+        while i < kinds.len() {
+            bits |= 1 << kinds[i] as u8;
+            i += 1;
+        }
+        Self(bits)
+    }
+
+    /// Add the kind.
+    pub const fn with(self, kind: MutationKind) -> Self {
+        Self(self.0 | 1 << kind as u8)
+    }
+
+    /// Remove the kind.
+    pub const fn without(self, kind: MutationKind) -> Self {
+        Self(self.0 & !(1 << kind as u8))
+    }
+
+    /// Is the kind in the set?
+    pub const fn contains(self, kind: MutationKind) -> bool {
+        self.0 & (1 << kind as u8) != 0
+    }
+}
+
+impl FromIterator<MutationKind> for MutationKinds {
+    fn from_iter<I: IntoIterator<Item = MutationKind>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::default(), Self::with)
+    }
+}
+
+/// Applied edit.
+///
+/// The paths are relative to the directory root.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Mutation {
+    /// The modification time was changed.
+    Touch {
+        /// File path.
+        path: PathBuf,
+        /// New modification time.
+        mtime: SystemTime,
+    },
+    /// The byte was flipped, the modification time was restored.
+    FlipByte {
+        /// File path.
+        path: PathBuf,
+        /// Byte offset.
+        offset: u64,
+    },
+    /// The file was renamed.
+    Rename {
+        /// Old path.
+        from: PathBuf,
+        /// New path.
+        to: PathBuf,
+    },
+    /// The file was deleted.
+    Delete {
+        /// File path.
+        path: PathBuf,
+    },
+    /// The regular file was added.
+    AddFile {
+        /// File path.
+        path: PathBuf,
+    },
+    /// The permission bits were changed.
+    Chmod {
+        /// File path.
+        path: PathBuf,
+        /// New permission bits.
+        mode: u32,
+    },
+}
+
+impl Mutation {
+    /// Mutation kind.
+    pub fn kind(&self) -> MutationKind {
+        match self {
+            Self::Touch { .. } => MutationKind::Touch,
+            Self::FlipByte { .. } => MutationKind::FlipByte,
+            Self::Rename { .. } => MutationKind::Rename,
+            Self::Delete { .. } => MutationKind::Delete,
+            Self::AddFile { .. } => MutationKind::AddFile,
+            Self::Chmod { .. } => MutationKind::Chmod,
+        }
+    }
+
+    /// The differences between the tree before and after the mutation (see [`DirDiff`]).
+    ///
+    /// [`DirDiff`]: crate::DirDiff
+    pub fn differences(&self) -> Vec<Difference> {
+        let difference = |path: &Path, kind| Difference {
+            path: path.to_path_buf(),
+            kind,
+        };
+        match self {
+            Self::Touch { path, .. } => vec![difference(
+                path,
+                DiffKind::Metadata(vec![MetadataField::Mtime]),
+            )],
+            Self::FlipByte { path, .. } => vec![difference(path, DiffKind::Content)],
+            Self::Rename { from, to } => vec![
+                difference(from, DiffKind::Removed),
+                difference(to, DiffKind::Added),
+            ],
+            Self::Delete { path } => vec![difference(path, DiffKind::Removed)],
+            Self::AddFile { path } => vec![difference(path, DiffKind::Added)],
+            Self::Chmod { path, .. } => vec![difference(
+                path,
+                DiffKind::Metadata(vec![MetadataField::Mode]),
+            )],
+        }
+    }
+}
+
+/// Apply random edits to the existing tree and return the change log.
+///
+/// Each file is edited at most once, directories are never renamed or deleted, their contents
+/// are not changed after they were edited, and the files with multiple hard links are left
+/// intact, i.e. the log fully describes the changes except the modification times of the
+/// parent directories of renamed, deleted and added files, and the remapped inode numbers (see
+/// [`list_dir_all`](crate::list_dir_all)). The permission bits always allow the owner to list
+/// the tree. All modifications are checked with [`SafetyGuard`].
+///
+/// Panics on I/O errors.
+pub fn mutate<P: AsRef<Path>>(
+    dir: P,
+    u: &mut Unstructured<'_>,
+    kinds: MutationKinds,
+) -> arbitrary::Result<Vec<Mutation>> {
+    let mut mutator = Mutator::new(dir.as_ref(), kinds);
+    let mut mutations = Vec::new();
+    if mutator.kinds.is_empty() {
+        return Ok(mutations);
+    }
+    let num_mutations: usize = u.int_in_range(1..=MAX_MUTATIONS)?;
+    for _ in 0..num_mutations {
+        if let Some(mutation) = mutator.mutate_one(u)? {
+            mutations.push(mutation);
+        }
+    }
+    Ok(mutations)
+}
+
+/// Applies the mutations one by one editing each file at most once.
+pub(crate) struct Mutator {
+    root: PathBuf,
+    guard: SafetyGuard,
+    kinds: Vec<MutationKind>,
+    /// The edited paths including the new ones.
+    edited: HashSet<PathBuf>,
+}
+
+impl Mutator {
+    pub(crate) fn new(root: &Path, kinds: MutationKinds) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            guard: SafetyGuard::new(root).unwrap(),
+            kinds: ALL_MUTATION_KINDS
+                .into_iter()
+                .filter(|kind| kinds.contains(*kind))
+                .collect(),
+            edited: HashSet::new(),
+        }
+    }
+
+    /// Returns `true` if no mutation kinds were requested, i.e. nothing is ever edited.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    /// Returns `None` if there is nothing to edit or the mutation kinds are empty.
+    pub(crate) fn mutate_one(
+        &mut self,
+        u: &mut Unstructured<'_>,
+    ) -> arbitrary::Result<Option<Mutation>> {
+        if self.kinds.is_empty() {
+            return Ok(None);
+        }
+        let kind = *u.choose(&self.kinds[..])?;
+        let files = list_files(&self.root);
+        let candidates: Vec<&(PathBuf, Metadata)> = files
+            .iter()
+            .filter(|(path, metadata)| {
+                if self.edited.contains(path) {
+                    return false;
+                }
+                let single_link = metadata.is_dir() || metadata.nlink() == 1;
+                match kind {
+                    MutationKind::Touch => path != &self.root && single_link,
+                    MutationKind::FlipByte => {
+                        metadata.is_file()
+                            && metadata.len() != 0
+                            && single_link
+                            && access(path, libc::W_OK)
+                    }
+                    MutationKind::Rename | MutationKind::Delete => {
+                        let parent = path.parent().unwrap();
+                        !metadata.is_dir()
+                            && single_link
+                            && !self.edited.contains(parent)
+                            && access(parent, libc::W_OK | libc::X_OK)
+                    }
+                    MutationKind::AddFile => {
+                        metadata.is_dir() && access(path, libc::W_OK | libc::X_OK)
+                    }
+                    MutationKind::Chmod => {
+                        path != &self.root && !metadata.is_symlink() && single_link
+                    }
+                }
+            })
+            .collect();
+        let Ok((path, metadata)) = u.choose(&candidates[..]).copied() else {
+            return Ok(None);
+        };
+        let relative = |path: &Path| path.strip_prefix(&self.root).unwrap().to_path_buf();
+        self.guard.check(path).unwrap();
+        let mutation = match kind {
+            MutationKind::Touch => {
+                let old_mtime = metadata.mtime() as u64;
+                let mut secs = u.int_in_range(0..=max_mtime())?;
+                if secs == old_mtime {
+                    secs += 1;
+                }
+                let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+                set_file_modified_time(&path_to_c_string(path.clone()).unwrap(), mtime).unwrap();
+                Mutation::Touch {
+                    path: relative(path),
+                    mtime,
+                }
+            }
+            MutationKind::FlipByte => {
+                let offset = u.int_in_range(0..=metadata.len() - 1)?;
+                let mask = u.int_in_range(1..=u8::MAX)?;
+                flip_byte(path, offset, mask);
+                let mtime = metadata.modified().unwrap();
+                set_file_modified_time(&path_to_c_string(path.clone()).unwrap(), mtime).unwrap();
+                Mutation::FlipByte {
+                    path: relative(path),
+                    offset,
+                }
+            }
+            MutationKind::Rename => {
+                let dirs: Vec<&PathBuf> = files
+                    .iter()
+                    .filter(|(path, metadata)| {
+                        metadata.is_dir()
+                            && !self.edited.contains(path)
+                            && access(path, libc::W_OK | libc::X_OK)
+                    })
+                    .map(|(path, _)| path)
+                    .collect();
+                let Some(to) = new_path(u, &dirs, &self.edited)? else {
+                    return Ok(None);
+                };
+                self.guard.check(&to).unwrap();
+                std::fs::rename(path, &to).unwrap();
+                self.edited.insert(to.clone());
+                Mutation::Rename {
+                    from: relative(path),
+                    to: relative(&to),
+                }
+            }
+            MutationKind::Delete => {
+                self.guard.remove_file(path).unwrap();
+                Mutation::Delete {
+                    path: relative(path),
+                }
+            }
+            MutationKind::AddFile => {
+                let Some(new_path) = new_path(u, &[path], &self.edited)? else {
+                    return Ok(None);
+                };
+                self.guard.check(&new_path).unwrap();
+                let contents: Vec<u8> = u.arbitrary()?;
+                std::fs::write(&new_path, contents).unwrap();
+                self.edited.insert(new_path.clone());
+                Mutation::AddFile {
+                    path: relative(&new_path),
+                }
+            }
+            MutationKind::Chmod => {
+                let old_mode = metadata.permissions().mode() & 0o7777;
+                let owner_bits = if metadata.is_dir() { 0o700 } else { 0o400 };
+                let mut mode = u.int_in_range(0..=0o7777)? | owner_bits;
+                if mode == old_mode {
+                    mode ^= 0o001;
+                }
+                self.guard
+                    .set_permissions(path, Permissions::from_mode(mode))
+                    .unwrap();
+                Mutation::Chmod {
+                    path: relative(path),
+                    mode,
+                }
+            }
+        };
+        self.edited.insert(path.clone());
+        Ok(Some(mutation))
+    }
+}
+
+/// List all the files including the root sorted by path.
+fn list_files(root: &Path) -> Vec<(PathBuf, Metadata)> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(root).sort_by_file_name().into_iter() {
+        let entry = entry.unwrap();
+        let metadata = entry.path().symlink_metadata().unwrap();
+        files.push((entry.into_path(), metadata));
+    }
+    files
+}
+
+/// Generate a path that doesn't exist in one of the directories.
+fn new_path(
+    u: &mut Unstructured<'_>,
+    dirs: &[&PathBuf],
+    edited: &HashSet<PathBuf>,
+) -> arbitrary::Result<Option<PathBuf>> {
+    let Ok(dir) = u.choose(dirs) else {
+        return Ok(None);
+    };
+    let len: usize = u.int_in_range(1..=10)?;
+    let mut name = String::with_capacity(len);
+    for _ in 0..len {
+        name.push(u.int_in_range(b'a'..=b'z')? as char);
+    }
+    let path = dir.join(name);
+    if path.symlink_metadata().is_ok() || edited.contains(&path) {
+        return Ok(None);
+    }
+    Ok(Some(path))
+}
+
+fn flip_byte(path: &Path, offset: u64, mask: u8) {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap_or_else(|e| panic!("failed to open `{}`: {}", path.display(), e));
+    let mut byte = [0_u8; 1];
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.read_exact(&mut byte).unwrap();
+    byte[0] ^= mask;
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.write_all(&byte).unwrap();
+}
+
+/// Check the permissions of the current process, e.g. can we create the files in the directory?
+fn access(path: &Path, mode: libc::c_int) -> bool {
+    let Ok(c_path) = path_to_c_string(path.to_path_buf()) else {
+        return false;
+    };
+    unsafe { libc::access(c_path.as_ptr(), mode) == 0 }
+}
+
+fn max_mtime() -> u64 {
+    let t = SystemTime::now() + Duration::from_secs(60 * 60 * 24);
+    t.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
+}
+
+const MAX_MUTATIONS: usize = 10;
+
+#[cfg(test)]
+mod tests {
+    use arbtest::arbtest;
+
+    use super::*;
+    use crate::list_dir_all;
+    use crate::DirBuilder;
+    use crate::DirDiff;
+    use crate::ALL_METADATA_FIELDS;
+
+    #[test]
+    fn log_describes_the_changes() {
+        arbtest(|u| {
+            let dir = DirBuilder::new().printable_names(true).create(u)?;
+            let before = list_dir_all(dir.path()).unwrap();
+            let log = mutate(dir.path(), u, MutationKinds::ALL)?;
+            let after = list_dir_all(dir.path()).unwrap();
+            // Inodes are remapped in the listing order, i.e. added and deleted files shift them.
+            let fields: Vec<MetadataField> = ALL_METADATA_FIELDS
+                .into_iter()
+                .filter(|field| !matches!(field, MetadataField::Dev | MetadataField::Ino))
+                .collect();
+            let mut parents = HashSet::new();
+            let mut touched = HashSet::new();
+            for mutation in log.iter() {
+                match mutation {
+                    Mutation::Rename { from, to } => {
+                        parents.insert(from.parent().unwrap().to_path_buf());
+                        parents.insert(to.parent().unwrap().to_path_buf());
+                    }
+                    Mutation::Delete { path } | Mutation::AddFile { path } => {
+                        parents.insert(path.parent().unwrap().to_path_buf());
+                    }
+                    Mutation::Touch { path, .. } => {
+                        touched.insert(path.clone());
+                    }
+                    _ => {}
+                }
+            }
+            let mut actual: Vec<Difference> = DirDiff::new(&before, &after)
+                .restricted_to(&fields)
+                .differences()
+                .iter()
+                .filter_map(|difference| {
+                    let DiffKind::Metadata(fields) = &difference.kind else {
+                        return Some(difference.clone());
+                    };
+                    if !parents.contains(&difference.path) {
+                        return Some(difference.clone());
+                    }
+                    let fields: Vec<MetadataField> = fields
+                        .iter()
+                        .copied()
+                        .filter(|field| match field {
+                            MetadataField::Mtime => touched.contains(&difference.path),
+                            MetadataField::FileSize | MetadataField::Blocks => false,
+                            _ => true,
+                        })
+                        .collect();
+                    (!fields.is_empty()).then(|| Difference {
+                        path: difference.path.clone(),
+                        kind: DiffKind::Metadata(fields),
+                    })
+                })
+                .collect();
+            let mut expected: Vec<Difference> =
+                log.iter().flat_map(Mutation::differences).collect();
+            let key = |difference: &Difference| (difference.path.clone(), difference.severity());
+            expected.sort_by_key(key);
+            actual.sort_by_key(key);
+            assert_eq!(expected, actual, "{:?}", log);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn only_the_requested_kinds() {
+        arbtest(|u| {
+            let dir = DirBuilder::new().printable_names(true).create(u)?;
+            let before = list_dir_all(dir.path()).unwrap();
+            assert!(mutate(dir.path(), u, MutationKinds::default())?.is_empty());
+            assert_eq!(before, list_dir_all(dir.path()).unwrap());
+            let kind = *u.choose(&ALL_MUTATION_KINDS[..])?;
+            let log = mutate(dir.path(), u, MutationKinds::new(&[kind]))?;
+            assert!(
+                log.iter().all(|mutation| mutation.kind() == kind),
+                "{:?}",
+                log
+            );
+            Ok(())
+        });
+    }
+}
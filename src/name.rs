@@ -0,0 +1,694 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+
+use arbitrary::Unstructured;
+
+use crate::Preset;
+
+/// File name generator.
+///
+/// Implement this trait to generate names from a dictionary, only NFC-normalized names,
+/// only 8.3 names etc.
+pub trait NameGenerator {
+    /// Generate file path relative to the directory root.
+    ///
+    /// The path may contain separators, in which case the intermediate directories are
+    /// created automatically. Empty paths are skipped.
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString>;
+}
+
+impl<F> NameGenerator for F
+where
+    F: FnMut(&mut Unstructured<'_>) -> arbitrary::Result<OsString>,
+{
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        self(u)
+    }
+}
+
+/// Generates names consisting of arbitrary bytes except NUL.
+///
+/// This is the default name generator on platforms that allow such names.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ArbitraryNames;
+
+impl NameGenerator for ArbitraryNames {
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        let name: CString = u.arbitrary()?;
+        Ok(OsString::from_vec(name.into_bytes()))
+    }
+}
+
+/// Generates names consisting only from lowercase ASCII letters.
+///
+/// Useful to test CLI applications.
+#[derive(Debug, Clone, Copy)]
+pub struct PrintableNames {
+    /// Maximum name length in bytes.
+    pub max_len: usize,
+}
+
+impl Default for PrintableNames {
+    fn default() -> Self {
+        Self { max_len: 10 }
+    }
+}
+
+impl NameGenerator for PrintableNames {
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        let len: usize = u.int_in_range(1..=self.max_len.max(1))?;
+        let mut string = String::with_capacity(len);
+        for _ in 0..len {
+            string.push(u.int_in_range(b'a'..=b'z')? as char);
+        }
+        Ok(string.into())
+    }
+}
+
+/// Character set for [`CharsetNames`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameCharset {
+    /// Printable ASCII characters.
+    Ascii,
+    /// Printable Latin-1 characters.
+    Latin1,
+    /// Latin letters followed by arbitrary combining characters.
+    Combining,
+    /// Precomposed Latin letters with diacritics (NFC-normalized).
+    Nfc,
+    /// Decomposed Latin letters with diacritics (NFD-normalized).
+    ///
+    /// Useful to test MacOS/HFS+ normalization behavior.
+    Nfd,
+    /// Emoji.
+    Emoji,
+    /// Chinese, Japanese and Korean characters.
+    Cjk,
+    /// Any of the above character sets mixed in a single name.
+    Mixed,
+}
+
+/// All character sets.
+pub const ALL_NAME_CHARSETS: [NameCharset; 8] = {
+    use NameCharset::*;
+    [Ascii, Latin1, Combining, Nfc, Nfd, Emoji, Cjk, Mixed]
+};
+
+/// Generates valid UTF-8 names from the specified character set.
+#[derive(Debug, Clone, Copy)]
+pub struct CharsetNames {
+    /// Character set.
+    pub charset: NameCharset,
+    /// Maximum name length in characters.
+    ///
+    /// For NFD and combining character sets a letter with its combining characters counts as
+    /// a single character.
+    pub max_len: usize,
+}
+
+impl CharsetNames {
+    /// Create new generator for the specified character set.
+    pub fn new(charset: NameCharset) -> Self {
+        Self {
+            charset,
+            max_len: 10,
+        }
+    }
+}
+
+impl NameGenerator for CharsetNames {
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        let len: usize = u.int_in_range(1..=self.max_len.max(1))?;
+        let mut string = String::new();
+        for _ in 0..len {
+            let charset = match self.charset {
+                NameCharset::Mixed => {
+                    *u.choose(&ALL_NAME_CHARSETS[..ALL_NAME_CHARSETS.len() - 1])?
+                }
+                other => other,
+            };
+            push_char(u, charset, &mut string)?;
+        }
+        if string == "." || string == ".." {
+            // do not allow special names
+            string.push('_');
+        }
+        Ok(string.into())
+    }
+}
+
+/// Generates names that differ only in case or in Unicode normalization from the previously
+/// generated names.
+///
+/// E.g. `File` after `file`, or decomposed `e\u{301}` after precomposed `é`. The names consist of
+/// ASCII and Latin-1 letters. Use it to test the archivers' behavior on case-insensitive and
+/// normalization-insensitive targets. Such file systems collapse the names into one file, and
+/// the collapsed names are recorded in [`Manifest::collapsed_names`](crate::Manifest::collapsed_names).
+#[derive(Debug, Clone)]
+pub struct CollidingNames {
+    /// Maximum name length in characters.
+    pub max_len: usize,
+    /// Previously generated names.
+    names: Vec<String>,
+}
+
+impl Default for CollidingNames {
+    fn default() -> Self {
+        Self {
+            max_len: 10,
+            names: Vec::new(),
+        }
+    }
+}
+
+impl NameGenerator for CollidingNames {
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        let name = if !self.names.is_empty() && u.ratio(2, 3)? {
+            let name = u.choose(&self.names)?;
+            let variant = if u.arbitrary()? {
+                normalization_variant(name)
+            } else {
+                None
+            };
+            match variant {
+                Some(variant) => variant,
+                None => case_variant(u, name)?,
+            }
+        } else {
+            let len: usize = u.int_in_range(1..=self.max_len.max(1))?;
+            let mut string = String::new();
+            for _ in 0..len {
+                let charset = *u.choose(&[NameCharset::Nfc, NameCharset::Nfd])?;
+                if u.arbitrary()? {
+                    push_char(u, charset, &mut string)?;
+                } else {
+                    let c = u.int_in_range(b'a'..=b'z')? as char;
+                    string.push(if u.arbitrary()? {
+                        c.to_ascii_uppercase()
+                    } else {
+                        c
+                    });
+                }
+            }
+            string
+        };
+        self.names.push(name.clone());
+        Ok(name.into())
+    }
+}
+
+/// Change the case of at least one letter.
+fn case_variant(u: &mut Unstructured<'_>, name: &str) -> arbitrary::Result<String> {
+    let cased: Vec<usize> = name
+        .char_indices()
+        .filter(|(_, c)| c.is_lowercase() || c.is_uppercase())
+        .map(|(i, _)| i)
+        .collect();
+    let first = match cased.is_empty() {
+        true => None,
+        false => Some(*u.choose(&cased)?),
+    };
+    let mut variant = String::with_capacity(name.len());
+    for (i, c) in name.char_indices() {
+        if Some(i) == first || u.ratio(1, 4)? {
+            if c.is_lowercase() {
+                variant.extend(c.to_uppercase());
+            } else {
+                variant.extend(c.to_lowercase());
+            }
+        } else {
+            variant.push(c);
+        }
+    }
+    Ok(variant)
+}
+
+/// Decompose the precomposed letters or compose the decomposed ones.
+///
+/// Returns `None` if the name is the same in both normalization forms.
+fn normalization_variant(name: &str) -> Option<String> {
+    let mut decomposed = String::with_capacity(name.len() * 2);
+    let mut composed = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match LATIN1_DECOMPOSITIONS.iter().find(|(p, _, _)| *p == c) {
+            Some((_, letter, mark)) => {
+                decomposed.push(*letter);
+                decomposed.push(*mark);
+            }
+            None => decomposed.push(c),
+        }
+        let next = chars.peek().copied();
+        match LATIN1_DECOMPOSITIONS
+            .iter()
+            .find(|(_, letter, mark)| *letter == c && Some(*mark) == next)
+        {
+            Some((precomposed, _, _)) => {
+                composed.push(*precomposed);
+                chars.next();
+                decomposed.push(next.unwrap());
+            }
+            None => composed.push(c),
+        }
+    }
+    if decomposed != name {
+        Some(decomposed)
+    } else if composed != name {
+        Some(composed)
+    } else {
+        None
+    }
+}
+
+/// Category of names for [`HostileNames`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostileNameCategory {
+    /// Names consisting only from dots or starting with dots, e.g. `...` or `..a`.
+    Dots,
+    /// Names starting with `-`, i.e. names that look like command-line options, e.g. `-rf`.
+    LeadingDash,
+    /// Names ending with spaces or dots, e.g. `a. ` (not allowed on Windows).
+    TrailingSpaceOrDot,
+    /// Names containing `\n` or `\r`.
+    Newline,
+    /// Names containing ASCII control characters other than newlines.
+    Control,
+    /// Names containing bytes that are not valid UTF-8, e.g. `0xff`.
+    ///
+    /// Not supported by the file systems that enforce UTF-8, e.g. APFS.
+    InvalidUtf8,
+    /// Names containing shell metacharacters and quotes, e.g. `$(a)` or `'a'`.
+    ShellMeta,
+}
+
+/// All hostile name categories.
+pub const ALL_HOSTILE_NAME_CATEGORIES: [HostileNameCategory; 7] = {
+    use HostileNameCategory::*;
+    [
+        Dots,
+        LeadingDash,
+        TrailingSpaceOrDot,
+        Newline,
+        Control,
+        InvalidUtf8,
+        ShellMeta,
+    ]
+};
+
+/// Generates names that are hard to quote and escape.
+///
+/// Each name is a short lowercase ASCII name mangled according to one of the categories. Useful
+/// to test quoting and escaping in CLI applications.
+#[derive(Debug, Clone)]
+pub struct HostileNames {
+    /// Name categories to choose from.
+    pub categories: Vec<HostileNameCategory>,
+    /// Maximum length of the unmangled name in bytes.
+    pub max_len: usize,
+}
+
+impl HostileNames {
+    /// Create new generator for the specified categories.
+    pub fn new<I>(categories: I) -> Self
+    where
+        I: IntoIterator<Item = HostileNameCategory>,
+    {
+        Self {
+            categories: categories.into_iter().collect(),
+            max_len: 10,
+        }
+    }
+}
+
+impl Default for HostileNames {
+    fn default() -> Self {
+        Self::new(ALL_HOSTILE_NAME_CATEGORIES)
+    }
+}
+
+impl NameGenerator for HostileNames {
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        use HostileNameCategory::*;
+        let len: usize = u.int_in_range(1..=self.max_len.max(1))?;
+        let mut name = Vec::with_capacity(len + 3);
+        for _ in 0..len {
+            name.push(u.int_in_range(b'a'..=b'z')?);
+        }
+        let Ok(category) = u.choose(&self.categories[..]) else {
+            return Ok(OsString::from_vec(name));
+        };
+        match category {
+            Dots => match u.int_in_range(0..=2)? {
+                0 => name = vec![b'.'; len.max(3)],
+                1 => name.insert(0, b'.'),
+                _ => {
+                    name.splice(0..0, *b"..");
+                }
+            },
+            LeadingDash => {
+                let num_dashes = u.int_in_range(1..=2)?;
+                name.splice(0..0, std::iter::repeat(b'-').take(num_dashes));
+                if u.ratio(1, 4)? {
+                    name.truncate(num_dashes);
+                }
+            }
+            TrailingSpaceOrDot => {
+                let num_chars: usize = u.int_in_range(1..=3)?;
+                for _ in 0..num_chars {
+                    name.push(*u.choose(b" .")?);
+                }
+            }
+            Newline => {
+                let newline: &[u8] = u.choose(&[&b"\n"[..], b"\r", b"\r\n"])?;
+                insert_bytes(u, &mut name, newline)?;
+            }
+            Control => {
+                let c = match u.int_in_range(0..=0x1f_u8)? {
+                    0 | b'\n' | b'\r' => 0x7f,
+                    c => c,
+                };
+                insert_bytes(u, &mut name, &[c])?;
+            }
+            InvalidUtf8 => {
+                let c = if u.arbitrary()? {
+                    0xff
+                } else {
+                    u.int_in_range(0x80..=0xff)?
+                };
+                insert_bytes(u, &mut name, &[c])?;
+            }
+            ShellMeta => {
+                let c = *u.choose(SHELL_METACHARACTERS)?;
+                insert_bytes(u, &mut name, &[c])?;
+            }
+        }
+        Ok(OsString::from_vec(name))
+    }
+}
+
+fn insert_bytes(
+    u: &mut Unstructured<'_>,
+    name: &mut Vec<u8>,
+    bytes: &[u8],
+) -> arbitrary::Result<()> {
+    let i = u.int_in_range(0..=name.len())?;
+    name.splice(i..i, bytes.iter().copied());
+    Ok(())
+}
+
+const SHELL_METACHARACTERS: &[u8] = b" \t*?[]{}()<>|&;$`'\"\\!#~=%";
+
+/// Prepends `.` to the last component of the names produced by the inner generator (see
+/// [`DirBuilder::hidden_names`](crate::DirBuilder::hidden_names)).
+pub(crate) struct HiddenNames {
+    inner: Box<dyn NameGenerator>,
+    percent: u8,
+}
+
+impl HiddenNames {
+    pub(crate) fn new(inner: Box<dyn NameGenerator>, percent: u8) -> Self {
+        Self { inner, percent }
+    }
+}
+
+impl NameGenerator for HiddenNames {
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        let name = self.inner.generate(u)?;
+        let roll: u8 = u.int_in_range(0..=99)?;
+        if roll >= self.percent {
+            return Ok(name);
+        }
+        let mut name = name.into_vec();
+        let i = last_component_start(&name);
+        if i != name.len() && name[i] != b'.' {
+            name.insert(i, b'.');
+        }
+        Ok(OsString::from_vec(name))
+    }
+}
+
+/// Replaces the last component of the names produced by the inner generator with the special
+/// names (see [`DirBuilder::special_names`](crate::DirBuilder::special_names)).
+pub(crate) struct SpecialNames {
+    inner: Box<dyn NameGenerator>,
+    names: Vec<OsString>,
+    percent: u8,
+}
+
+impl SpecialNames {
+    pub(crate) fn new(inner: Box<dyn NameGenerator>, names: Vec<OsString>, percent: u8) -> Self {
+        Self {
+            inner,
+            names,
+            percent,
+        }
+    }
+}
+
+impl NameGenerator for SpecialNames {
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        let name = self.inner.generate(u)?;
+        let roll: u8 = u.int_in_range(0..=99)?;
+        if roll >= self.percent || self.names.is_empty() {
+            return Ok(name);
+        }
+        let mut name = name.into_vec();
+        name.truncate(last_component_start(&name));
+        name.extend_from_slice(u.choose(&self.names[..])?.as_bytes());
+        Ok(OsString::from_vec(name))
+    }
+}
+
+fn last_component_start(path: &[u8]) -> usize {
+    path.iter().rposition(|c| *c == b'/').map_or(0, |i| i + 1)
+}
+
+/// Well-known names that the tools treat specially, e.g. ignore files, VCS directories and
+/// desktop metadata (see [`DirBuilder::special_names`](crate::DirBuilder::special_names)).
+pub const SPECIAL_NAMES: [&str; 17] = [
+    ".gitignore",
+    ".gitattributes",
+    ".gitkeep",
+    ".git",
+    ".hgignore",
+    ".ignore",
+    ".rgignore",
+    ".dockerignore",
+    ".npmignore",
+    ".editorconfig",
+    ".env",
+    ".htaccess",
+    ".nomedia",
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+    "CACHEDIR.TAG",
+];
+
+/// Generates names that are valid under the specified [`Preset`].
+///
+/// For case-insensitive presets the directories that differ only in case are merged and the
+/// files that differ only in case are skipped.
+#[derive(Debug, Clone)]
+pub struct PresetNames {
+    /// Preset.
+    pub preset: Preset,
+    /// Maximum length of a path component in bytes.
+    ///
+    /// Capped by the preset's limit.
+    pub max_len: usize,
+    /// Lowercase paths mapped to the previously generated paths.
+    paths: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl PresetNames {
+    /// Create new generator for the specified preset.
+    pub fn new(preset: Preset) -> Self {
+        Self {
+            preset,
+            max_len: 10,
+            paths: HashMap::new(),
+        }
+    }
+
+    fn generate_name(&self, u: &mut Unstructured<'_>) -> arbitrary::Result<Vec<u8>> {
+        let preset = &self.preset;
+        let max_len = self.max_len.min(preset.max_name_len).max(1);
+        let len: usize = u.int_in_range(1..=max_len)?;
+        let mut name = Vec::with_capacity(len + 1);
+        for i in 0..len {
+            let mut c = *u.choose(preset.name_chars)?;
+            if (i == 0 && preset.forbidden_first.contains(&c))
+                || (i == len - 1 && preset.forbidden_last.contains(&c))
+            {
+                c = b'_';
+            }
+            name.push(c);
+        }
+        let stem = name.split(|c| *c == b'.').next().unwrap_or_default();
+        let reserved = preset
+            .reserved_names
+            .iter()
+            .any(|reserved| reserved.as_bytes().eq_ignore_ascii_case(stem));
+        if reserved || name.iter().all(|c| *c == b'.') {
+            // do not allow device names and special names
+            name.insert(0, b'_');
+            name.truncate(max_len.max(2));
+        }
+        Ok(name)
+    }
+}
+
+impl NameGenerator for PresetNames {
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        let depth: usize = u.int_in_range(1..=self.preset.max_depth.max(1))?;
+        let mut path: Vec<u8> = Vec::new();
+        for i in 0..depth {
+            if i != 0 {
+                path.push(b'/');
+            }
+            let name = self.generate_name(u)?;
+            path.extend_from_slice(&name);
+            if !self.preset.case_insensitive {
+                continue;
+            }
+            let key = path.to_ascii_lowercase();
+            match self.paths.get(&key) {
+                Some(_) if i == depth - 1 => {
+                    // the file already exists
+                    return Ok(OsString::new());
+                }
+                Some(existing) => path.clone_from(existing),
+                None => {
+                    self.paths.insert(key, path.clone());
+                }
+            }
+        }
+        Ok(OsString::from_vec(path))
+    }
+}
+
+fn push_char(
+    u: &mut Unstructured<'_>,
+    charset: NameCharset,
+    string: &mut String,
+) -> arbitrary::Result<()> {
+    use NameCharset::*;
+    match charset {
+        Ascii => string.push(char_in_range(u, ' ', '~', &['/'])?),
+        Latin1 => {
+            let c = if u.arbitrary()? {
+                char_in_range(u, ' ', '~', &['/'])?
+            } else {
+                char_in_range(u, '\u{a0}', '\u{ff}', &[])?
+            };
+            string.push(c);
+        }
+        Combining => {
+            string.push(char_in_range(u, 'a', 'z', &[])?);
+            let num_marks: usize = u.int_in_range(1..=3)?;
+            for _ in 0..num_marks {
+                string.push(char_in_range(u, '\u{300}', '\u{36f}', &[])?);
+            }
+        }
+        Nfc => {
+            let (precomposed, _, _) = u.choose(&LATIN1_DECOMPOSITIONS[..])?;
+            string.push(*precomposed);
+        }
+        Nfd => {
+            let (_, letter, mark) = u.choose(&LATIN1_DECOMPOSITIONS[..])?;
+            string.push(*letter);
+            string.push(*mark);
+        }
+        Emoji => string.push(char_in_range(u, '\u{1f300}', '\u{1f64f}', &[])?),
+        Cjk => {
+            let c = match u.int_in_range(0..=2)? {
+                0 => char_in_range(u, '\u{4e00}', '\u{9fff}', &[])?,
+                1 => char_in_range(u, '\u{3041}', '\u{3096}', &[])?,
+                _ => char_in_range(u, '\u{ac00}', '\u{d7a3}', &[])?,
+            };
+            string.push(c);
+        }
+        Mixed => unreachable!(),
+    }
+    Ok(())
+}
+
+fn char_in_range(
+    u: &mut Unstructured<'_>,
+    first: char,
+    last: char,
+    exclude: &[char],
+) -> arbitrary::Result<char> {
+    let c = u.int_in_range(first as u32..=last as u32)?;
+    let c = char::from_u32(c).unwrap_or(first);
+    if exclude.contains(&c) {
+        return Ok(first);
+    }
+    Ok(c)
+}
+
+/// Precomposed Latin-1 letters and their canonical decompositions.
+const LATIN1_DECOMPOSITIONS: [(char, char, char); 58] = [
+    ('À', 'A', '\u{300}'),
+    ('Á', 'A', '\u{301}'),
+    ('Â', 'A', '\u{302}'),
+    ('Ã', 'A', '\u{303}'),
+    ('Ä', 'A', '\u{308}'),
+    ('Å', 'A', '\u{30a}'),
+    ('Ç', 'C', '\u{327}'),
+    ('È', 'E', '\u{300}'),
+    ('É', 'E', '\u{301}'),
+    ('Ê', 'E', '\u{302}'),
+    ('Ë', 'E', '\u{308}'),
+    ('Ì', 'I', '\u{300}'),
+    ('Í', 'I', '\u{301}'),
+    ('Î', 'I', '\u{302}'),
+    ('Ï', 'I', '\u{308}'),
+    ('Ñ', 'N', '\u{303}'),
+    ('Ò', 'O', '\u{300}'),
+    ('Ó', 'O', '\u{301}'),
+    ('Ô', 'O', '\u{302}'),
+    ('Õ', 'O', '\u{303}'),
+    ('Ö', 'O', '\u{308}'),
+    ('Ù', 'U', '\u{300}'),
+    ('Ú', 'U', '\u{301}'),
+    ('Û', 'U', '\u{302}'),
+    ('Ü', 'U', '\u{308}'),
+    ('Ý', 'Y', '\u{301}'),
+    ('à', 'a', '\u{300}'),
+    ('á', 'a', '\u{301}'),
+    ('â', 'a', '\u{302}'),
+    ('ã', 'a', '\u{303}'),
+    ('ä', 'a', '\u{308}'),
+    ('å', 'a', '\u{30a}'),
+    ('ç', 'c', '\u{327}'),
+    ('è', 'e', '\u{300}'),
+    ('é', 'e', '\u{301}'),
+    ('ê', 'e', '\u{302}'),
+    ('ë', 'e', '\u{308}'),
+    ('ì', 'i', '\u{300}'),
+    ('í', 'i', '\u{301}'),
+    ('î', 'i', '\u{302}'),
+    ('ï', 'i', '\u{308}'),
+    ('ñ', 'n', '\u{303}'),
+    ('ò', 'o', '\u{300}'),
+    ('ó', 'o', '\u{301}'),
+    ('ô', 'o', '\u{302}'),
+    ('õ', 'o', '\u{303}'),
+    ('ö', 'o', '\u{308}'),
+    ('ù', 'u', '\u{300}'),
+    ('ú', 'u', '\u{301}'),
+    ('û', 'u', '\u{302}'),
+    ('ü', 'u', '\u{308}'),
+    ('ý', 'y', '\u{301}'),
+    ('ÿ', 'y', '\u{308}'),
+    ('Ā', 'A', '\u{304}'),
+    ('ā', 'a', '\u{304}'),
+    ('Ē', 'E', '\u{304}'),
+    ('ē', 'e', '\u{304}'),
+    ('Ő', 'O', '\u{30b}'),
+];
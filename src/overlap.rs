@@ -0,0 +1,141 @@
+use arbitrary::Unstructured;
+
+use crate::Difference;
+use crate::Dir;
+use crate::DirBuilder;
+use crate::Mutation;
+use crate::MutationKinds;
+use crate::Mutator;
+use crate::TreeRelation;
+
+/// Two trees that share some of the entries and differ in the rest (see
+/// [`DirBuilder::create_overlapping`]).
+pub struct OverlappingPair {
+    /// The generated tree.
+    pub first: Dir,
+    /// The copy of the first tree with the mutations applied.
+    ///
+    /// The manifest describes the tree before the mutations.
+    pub second: Dir,
+    /// The edits that turned the copy of the first tree into the second tree.
+    pub mutations: Vec<Mutation>,
+}
+
+impl OverlappingPair {
+    /// The ground-truth differences between the first and the second tree (see
+    /// [`Mutation::differences`]).
+    ///
+    /// Compare them with the output of the diff, sync or merge tool under test.
+    pub fn differences(&self) -> Vec<Difference> {
+        self.mutations
+            .iter()
+            .flat_map(Mutation::differences)
+            .collect()
+    }
+}
+
+impl DirBuilder {
+    /// Create two trees that share `shared_percent` of the entries and differ in the rest.
+    ///
+    /// The second tree is a copy of the first one (see [`TreeRelation::Identical`]) edited by
+    /// the mutations of the specified kinds (see [`mutate`](crate::mutate)) until the
+    /// remaining entries are edited. The mutations have the same limitations, e.g. the files with
+    /// multiple hard links are never edited, i.e. the trees might share more entries than
+    /// requested.
+    pub fn create_overlapping(
+        self,
+        shared_percent: u8,
+        kinds: MutationKinds,
+        u: &mut Unstructured<'_>,
+    ) -> arbitrary::Result<OverlappingPair> {
+        let (first, second) = self.create_pair(TreeRelation::Identical, u)?;
+        let shared_percent = shared_percent.min(100) as usize;
+        let num_entries = second.entries().len();
+        let num_edits = (num_entries * (100 - shared_percent)).div_ceil(100);
+        let mut mutator = Mutator::new(second.path(), kinds);
+        let mut mutations = Vec::with_capacity(num_edits);
+        // some mutations have no candidates
+        for _ in 0..num_edits * MAX_ATTEMPTS_PER_EDIT {
+            if mutations.len() == num_edits {
+                break;
+            }
+            if let Some(mutation) = mutator.mutate_one(u)? {
+                mutations.push(mutation);
+            }
+        }
+        Ok(OverlappingPair {
+            first,
+            second,
+            mutations,
+        })
+    }
+}
+
+const MAX_ATTEMPTS_PER_EDIT: usize = 4;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+
+    use arbtest::arbtest;
+
+    use super::*;
+    use crate::diff_dirs;
+    use crate::DiffKind;
+    use crate::MetadataField;
+    use crate::ALL_METADATA_FIELDS;
+
+    #[test]
+    fn differences_match_diff() {
+        arbtest(|u| {
+            let shared_percent = u.int_in_range(0..=100)?;
+            let pair =
+                DirBuilder::new().create_overlapping(shared_percent, MutationKinds::ALL, u)?;
+            // the mutations don't track the inode numbers and the modification times of the
+            // parent directories of the renamed, deleted and added files
+            let parents: HashSet<PathBuf> = pair
+                .differences()
+                .into_iter()
+                .filter(|difference| matches!(difference.kind, DiffKind::Added | DiffKind::Removed))
+                .filter_map(|difference| Some(difference.path.parent()?.to_path_buf()))
+                .collect();
+            let fields: Vec<MetadataField> = ALL_METADATA_FIELDS
+                .into_iter()
+                .filter(|field| *field != MetadataField::Ino)
+                .collect();
+            let normalize = |differences: &[Difference]| {
+                let mut differences: Vec<Difference> = differences
+                    .iter()
+                    .filter_map(|difference| match &difference.kind {
+                        DiffKind::Metadata(fields) if parents.contains(&difference.path) => {
+                            let fields: Vec<MetadataField> = fields
+                                .iter()
+                                .copied()
+                                .filter(|field| *field != MetadataField::Mtime)
+                                .collect();
+                            (!fields.is_empty()).then(|| Difference {
+                                path: difference.path.clone(),
+                                kind: DiffKind::Metadata(fields),
+                            })
+                        }
+                        _ => Some(difference.clone()),
+                    })
+                    .collect();
+                differences.sort_by(|a, b| {
+                    a.path
+                        .cmp(&b.path)
+                        .then_with(|| b.severity().cmp(&a.severity()))
+                });
+                differences
+            };
+            let actual = diff_dirs(pair.first.path(), pair.second.path())
+                .unwrap()
+                .restricted_to(&fields);
+            let actual = normalize(actual.differences());
+            let expected = normalize(&pair.differences());
+            assert_eq!(expected, actual);
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,101 @@
+use crate::FileType;
+use crate::MetadataField;
+
+/// The subset of file types, names and metadata that is representable on the target backend.
+///
+/// Apply via `DirBuilder::preset` and compare the trees using
+/// [`DirDiff::restricted_to`](crate::DirDiff::restricted_to) with
+/// [`metadata_fields`](Self::metadata_fields).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preset {
+    /// File types.
+    pub file_types: &'static [FileType],
+    /// Characters allowed in the names.
+    pub name_chars: &'static [u8],
+    /// Characters that can't start the name.
+    pub forbidden_first: &'static [u8],
+    /// Characters that can't end the name.
+    pub forbidden_last: &'static [u8],
+    /// Reserved names.
+    ///
+    /// The names are compared case-insensitively and without the extension.
+    pub reserved_names: &'static [&'static str],
+    /// Do the names that differ only in case refer to the same file?
+    pub case_insensitive: bool,
+    /// Maximum length of a path component in bytes.
+    pub max_name_len: usize,
+    /// Maximum length of a path relative to the directory root in bytes.
+    pub max_path_len: usize,
+    /// Maximum no. of path components.
+    pub max_depth: usize,
+    /// Metadata fields that the backend preserves.
+    pub metadata_fields: &'static [MetadataField],
+}
+
+/// POSIX portable file names, file types and metadata.
+///
+/// The names consist of the portable file name character set and never start with a hyphen,
+/// the lengths are limited by `_POSIX_NAME_MAX` and `_POSIX_PATH_MAX`.
+pub const PORTABLE_POSIX: Preset = Preset {
+    file_types: {
+        use FileType::*;
+        &[Regular, Directory, Fifo, Symlink, HardLink]
+    },
+    name_chars: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789._-",
+    forbidden_first: b"-",
+    forbidden_last: b"",
+    reserved_names: &[],
+    case_insensitive: false,
+    max_name_len: 14,
+    max_path_len: 255,
+    max_depth: 4,
+    metadata_fields: {
+        use MetadataField::*;
+        &[Mode, Uid, Gid, Nlink, Mtime, FileSize]
+    },
+};
+
+/// Names, file types and metadata that are valid on Windows file systems.
+///
+/// The names consist of printable ASCII characters except `<>:"/\|?*`, never end with a dot or
+/// a space and never match device names (`CON`, `NUL`, `COM1` etc.). Only regular files and
+/// directories are generated, the names are unique case-insensitively. The path length is
+/// limited by `MAX_PATH`; note that on Windows the limit applies to the absolute path.
+pub const WINDOWS_SAFE: Preset = Preset {
+    file_types: &[FileType::Regular, FileType::Directory],
+    name_chars:
+        b" !#$%&'()+,-.0123456789;=@ABCDEFGHIJKLMNOPQRSTUVWXYZ[]^_`abcdefghijklmnopqrstuvwxyz{}~",
+    forbidden_first: b"",
+    forbidden_last: b". ",
+    reserved_names: &[
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ],
+    case_insensitive: true,
+    max_name_len: 255,
+    max_path_len: 259,
+    max_depth: 4,
+    metadata_fields: &[MetadataField::Mtime, MetadataField::FileSize],
+};
+
+/// Object keys that are safe for S3-compatible object stores.
+///
+/// Only regular files are generated, the directories are implied by the key prefixes, i.e.
+/// there are no empty directories. The names consist of the safe characters
+/// (alphanumerics and `!-_.*'()`), the key length is limited by 1024 bytes. Only the file sizes
+/// are preserved.
+pub const CLOUD_OBJECT_STORE_SAFE: Preset = Preset {
+    file_types: &[FileType::Regular],
+    name_chars: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!-_.*'()",
+    forbidden_first: b"",
+    forbidden_last: b"",
+    reserved_names: &[],
+    case_insensitive: false,
+    max_name_len: 255,
+    max_path_len: 1024,
+    max_depth: 4,
+    metadata_fields: &[MetadataField::FileSize],
+};
+
+/// All presets.
+pub const ALL_PRESETS: [Preset; 3] = [PORTABLE_POSIX, WINDOWS_SAFE, CLOUD_OBJECT_STORE_SAFE];
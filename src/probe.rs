@@ -0,0 +1,152 @@
+//! Run-time probing of the file system capabilities.
+
+use std::fs::hard_link;
+use std::fs::File;
+use std::io::Error;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+#[cfg(target_os = "linux")]
+use std::os::fd::AsFd;
+use std::os::unix::fs::symlink;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use libc::makedev;
+use tempfile::TempDir;
+
+use crate::arbitrary_char_dev;
+use crate::mkfifo;
+use crate::mknod;
+#[cfg(target_os = "linux")]
+use crate::os_str_to_c_string;
+use crate::path_to_c_string;
+#[cfg(target_os = "linux")]
+use crate::reflink;
+use crate::set_file_modified_time;
+#[cfg(target_os = "linux")]
+use crate::set_xattr;
+use crate::FileType;
+use crate::ALL_FILE_TYPES;
+
+/// Returns the file types that can be created in `dir` by the current process.
+///
+/// The probing is done in a temporary subdirectory that is removed afterwards.
+pub fn supported_file_types<P: AsRef<Path>>(dir: P) -> Result<Vec<FileType>, Error> {
+    let workdir = TempDir::new_in(dir)?;
+    let dir = workdir.path();
+    let original = dir.join("original");
+    File::create(&original)?;
+    let mut file_types = Vec::new();
+    for file_type in ALL_FILE_TYPES {
+        let path = dir.join(format!("{:?}", file_type));
+        let c_path = path_to_c_string(path.clone())?;
+        #[allow(unused_unsafe)]
+        let supported = match file_type {
+            FileType::Regular | FileType::Directory => true,
+            FileType::Fifo => mkfifo(&c_path, 0o600).is_ok(),
+            FileType::Socket => UnixDatagram::bind(&path).is_ok(),
+            FileType::BlockDevice => {
+                let dev = unsafe { makedev(7, 0) };
+                mknod(&c_path, libc::S_IFBLK | 0o600, dev).is_ok()
+            }
+            FileType::CharDevice => {
+                let dev = arbitrary_char_dev();
+                mknod(&c_path, libc::S_IFCHR | 0o600, dev).is_ok()
+            }
+            FileType::Whiteout => mknod(&c_path, libc::S_IFCHR | 0o600, 0).is_ok(),
+            FileType::Symlink => symlink(&original, &path).is_ok(),
+            FileType::HardLink => hard_link(&original, &path).is_ok(),
+        };
+        if supported {
+            file_types.push(file_type);
+        }
+    }
+    Ok(file_types)
+}
+
+/// File system features detected at run time (see [`fs_features`]).
+///
+/// Use it to skip the tests that the file system can't support, e.g. xattr tests on tmpfs
+/// without `user` namespace support or case-sensitivity tests on APFS.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct FsFeatures {
+    /// Symbolic links can be created.
+    pub symlinks: bool,
+    /// Hard links can be created.
+    pub hard_links: bool,
+    /// The holes in the files don't occupy disk blocks.
+    pub sparse_files: bool,
+    /// Extended attributes in `user` namespace can be set.
+    ///
+    /// Always `false` on the platforms other than Linux.
+    pub xattrs: bool,
+    /// The names that differ only in case refer to different files.
+    pub case_sensitive: bool,
+    /// The modification times are stored with sub-second precision.
+    pub subsecond_timestamps: bool,
+    /// The regular files can share the extents (see
+    /// [`Dir::clone_to_with_reflinks`](crate::Dir::clone_to_with_reflinks)).
+    ///
+    /// Always `false` on the platforms other than Linux.
+    pub reflinks: bool,
+}
+
+/// Detect the features of the file system that contains `dir`.
+///
+/// The probing is done in a temporary subdirectory that is removed afterwards.
+pub fn fs_features<P: AsRef<Path>>(dir: P) -> Result<FsFeatures, Error> {
+    let workdir = TempDir::new_in(dir)?;
+    let dir = workdir.path();
+    let original = dir.join("original");
+    File::create(&original)?;
+    let symlinks = symlink(&original, dir.join("symlink")).is_ok();
+    let hard_links = hard_link(&original, dir.join("hard-link")).is_ok();
+    // write only the last byte
+    let sparse_path = dir.join("sparse");
+    let mut file = File::create(&sparse_path)?;
+    file.seek(SeekFrom::Start(SPARSE_FILE_SIZE - 1))?;
+    file.write_all(&[1])?;
+    file.sync_all()?;
+    let sparse_files = file.metadata()?.blocks() * 512 < SPARSE_FILE_SIZE / 2;
+    #[cfg(target_os = "linux")]
+    let reflinks = {
+        let original = File::open(&sparse_path)?;
+        let clone = File::create(dir.join("clone"))?;
+        reflink(original.as_fd(), clone.as_fd()).is_ok()
+    };
+    #[cfg(not(target_os = "linux"))]
+    let reflinks = false;
+    drop(file);
+    #[cfg(target_os = "linux")]
+    let xattrs = {
+        let c_path = path_to_c_string(original.clone())?;
+        let c_name = os_str_to_c_string("user.random-dir".as_ref())?;
+        set_xattr(&c_path, &c_name, b"1").is_ok()
+    };
+    #[cfg(not(target_os = "linux"))]
+    let xattrs = false;
+    File::create(dir.join("case"))?;
+    let case_sensitive = dir.join("CASE").symlink_metadata().is_err();
+    let t = SystemTime::UNIX_EPOCH + Duration::new(1_000_000_000, 123_456_789);
+    set_file_modified_time(&path_to_c_string(original.clone())?, t)?;
+    let mtime = original.symlink_metadata()?.modified()?;
+    let subsecond_timestamps = mtime
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .is_ok_and(|d| d.subsec_nanos() != 0);
+    Ok(FsFeatures {
+        symlinks,
+        hard_links,
+        sparse_files,
+        xattrs,
+        case_sensitive,
+        subsecond_timestamps,
+        reflinks,
+    })
+}
+
+/// The size of the file that is used to probe sparse files support.
+const SPARSE_FILE_SIZE: u64 = 16 * 1024 * 1024;
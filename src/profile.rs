@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::io::Error;
+use std::path::Path;
+
+#[cfg(feature = "fs")]
+use crate::list_dir_all;
+use crate::DirDiff;
+use crate::FileInfo;
+use crate::MetadataField;
+use crate::MetadataMask;
+
+/// Metadata that the archive format preserves.
+///
+/// Compare the original tree with the unpacked one via [`diff_dirs`](Self::diff_dirs) to only
+/// report the differences that the format can represent.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct CompareProfile {
+    /// Preserved metadata fields.
+    ///
+    /// The containing device number is never compared; the inodes are compared after remapping
+    /// (see [`list_dir_all`](crate::list_dir_all)), i.e. they track the hard links.
+    pub mask: MetadataMask,
+    /// Preserved permission bits.
+    ///
+    /// The file type bits are always compared as part of [`MetadataField::Mode`].
+    pub mode_bits: u32,
+    /// Modification time granularity in seconds.
+    ///
+    /// The times that differ by less than the granularity are considered equal.
+    pub mtime_granularity: u64,
+    /// Compare the sizes of the directories?
+    ///
+    /// The sizes depend on the file system and on the order in which the entries were created.
+    pub directory_sizes: bool,
+    /// Compare the hard links to FIFOs, sockets and devices?
+    ///
+    /// Some formats (tools) only preserve the hard links to regular files and symbolic links.
+    pub special_file_links: bool,
+}
+
+impl CompareProfile {
+    /// Compare all the metadata except the status change times.
+    pub const ALL: Self = Self {
+        mask: MetadataMask::ALL
+            .without(MetadataField::Dev)
+            .without(MetadataField::Ctime),
+        mode_bits: 0o7777,
+        mtime_granularity: 1,
+        directory_sizes: true,
+        special_file_links: true,
+    };
+
+    /// POSIX tar archives (ustar and pax) unpacked with the original owners.
+    ///
+    /// All file types, permission bits, owners and device numbers are preserved. GNU tar only
+    /// archives the hard links to regular files and symbolic links. Add [`MetadataField::Acl`]
+    /// to the mask when archiving with `--acls` and [`MetadataField::SelinuxContext`] when
+    /// archiving with `--selinux`.
+    pub const TAR_POSIX: Self = Self {
+        mask: MetadataMask::ALL
+            .without(MetadataField::Dev)
+            .without(MetadataField::Acl)
+            .without(MetadataField::SelinuxContext)
+            .without(MetadataField::InodeFlags)
+            .without(MetadataField::Btime)
+            .without(MetadataField::Ctime)
+            .without(MetadataField::SharedExtents)
+            .without(MetadataField::Blocks),
+        mode_bits: 0o7777,
+        mtime_granularity: 1,
+        directory_sizes: false,
+        special_file_links: false,
+    };
+
+    /// Zip archives with Unix external attributes.
+    ///
+    /// The owners, device numbers and hard links are not preserved, neither are set-user-id,
+    /// set-group-id and sticky bits. MS-DOS timestamps have 2-second granularity.
+    pub const ZIP: Self = Self {
+        mask: MetadataMask::new(&[
+            MetadataField::Mode,
+            MetadataField::Mtime,
+            MetadataField::FileSize,
+        ]),
+        mode_bits: 0o777,
+        mtime_granularity: 2,
+        directory_sizes: false,
+        special_file_links: false,
+    };
+
+    /// `newc` cpio archives.
+    ///
+    /// All file types, permission bits, owners, device numbers and hard links are preserved,
+    /// the ACLs and the SELinux contexts are not.
+    pub const CPIO_NEWC: Self = Self {
+        mask: MetadataMask::ALL
+            .without(MetadataField::Dev)
+            .without(MetadataField::Acl)
+            .without(MetadataField::SelinuxContext)
+            .without(MetadataField::InodeFlags)
+            .without(MetadataField::Btime)
+            .without(MetadataField::Ctime)
+            .without(MetadataField::SharedExtents)
+            .without(MetadataField::Blocks),
+        mode_bits: 0o7777,
+        mtime_granularity: 1,
+        directory_sizes: false,
+        special_file_links: true,
+    };
+
+    /// SquashFS images.
+    ///
+    /// All file types, permission bits, owners, device numbers, hard links, ACLs and SELinux
+    /// contexts are preserved, the inode flags are not.
+    pub const SQUASHFS: Self = Self {
+        mask: MetadataMask::ALL
+            .without(MetadataField::Dev)
+            .without(MetadataField::InodeFlags)
+            .without(MetadataField::Btime)
+            .without(MetadataField::Ctime)
+            .without(MetadataField::SharedExtents)
+            .without(MetadataField::Blocks),
+        mode_bits: 0o7777,
+        mtime_granularity: 1,
+        directory_sizes: false,
+        special_file_links: true,
+    };
+
+    /// Zero out the metadata that the format doesn't preserve.
+    ///
+    /// The modification times are not rounded; use [`diff`](Self::diff) to compare them with
+    /// the granularity.
+    #[allow(clippy::unnecessary_cast)]
+    pub fn apply(&self, file: &FileInfo) -> FileInfo {
+        let mut file = file.masked(self.mask);
+        let format = file.metadata.mode & libc::S_IFMT as u32;
+        file.metadata.mode &= libc::S_IFMT as u32 | self.mode_bits;
+        if !self.directory_sizes && format == libc::S_IFDIR as u32 {
+            file.metadata.file_size = 0;
+        }
+        if !self.links_preserved(format) {
+            file.metadata.ino = 0;
+            file.metadata.nlink = 0;
+        }
+        file
+    }
+
+    /// Compare two listings using the profile.
+    pub fn diff(&self, a: &[FileInfo], b: &[FileInfo]) -> DirDiff {
+        let mut a: Vec<FileInfo> = a.iter().map(|file| self.apply(file)).collect();
+        let mut b: Vec<FileInfo> = b.iter().map(|file| self.apply(file)).collect();
+        self.remap_inodes(&mut a);
+        self.remap_inodes(&mut b);
+        let granularity = self.mtime_granularity.max(1);
+        let mtimes: HashMap<&Path, i64> = a
+            .iter()
+            .map(|file| (file.path.as_path(), file.metadata.mtime))
+            .collect();
+        for file in b.iter_mut() {
+            if let Some(mtime) = mtimes.get(file.path.as_path()) {
+                if mtime.abs_diff(file.metadata.mtime) < granularity {
+                    file.metadata.mtime = *mtime;
+                }
+            }
+        }
+        DirDiff::new(&a, &b)
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn links_preserved(&self, format: u32) -> bool {
+        self.special_file_links
+            || [
+                libc::S_IFREG as u32,
+                libc::S_IFLNK as u32,
+                libc::S_IFDIR as u32,
+            ]
+            .contains(&format)
+    }
+
+    /// Renumber the inodes skipping the files which hard links are not preserved.
+    #[allow(clippy::unnecessary_cast)]
+    fn remap_inodes(&self, files: &mut [FileInfo]) {
+        if self.special_file_links || !self.mask.contains(MetadataField::Ino) {
+            return;
+        }
+        let mut inodes = HashMap::new();
+        for file in files.iter_mut() {
+            if !self.links_preserved(file.metadata.mode & libc::S_IFMT as u32) {
+                continue;
+            }
+            let next_inode = inodes.len() as u64;
+            file.metadata.ino = *inodes.entry(file.metadata.ino).or_insert(next_inode);
+        }
+    }
+
+    /// Recursively list and compare two directories using the profile.
+    #[cfg(feature = "fs")]
+    pub fn diff_dirs<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &self,
+        a: P1,
+        b: P2,
+    ) -> Result<DirDiff, Error> {
+        Ok(self.diff(&list_dir_all(a)?, &list_dir_all(b)?))
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::Error;
+#[cfg(feature = "parallel")]
+use std::sync::Mutex;
+
+/// Deferred independent file system operations, e.g. writing the contents of regular files.
+///
+/// The jobs are run in parallel when `parallel` feature is enabled and sequentially otherwise.
+/// The order in which the jobs are run is unspecified.
+pub(crate) struct WorkQueue<'a> {
+    jobs: Vec<Job<'a>>,
+}
+
+impl<'a> WorkQueue<'a> {
+    pub(crate) fn new() -> Self {
+        Self { jobs: Vec::new() }
+    }
+
+    pub(crate) fn push<F>(&mut self, job: F)
+    where
+        F: FnOnce() -> Result<(), Error> + Send + 'a,
+    {
+        self.jobs.push(Box::new(job));
+    }
+
+    /// Run all the jobs.
+    ///
+    /// Returns the first error.
+    #[cfg(not(feature = "parallel"))]
+    pub(crate) fn run(self) -> Result<(), Error> {
+        for job in self.jobs.into_iter() {
+            job()?;
+        }
+        Ok(())
+    }
+
+    /// Run all the jobs.
+    ///
+    /// Returns the first error.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn run(self) -> Result<(), Error> {
+        let num_threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(self.jobs.len());
+        let jobs = Mutex::new(self.jobs.into_iter());
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..num_threads)
+                .map(|_| {
+                    scope.spawn(|| loop {
+                        let Some(job) = jobs.lock().unwrap().next() else {
+                            return Ok(());
+                        };
+                        job()?;
+                    })
+                })
+                .collect();
+            let mut result = Ok(());
+            for thread in threads.into_iter() {
+                let thread_result = thread
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e));
+                if result.is_ok() {
+                    result = thread_result;
+                }
+            }
+            result
+        })
+    }
+}
+
+type Job<'a> = Box<dyn FnOnce() -> Result<(), Error> + Send + 'a>;
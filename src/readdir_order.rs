@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The order in which `readdir` returned the entries of each directory.
+///
+/// Listed by [`list_dir_all_with_readdir_order`](crate::list_dir_all_with_readdir_order). The
+/// order depends on the file system and on the order in which the entries were created, hence
+/// compare it only between the directories on the same file system or check that the tool under
+/// test sorts the entries deliberately.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ReaddirOrder {
+    /// The names of the entries of each directory in `readdir` order.
+    ///
+    /// The paths are relative to the listed directory, the empty path is the directory itself.
+    pub dirs: BTreeMap<PathBuf, Vec<OsString>>,
+}
+
+impl ReaddirOrder {
+    /// The names of the entries of the directory `dir` in `readdir` order.
+    pub fn names<P: AsRef<Path>>(&self, dir: P) -> Option<&[OsString]> {
+        self.dirs.get(dir.as_ref()).map(|names| &names[..])
+    }
+
+    /// Are the entries of each directory returned in byte order?
+    pub fn is_sorted(&self) -> bool {
+        self.dirs
+            .values()
+            .all(|names| names.windows(2).all(|pair| pair[0] <= pair[1]))
+    }
+
+    /// The directories whose entries are returned in a different order than in `other`.
+    ///
+    /// The names that exist only in one of the listings and the directories that exist only in
+    /// one of the listings are ignored.
+    pub fn differing_dirs<'a>(&'a self, other: &Self) -> Vec<&'a Path> {
+        self.dirs
+            .iter()
+            .filter(|(dir, names)| {
+                let Some(other_names) = other.dirs.get(*dir) else {
+                    return false;
+                };
+                common_names(names, other_names) != common_names(other_names, names)
+            })
+            .map(|(dir, _)| dir.as_path())
+            .collect()
+    }
+}
+
+/// The names from `a` that also exist in `b` in the order of `a`.
+fn common_names<'a>(a: &'a [OsString], b: &[OsString]) -> Vec<&'a OsString> {
+    let b: HashSet<&OsString> = b.iter().collect();
+    a.iter().filter(|name| b.contains(name)).collect()
+}
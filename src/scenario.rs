@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use arbitrary::Unstructured;
+
+use crate::list_dir_all;
+use crate::mutate;
+use crate::Difference;
+use crate::Dir;
+use crate::DirBuilder;
+use crate::FileInfo;
+use crate::Mutation;
+use crate::MutationKinds;
+
+/// Sequence of tree states S0, S1, …, Sn with the recorded change sets between them.
+///
+/// The tree is generated by [`DirBuilder`] (S0) and then modified in place by [`mutate`]
+/// (see [`next_state`](Self::next_state)), i.e. the unchanged files keep their inodes. Run the
+/// incremental backup or the sync tool against [`path`](Self::path) after each state
+/// transition and compare the computed delta with the change set. The listing of each state is
+/// recorded to verify the restored trees.
+pub struct Scenario {
+    dir: Dir,
+    kinds: MutationKinds,
+    listings: Vec<Vec<FileInfo>>,
+    change_sets: Vec<Vec<Mutation>>,
+}
+
+impl Scenario {
+    /// Generate the initial state S0.
+    ///
+    /// The next states are produced by the mutations of the specified kinds.
+    pub fn new(
+        u: &mut Unstructured<'_>,
+        builder: DirBuilder,
+        kinds: MutationKinds,
+    ) -> arbitrary::Result<Self> {
+        let dir = builder.create(u)?;
+        let listing = list_dir_all(dir.path()).unwrap();
+        Ok(Self {
+            dir,
+            kinds,
+            listings: vec![listing],
+            change_sets: Vec::new(),
+        })
+    }
+
+    /// The directory in the current state.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// The index of the current state.
+    pub fn state(&self) -> usize {
+        self.change_sets.len()
+    }
+
+    /// Transition to the next state and return the change set.
+    pub fn next_state(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<&[Mutation]> {
+        let mutations = mutate(self.dir.path(), u, self.kinds)?;
+        self.listings.push(list_dir_all(self.dir.path()).unwrap());
+        self.change_sets.push(mutations);
+        Ok(&self.change_sets[self.change_sets.len() - 1][..])
+    }
+
+    /// The listing of the tree in the specified state (see [`list_dir_all`]).
+    ///
+    /// Panics if the state wasn't reached yet.
+    pub fn listing(&self, state: usize) -> &[FileInfo] {
+        &self.listings[state][..]
+    }
+
+    /// The mutations that transformed the state `state - 1` into `state`.
+    ///
+    /// Panics if the state is zero or wasn't reached yet.
+    pub fn change_set(&self, state: usize) -> &[Mutation] {
+        &self.change_sets[state - 1][..]
+    }
+
+    /// The differences between the state `state - 1` and `state` sorted as in
+    /// [`DirDiff::restricted_to`](crate::DirDiff::restricted_to).
+    ///
+    /// See [`mutate`] for the changes that are not included.
+    pub fn delta(&self, state: usize) -> Vec<Difference> {
+        let mut differences: Vec<Difference> = self
+            .change_set(state)
+            .iter()
+            .flat_map(Mutation::differences)
+            .collect();
+        differences.sort_by(|a, b| {
+            a.path
+                .cmp(&b.path)
+                .then_with(|| b.severity().cmp(&a.severity()))
+        });
+        differences
+    }
+
+    /// Get the underlying directory.
+    pub fn dir(&self) -> &Dir {
+        &self.dir
+    }
+}
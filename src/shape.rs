@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use arbitrary::Unstructured;
+
+use crate::NameGenerator;
+
+/// Tree shape (see [`DirBuilder::shape`](crate::DirBuilder::shape)).
+///
+/// By default the structure of the tree is determined by the separators in the generated names.
+/// The shape instead places each file at the depth sampled from [`depth_weights`](Self::depth_weights)
+/// and creates the intermediate directories as needed. Only the last component of the generated
+/// names is used; a number is appended to the names that already exist in the same directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeShape {
+    /// Minimum no. of generated files.
+    pub min_files: usize,
+    /// Maximum no. of generated files.
+    ///
+    /// The intermediate directories don't count.
+    pub max_files: usize,
+    /// Maximum no. of children per directory including the root.
+    ///
+    /// The files that don't fit are skipped.
+    pub max_children: usize,
+    /// Relative weights of the depths; the first element is the weight of the root's children.
+    ///
+    /// All files are created in the root if the weights are empty or zero.
+    pub depth_weights: Vec<u32>,
+    /// How to distribute the files between the directories at the same depth.
+    pub balance: Balance,
+}
+
+impl TreeShape {
+    /// A single directory with `num_files` children.
+    pub fn wide(num_files: usize) -> Self {
+        Self {
+            min_files: num_files,
+            max_files: num_files,
+            depth_weights: vec![0, 1],
+            ..Default::default()
+        }
+    }
+
+    /// A chain of directories with a single file at the specified depth.
+    ///
+    /// Use short names (e.g. [`PrintableNames`](crate::PrintableNames)) for the paths to fit into
+    /// [`DirBuilder::max_path_len`](crate::DirBuilder::max_path_len).
+    pub fn deep(depth: usize) -> Self {
+        let mut depth_weights = vec![0; depth.max(1)];
+        *depth_weights.last_mut().unwrap() = 1;
+        Self {
+            min_files: 1,
+            max_files: 1,
+            depth_weights,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for TreeShape {
+    fn default() -> Self {
+        Self {
+            min_files: 0,
+            max_files: 10,
+            max_children: usize::MAX,
+            depth_weights: vec![1, 1, 1],
+            balance: Default::default(),
+        }
+    }
+}
+
+/// How to distribute the files between the directories (see [`TreeShape::balance`]).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Balance {
+    /// Add the file to the oldest directory that has room.
+    ///
+    /// New directories are created only when the existing ones are full, i.e. produces few
+    /// large directories.
+    #[default]
+    Skewed,
+    /// Add the file to a new directory while the parent directories have room, otherwise to the
+    /// directory with the fewest children.
+    ///
+    /// Produces directories with approximately the same no. of children.
+    Balanced,
+}
+
+/// Generates the paths according to [`TreeShape`].
+pub(crate) struct ShapedPaths {
+    shape: TreeShape,
+    dirs: Vec<ShapedDir>,
+    /// Directory indices grouped by depth.
+    levels: Vec<Vec<usize>>,
+}
+
+struct ShapedDir {
+    path: PathBuf,
+    names: HashSet<OsString>,
+}
+
+impl ShapedPaths {
+    pub(crate) fn new(shape: TreeShape) -> Self {
+        let root = ShapedDir {
+            path: PathBuf::new(),
+            names: HashSet::new(),
+        };
+        Self {
+            shape,
+            dirs: vec![root],
+            levels: vec![vec![0]],
+        }
+    }
+
+    pub(crate) fn num_files(&self, u: &mut Unstructured<'_>) -> arbitrary::Result<usize> {
+        let min = self.shape.min_files.min(self.shape.max_files);
+        u.int_in_range(min..=self.shape.max_files)
+    }
+
+    /// Returns empty path if all the directories are full.
+    pub(crate) fn generate(
+        &mut self,
+        u: &mut Unstructured<'_>,
+        names: &mut dyn NameGenerator,
+        max_name_len: usize,
+    ) -> arbitrary::Result<OsString> {
+        let depth = self.arbitrary_depth(u)?;
+        let Some(parent) = self.parent_at(u, names, max_name_len, depth - 1)? else {
+            return Ok(OsString::new());
+        };
+        let name = self.new_name(u, names, max_name_len, parent)?;
+        Ok(self.dirs[parent].path.join(name).into_os_string())
+    }
+
+    fn arbitrary_depth(&self, u: &mut Unstructured<'_>) -> arbitrary::Result<usize> {
+        let weights = &self.shape.depth_weights;
+        let total: u64 = weights.iter().map(|w| *w as u64).sum();
+        if total == 0 {
+            return Ok(1);
+        }
+        let mut x = u.int_in_range(0..=total - 1)?;
+        for (i, w) in weights.iter().enumerate() {
+            match x.checked_sub(*w as u64) {
+                Some(y) => x = y,
+                None => return Ok(i + 1),
+            }
+        }
+        Ok(weights.len())
+    }
+
+    /// Returns the index of the directory at the specified level that has room for one more child.
+    fn parent_at(
+        &mut self,
+        u: &mut Unstructured<'_>,
+        names: &mut dyn NameGenerator,
+        max_name_len: usize,
+        level: usize,
+    ) -> arbitrary::Result<Option<usize>> {
+        let max_children = self.shape.max_children;
+        let existing = self.levels.get(level).and_then(|dirs| {
+            let dirs = dirs
+                .iter()
+                .filter(|i| self.dirs[**i].names.len() < max_children);
+            match self.shape.balance {
+                Balance::Skewed => dirs.copied().next(),
+                Balance::Balanced => dirs.copied().min_by_key(|i| self.dirs[*i].names.len()),
+            }
+        });
+        let prefer_new = self.shape.balance == Balance::Balanced && self.can_create(level);
+        if let (Some(i), false) = (existing, prefer_new) {
+            return Ok(Some(i));
+        }
+        if level == 0 {
+            return Ok(existing);
+        }
+        let Some(parent) = self.parent_at(u, names, max_name_len, level - 1)? else {
+            return Ok(existing);
+        };
+        let name = self.new_name(u, names, max_name_len, parent)?;
+        let dir = ShapedDir {
+            path: self.dirs[parent].path.join(name),
+            names: HashSet::new(),
+        };
+        self.dirs.push(dir);
+        if self.levels.len() <= level {
+            self.levels.resize_with(level + 1, Vec::new);
+        }
+        self.levels[level].push(self.dirs.len() - 1);
+        Ok(Some(self.dirs.len() - 1))
+    }
+
+    /// Can a new directory be created at the specified level?
+    fn can_create(&self, level: usize) -> bool {
+        self.levels[..level.min(self.levels.len())]
+            .iter()
+            .flatten()
+            .any(|i| self.dirs[*i].names.len() < self.shape.max_children)
+    }
+
+    /// Generate a name that doesn't exist in the directory yet and add it to the directory.
+    fn new_name(
+        &mut self,
+        u: &mut Unstructured<'_>,
+        names: &mut dyn NameGenerator,
+        max_name_len: usize,
+        dir: usize,
+    ) -> arbitrary::Result<OsString> {
+        let name = names.generate(u)?;
+        let name = Path::new(&name).file_name().unwrap_or_default().as_bytes();
+        let dir = &mut self.dirs[dir];
+        let mut new_name = OsStr::from_bytes(&name[..name.len().min(max_name_len)]).to_os_string();
+        let mut i = dir.names.len();
+        while new_name.is_empty() || dir.names.contains(&new_name) {
+            let suffix = i.to_string();
+            let len = name.len().min(max_name_len.saturating_sub(suffix.len()));
+            let mut bytes = name[..len].to_vec();
+            bytes.extend_from_slice(suffix.as_bytes());
+            new_name = OsString::from_vec(bytes);
+            i += 1;
+        }
+        dir.names.insert(new_name.clone());
+        Ok(new_name)
+    }
+}
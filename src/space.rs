@@ -0,0 +1,150 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io::Error;
+use std::path::Path;
+
+use crate::path_to_c_string;
+use crate::statvfs;
+use crate::DirSpec;
+use crate::FileType;
+
+/// Disk space and inodes (see [`DirSpec::estimate_space`] and [`available_space`]).
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Space {
+    /// Size in bytes.
+    pub bytes: u64,
+    /// No. of inodes.
+    ///
+    /// `None` if the file system doesn't limit the no. of inodes.
+    pub inodes: Option<u64>,
+}
+
+/// Not enough free space or inodes on the target file system.
+///
+/// Returned as the payload of the [`ErrorKind::Other`](std::io::ErrorKind::Other) I/O error, use
+/// [`InsufficientSpace::from_error`] to get it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct InsufficientSpace {
+    /// Space needed to create the tree.
+    pub required: Space,
+    /// Space available to unprivileged users.
+    pub available: Space,
+}
+
+impl InsufficientSpace {
+    /// Get the payload of the I/O error.
+    pub fn from_error(error: &Error) -> Option<&Self> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl Display for InsufficientSpace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "insufficient space: required {} bytes and {} inodes, available {} bytes and {} inodes",
+            self.required.bytes,
+            InodeCount(self.required.inodes),
+            self.available.bytes,
+            InodeCount(self.available.inodes)
+        )
+    }
+}
+
+struct InodeCount(Option<u64>);
+
+impl Display for InodeCount {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(n) => write!(f, "{}", n),
+            None => f.write_str("unlimited"),
+        }
+    }
+}
+
+impl std::error::Error for InsufficientSpace {}
+
+impl From<InsufficientSpace> for Error {
+    fn from(other: InsufficientSpace) -> Self {
+        Error::other(other)
+    }
+}
+
+/// Get the space available to unprivileged users on the file system that contains `path`.
+///
+/// Returns the space and the allocation block size. Disk quotas are not taken into account.
+#[allow(clippy::unnecessary_cast)]
+pub fn available_space<P: AsRef<Path>>(path: P) -> Result<(Space, u64), Error> {
+    let stat = statvfs(&path_to_c_string(path.as_ref().to_path_buf())?)?;
+    let block_size = match stat.f_frsize as u64 {
+        0 => stat.f_bsize as u64,
+        n => n,
+    };
+    let space = Space {
+        bytes: (stat.f_bavail as u64).saturating_mul(block_size),
+        // e.g. btrfs doesn't limit the no. of inodes
+        inodes: if stat.f_files == 0 {
+            None
+        } else {
+            Some(stat.f_favail as u64)
+        },
+    };
+    Ok((space, block_size))
+}
+
+impl DirSpec {
+    /// Estimate the space needed to create the tree on the file system with the specified
+    /// allocation block size.
+    ///
+    /// File contents and directories are rounded up to whole blocks, symbolic link targets
+    /// longer than 60 bytes take one block, the other files take only an inode. Hard links don't
+    /// count.
+    pub fn estimate_space(&self, block_size: u64) -> Space {
+        let block_size = block_size.max(1);
+        let mut space = Space::default();
+        let mut inodes = 0;
+        for entry in self.entries() {
+            let bytes = match entry.file_type {
+                FileType::HardLink => continue,
+                FileType::Regular => {
+                    let size = self.contents(&entry.path).unwrap_or_default().len() as u64;
+                    size.div_ceil(block_size) * block_size
+                }
+                FileType::Directory => block_size,
+                FileType::Symlink => match entry.target.as_ref() {
+                    // shorter targets are stored in the inode
+                    Some(target) if target.as_os_str().len() > FAST_SYMLINK_LEN => block_size,
+                    _ => 0,
+                },
+                _ => 0,
+            };
+            space.bytes += bytes;
+            inodes += 1;
+        }
+        space.inodes = Some(inodes);
+        space
+    }
+
+    /// Check that the file system that contains `path` has enough space to create the tree.
+    ///
+    /// Fails with [`InsufficientSpace`] error otherwise.
+    pub fn check_space<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let (available, block_size) = available_space(path)?;
+        let required = self.estimate_space(block_size);
+        let enough_inodes = match (required.inodes, available.inodes) {
+            (Some(required), Some(available)) => required <= available,
+            _ => true,
+        };
+        if required.bytes > available.bytes || !enough_inodes {
+            return Err(InsufficientSpace {
+                required,
+                available,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// The maximum length of the symbolic link target that is stored in the inode on ext4.
+const FAST_SYMLINK_LEN: usize = 60;
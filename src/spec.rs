@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::fs::hard_link;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::fs::Permissions;
+#[cfg(feature = "fs")]
+use std::io::Error;
+#[cfg(all(feature = "fs", not(target_os = "linux")))]
+use std::io::ErrorKind;
+#[cfg(feature = "fs")]
+use std::io::Write;
+#[cfg(feature = "fs")]
+use std::os::fd::AsFd;
+#[cfg(feature = "fs")]
+use std::os::fd::AsRawFd;
+#[cfg(feature = "fs")]
+use std::os::unix::fs::symlink;
+#[cfg(feature = "fs")]
+use std::os::unix::fs::DirBuilderExt;
+#[cfg(feature = "fs")]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(feature = "fs")]
+use arbitrary::Arbitrary;
+#[cfg(feature = "fs")]
+use arbitrary::Unstructured;
+
+#[cfg(feature = "fs")]
+use crate::bind_socket;
+#[cfg(all(feature = "fs", target_os = "linux"))]
+use crate::bind_socket_at;
+#[cfg(feature = "fs")]
+use crate::create_dir_all_at;
+#[cfg(feature = "fs")]
+use crate::create_file_at;
+#[cfg(feature = "fs")]
+use crate::fchmod_at;
+#[cfg(feature = "fs")]
+use crate::link_at;
+#[cfg(feature = "fs")]
+use crate::mkdir_at;
+#[cfg(feature = "fs")]
+use crate::mkfifo;
+#[cfg(feature = "fs")]
+use crate::mkfifo_at;
+#[cfg(feature = "fs")]
+use crate::mknod;
+#[cfg(feature = "fs")]
+use crate::mknod_at;
+#[cfg(feature = "fs")]
+use crate::os_str_to_c_string;
+#[cfg(feature = "fs")]
+use crate::path_to_c_string;
+#[cfg(feature = "fs")]
+use crate::set_file_modified_time;
+#[cfg(feature = "fs")]
+use crate::set_file_modified_time_at;
+#[cfg(feature = "fs")]
+use crate::symlink_at;
+#[cfg(feature = "fs")]
+use crate::DirBuilder;
+use crate::Entry;
+#[cfg(feature = "fs")]
+use crate::FileType;
+use crate::Manifest;
+#[cfg(feature = "fs")]
+use crate::WorkQueue;
+#[cfg(feature = "fs")]
+use crate::PATH_MAX;
+
+/// Randomly generated tree that exists only in memory.
+///
+/// Use it to feed archive readers that don't need real inodes; generating the tree is much faster
+/// than creating the files. All parent directories are listed explicitly and precede their
+/// children. Symbolic links point to other entries via relative paths.
+///
+/// Created via [`DirBuilder::create_spec`].
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DirSpec {
+    manifest: Manifest,
+    contents: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl DirSpec {
+    pub(crate) fn new(manifest: Manifest, contents: HashMap<PathBuf, Vec<u8>>) -> Self {
+        Self { manifest, contents }
+    }
+
+    /// Generated entries.
+    pub fn entries(&self) -> &[Entry] {
+        &self.manifest.entries[..]
+    }
+
+    /// Get the manifest describing the tree.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Get the contents of the regular file.
+    ///
+    /// Returns `None` if there is no regular file with such path.
+    pub fn contents<P: AsRef<Path>>(&self, path: P) -> Option<&[u8]> {
+        self.contents.get(path.as_ref()).map(Vec::as_slice)
+    }
+
+    /// Create the files in the existing directory.
+    ///
+    /// Use this method to compare the archives generated from the spec with the archives of the
+    /// same tree created by other tools. Directory permissions and modification times are set
+    /// after all the files are created. The files with paths longer than
+    /// [`PATH_MAX`](crate::PATH_MAX) are created relative to the parent directory's descriptor.
+    /// Fails if some file can't be created, e.g. device files without privileges.
+    ///
+    /// Fails with [`InsufficientSpace`](crate::InsufficientSpace) error before creating any
+    /// files if the file system doesn't have enough space (see [`check_space`](Self::check_space)).
+    #[cfg(feature = "fs")]
+    pub fn create_in<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let root = path.as_ref();
+        self.check_space(root)?;
+        let mut queue = WorkQueue::new();
+        for entry in self.entries() {
+            let path = root.join(&entry.path);
+            if path.as_os_str().len() >= PATH_MAX {
+                let contents = self.contents(&entry.path).unwrap_or_default();
+                create_long_entry(root, entry, contents)?;
+                continue;
+            }
+            let mode = entry.mode as libc::mode_t;
+            match entry.file_type {
+                FileType::Regular => {
+                    // create the file now, write the contents later
+                    File::create(&path)?;
+                    let contents = self.contents(&entry.path).unwrap_or_default();
+                    let mode = entry.mode;
+                    queue.push(move || {
+                        std::fs::write(&path, contents)?;
+                        std::fs::set_permissions(&path, Permissions::from_mode(mode))
+                    });
+                }
+                FileType::Directory => {
+                    std::fs::DirBuilder::new().mode(0o700).create(&path)?;
+                }
+                FileType::Fifo => {
+                    mkfifo(&path_to_c_string(path.clone())?, mode)?;
+                    std::fs::set_permissions(&path, Permissions::from_mode(entry.mode))?;
+                }
+                FileType::Socket => {
+                    bind_socket(&path)?;
+                    std::fs::set_permissions(&path, Permissions::from_mode(entry.mode))?;
+                }
+                FileType::BlockDevice | FileType::CharDevice | FileType::Whiteout => {
+                    let format = if entry.file_type == FileType::BlockDevice {
+                        libc::S_IFBLK
+                    } else {
+                        libc::S_IFCHR
+                    };
+                    let c_path = path_to_c_string(path.clone())?;
+                    mknod(&c_path, format | mode, entry.rdev as libc::dev_t)?;
+                    std::fs::set_permissions(&path, Permissions::from_mode(entry.mode))?;
+                }
+                FileType::Symlink => {
+                    symlink(entry.target.as_deref().unwrap_or(Path::new("")), &path)?;
+                }
+                FileType::HardLink => {
+                    hard_link(
+                        root.join(entry.target.as_deref().unwrap_or(Path::new(""))),
+                        &path,
+                    )?;
+                }
+            }
+        }
+        queue.run()?;
+        // children update the modification time of their parents
+        for entry in self.entries().iter().rev() {
+            let path = root.join(&entry.path);
+            if path.as_os_str().len() >= PATH_MAX {
+                set_long_entry_metadata(root, entry)?;
+                continue;
+            }
+            if entry.file_type == FileType::Directory {
+                std::fs::set_permissions(&path, Permissions::from_mode(entry.mode))?;
+            }
+            if let Some(t) = entry
+                .mtime
+                .filter(|_| entry.file_type != FileType::HardLink)
+            {
+                set_file_modified_time(&path_to_c_string(path)?, t)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<'a> Arbitrary<'a> for DirSpec {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        DirBuilder::new().create_spec(u)
+    }
+}
+
+/// Create the file with the path that is longer than `PATH_MAX` via `*at` system calls.
+#[cfg(feature = "fs")]
+fn create_long_entry(root: &Path, entry: &Entry, contents: &[u8]) -> Result<(), Error> {
+    let root = File::open(root)?;
+    let parent = create_dir_all_at(root.as_fd(), entry.path.parent().unwrap_or(Path::new("")))?;
+    let file_name = entry.path.file_name().unwrap_or_default();
+    let name = os_str_to_c_string(file_name)?;
+    let mode = entry.mode as libc::mode_t;
+    match entry.file_type {
+        FileType::Regular => {
+            let mut file = create_file_at(parent.as_fd(), &name, 0o600)?;
+            file.write_all(contents)?;
+            file.set_permissions(Permissions::from_mode(entry.mode))?;
+        }
+        FileType::Directory => {
+            mkdir_at(parent.as_fd(), &name, 0o700)?;
+        }
+        FileType::Fifo => {
+            mkfifo_at(parent.as_fd(), &name, mode)?;
+            fchmod_at(parent.as_fd(), &name, mode)?;
+        }
+        FileType::Socket => {
+            #[cfg(target_os = "linux")]
+            bind_socket_at(parent.as_fd(), file_name)?;
+            #[cfg(not(target_os = "linux"))]
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("socket path `{}` is too long", entry.path.display()),
+            ));
+            #[cfg(target_os = "linux")]
+            fchmod_at(parent.as_fd(), &name, mode)?;
+        }
+        FileType::BlockDevice | FileType::CharDevice | FileType::Whiteout => {
+            let format = if entry.file_type == FileType::BlockDevice {
+                libc::S_IFBLK
+            } else {
+                libc::S_IFCHR
+            };
+            mknod_at(
+                parent.as_fd(),
+                &name,
+                format | mode,
+                entry.rdev as libc::dev_t,
+            )?;
+            fchmod_at(parent.as_fd(), &name, mode)?;
+        }
+        FileType::Symlink => {
+            let target = entry.target.clone().unwrap_or_default();
+            symlink_at(&path_to_c_string(target)?, parent.as_fd(), &name)?;
+        }
+        FileType::HardLink => {
+            // the original might be long as well
+            let original = entry.target.as_deref().unwrap_or(Path::new(""));
+            let original_parent =
+                create_dir_all_at(root.as_fd(), original.parent().unwrap_or(Path::new("")))?;
+            let original_name = os_str_to_c_string(original.file_name().unwrap_or_default())?;
+            link_at(
+                original_parent.as_fd(),
+                &original_name,
+                parent.as_fd(),
+                &name,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Set the permissions of the directory and the modification time of the file with the path that
+/// is longer than `PATH_MAX`.
+#[cfg(feature = "fs")]
+fn set_long_entry_metadata(root: &Path, entry: &Entry) -> Result<(), Error> {
+    let root = File::open(root)?;
+    let parent = create_dir_all_at(root.as_fd(), entry.path.parent().unwrap_or(Path::new("")))?;
+    let name = os_str_to_c_string(entry.path.file_name().unwrap_or_default())?;
+    if entry.file_type == FileType::Directory {
+        fchmod_at(parent.as_fd(), &name, entry.mode as libc::mode_t)?;
+    }
+    if let Some(t) = entry
+        .mtime
+        .filter(|_| entry.file_type != FileType::HardLink)
+    {
+        set_file_modified_time_at(parent.as_raw_fd(), &name, t)?;
+    }
+    Ok(())
+}
+
+#[allow(unused_unsafe)]
+#[cfg(any(feature = "tar", feature = "cpio", feature = "fs"))]
+pub(crate) fn split_device_number(rdev: u64) -> (u32, u32) {
+    let rdev = rdev as libc::dev_t;
+    unsafe { (libc::major(rdev) as u32, libc::minor(rdev) as u32) }
+}
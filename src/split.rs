@@ -0,0 +1,52 @@
+use arbitrary::Arbitrary;
+use arbitrary::Unstructured;
+
+use crate::Dir;
+use crate::DirBuilder;
+
+/// [`Dir`] generated together with other fuzz inputs.
+///
+/// The directory gets `PERCENT` percent of the remaining bytes, the other inputs get the rest.
+/// Use this type instead of a plain [`Dir`] field when deriving [`Arbitrary`] for the inputs,
+/// otherwise the directory consumes the whole buffer and the other fields are always empty.
+///
+/// ```rust
+/// use arbitrary::Arbitrary;
+/// use random_dir::DirAnd;
+///
+/// #[derive(Arbitrary)]
+/// struct Input {
+///     // 30% for the directory, 70% for the archiver options
+///     dir: DirAnd<(u8, Vec<u8>), 30>,
+/// }
+/// ```
+pub struct DirAnd<T, const PERCENT: u8 = 50> {
+    /// The directory.
+    pub dir: Dir,
+    /// Other inputs.
+    pub other: T,
+}
+
+impl<'a, T: Arbitrary<'a>, const PERCENT: u8> Arbitrary<'a> for DirAnd<T, PERCENT> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let dir = DirBuilder::new().create(&mut split_off(u, PERCENT)?)?;
+        let other = u.arbitrary()?;
+        Ok(Self { dir, other })
+    }
+
+    fn arbitrary_take_rest(mut u: Unstructured<'a>) -> arbitrary::Result<Self> {
+        let dir = DirBuilder::new().create(&mut split_off(&mut u, PERCENT)?)?;
+        let other = T::arbitrary_take_rest(u)?;
+        Ok(Self { dir, other })
+    }
+}
+
+/// Take `percent` percent of the remaining bytes as a separate unstructured data.
+///
+/// Useful to control how entropy is split between the directory and the other fuzz inputs in
+/// hand-written [`Arbitrary`] implementations, e.g. when the directory is generated with a
+/// custom [`DirBuilder`].
+pub fn split_off<'a>(u: &mut Unstructured<'a>, percent: u8) -> arbitrary::Result<Unstructured<'a>> {
+    let n = u.len() * percent.min(100) as usize / 100;
+    Ok(Unstructured::new(u.bytes(n)?))
+}
@@ -0,0 +1,265 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::os::unix::ffi::OsStrExt;
+
+use crate::FileType;
+use crate::Manifest;
+use crate::ALL_FILE_TYPES;
+
+/// What was actually generated (see [`Manifest::stats`]).
+///
+/// Log the statistics in fuzz harnesses to verify that the corpus exercises the intended
+/// diversity.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Stats {
+    /// No. of entries.
+    pub num_entries: u64,
+    /// No. of entries of each file type in the order of [`ALL_FILE_TYPES`].
+    ///
+    /// The file types that weren't generated are omitted.
+    pub file_types: Vec<(FileType, u64)>,
+    /// No. of entries whose names belong to each class in the order of [`ALL_NAME_CLASSES`].
+    ///
+    /// Only the last path component is classified. The classes that weren't generated are
+    /// omitted.
+    pub name_classes: Vec<(NameClass, u64)>,
+    /// Union of the permission bits (`0o7777`) of the entries of each file type in the order of
+    /// [`ALL_FILE_TYPES`].
+    pub mode_bits: Vec<(FileType, u32)>,
+    /// Distinct pairs of the file types and the name classes of the entries.
+    pub file_type_name_classes: Vec<(FileType, NameClass)>,
+    /// Total size of the regular files in bytes; hard links are not counted.
+    pub total_bytes: u64,
+    /// Maximum no. of path components; zero for the empty tree.
+    pub max_depth: usize,
+}
+
+impl Manifest {
+    /// Collect the statistics of the generated entries.
+    ///
+    /// The crafted entries are not included.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        let mut file_types = [0; ALL_FILE_TYPES.len() + 1];
+        let mut name_classes = [0; ALL_NAME_CLASSES.len()];
+        let mut mode_bits = [0; ALL_FILE_TYPES.len() + 1];
+        let mut pairs = [[false; ALL_NAME_CLASSES.len()]; ALL_FILE_TYPES.len() + 1];
+        for entry in self.entries.iter() {
+            stats.num_entries += 1;
+            let i = ALL_FILE_TYPES
+                .iter()
+                .position(|kind| *kind == entry.file_type)
+                .unwrap_or(ALL_FILE_TYPES.len());
+            file_types[i] += 1;
+            let name = entry.path.file_name().unwrap_or_default().as_bytes();
+            let class = NameClass::of(name);
+            name_classes[class as usize] += 1;
+            mode_bits[i] |= entry.mode & 0o7777;
+            pairs[i][class as usize] = true;
+            if entry.file_type == FileType::Regular {
+                stats.total_bytes += entry.size;
+            }
+            stats.max_depth = stats.max_depth.max(entry.path.components().count());
+        }
+        let all_file_types = || ALL_FILE_TYPES.into_iter().chain([FileType::Whiteout]);
+        stats.file_types = all_file_types()
+            .zip(file_types)
+            .filter(|(_, n)| *n != 0)
+            .collect();
+        stats.mode_bits = all_file_types()
+            .zip(file_types.into_iter().zip(mode_bits))
+            .filter(|(_, (n, _))| *n != 0)
+            .map(|(kind, (_, bits))| (kind, bits))
+            .collect();
+        stats.file_type_name_classes = all_file_types()
+            .zip(pairs)
+            .flat_map(|(kind, classes)| {
+                ALL_NAME_CLASSES
+                    .into_iter()
+                    .zip(classes)
+                    .filter(|(_, seen)| *seen)
+                    .map(move |(class, _)| (kind, class))
+            })
+            .collect();
+        stats.name_classes = ALL_NAME_CLASSES
+            .into_iter()
+            .zip(name_classes)
+            .filter(|(_, n)| *n != 0)
+            .collect();
+        stats
+    }
+}
+
+#[cfg(feature = "fs")]
+impl crate::Dir {
+    /// Collect the statistics of the generated entries (see [`Manifest::stats`]).
+    pub fn stats(&self) -> Stats {
+        self.manifest().stats()
+    }
+}
+
+/// Class of a file name (see [`Stats::name_classes`]).
+///
+/// Each name belongs to the first matching class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameClass {
+    /// Names that are not valid UTF-8.
+    InvalidUtf8,
+    /// Names containing ASCII control characters including newlines.
+    Control,
+    /// Valid UTF-8 names containing non-ASCII characters.
+    Unicode,
+    /// Names that consist only from the POSIX portable file name characters (`A-Za-z0-9._-`)
+    /// and don't start with `-`.
+    Portable,
+    /// Other printable ASCII names, e.g. names with spaces or shell metacharacters.
+    Ascii,
+}
+
+impl NameClass {
+    /// Classify the name.
+    pub fn of(name: &[u8]) -> Self {
+        if std::str::from_utf8(name).is_err() {
+            Self::InvalidUtf8
+        } else if name.iter().any(|c| c.is_ascii_control()) {
+            Self::Control
+        } else if !name.is_ascii() {
+            Self::Unicode
+        } else if !name.starts_with(b"-")
+            && name
+                .iter()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, b'.' | b'_' | b'-'))
+        {
+            Self::Portable
+        } else {
+            Self::Ascii
+        }
+    }
+}
+
+/// All name classes.
+pub const ALL_NAME_CLASSES: [NameClass; 5] = {
+    use NameClass::*;
+    [InvalidUtf8, Control, Unicode, Portable, Ascii]
+};
+
+/// Accumulates the statistics across the runs and reports the combinations that were never
+/// generated.
+///
+/// Feed each run's [`Stats`] via [`add`](Self::add) and print [`missing`](Self::missing) at the
+/// end of the fuzzing session to tune the builder settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageTracker {
+    file_types: Vec<FileType>,
+    num_runs: u64,
+    mode_bits: Vec<(FileType, u32)>,
+    file_type_name_classes: Vec<(FileType, NameClass)>,
+}
+
+impl CoverageTracker {
+    /// Create new tracker that expects the specified file types.
+    pub fn new<I>(file_types: I) -> Self
+    where
+        I: IntoIterator<Item = FileType>,
+    {
+        let mut expected = Vec::new();
+        for kind in file_types.into_iter() {
+            if !expected.contains(&kind) {
+                expected.push(kind);
+            }
+        }
+        Self {
+            file_types: expected,
+            num_runs: 0,
+            mode_bits: Vec::new(),
+            file_type_name_classes: Vec::new(),
+        }
+    }
+
+    /// Record the statistics of one run.
+    pub fn add(&mut self, stats: &Stats) {
+        self.num_runs += 1;
+        for (kind, bits) in stats.mode_bits.iter() {
+            match self.mode_bits.iter_mut().find(|(other, _)| other == kind) {
+                Some((_, seen)) => *seen |= bits,
+                None => self.mode_bits.push((*kind, *bits)),
+            }
+        }
+        for pair in stats.file_type_name_classes.iter() {
+            if !self.file_type_name_classes.contains(pair) {
+                self.file_type_name_classes.push(*pair);
+            }
+        }
+    }
+
+    /// No. of recorded runs.
+    pub fn num_runs(&self) -> u64 {
+        self.num_runs
+    }
+
+    /// The combinations of the expected file types, the mode bits and the name classes that were
+    /// never generated.
+    ///
+    /// The file types that were never generated are reported without the other combinations. The
+    /// mode bits of the symbolic links are not reported, they are fixed.
+    pub fn missing(&self) -> Vec<CoverageGap> {
+        let mut gaps = Vec::new();
+        for kind in self.file_types.iter().copied() {
+            let Some((_, seen_bits)) = self.mode_bits.iter().find(|(other, _)| *other == kind)
+            else {
+                gaps.push(CoverageGap::FileType(kind));
+                continue;
+            };
+            if kind != FileType::Symlink {
+                for bit in MODE_BITS {
+                    if seen_bits & bit == 0 {
+                        gaps.push(CoverageGap::ModeBit(kind, bit));
+                    }
+                }
+            }
+            for class in ALL_NAME_CLASSES {
+                if !self.file_type_name_classes.contains(&(kind, class)) {
+                    gaps.push(CoverageGap::NameClass(kind, class));
+                }
+            }
+        }
+        gaps
+    }
+}
+
+impl Default for CoverageTracker {
+    fn default() -> Self {
+        Self::new(ALL_FILE_TYPES)
+    }
+}
+
+/// The combination that was never generated (see [`CoverageTracker::missing`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverageGap {
+    /// No entries of the file type.
+    FileType(FileType),
+    /// No entries of the file type with the mode bit set.
+    ModeBit(FileType, u32),
+    /// No entries of the file type with the name of the class.
+    NameClass(FileType, NameClass),
+}
+
+impl Display for CoverageGap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FileType(kind) => write!(f, "no {:?} entries", kind),
+            Self::ModeBit(kind, bit) => {
+                write!(f, "no {:?} entries with mode bit {:04o}", kind, bit)
+            }
+            Self::NameClass(kind, class) => {
+                write!(f, "no {:?} entries with {:?} names", kind, class)
+            }
+        }
+    }
+}
+
+/// The permission bits tracked by [`CoverageTracker`]: read/write/execute bits for the owner, the
+/// group and the others.
+///
+/// Set-user-ID, set-group-ID and sticky bits are not tracked, the generator never sets them.
+const MODE_BITS: [u32; 9] = [0o400, 0o200, 0o100, 0o40, 0o20, 0o10, 0o4, 0o2, 0o1];
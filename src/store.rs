@@ -0,0 +1,407 @@
+use std::ffi::OsString;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::from_unix_time;
+use crate::unix_time;
+use crate::AgeBucket;
+use crate::AgedEntry;
+use crate::CollapsedName;
+use crate::CraftedEntry;
+use crate::Downgrade;
+use crate::Entry;
+use crate::ExpectedAction;
+use crate::FileType;
+use crate::Limit;
+use crate::Manifest;
+use crate::NameEncoding;
+
+/// Compression of the stored specs and manifests.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Compression {
+    /// Store as is.
+    #[default]
+    None,
+    /// Zstandard with the specified compression level (`0` means the default level).
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    /// File name extension including the leading dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::None => "",
+            #[cfg(feature = "zstd")]
+            Self::Zstd(..) => ".zst",
+        }
+    }
+
+    /// Wrap `writer` into streaming compressor.
+    ///
+    /// The compressed stream is finished when the returned writer is dropped.
+    pub fn writer<'a, W: Write + 'a>(self, writer: W) -> Result<Box<dyn Write + 'a>, Error> {
+        match self {
+            Self::None => Ok(Box::new(writer)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(level) => Ok(Box::new(
+                zstd::stream::write::Encoder::new(writer, level)?.auto_finish(),
+            )),
+        }
+    }
+
+    /// Wrap `reader` into streaming decompressor.
+    pub fn reader<'a, R: Read + 'a>(self, reader: R) -> Result<Box<dyn Read + 'a>, Error> {
+        match self {
+            Self::None => Ok(Box::new(reader)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(..) => Ok(Box::new(zstd::stream::read::Decoder::new(reader)?)),
+        }
+    }
+}
+
+/// All supported compressions.
+#[cfg(feature = "fs")]
+pub(crate) fn all_compressions() -> Vec<Compression> {
+    vec![
+        Compression::None,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd(0),
+    ]
+}
+
+impl Manifest {
+    /// Serialize the manifest in line-based text format.
+    ///
+    /// Each line is a record with tab-separated fields. Paths and attribute values are
+    /// percent-encoded.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writeln!(writer, "{}", MAGIC)?;
+        writeln!(
+            writer,
+            "encoding\t{}",
+            match self.name_encoding {
+                NameEncoding::Utf8 => "utf8",
+                NameEncoding::Cp437 => "cp437",
+            }
+        )?;
+        for entry in self.entries.iter() {
+            let mtime = match entry.mtime {
+                Some(t) => {
+                    let (secs, nanos) = unix_time(t);
+                    format!("{}.{:09}", secs, nanos)
+                }
+                None => "-".into(),
+            };
+            let owner = match entry.owner {
+                Some((uid, gid)) => format!("{}:{}", uid, gid),
+                None => "-".into(),
+            };
+            let target = match entry.target.as_ref() {
+                Some(target) => escape(target.as_os_str().as_bytes()),
+                None => String::new(),
+            };
+            writeln!(
+                writer,
+                "entry\t{}\t{:o}\t{}\t{}\t{}\t{}\t{}\t{}",
+                file_type_name(entry.file_type),
+                entry.mode,
+                mtime,
+                entry.size,
+                entry.rdev,
+                owner,
+                escape(entry.path.as_os_str().as_bytes()),
+                target
+            )?;
+            for (name, value) in entry.xattrs.iter() {
+                writeln!(
+                    writer,
+                    "xattr\t{}\t{}",
+                    escape(name.as_bytes()),
+                    escape(value)
+                )?;
+            }
+            if entry.inode_flags != 0 {
+                writeln!(writer, "flags\t{:x}", entry.inode_flags)?;
+            }
+        }
+        for crafted in self.crafted_entries.iter() {
+            let (kind, value) = match crafted.limit {
+                Limit::PathLength(n) => ("path-length", n as u64),
+                Limit::LinkCount(n) => ("link-count", n),
+                Limit::SymlinkTargetLength(n) => ("symlink-target-length", n as u64),
+            };
+            writeln!(
+                writer,
+                "crafted\t{}\t{}\t{}",
+                kind,
+                value,
+                escape(crafted.path.as_os_str().as_bytes())
+            )?;
+        }
+        for downgrade in self.downgrades.iter() {
+            writeln!(
+                writer,
+                "downgrade\t{}\t{}\t{}\t{}",
+                file_type_name(downgrade.requested),
+                file_type_name(downgrade.actual),
+                downgrade.rdev,
+                escape(downgrade.path.as_os_str().as_bytes())
+            )?;
+        }
+        for collapsed in self.collapsed_names.iter() {
+            writeln!(
+                writer,
+                "collapsed\t{}\t{}",
+                escape(collapsed.path.as_os_str().as_bytes()),
+                escape(collapsed.existing.as_os_str().as_bytes())
+            )?;
+        }
+        for path in self.escaping_symlinks.iter() {
+            writeln!(writer, "escaping\t{}", escape(path.as_os_str().as_bytes()))?;
+        }
+        for expected in self.expected_actions.iter() {
+            writeln!(
+                writer,
+                "action\t{}\t{}",
+                escape(expected.action.as_bytes()),
+                escape(expected.path.as_os_str().as_bytes())
+            )?;
+        }
+        for aged in self.aged_entries.iter() {
+            let min_age = aged.bucket.min_age;
+            writeln!(
+                writer,
+                "age\t{}.{:09}\t{}\t{}",
+                min_age.as_secs(),
+                min_age.subsec_nanos(),
+                aged.bucket.percent,
+                escape(aged.path.as_os_str().as_bytes())
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Deserialize the manifest written by [`write_to`](Self::write_to).
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, Error> {
+        let mut manifest = Manifest::default();
+        let mut lines = BufReader::new(reader).lines();
+        if lines.next().transpose()?.as_deref() != Some(MAGIC) {
+            return Err(invalid_data("not a manifest"));
+        }
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            parse_record(&line, &mut manifest)
+                .ok_or_else(|| invalid_data(format!("invalid record at line {}", i + 2)))?;
+        }
+        Ok(manifest)
+    }
+}
+
+fn parse_record(line: &str, manifest: &mut Manifest) -> Option<()> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    match fields[..] {
+        ["encoding", encoding] => {
+            manifest.name_encoding = match encoding {
+                "utf8" => NameEncoding::Utf8,
+                "cp437" => NameEncoding::Cp437,
+                _ => return None,
+            };
+        }
+        ["entry", file_type, mode, mtime, size, rdev, owner, path, target] => {
+            let mtime = match mtime {
+                "-" => None,
+                _ => {
+                    let (secs, nanos) = mtime.split_once('.')?;
+                    let nanos: u32 = nanos.parse().ok()?;
+                    if nanos >= 1_000_000_000 {
+                        return None;
+                    }
+                    Some(from_unix_time(secs.parse().ok()?, nanos))
+                }
+            };
+            let owner = match owner {
+                "-" => None,
+                _ => {
+                    let (uid, gid) = owner.split_once(':')?;
+                    Some((uid.parse().ok()?, gid.parse().ok()?))
+                }
+            };
+            let target = match target {
+                "" => None,
+                _ => Some(unescape_path(target)?),
+            };
+            manifest.entries.push(Entry {
+                path: unescape_path(path)?,
+                file_type: parse_file_type(file_type)?,
+                mode: u32::from_str_radix(mode, 8).ok()?,
+                mtime,
+                target,
+                size: size.parse().ok()?,
+                rdev: rdev.parse().ok()?,
+                owner,
+                xattrs: Vec::new(),
+                inode_flags: 0,
+            });
+        }
+        ["flags", flags] => {
+            let entry = manifest.entries.last_mut()?;
+            entry.inode_flags = u32::from_str_radix(flags, 16).ok()?;
+        }
+        ["xattr", name, value] => {
+            let entry = manifest.entries.last_mut()?;
+            entry
+                .xattrs
+                .push((OsString::from_vec(unescape(name)?), unescape(value)?));
+        }
+        ["crafted", kind, value, path] => {
+            let value: u64 = value.parse().ok()?;
+            let limit = match kind {
+                "path-length" => Limit::PathLength(value.try_into().ok()?),
+                "link-count" => Limit::LinkCount(value),
+                "symlink-target-length" => Limit::SymlinkTargetLength(value.try_into().ok()?),
+                _ => return None,
+            };
+            manifest.crafted_entries.push(CraftedEntry {
+                path: unescape_path(path)?,
+                limit,
+            });
+        }
+        ["downgrade", requested, actual, rdev, path] => {
+            manifest.downgrades.push(Downgrade {
+                path: unescape_path(path)?,
+                requested: parse_file_type(requested)?,
+                actual: parse_file_type(actual)?,
+                rdev: rdev.parse().ok()?,
+            });
+        }
+        ["collapsed", path, existing] => {
+            manifest.collapsed_names.push(CollapsedName {
+                path: unescape_path(path)?,
+                existing: unescape_path(existing)?,
+            });
+        }
+        ["escaping", path] => {
+            manifest.escaping_symlinks.push(unescape_path(path)?);
+        }
+        ["action", action, path] => {
+            manifest.expected_actions.push(ExpectedAction {
+                path: unescape_path(path)?,
+                action: String::from_utf8(unescape(action)?).ok()?,
+            });
+        }
+        ["age", min_age, percent, path] => {
+            let (secs, nanos) = min_age.split_once('.')?;
+            let nanos: u32 = nanos.parse().ok()?;
+            if nanos >= 1_000_000_000 {
+                return None;
+            }
+            manifest.aged_entries.push(AgedEntry {
+                path: unescape_path(path)?,
+                bucket: AgeBucket::new(
+                    Duration::new(secs.parse().ok()?, nanos),
+                    percent.parse().ok()?,
+                ),
+            });
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+fn file_type_name(file_type: FileType) -> &'static str {
+    use FileType::*;
+    match file_type {
+        Regular => "regular",
+        Directory => "directory",
+        Fifo => "fifo",
+        Socket => "socket",
+        BlockDevice => "block-device",
+        CharDevice => "char-device",
+        Symlink => "symlink",
+        HardLink => "hard-link",
+        Whiteout => "whiteout",
+    }
+}
+
+fn parse_file_type(name: &str) -> Option<FileType> {
+    use FileType::*;
+    [
+        Regular,
+        Directory,
+        Fifo,
+        Socket,
+        BlockDevice,
+        CharDevice,
+        Symlink,
+        HardLink,
+        Whiteout,
+    ]
+    .into_iter()
+    .find(|file_type| file_type_name(*file_type) == name)
+}
+
+/// Percent-encode non-printable and non-ASCII bytes, and the percent sign itself.
+fn escape(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len());
+    for b in bytes {
+        if b.is_ascii_graphic() && *b != b'%' || *b == b' ' {
+            s.push(*b as char);
+        } else {
+            s.push_str(&format!("%{:02X}", b));
+        }
+    }
+    s
+}
+
+fn unescape(s: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    Some(bytes)
+}
+
+fn unescape_path(s: &str) -> Option<PathBuf> {
+    Some(OsString::from_vec(unescape(s)?).into())
+}
+
+fn invalid_data<S: Into<String>>(message: S) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+const MAGIC: &str = "random-dir manifest 1";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_records() {
+        for record in [
+            "age\t1.1000000000\t50\ta",
+            "age\t-1.000000000\t50\ta",
+            "age\t1\t50\ta",
+            "downgrade\tblock-device\tregular\ta",
+            "entry\tregular\t644\t1.1000000000\t0\t0\t-\ta\t",
+        ] {
+            let manifest = format!("{}\n{}\n", MAGIC, record);
+            let error = Manifest::read_from(manifest.as_bytes()).unwrap_err();
+            assert_eq!(ErrorKind::InvalidData, error.kind(), "{:?}", record);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+use std::io::Error;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::split_device_number;
+use crate::unix_time;
+use crate::DirSpec;
+use crate::FileType;
+use crate::USTAR_NAME_LEN;
+use crate::USTAR_PREFIX_LEN;
+
+impl DirSpec {
+    /// Write the tree as POSIX tar archive.
+    ///
+    /// The values that don't fit into ustar header (long paths and link targets, large sizes and
+    /// ids, sub-second modification times, extended attributes) are stored in pax extended
+    /// headers. The modification times before the epoch are stored as negative pax times. Sockets can't be archived and are skipped together with their hard links.
+    pub fn write_tar<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut skipped = HashSet::new();
+        for (i, entry) in self.entries().iter().enumerate() {
+            let (type_flag, size) = match entry.file_type {
+                FileType::Regular => (b'0', entry.size),
+                FileType::HardLink => (b'1', 0),
+                FileType::Symlink => (b'2', 0),
+                FileType::CharDevice | FileType::Whiteout => (b'3', 0),
+                FileType::BlockDevice => (b'4', 0),
+                FileType::Directory => (b'5', 0),
+                FileType::Fifo => (b'6', 0),
+                FileType::Socket => {
+                    skipped.insert(entry.path.as_path());
+                    continue;
+                }
+            };
+            let target = entry.target.as_deref().map(Path::as_os_str);
+            if entry.file_type == FileType::HardLink
+                && entry.target.as_deref().is_some_and(|t| skipped.contains(t))
+            {
+                continue;
+            }
+            let mut path = entry.path.as_os_str().as_bytes().to_vec();
+            if entry.file_type == FileType::Directory {
+                path.push(b'/');
+            }
+            let (uid, gid) = entry.owner.unwrap_or((0, 0));
+            let (secs, nanos) = entry.mtime.map(unix_time).unwrap_or((0, 0));
+            let mut header = Header::new();
+            let mut pax = Vec::new();
+            // non-UTF-8 paths are stored as is like GNU tar does
+            if !header.set_path(&path) {
+                pax_record(&mut pax, b"path", &path);
+            }
+            if let Some(target) = target {
+                let target = target.as_bytes();
+                if target.len() <= USTAR_NAME_LEN {
+                    header.0[157..157 + target.len()].copy_from_slice(target);
+                } else {
+                    pax_record(&mut pax, b"linkpath", target);
+                }
+            }
+            if !header.set_number(124..136, size) {
+                pax_record(&mut pax, b"size", size.to_string().as_bytes());
+            }
+            if !header.set_number(108..116, uid.into()) {
+                pax_record(&mut pax, b"uid", uid.to_string().as_bytes());
+            }
+            if !header.set_number(116..124, gid.into()) {
+                pax_record(&mut pax, b"gid", gid.to_string().as_bytes());
+            }
+            let in_header = u64::try_from(secs).is_ok_and(|secs| header.set_number(136..148, secs));
+            if !in_header || nanos != 0 {
+                pax_record(&mut pax, b"mtime", pax_time(secs, nanos).as_bytes());
+            }
+            for (name, value) in entry.xattrs.iter() {
+                let mut key = b"SCHILY.xattr.".to_vec();
+                key.extend_from_slice(name.as_bytes());
+                pax_record(&mut pax, &key, value);
+            }
+            header.set_number(100..108, (entry.mode & 0o7777).into());
+            header.0[156] = type_flag;
+            if matches!(type_flag, b'3' | b'4') {
+                // the numbers fit into the ustar fields on all supported platforms
+                let (major, minor) = split_device_number(entry.rdev);
+                header.set_number(329..337, major.into());
+                header.set_number(337..345, minor.into());
+            }
+            if !pax.is_empty() {
+                let mut pax_header = Header::new();
+                pax_header.set_path(format!("PaxHeaders/{}", i).as_bytes());
+                pax_header.set_number(100..108, 0o644);
+                pax_header.set_number(124..136, pax.len() as u64);
+                pax_header.0[156] = b'x';
+                pax_header.write(&mut writer)?;
+                write_padded(&mut writer, &pax)?;
+            }
+            header.write(&mut writer)?;
+            if entry.file_type == FileType::Regular {
+                write_padded(&mut writer, self.contents(&entry.path).unwrap_or_default())?;
+            }
+        }
+        // end-of-archive marker
+        writer.write_all(&[0_u8; 2 * BLOCK_SIZE])?;
+        Ok(())
+    }
+}
+
+struct Header([u8; BLOCK_SIZE]);
+
+impl Header {
+    fn new() -> Self {
+        let mut header = Self([0_u8; BLOCK_SIZE]);
+        header.0[257..263].copy_from_slice(b"ustar\0");
+        header.0[263..265].copy_from_slice(b"00");
+        header
+    }
+
+    /// Store the path in name and prefix fields.
+    ///
+    /// Returns `false` if the path doesn't fit.
+    fn set_path(&mut self, path: &[u8]) -> bool {
+        if path.len() <= USTAR_NAME_LEN {
+            self.0[..path.len()].copy_from_slice(path);
+            return true;
+        }
+        // split at the first slash that makes the name fit
+        let split = path.iter().enumerate().position(|(i, b)| {
+            *b == b'/'
+                && i <= USTAR_PREFIX_LEN
+                && path.len() - i - 1 <= USTAR_NAME_LEN
+                && i + 1 != path.len()
+        });
+        let Some(i) = split else {
+            return false;
+        };
+        let (prefix, name) = (&path[..i], &path[i + 1..]);
+        self.0[..name.len()].copy_from_slice(name);
+        self.0[345..345 + prefix.len()].copy_from_slice(prefix);
+        true
+    }
+
+    /// Store the number as NUL-terminated octal string.
+    ///
+    /// Returns `false` if the number doesn't fit.
+    fn set_number(&mut self, range: std::ops::Range<usize>, value: u64) -> bool {
+        let width = range.len() - 1;
+        let s = format!("{:0width$o}", value, width = width);
+        if s.len() > width {
+            return false;
+        }
+        self.0[range.start..range.start + width].copy_from_slice(s.as_bytes());
+        true
+    }
+
+    fn write<W: Write>(&mut self, mut writer: W) -> Result<(), Error> {
+        self.0[148..156].fill(b' ');
+        let checksum: u64 = self.0.iter().map(|b| *b as u64).sum();
+        let s = format!("{:06o}\0 ", checksum);
+        self.0[148..156].copy_from_slice(s.as_bytes());
+        writer.write_all(&self.0)
+    }
+}
+
+/// Format the time as decimal seconds since Unix epoch with the fractional part.
+///
+/// The times before the epoch are negative as pax requires, i.e. the fractional part has the
+/// same sign as the seconds.
+fn pax_time(secs: i64, nanos: u32) -> String {
+    if secs < 0 && nanos != 0 {
+        format!("-{}.{:09}", -(secs + 1), 1_000_000_000 - nanos)
+    } else {
+        format!("{}.{:09}", secs, nanos)
+    }
+}
+
+/// Append `<length> <key>=<value>\n` record where the length includes itself.
+fn pax_record(pax: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    let len = key.len() + value.len() + 3;
+    let mut total = len + len.to_string().len();
+    if total.to_string().len() != len.to_string().len() {
+        total += 1;
+    }
+    pax.extend_from_slice(total.to_string().as_bytes());
+    pax.push(b' ');
+    pax.extend_from_slice(key);
+    pax.push(b'=');
+    pax.extend_from_slice(value);
+    pax.push(b'\n');
+}
+
+fn write_padded<W: Write>(mut writer: W, data: &[u8]) -> Result<(), Error> {
+    writer.write_all(data)?;
+    let padding = data.len().next_multiple_of(BLOCK_SIZE) - data.len();
+    writer.write_all(&[0_u8; BLOCK_SIZE][..padding])
+}
+
+const BLOCK_SIZE: usize = 512;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn archive_framing() {
+        use std::collections::HashMap;
+        use std::os::unix::ffi::OsStrExt;
+
+        arbtest::arbtest(|u| {
+            let spec: DirSpec = u.arbitrary()?;
+            let mut archive = Vec::new();
+            spec.write_tar(&mut archive).unwrap();
+            assert_eq!(0, archive.len() % BLOCK_SIZE);
+            let mut offset = 0;
+            let mut pax: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+            let mut archived = Vec::new();
+            loop {
+                let header = &archive[offset..offset + BLOCK_SIZE];
+                offset += BLOCK_SIZE;
+                if header.iter().all(|b| *b == 0) {
+                    break;
+                }
+                let checksum: u64 = header
+                    .iter()
+                    .enumerate()
+                    .map(|(i, b)| if (148..156).contains(&i) { b' ' } else { *b } as u64)
+                    .sum();
+                assert_eq!(checksum, octal(&header[148..154]));
+                let mut size = octal(&header[124..135]) as usize;
+                if let Some(value) = pax.get(&b"size"[..]) {
+                    size = std::str::from_utf8(value).unwrap().parse().unwrap();
+                }
+                let data = &archive[offset..offset + size];
+                offset += size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+                if header[156] == b'x' {
+                    pax = parse_pax(data);
+                    continue;
+                }
+                let path = match pax.remove(&b"path"[..]) {
+                    Some(path) => path,
+                    None => {
+                        let name = &header[..USTAR_NAME_LEN];
+                        let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+                        let prefix = &header[345..345 + USTAR_PREFIX_LEN];
+                        let prefix =
+                            &prefix[..prefix.iter().position(|b| *b == 0).unwrap_or(prefix.len())];
+                        match prefix {
+                            [] => name.to_vec(),
+                            _ => [prefix, b"/", name].concat(),
+                        }
+                    }
+                };
+                pax.clear();
+                let path = path.strip_suffix(b"/").unwrap_or(&path[..]).to_vec();
+                let entry = spec
+                    .entries()
+                    .iter()
+                    .find(|e| e.path.as_os_str().as_bytes() == &path[..])
+                    .unwrap();
+                if entry.file_type == FileType::Regular {
+                    assert_eq!(spec.contents(&entry.path).unwrap(), data);
+                }
+                archived.push(path);
+            }
+            assert_eq!(archive.len(), offset + BLOCK_SIZE);
+            let expected: Vec<&[u8]> = spec
+                .entries()
+                .iter()
+                .filter(|e| {
+                    let original = match e.file_type {
+                        FileType::HardLink => spec
+                            .entries()
+                            .iter()
+                            .find(|o| Some(&o.path) == e.target.as_ref())
+                            .unwrap(),
+                        _ => e,
+                    };
+                    original.file_type != FileType::Socket
+                })
+                .map(|e| e.path.as_os_str().as_bytes())
+                .collect();
+            assert_eq!(expected, archived);
+            Ok(())
+        });
+    }
+
+    #[cfg(feature = "fs")]
+    fn octal(field: &[u8]) -> u64 {
+        let s = std::str::from_utf8(field)
+            .unwrap()
+            .trim_matches(['\0', ' ']);
+        u64::from_str_radix(s, 8).unwrap()
+    }
+
+    #[cfg(feature = "fs")]
+    fn parse_pax(mut data: &[u8]) -> std::collections::HashMap<Vec<u8>, Vec<u8>> {
+        let mut records = std::collections::HashMap::new();
+        while !data.is_empty() {
+            let space = data.iter().position(|b| *b == b' ').unwrap();
+            let len: usize = std::str::from_utf8(&data[..space])
+                .unwrap()
+                .parse()
+                .unwrap();
+            let record = &data[space + 1..len - 1];
+            let equals = record.iter().position(|b| *b == b'=').unwrap();
+            records.insert(record[..equals].to_vec(), record[equals + 1..].to_vec());
+            data = &data[len..];
+        }
+        records
+    }
+
+    #[test]
+    fn pax_times() {
+        for (secs, nanos, expected) in [
+            (0, 0, "0.000000000"),
+            (1, 500_000_000, "1.500000000"),
+            (-1, 0, "-1.000000000"),
+            (-1, 500_000_000, "-0.500000000"),
+            (-2, 1, "-1.999999999"),
+        ] {
+            assert_eq!(expected, pax_time(secs, nanos), "{secs} {nanos}");
+        }
+    }
+}
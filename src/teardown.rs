@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fs::File;
+use std::fs::Permissions;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::os::fd::AsFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+use tempfile::TempDir;
+
+use crate::fchmod;
+use crate::fchmod_at;
+use crate::is_dir_at;
+use crate::open_dir_at;
+use crate::open_parent_at;
+use crate::read_dir_at;
+use crate::unlink_at;
+use crate::Entry;
+use crate::FileType;
+use crate::SafetyGuard;
+
+/// Teardown time and system call counts (see [`Dir::delete_benchmark`]).
+///
+/// [`Dir::delete_benchmark`]: crate::Dir::delete_benchmark
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct DeleteReport {
+    /// Wall-clock time spent deleting the tree.
+    pub duration: Duration,
+    /// No. of `unlinkat` calls for files including the failed ones.
+    pub unlink: u64,
+    /// No. of `unlinkat` calls for directories including the failed ones.
+    pub rmdir: u64,
+    /// No. of `openat` calls for directories including the failed ones.
+    ///
+    /// Includes reopening the parents of the directories deeper than 64 levels.
+    pub open: u64,
+    /// No. of directory listings.
+    pub readdir: u64,
+    /// No. of `fstatat` calls.
+    ///
+    /// The file type is looked up only if the file system doesn't report it in the listing.
+    pub stat: u64,
+    /// No. of `fchmod` calls that made the directories accessible.
+    pub chmod: u64,
+}
+
+impl DeleteReport {
+    /// Total no. of system calls.
+    ///
+    /// Each directory listing is counted once regardless of the no. of `getdents` calls.
+    pub fn syscalls(&self) -> u64 {
+        self.unlink + self.rmdir + self.open + self.readdir + self.stat + self.chmod
+    }
+}
+
+impl Display for DeleteReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "deleted in {:?}: {} syscalls ({} unlink, {} rmdir, {} open, {} readdir, {} stat, {} chmod)",
+            self.duration,
+            self.syscalls(),
+            self.unlink,
+            self.rmdir,
+            self.open,
+            self.readdir,
+            self.stat,
+            self.chmod
+        )
+    }
+}
+
+/// Delete the contents of the directory depth-first and optionally the directory itself.
+///
+/// All the calls are relative to the parent directory, i.e. works for paths longer than
+/// `PATH_MAX`.
+pub(crate) fn delete_dir_all(path: &Path, remove_root: bool) -> Result<DeleteReport, Error> {
+    let mut report = DeleteReport::default();
+    let start = Instant::now();
+    let dir = match File::open(path) {
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            report.open += 1;
+            std::fs::set_permissions(path, Permissions::from_mode(0o700))?;
+            report.chmod += 1;
+            File::open(path)?
+        }
+        other => other?,
+    };
+    report.open += 1;
+    drop(delete_contents(dir.into(), 0, &mut report)?);
+    if remove_root {
+        std::fs::remove_dir(path)?;
+        report.rmdir += 1;
+    }
+    report.duration = start.elapsed();
+    Ok(report)
+}
+
+/// Delete the generated `entries` from the existing directory `root`.
+///
+/// The generated directories are deleted depth-first with all their contents, the other files in
+/// `root` are left intact. The paths are checked by `guard` before anything is deleted. All the calls are
+/// relative to `root` and do not follow symlinks.
+pub(crate) fn delete_entries(
+    root: &Path,
+    entries: &[Entry],
+    guard: &SafetyGuard,
+) -> Result<DeleteReport, Error> {
+    // everything inside the generated directories was generated as well
+    let paths: HashSet<&Path> = entries.iter().map(|entry| entry.path.as_path()).collect();
+    let top_level: Vec<&Entry> = entries
+        .iter()
+        .filter(|entry| !entry.path.parent().is_some_and(|p| paths.contains(p)))
+        .collect();
+    for entry in top_level.iter() {
+        guard.check(root.join(&entry.path))?;
+    }
+    let mut report = DeleteReport::default();
+    let start = Instant::now();
+    let root = File::open(root)?;
+    report.open += 1;
+    for entry in top_level.into_iter() {
+        let (parent, name) = open_parent_at(root.as_fd(), &entry.path)?;
+        report.open += entry.path.components().count().saturating_sub(1) as u64;
+        if entry.file_type == FileType::Directory {
+            let mut result = open_dir_at(parent.as_fd(), &name);
+            if matches!(&result, Err(e) if e.kind() == ErrorKind::PermissionDenied) {
+                // the directory itself might be unreadable
+                report.open += 1;
+                fchmod_at(parent.as_fd(), &name, 0o700)?;
+                report.chmod += 1;
+                result = open_dir_at(parent.as_fd(), &name);
+            }
+            let fd = result?;
+            report.open += 1;
+            drop(delete_contents(fd, 0, &mut report)?);
+            report.rmdir += 1;
+            unlink_at(parent.as_fd(), &name, libc::AT_REMOVEDIR)?;
+        } else {
+            report.unlink += 1;
+            unlink_at(parent.as_fd(), &name, 0)?;
+        }
+    }
+    report.duration = start.elapsed();
+    Ok(report)
+}
+
+/// Temporary directory that is deleted via [`delete_dir_all`] on drop.
+///
+/// Unlike [`TempDir`] works if the tree contains read-only directories.
+pub(crate) struct WorkDir(Option<TempDir>);
+
+impl WorkDir {
+    pub(crate) fn new() -> Result<Self, Error> {
+        Ok(Self(Some(TempDir::new()?)))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.0.as_ref().unwrap().path()
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        if let Some(dir) = self.0.take() {
+            let _ = delete_dir_all(&dir.keep(), true);
+        }
+    }
+}
+
+/// Returns the directory descriptor that might have been reopened.
+fn delete_contents(
+    mut dirfd: OwnedFd,
+    depth: usize,
+    report: &mut DeleteReport,
+) -> Result<OwnedFd, Error> {
+    let entries = read_dir_at(dirfd.as_fd())?;
+    report.readdir += 1;
+    let mut writable = false;
+    for (name, is_dir) in entries.into_iter() {
+        let is_dir = match is_dir {
+            Some(is_dir) => is_dir,
+            None => {
+                report.stat += 1;
+                is_dir_at(dirfd.as_fd(), &name)?
+            }
+        };
+        if is_dir {
+            let mut result = open_dir_at(dirfd.as_fd(), &name);
+            if !writable && matches!(&result, Err(e) if e.kind() == ErrorKind::PermissionDenied) {
+                // the parent might be not searchable
+                report.open += 1;
+                fchmod(dirfd.as_fd(), 0o700)?;
+                report.chmod += 1;
+                writable = true;
+                result = open_dir_at(dirfd.as_fd(), &name);
+            }
+            if matches!(&result, Err(e) if e.kind() == ErrorKind::PermissionDenied) {
+                // the directory itself might be unreadable
+                report.open += 1;
+                fchmod_at(dirfd.as_fd(), &name, 0o700)?;
+                report.chmod += 1;
+                result = open_dir_at(dirfd.as_fd(), &name);
+            }
+            let fd = result?;
+            report.open += 1;
+            if depth < MAX_OPEN_DIRS {
+                delete_contents(fd, depth + 1, report)?;
+            } else {
+                // do not run out of file descriptors in deep trees
+                drop(dirfd);
+                let fd = delete_contents(fd, depth + 1, report)?;
+                dirfd = open_dir_at(fd.as_fd(), c"..")?;
+                report.open += 1;
+            }
+        }
+        let flags = if is_dir { libc::AT_REMOVEDIR } else { 0 };
+        let mut calls = 1;
+        let mut result = unlink_at(dirfd.as_fd(), &name, flags);
+        if !writable && matches!(&result, Err(e) if e.kind() == ErrorKind::PermissionDenied) {
+            // the generated directories might be read-only
+            fchmod(dirfd.as_fd(), 0o700)?;
+            report.chmod += 1;
+            writable = true;
+            calls += 1;
+            result = unlink_at(dirfd.as_fd(), &name, flags);
+        }
+        if is_dir {
+            report.rmdir += calls;
+        } else {
+            report.unlink += calls;
+        }
+        result?;
+    }
+    Ok(dirfd)
+}
+
+/// The maximum no. of directory descriptors that are kept open.
+///
+/// Deeper directories are reopened via `..`.
+const MAX_OPEN_DIRS: usize = 64;
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    #[test]
+    fn delete_dir_all_counts() {
+        let workdir = TempDir::new().unwrap();
+        let root = workdir.path().join("root");
+        create_tree(&root, &["a", "b/c"], &["b", "e"]);
+        symlink("c", root.join("b/d")).unwrap();
+        let report = delete_dir_all(&root, true).unwrap();
+        assert!(!root.exists());
+        // two files and a symlink, two directories and the root
+        assert_eq!(3, report.unlink);
+        assert_eq!(3, report.rmdir);
+        assert_eq!(3, report.open);
+        assert_eq!(3, report.readdir);
+        assert_eq!(0, report.chmod);
+    }
+
+    #[test]
+    fn delete_entries_counts() {
+        let workdir = TempDir::new().unwrap();
+        let root = workdir.path();
+        create_tree(root, &["keep", "old/keep"], &["old"]);
+        create_tree(root, &["a", "b/c", "old/e", "old/f/g"], &["b", "old/f"]);
+        symlink("c", root.join("b/d")).unwrap();
+        let entries: Vec<Entry> = [
+            ("a", FileType::Regular),
+            ("b", FileType::Directory),
+            ("b/c", FileType::Regular),
+            ("b/d", FileType::Symlink),
+            ("old/e", FileType::Regular),
+            ("old/f", FileType::Directory),
+            ("old/f/g", FileType::Regular),
+        ]
+        .into_iter()
+        .map(|(path, file_type)| Entry::from_file(root, path.into(), file_type, None).unwrap())
+        .collect();
+        let guard = SafetyGuard::new(root).unwrap();
+        let report = delete_entries(root, &entries, &guard).unwrap();
+        for path in ["a", "b", "old/e", "old/f"] {
+            assert!(root.join(path).symlink_metadata().is_err(), "{}", path);
+        }
+        for path in ["keep", "old", "old/keep"] {
+            assert!(root.join(path).exists(), "{}", path);
+        }
+        // the non-directory entries
+        assert_eq!(5, report.unlink);
+        assert_eq!(2, report.rmdir);
+        // the root, `b`, `old` twice and `old/f`
+        assert_eq!(5, report.open);
+        assert_eq!(2, report.readdir);
+        assert_eq!(0, report.chmod);
+    }
+
+    fn create_tree(root: &Path, files: &[&str], dirs: &[&str]) {
+        std::fs::create_dir_all(root).unwrap();
+        for dir in dirs.iter() {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in files.iter() {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, file).unwrap();
+        }
+    }
+}
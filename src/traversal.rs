@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+#[cfg(any(feature = "tar", feature = "zip", feature = "cpio"))]
+use std::io::Error;
+#[cfg(feature = "zip")]
+use std::io::Seek;
+#[cfg(any(feature = "tar", feature = "zip", feature = "cpio"))]
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use arbitrary::Arbitrary;
+use arbitrary::Unstructured;
+
+use crate::DirSpec;
+use crate::Entry;
+use crate::FileType;
+use crate::Manifest;
+#[cfg(feature = "zip")]
+use crate::ZipMethod;
+
+/// Archive entries that try to escape the extraction directory.
+///
+/// Use it to check that the extractor under test doesn't create or modify any files outside of
+/// the target directory. The entries are not meant to be created on disk, hence there is no way
+/// to convert them to [`DirSpec`] or [`Dir`](crate::Dir); write them as an archive instead.
+///
+/// The escaping paths end with random names, and the absolute paths point into
+/// [`std::env::temp_dir`], so that a vulnerable extractor doesn't overwrite any system files.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TraversalSpec {
+    spec: DirSpec,
+    attacks: Vec<TraversalAttack>,
+}
+
+impl TraversalSpec {
+    /// All entries including the benign ones in the order they are archived.
+    pub fn entries(&self) -> &[Entry] {
+        self.spec.entries()
+    }
+
+    /// The entries that try to escape the extraction directory.
+    pub fn attacks(&self) -> &[TraversalAttack] {
+        &self.attacks[..]
+    }
+
+    /// Get the contents of the regular file.
+    ///
+    /// Returns `None` if there is no regular file with such path.
+    pub fn contents<P: AsRef<Path>>(&self, path: P) -> Option<&[u8]> {
+        self.spec.contents(path)
+    }
+
+    /// Write the entries as POSIX tar archive (see [`DirSpec::write_tar`]).
+    #[cfg(feature = "tar")]
+    pub fn write_tar<W: Write>(&self, writer: W) -> Result<(), Error> {
+        self.spec.write_tar(writer)
+    }
+
+    /// Write the entries as zip archive (see [`DirSpec::write_zip`]).
+    ///
+    /// Zip archives can't store hard links, i.e. [`TraversalKind::EscapingHardLink`] entries are
+    /// skipped.
+    #[cfg(feature = "zip")]
+    pub fn write_zip<W: Write + Seek>(&self, writer: W, method: ZipMethod) -> Result<(), Error> {
+        self.spec.write_zip(writer, method)
+    }
+
+    /// Write the entries as cpio archive in "new ASCII" format (see [`DirSpec::write_cpio`]).
+    ///
+    /// Cpio archives link the files by inode numbers, i.e. [`TraversalKind::EscapingHardLink`]
+    /// entries are stored as empty files.
+    #[cfg(feature = "cpio")]
+    pub fn write_cpio<W: Write>(&self, writer: W) -> Result<(), Error> {
+        self.spec.write_cpio(writer)
+    }
+}
+
+impl<'a> Arbitrary<'a> for TraversalSpec {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        use FileType::*;
+        let mut entries = Vec::new();
+        let mut contents = HashMap::new();
+        let mut attacks = Vec::new();
+        // benign entries
+        let num_dirs: usize = u.int_in_range(0..=2)?;
+        let mut dirs = Vec::with_capacity(num_dirs);
+        for _ in 0..num_dirs {
+            let dir = PathBuf::from(arbitrary_name(u)?);
+            if dirs.contains(&dir) {
+                continue;
+            }
+            entries.push(new_entry(dir.clone(), Directory, 0o755));
+            dirs.push(dir);
+        }
+        let num_files: usize = u.int_in_range(0..=2)?;
+        for _ in 0..num_files {
+            let path = match u.choose(&dirs[..]) {
+                Ok(dir) if u.arbitrary()? => dir.join(arbitrary_name(u)?),
+                _ => arbitrary_name(u)?.into(),
+            };
+            push_file(u, &mut entries, &mut contents, path)?;
+        }
+        let num_attacks: usize = u.int_in_range(1..=4)?;
+        for _ in 0..num_attacks {
+            let kind: TraversalKind = u.arbitrary()?;
+            let path = match kind {
+                TraversalKind::ParentDir => {
+                    let path = escaping_path(u, &dirs)?;
+                    if !push_file(u, &mut entries, &mut contents, path.clone())? {
+                        continue;
+                    }
+                    path
+                }
+                TraversalKind::AbsolutePath => {
+                    let path = absolute_path(u)?;
+                    if !push_file(u, &mut entries, &mut contents, path.clone())? {
+                        continue;
+                    }
+                    path
+                }
+                TraversalKind::EscapingSymlink | TraversalKind::WriteThroughSymlink => {
+                    let link = PathBuf::from(arbitrary_name(u)?);
+                    if contains(&entries, &link) {
+                        continue;
+                    }
+                    let target = if u.arbitrary()? {
+                        escaping_dir(u)?
+                    } else {
+                        absolute_dir()
+                    };
+                    let mut entry = new_entry(link.clone(), Symlink, 0o777);
+                    entry.target = Some(target);
+                    entries.push(entry);
+                    if kind == TraversalKind::EscapingSymlink {
+                        link
+                    } else {
+                        // the symbolic link is followed by the file that is written through it
+                        let path = link.join(arbitrary_name(u)?);
+                        push_file(u, &mut entries, &mut contents, path.clone())?;
+                        path
+                    }
+                }
+                TraversalKind::EscapingHardLink => {
+                    let path = PathBuf::from(arbitrary_name(u)?);
+                    if contains(&entries, &path) {
+                        continue;
+                    }
+                    let target = if u.arbitrary()? {
+                        escaping_path(u, &[])?
+                    } else {
+                        absolute_path(u)?
+                    };
+                    let mut entry = new_entry(path.clone(), HardLink, 0o644);
+                    entry.target = Some(target);
+                    entries.push(entry);
+                    path
+                }
+            };
+            attacks.push(TraversalAttack { path, kind });
+        }
+        if attacks.is_empty() {
+            // all the paths were taken; the absolute paths are only added by the attacks
+            let path = absolute_path(u)?;
+            push_file(u, &mut entries, &mut contents, path.clone())?;
+            attacks.push(TraversalAttack {
+                path,
+                kind: TraversalKind::AbsolutePath,
+            });
+        }
+        let manifest = Manifest {
+            entries,
+            ..Default::default()
+        };
+        Ok(Self {
+            spec: DirSpec::new(manifest, contents),
+            attacks,
+        })
+    }
+}
+
+/// An entry that tries to escape the extraction directory.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TraversalAttack {
+    /// Entry path as stored in the archive.
+    pub path: PathBuf,
+    /// How the entry escapes the extraction directory.
+    pub kind: TraversalKind,
+}
+
+/// How the entry escapes the extraction directory.
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TraversalKind {
+    /// The path contains `..` components that lead outside, e.g. `../../a` or `a/../../b`.
+    ParentDir,
+    /// The path is absolute.
+    AbsolutePath,
+    /// The symbolic link points outside via `..` components or an absolute path.
+    EscapingSymlink,
+    /// The symbolic link that points outside is followed by the file with the link as the
+    /// parent directory, e.g. `a -> /tmp` and `a/b`.
+    ///
+    /// The path of the attack is the path of the file.
+    WriteThroughSymlink,
+    /// The hard link's target is outside, i.e. the extractor links to or overwrites the
+    /// outside file.
+    EscapingHardLink,
+}
+
+/// Add the regular file unless the path is already taken.
+///
+/// Returns `false` for the duplicate path: the archives would store the entries with the same
+/// contents but different sizes.
+fn push_file(
+    u: &mut Unstructured<'_>,
+    entries: &mut Vec<Entry>,
+    contents: &mut HashMap<PathBuf, Vec<u8>>,
+    path: PathBuf,
+) -> arbitrary::Result<bool> {
+    if contains(entries, &path) {
+        return Ok(false);
+    }
+    let data: Vec<u8> = u.arbitrary()?;
+    let mut entry = new_entry(path.clone(), FileType::Regular, 0o644);
+    entry.size = data.len() as u64;
+    entries.push(entry);
+    contents.insert(path, data);
+    Ok(true)
+}
+
+fn contains(entries: &[Entry], path: &Path) -> bool {
+    entries.iter().any(|entry| entry.path == path)
+}
+
+/// Path with enough `..` components to escape the extraction directory.
+fn escaping_path(u: &mut Unstructured<'_>, dirs: &[PathBuf]) -> arbitrary::Result<PathBuf> {
+    let mut path = PathBuf::new();
+    let mut depth = 0;
+    if let Ok(dir) = u.choose(dirs) {
+        if u.arbitrary()? {
+            // descend first, e.g. `a/../../b`
+            path.push(dir);
+            depth += 1;
+        }
+    }
+    let num_parents: usize = u.int_in_range(depth + 1..=depth + MAX_PARENTS)?;
+    for _ in 0..num_parents {
+        path.push("..");
+    }
+    path.push(arbitrary_name(u)?);
+    Ok(path)
+}
+
+fn escaping_dir(u: &mut Unstructured<'_>) -> arbitrary::Result<PathBuf> {
+    let num_parents: usize = u.int_in_range(1..=MAX_PARENTS)?;
+    Ok((0..num_parents).map(|_| "..").collect())
+}
+
+fn absolute_path(u: &mut Unstructured<'_>) -> arbitrary::Result<PathBuf> {
+    Ok(absolute_dir().join(arbitrary_name(u)?))
+}
+
+fn absolute_dir() -> PathBuf {
+    std::env::temp_dir()
+}
+
+fn arbitrary_name(u: &mut Unstructured<'_>) -> arbitrary::Result<String> {
+    let len: usize = u.int_in_range(1..=8)?;
+    let mut name = String::with_capacity(len);
+    for _ in 0..len {
+        name.push(u.int_in_range(b'a'..=b'z')? as char);
+    }
+    Ok(name)
+}
+
+fn new_entry(path: PathBuf, file_type: FileType, mode: u32) -> Entry {
+    Entry {
+        path,
+        file_type,
+        mode,
+        mtime: None,
+        target: None,
+        size: 0,
+        rdev: 0,
+        owner: None,
+        xattrs: Vec::new(),
+        inode_flags: 0,
+    }
+}
+
+const MAX_PARENTS: usize = 5;
+
+#[cfg(test)]
+mod tests {
+    use std::path::Component;
+
+    use arbtest::arbtest;
+
+    use super::*;
+
+    #[test]
+    fn attacks_escape() {
+        let root = Path::new("/fake/root");
+        arbtest(|u| {
+            let spec: TraversalSpec = u.arbitrary()?;
+            assert!(!spec.attacks().is_empty());
+            for attack in spec.attacks() {
+                let entry = spec
+                    .entries()
+                    .iter()
+                    .find(|entry| entry.path == attack.path)
+                    .unwrap();
+                let resolved = match attack.kind {
+                    // the hard link itself is inside, its target is not
+                    TraversalKind::EscapingHardLink => {
+                        assert_eq!(FileType::HardLink, entry.file_type);
+                        resolve(root, root, spec.entries(), entry.target.as_ref().unwrap())
+                    }
+                    _ => resolve(root, root, spec.entries(), &attack.path),
+                };
+                assert!(
+                    !resolved.starts_with(root),
+                    "{:?} -> {:?}",
+                    attack,
+                    resolved
+                );
+            }
+            for entry in spec.entries() {
+                if spec
+                    .attacks()
+                    .iter()
+                    .any(|attack| attack.path == entry.path)
+                {
+                    continue;
+                }
+                let parent = resolve(root, root, spec.entries(), entry.path.parent().unwrap());
+                assert!(parent.starts_with(root), "{:?} -> {:?}", entry, parent);
+            }
+            Ok(())
+        });
+    }
+
+    /// Resolve the path like a naive extractor would do, i.e. follow the archived symbolic links.
+    fn resolve(root: &Path, dir: &Path, entries: &[Entry], path: &Path) -> PathBuf {
+        let mut resolved = dir.to_path_buf();
+        for component in path.components() {
+            match component {
+                Component::RootDir => resolved = PathBuf::from("/"),
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::Normal(name) => {
+                    resolved.push(name);
+                    let symlink = resolved.strip_prefix(root).ok().and_then(|relative| {
+                        entries.iter().find(|entry| {
+                            entry.file_type == FileType::Symlink && entry.path == relative
+                        })
+                    });
+                    if let Some(symlink) = symlink {
+                        resolved.pop();
+                        resolved =
+                            resolve(root, &resolved, entries, symlink.target.as_ref().unwrap());
+                    }
+                }
+                Component::CurDir | Component::Prefix(..) => {}
+            }
+        }
+        resolved
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::read_dir;
+use std::io::Error;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use arbitrary::Unstructured;
+
+use crate::choose_weighted;
+use crate::NameGenerator;
+use crate::SizeRecipe;
+use crate::TreeShape;
+
+/// Statistical profile of a tree (see [`DirBuilder::profile`](crate::DirBuilder::profile)).
+///
+/// Sample the profile from a real directory via [`sample`](Self::sample) to generate the trees
+/// that resemble real-world data, e.g. a Linux source tree.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TreeProfile {
+    /// No. of files excluding the directories.
+    pub num_files: usize,
+    /// No. of regular files in each power-of-two size bucket (see [`SizeRecipe::Histogram`]).
+    pub size_histogram: Vec<u64>,
+    /// No. of files with each extension; empty extension means no extension.
+    ///
+    /// Sorted by the no. of files in descending order.
+    pub extensions: Vec<(OsString, u64)>,
+    /// No. of files at each depth; the first element is the no. of the root's children (see
+    /// [`TreeShape::depth_weights`]).
+    pub depth_histogram: Vec<u64>,
+}
+
+impl TreeProfile {
+    /// Collect the profile of the directory `dir` recursively.
+    ///
+    /// The symbolic links are not followed.
+    pub fn sample<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let mut profile = Self::default();
+        let mut extensions = HashMap::new();
+        let mut dirs = vec![(dir.as_ref().to_path_buf(), 0)];
+        while let Some((dir, depth)) = dirs.pop() {
+            for entry in read_dir(&dir)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if metadata.is_dir() {
+                    dirs.push((entry.path(), depth + 1));
+                    continue;
+                }
+                profile.num_files += 1;
+                if metadata.is_file() {
+                    let bucket = (u64::BITS - metadata.len().leading_zeros()) as usize;
+                    increment(&mut profile.size_histogram, bucket);
+                }
+                increment(&mut profile.depth_histogram, depth);
+                let extension = Path::new(&entry.file_name())
+                    .extension()
+                    .map(|extension| extension.to_os_string())
+                    .unwrap_or_default();
+                *extensions.entry(extension).or_insert(0) += 1;
+            }
+        }
+        profile.extensions = extensions.into_iter().collect();
+        profile
+            .extensions
+            .sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(profile)
+    }
+
+    /// Tree shape with the profile's no. of files and depth distribution.
+    pub fn shape(&self) -> TreeShape {
+        TreeShape {
+            min_files: self.num_files,
+            max_files: self.num_files,
+            depth_weights: self
+                .depth_histogram
+                .iter()
+                .map(|n| (*n).min(u32::MAX as u64) as u32)
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Size recipe with the profile's size distribution.
+    pub fn size_recipe(&self) -> SizeRecipe {
+        SizeRecipe::Histogram(self.size_histogram.clone())
+    }
+}
+
+fn increment(histogram: &mut Vec<u64>, i: usize) {
+    if histogram.len() <= i {
+        histogram.resize(i + 1, 0);
+    }
+    histogram[i] += 1;
+}
+
+/// Appends the extensions sampled from [`TreeProfile::extensions`] to the names generated by
+/// the inner generator.
+pub(crate) struct ExtensionNames {
+    inner: Box<dyn NameGenerator>,
+    extensions: Vec<OsString>,
+    weights: Vec<u64>,
+}
+
+impl ExtensionNames {
+    pub(crate) fn new(inner: Box<dyn NameGenerator>, profile: &TreeProfile) -> Self {
+        let (extensions, weights) = profile.extensions.iter().cloned().unzip();
+        Self {
+            inner,
+            extensions,
+            weights,
+        }
+    }
+
+    /// Choose the extensions with equal probability.
+    pub(crate) fn uniform(inner: Box<dyn NameGenerator>, extensions: Vec<OsString>) -> Self {
+        let weights = vec![1; extensions.len()];
+        Self {
+            inner,
+            extensions,
+            weights,
+        }
+    }
+}
+
+impl NameGenerator for ExtensionNames {
+    fn generate(&mut self, u: &mut Unstructured<'_>) -> arbitrary::Result<OsString> {
+        let mut name = self.inner.generate(u)?;
+        if name.is_empty() || self.extensions.is_empty() {
+            return Ok(name);
+        }
+        let extension = &self.extensions[choose_weighted(u, &self.weights)?];
+        if !extension.as_bytes().is_empty() {
+            name.push(".");
+            name.push(extension);
+        }
+        Ok(name)
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use arbitrary::Unstructured;
+
+use crate::path_to_c_string;
+use crate::Entry;
+use crate::FileType;
+use crate::PATH_MAX;
+
+/// The mapping of a contiguous range of ids into the user namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IdRange {
+    /// The first id inside the namespace.
+    inside: u32,
+    /// The first id outside the namespace.
+    outside: u32,
+    count: u32,
+}
+
+/// Change the owners of the entries to arbitrary ids mapped into a new user namespace.
+///
+/// The ownership is changed by a child process that is uid 0 in the namespace.
+/// Directories are left intact so that the caller can delete the tree.
+pub(crate) fn chown_in_user_namespace(
+    u: &mut Unstructured<'_>,
+    root: &Path,
+    entries: &mut [Entry],
+) -> arbitrary::Result<()> {
+    let (uid_ranges, gid_ranges) = own_id_ranges();
+    let mut owners = HashMap::new();
+    let mut paths = Vec::new();
+    for entry in entries.iter_mut() {
+        if matches!(entry.file_type, FileType::Directory | FileType::HardLink) {
+            continue;
+        }
+        let path = root.join(&entry.path);
+        if path.as_os_str().len() >= PATH_MAX {
+            continue;
+        }
+        let (uid_inside, uid_outside) = arbitrary_id(u, &uid_ranges)?;
+        let (gid_inside, gid_outside) = arbitrary_id(u, &gid_ranges)?;
+        let c_path = path_to_c_string(path).unwrap();
+        paths.push((c_path, uid_inside, gid_inside));
+        entry.owner = Some((uid_outside, gid_outside));
+        owners.insert(entry.path.clone(), (uid_outside, gid_outside));
+    }
+    // hard links share the owner with the original file
+    for entry in entries.iter_mut() {
+        if entry.file_type == FileType::HardLink {
+            entry.owner = entry
+                .target
+                .as_ref()
+                .and_then(|target| owners.get(target))
+                .copied();
+        }
+    }
+    if run_chown(&paths, &uid_ranges, &gid_ranges).is_err() {
+        // user namespaces are disabled or restricted, record only the owners that were changed
+        forget_unchanged_owners(root, entries);
+    }
+    Ok(())
+}
+
+/// Change the owners of the entries to the recorded ids (see [`Entry::owner`]) from inside a new
+/// user namespace.
+///
+/// The owners that can't be changed are reset to `None`.
+pub(crate) fn restore_owners_in_user_namespace(root: &Path, entries: &mut [Entry]) {
+    let (uid_ranges, gid_ranges) = own_id_ranges();
+    let mut paths = Vec::new();
+    for entry in entries.iter() {
+        if entry.file_type == FileType::HardLink {
+            continue;
+        }
+        let Some((uid, gid)) = entry.owner else {
+            continue;
+        };
+        let (Some(uid), Some(gid)) = (inside_id(uid, &uid_ranges), inside_id(gid, &gid_ranges))
+        else {
+            continue;
+        };
+        let Ok(c_path) = path_to_c_string(root.join(&entry.path)) else {
+            continue;
+        };
+        paths.push((c_path, uid, gid));
+    }
+    if !paths.is_empty() {
+        let _ = run_chown(&paths, &uid_ranges, &gid_ranges);
+    }
+    forget_unchanged_owners(root, entries);
+}
+
+/// Reset [`Entry::owner`] to `None` for the entries that are not owned by the recorded ids.
+fn forget_unchanged_owners(root: &Path, entries: &mut [Entry]) {
+    for entry in entries.iter_mut() {
+        let Some(owner) = entry.owner else {
+            continue;
+        };
+        let actual = root
+            .join(&entry.path)
+            .symlink_metadata()
+            .ok()
+            .map(|metadata| (metadata.uid(), metadata.gid()));
+        if actual != Some(owner) {
+            entry.owner = None;
+        }
+    }
+}
+
+fn run_chown(
+    paths: &[(CString, u32, u32)],
+    uid_ranges: &[IdRange],
+    gid_ranges: &[IdRange],
+) -> Result<(), Error> {
+    let (ready_read, ready_write) = pipe()?;
+    let (mapped_read, mapped_write) = pipe()?;
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(Error::last_os_error());
+    }
+    if pid == 0 {
+        // only async-signal-safe functions from here on
+        drop(ready_read);
+        drop(mapped_write);
+        let status = unsafe { chown_child(paths, ready_write, mapped_read) };
+        unsafe { libc::_exit(status) };
+    }
+    drop(ready_write);
+    drop(mapped_read);
+    let result = read_byte(&ready_read)
+        .and_then(|_| write_id_maps(pid, uid_ranges, gid_ranges))
+        .and_then(|_| write_byte(&mapped_write));
+    drop(mapped_write);
+    let mut status = 0;
+    if unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    result?;
+    if !libc::WIFEXITED(status) || libc::WEXITSTATUS(status) != 0 {
+        return Err(Error::other(format!(
+            "failed to change the owners in the user namespace: exit status {}",
+            status
+        )));
+    }
+    Ok(())
+}
+
+unsafe fn chown_child(
+    paths: &[(CString, u32, u32)],
+    ready_write: OwnedFd,
+    mapped_read: OwnedFd,
+) -> libc::c_int {
+    if libc::unshare(libc::CLONE_NEWUSER) < 0 {
+        return 1;
+    }
+    if write_byte(&ready_write).is_err() || read_byte(&mapped_read).is_err() {
+        return 2;
+    }
+    for (path, uid, gid) in paths.iter() {
+        if libc::lchown(path.as_ptr(), *uid, *gid) < 0 {
+            return 3;
+        }
+    }
+    0
+}
+
+fn write_id_maps(
+    pid: libc::pid_t,
+    uid_ranges: &[IdRange],
+    gid_ranges: &[IdRange],
+) -> Result<(), Error> {
+    if let ([uid_range], [_]) = (uid_ranges, gid_ranges) {
+        // a single range can be mapped without the helpers
+        if uid_range.count == 1 {
+            std::fs::write(format!("/proc/{}/setgroups", pid), "deny")?;
+        }
+        std::fs::write(format!("/proc/{}/uid_map", pid), id_map(uid_ranges))?;
+        std::fs::write(format!("/proc/{}/gid_map", pid), id_map(gid_ranges))?;
+        return Ok(());
+    }
+    run_id_map_helper("newuidmap", pid, uid_ranges)?;
+    run_id_map_helper("newgidmap", pid, gid_ranges)?;
+    Ok(())
+}
+
+fn run_id_map_helper(helper: &str, pid: libc::pid_t, ranges: &[IdRange]) -> Result<(), Error> {
+    let mut command = Command::new(helper);
+    command.arg(pid.to_string());
+    for range in ranges.iter() {
+        command.arg(range.inside.to_string());
+        command.arg(range.outside.to_string());
+        command.arg(range.count.to_string());
+    }
+    let status = command.status()?;
+    if !status.success() {
+        return Err(Error::other(format!("`{}` failed: {}", helper, status)));
+    }
+    Ok(())
+}
+
+fn id_map(ranges: &[IdRange]) -> String {
+    ranges
+        .iter()
+        .map(|range| format!("{} {} {}\n", range.inside, range.outside, range.count))
+        .collect()
+}
+
+/// Map own id to 0 and the subordinate ids starting from 1.
+fn id_ranges(id: u32, subordinate: Option<(u32, u32)>) -> Vec<IdRange> {
+    let mut ranges = vec![IdRange {
+        inside: 0,
+        outside: id,
+        count: 1,
+    }];
+    // subordinate ids can only be mapped via the helpers
+    if let Some((start, count)) = subordinate.filter(|_| has_id_map_helpers()) {
+        ranges.push(IdRange {
+            inside: 1,
+            outside: start,
+            count,
+        });
+    }
+    ranges
+}
+
+/// The ranges of ids that the current user can map into the namespace.
+fn own_id_ranges() -> (Vec<IdRange>, Vec<IdRange>) {
+    let uid = unsafe { libc::geteuid() };
+    let gid = unsafe { libc::getegid() };
+    if uid == 0 {
+        (vec![full_range()], vec![full_range()])
+    } else {
+        (
+            id_ranges(uid, subordinate_ids("/etc/subuid", uid)),
+            id_ranges(gid, subordinate_ids("/etc/subgid", uid)),
+        )
+    }
+}
+
+/// The id inside the namespace that is mapped to the id `outside` of it.
+fn inside_id(outside: u32, ranges: &[IdRange]) -> Option<u32> {
+    ranges.iter().find_map(|range| {
+        let offset = outside.checked_sub(range.outside)?;
+        (offset < range.count).then_some(range.inside + offset)
+    })
+}
+
+fn full_range() -> IdRange {
+    // `u32::MAX` means "do not change" in `chown`
+    IdRange {
+        inside: 0,
+        outside: 0,
+        count: u32::MAX,
+    }
+}
+
+fn arbitrary_id(u: &mut Unstructured<'_>, ranges: &[IdRange]) -> arbitrary::Result<(u32, u32)> {
+    let range = u.choose(ranges)?;
+    let offset = u.int_in_range(0..=range.count - 1)?;
+    Ok((range.inside + offset, range.outside + offset))
+}
+
+/// Parse the first range of subordinate ids of the user from `/etc/subuid`-like file.
+fn subordinate_ids<P: AsRef<Path>>(path: P, uid: u32) -> Option<(u32, u32)> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let name = user_name(uid);
+    contents.lines().find_map(|line| {
+        let mut fields = line.trim().split(':');
+        let user = fields.next()?;
+        let start = fields.next()?.parse().ok()?;
+        let count = fields.next()?.parse().ok()?;
+        let matches = user == uid.to_string() || name.as_deref() == Some(user);
+        (matches && count != 0).then_some((start, count))
+    })
+}
+
+fn user_name(uid: u32) -> Option<String> {
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0_u8; 4096];
+    let mut result = std::ptr::null_mut();
+    let ret = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut passwd,
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if ret != 0 || result.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(passwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+fn has_id_map_helpers() -> bool {
+    let Some(paths) = std::env::var_os("PATH") else {
+        return false;
+    };
+    ["newuidmap", "newgidmap"].iter().all(|helper| {
+        std::env::split_paths(&paths)
+            .map(|dir: PathBuf| dir.join(helper))
+            .any(|path| path.exists())
+    })
+}
+
+fn pipe() -> Result<(OwnedFd, OwnedFd), Error> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+fn read_byte(fd: &OwnedFd) -> Result<(), Error> {
+    let mut byte = 0_u8;
+    let ret = unsafe { libc::read(fd.as_raw_fd(), (&mut byte as *mut u8).cast(), 1) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    if ret == 0 {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+fn write_byte(fd: &OwnedFd) -> Result<(), Error> {
+    let byte = 0_u8;
+    let ret = unsafe { libc::write(fd.as_raw_fd(), (&byte as *const u8).cast(), 1) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,514 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io::Error;
+use std::ops::Bound;
+use std::os::unix::ffi::OsStrExt;
+#[cfg(feature = "fs")]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(feature = "fs")]
+use std::os::unix::fs::MetadataExt;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+#[cfg(feature = "fs")]
+use arbitrary::Arbitrary;
+#[cfg(feature = "fs")]
+use arbitrary::Unstructured;
+#[cfg(feature = "fs")]
+use walkdir::WalkDir;
+
+use crate::unix_time;
+use crate::DirDiff;
+use crate::DirSpec;
+use crate::FileInfo;
+use crate::FileType;
+use crate::Metadata;
+
+/// In-memory file system tree.
+///
+/// The "directory" is a map of paths to nodes; hard links share the node. Use it to test the
+/// code that works with an abstract file system instead of the real paths: the tree is listed
+/// in the same format as [`list_dir_all`](crate::list_dir_all) does and can be compared using
+/// [`DirDiff`].
+///
+/// The paths are relative to the root, symbolic links are never followed. The errors mimic the
+/// ones returned by the real file system (`ENOENT`, `EEXIST`, `ENOTDIR` etc.). The modification
+/// times change only via [`set_modified`](Self::set_modified), the new nodes get Unix epoch.
+/// Directory sizes are zero.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Vfs {
+    paths: BTreeMap<PathBuf, u64>,
+    nodes: HashMap<u64, Node>,
+    next_ino: u64,
+}
+
+impl Vfs {
+    /// Create an empty tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the existing directory into memory.
+    ///
+    /// Hard links are preserved, symbolic links are not followed.
+    #[cfg(feature = "fs")]
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        use std::collections::hash_map::Entry::*;
+        let dir = dir.as_ref();
+        let mut vfs = Vfs::new();
+        let mut originals: HashMap<(u64, u64), PathBuf> = HashMap::new();
+        for entry in WalkDir::new(dir).sort_by_file_name().into_iter() {
+            let entry = entry?;
+            if entry.path() == dir {
+                continue;
+            }
+            let path = entry.path().strip_prefix(dir).map_err(Error::other)?;
+            let metadata = entry.path().symlink_metadata()?;
+            let file_type = metadata.file_type();
+            if !file_type.is_dir() && metadata.nlink() > 1 {
+                match originals.entry((metadata.dev(), metadata.ino())) {
+                    Occupied(o) => {
+                        vfs.hard_link(o.get(), path)?;
+                        continue;
+                    }
+                    Vacant(v) => {
+                        v.insert(path.to_path_buf());
+                    }
+                }
+            }
+            let mode = metadata.mode() & 0o7777;
+            if file_type.is_file() {
+                vfs.write(path, std::fs::read(entry.path())?)?;
+            } else if file_type.is_dir() {
+                vfs.create_dir(path, mode)?;
+            } else if file_type.is_symlink() {
+                vfs.symlink(std::fs::read_link(entry.path())?, path)?;
+            } else {
+                let file_type = if file_type.is_fifo() {
+                    FileType::Fifo
+                } else if file_type.is_socket() {
+                    FileType::Socket
+                } else if file_type.is_block_device() {
+                    FileType::BlockDevice
+                } else {
+                    FileType::CharDevice
+                };
+                vfs.create_node(path, file_type, mode, metadata.rdev())?;
+            }
+            let node = vfs.node_mut(path)?;
+            if !file_type.is_symlink() {
+                node.mode = mode;
+            }
+            node.uid = metadata.uid();
+            node.gid = metadata.gid();
+            node.mtime = metadata.modified()?;
+        }
+        Ok(vfs)
+    }
+
+    /// Recursively list the tree.
+    ///
+    /// The entries are sorted by path and the inodes are numbered in the order of appearance.
+    pub fn list(&self) -> Vec<FileInfo> {
+        let mut inodes = HashMap::new();
+        let mut link_counts: HashMap<u64, u32> = HashMap::new();
+        for ino in self.paths.values() {
+            *link_counts.entry(*ino).or_default() += 1;
+        }
+        self.paths
+            .iter()
+            .map(|(path, ino)| {
+                let next_ino = inodes.len() as u64;
+                let inode = *inodes.entry(*ino).or_insert(next_ino);
+                let node = &self.nodes[ino];
+                let nlink = match node.file_type {
+                    FileType::Directory => 2 + self.num_subdirs(path),
+                    _ => link_counts[ino],
+                };
+                FileInfo {
+                    path: path.clone(),
+                    metadata: node.metadata(inode, nlink),
+                    contents: match node.file_type {
+                        FileType::Regular | FileType::Symlink => node.data.clone(),
+                        _ => Vec::new(),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Compare the listings of the two trees.
+    pub fn diff(&self, other: &Vfs) -> DirDiff {
+        DirDiff::new(&self.list(), &other.list())
+    }
+
+    /// Get the metadata without following symlinks.
+    ///
+    /// The inode is the internal node number.
+    pub fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata, Error> {
+        let path = relative_path(path.as_ref())?;
+        let node = self.node(&path)?;
+        let nlink = match node.file_type {
+            FileType::Directory => 2 + self.num_subdirs(&path),
+            _ => {
+                let ino = self.paths[&path];
+                self.paths.values().filter(|other| **other == ino).count() as u32
+            }
+        };
+        Ok(node.metadata(self.paths[&path], nlink))
+    }
+
+    /// Get the contents of the regular file.
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<&[u8], Error> {
+        let node = self.node(&relative_path(path.as_ref())?)?;
+        match node.file_type {
+            FileType::Regular => Ok(&node.data[..]),
+            FileType::Directory => Err(Error::from_raw_os_error(libc::EISDIR)),
+            _ => Err(Error::from_raw_os_error(libc::EINVAL)),
+        }
+    }
+
+    /// Get the target of the symbolic link.
+    pub fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf, Error> {
+        let node = self.node(&relative_path(path.as_ref())?)?;
+        match node.file_type {
+            FileType::Symlink => Ok(std::ffi::OsStr::from_bytes(&node.data).into()),
+            _ => Err(Error::from_raw_os_error(libc::EINVAL)),
+        }
+    }
+
+    /// List the names of the directory's children in sorted order.
+    ///
+    /// Use empty path to list the root.
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>, Error> {
+        let path = relative_path(path.as_ref())?;
+        self.check_dir(&path)?;
+        Ok(self
+            .children(&path)
+            .filter_map(|child| child.file_name().map(PathBuf::from))
+            .collect())
+    }
+
+    /// Write the regular file creating it if needed.
+    ///
+    /// The existing file is truncated, its metadata is left intact.
+    pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(
+        &mut self,
+        path: P,
+        contents: C,
+    ) -> Result<(), Error> {
+        let path = relative_path(path.as_ref())?;
+        let contents = contents.as_ref().to_vec();
+        match self.paths.get(&path) {
+            Some(ino) => {
+                let node = self.nodes.get_mut(ino).unwrap();
+                match node.file_type {
+                    FileType::Regular => {
+                        node.data = contents;
+                        Ok(())
+                    }
+                    FileType::Directory => Err(Error::from_raw_os_error(libc::EISDIR)),
+                    _ => Err(Error::from_raw_os_error(libc::EINVAL)),
+                }
+            }
+            None => self.insert(path, Node::new(FileType::Regular, 0o644, contents)),
+        }
+    }
+
+    /// Create a directory.
+    ///
+    /// The parent directory must exist.
+    pub fn create_dir<P: AsRef<Path>>(&mut self, path: P, mode: u32) -> Result<(), Error> {
+        let path = relative_path(path.as_ref())?;
+        self.insert(path, Node::new(FileType::Directory, mode, Vec::new()))
+    }
+
+    /// Create a symbolic link at `path` pointing to `target`.
+    pub fn symlink<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &mut self,
+        target: P1,
+        path: P2,
+    ) -> Result<(), Error> {
+        let path = relative_path(path.as_ref())?;
+        let target = target.as_ref().as_os_str().as_bytes().to_vec();
+        self.insert(path, Node::new(FileType::Symlink, 0o777, target))
+    }
+
+    /// Create a hard link at `link` to the `original` file.
+    pub fn hard_link<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &mut self,
+        original: P1,
+        link: P2,
+    ) -> Result<(), Error> {
+        let original = relative_path(original.as_ref())?;
+        let link = relative_path(link.as_ref())?;
+        if self.node(&original)?.file_type == FileType::Directory {
+            return Err(Error::from_raw_os_error(libc::EPERM));
+        }
+        self.check_new(&link)?;
+        let ino = self.paths[&original];
+        self.paths.insert(link, ino);
+        Ok(())
+    }
+
+    /// Create a special file or a node of any other type.
+    ///
+    /// The device number is used only for block and character devices.
+    pub fn create_node<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        file_type: FileType,
+        mode: u32,
+        rdev: u64,
+    ) -> Result<(), Error> {
+        let path = relative_path(path.as_ref())?;
+        let mut node = Node::new(file_type, mode, Vec::new());
+        node.rdev = match file_type {
+            FileType::BlockDevice | FileType::CharDevice => rdev,
+            FileType::HardLink => return Err(Error::from_raw_os_error(libc::EINVAL)),
+            _ => 0,
+        };
+        self.insert(path, node)
+    }
+
+    /// Remove the file or the empty directory.
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let path = relative_path(path.as_ref())?;
+        if self.node(&path)?.file_type == FileType::Directory
+            && self.children(&path).next().is_some()
+        {
+            return Err(Error::from_raw_os_error(libc::ENOTEMPTY));
+        }
+        let ino = self.paths.remove(&path).unwrap();
+        if !self.paths.values().any(|other| *other == ino) {
+            self.nodes.remove(&ino);
+        }
+        Ok(())
+    }
+
+    /// Change the permission bits.
+    pub fn set_permissions<P: AsRef<Path>>(&mut self, path: P, mode: u32) -> Result<(), Error> {
+        self.node_mut(path.as_ref())?.mode = mode & 0o7777;
+        Ok(())
+    }
+
+    /// Change the owner.
+    pub fn set_owner<P: AsRef<Path>>(&mut self, path: P, uid: u32, gid: u32) -> Result<(), Error> {
+        let node = self.node_mut(path.as_ref())?;
+        node.uid = uid;
+        node.gid = gid;
+        Ok(())
+    }
+
+    /// Change the modification time.
+    pub fn set_modified<P: AsRef<Path>>(&mut self, path: P, t: SystemTime) -> Result<(), Error> {
+        self.node_mut(path.as_ref())?.mtime = t;
+        Ok(())
+    }
+
+    /// Change the file type of the existing node keeping its metadata and hard links.
+    #[cfg(all(target_os = "linux", feature = "fuse", feature = "fs"))]
+    pub(crate) fn set_file_type(
+        &mut self,
+        path: &Path,
+        file_type: FileType,
+        rdev: u64,
+    ) -> Result<(), Error> {
+        let node = self.node_mut(path)?;
+        node.file_type = file_type;
+        node.rdev = match file_type {
+            FileType::BlockDevice | FileType::CharDevice => rdev,
+            _ => 0,
+        };
+        Ok(())
+    }
+
+    fn node(&self, path: &Path) -> Result<&Node, Error> {
+        self.check_parent(path)?;
+        match self.paths.get(path) {
+            Some(ino) => Ok(&self.nodes[ino]),
+            None => Err(Error::from_raw_os_error(libc::ENOENT)),
+        }
+    }
+
+    fn node_mut(&mut self, path: &Path) -> Result<&mut Node, Error> {
+        let path = relative_path(path)?;
+        self.node(&path)?;
+        let ino = self.paths[&path];
+        Ok(self.nodes.get_mut(&ino).unwrap())
+    }
+
+    fn insert(&mut self, path: PathBuf, node: Node) -> Result<(), Error> {
+        self.check_new(&path)?;
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        self.paths.insert(path, ino);
+        self.nodes.insert(ino, node);
+        Ok(())
+    }
+
+    /// Check that the path can be created.
+    fn check_new(&self, path: &Path) -> Result<(), Error> {
+        if path.as_os_str().is_empty() || self.paths.contains_key(path) {
+            return Err(Error::from_raw_os_error(libc::EEXIST));
+        }
+        self.check_parent(path)
+    }
+
+    /// Check that all the ancestors are directories.
+    fn check_parent(&self, path: &Path) -> Result<(), Error> {
+        match path.parent() {
+            Some(parent) => self.check_dir(parent),
+            None => Ok(()),
+        }
+    }
+
+    fn check_dir(&self, path: &Path) -> Result<(), Error> {
+        if path.as_os_str().is_empty() {
+            // the root
+            return Ok(());
+        }
+        self.check_parent(path)?;
+        match self.paths.get(path) {
+            Some(ino) if self.nodes[ino].file_type == FileType::Directory => Ok(()),
+            Some(_) => Err(Error::from_raw_os_error(libc::ENOTDIR)),
+            None => Err(Error::from_raw_os_error(libc::ENOENT)),
+        }
+    }
+
+    fn children<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = &'a Path> + 'a {
+        // the descendants directly follow the directory in component-wise order
+        self.paths
+            .range::<Path, _>((Bound::Excluded(path), Bound::Unbounded))
+            .map(|(child, _)| child.as_path())
+            .take_while(move |child| child.starts_with(path))
+            .filter(move |child| child.parent() == Some(path))
+    }
+
+    fn num_subdirs(&self, path: &Path) -> u32 {
+        self.children(path)
+            .filter(|child| self.nodes[&self.paths[*child]].file_type == FileType::Directory)
+            .count() as u32
+    }
+}
+
+impl From<&DirSpec> for Vfs {
+    fn from(spec: &DirSpec) -> Self {
+        let mut vfs = Vfs::new();
+        for entry in spec.entries() {
+            let path = entry.path.as_path();
+            let result = match entry.file_type {
+                FileType::Regular => vfs.write(path, spec.contents(path).unwrap_or_default()),
+                FileType::Directory => vfs.create_dir(path, entry.mode),
+                FileType::Symlink => {
+                    vfs.symlink(entry.target.as_deref().unwrap_or(Path::new("")), path)
+                }
+                FileType::HardLink => {
+                    vfs.hard_link(entry.target.as_deref().unwrap_or(Path::new("")), path)
+                }
+                file_type => vfs.create_node(path, file_type, entry.mode, entry.rdev),
+            };
+            if result.is_err() || entry.file_type == FileType::HardLink {
+                continue;
+            }
+            let node = vfs.node_mut(path).unwrap();
+            if entry.file_type != FileType::Symlink {
+                node.mode = entry.mode & 0o7777;
+            }
+            if let Some(t) = entry.mtime {
+                node.mtime = t;
+            }
+            if let Some((uid, gid)) = entry.owner {
+                node.uid = uid;
+                node.gid = gid;
+            }
+        }
+        vfs
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<'a> Arbitrary<'a> for Vfs {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let spec: DirSpec = u.arbitrary()?;
+        Ok((&spec).into())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Node {
+    file_type: FileType,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    rdev: u64,
+    mtime: SystemTime,
+    /// File contents or symlink target.
+    data: Vec<u8>,
+}
+
+impl Node {
+    fn new(file_type: FileType, mode: u32, data: Vec<u8>) -> Self {
+        Self {
+            file_type,
+            mode: mode & 0o7777,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            mtime: SystemTime::UNIX_EPOCH,
+            data,
+        }
+    }
+
+    // `mode_t` is 16-bit on MacOS
+    #[allow(clippy::unnecessary_cast)]
+    fn metadata(&self, ino: u64, nlink: u32) -> Metadata {
+        let format = match self.file_type {
+            FileType::Regular | FileType::HardLink => libc::S_IFREG,
+            FileType::Directory => libc::S_IFDIR,
+            FileType::Fifo => libc::S_IFIFO,
+            FileType::Socket => libc::S_IFSOCK,
+            FileType::BlockDevice => libc::S_IFBLK,
+            FileType::CharDevice | FileType::Whiteout => libc::S_IFCHR,
+            FileType::Symlink => libc::S_IFLNK,
+        };
+        let (mtime, _) = unix_time(self.mtime);
+        let file_size = match self.file_type {
+            FileType::Regular | FileType::Symlink => self.data.len() as u64,
+            _ => 0,
+        };
+        Metadata {
+            dev: 0,
+            ino,
+            mode: format as u32 | self.mode,
+            uid: self.uid,
+            gid: self.gid,
+            nlink,
+            rdev: self.rdev,
+            mtime,
+            file_size,
+            acl: Default::default(),
+            selinux_context: Default::default(),
+            inode_flags: 0,
+            btime: None,
+            ctime: None,
+            shared_extents: None,
+            blocks: None,
+        }
+    }
+}
+
+/// Convert the path to the key: strip the root and the current directory components.
+fn relative_path(path: &Path) -> Result<PathBuf, Error> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::RootDir | Component::CurDir => {}
+            _ => return Err(Error::from_raw_os_error(libc::EINVAL)),
+        }
+    }
+    Ok(relative)
+}
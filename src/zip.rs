@@ -0,0 +1,256 @@
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+
+use flate2::write::DeflateEncoder;
+use flate2::Crc;
+
+use crate::civil_from_days;
+use crate::unix_time;
+use crate::DirSpec;
+use crate::FileType;
+
+/// Zip compression method.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ZipMethod {
+    /// Store as is.
+    Stored,
+    /// Deflate with the default compression level.
+    #[default]
+    Deflated,
+}
+
+impl DirSpec {
+    /// Write the tree as zip archive.
+    ///
+    /// The names are encoded using the intended name encoding (see [`Manifest::encoded_name`]).
+    /// Directories and empty files are always stored. Symbolic links are stored as Info-ZIP
+    /// does, i.e. as files with the target as the contents and Unix file type in the external
+    /// attributes. Hard links are stored as copies of the original files. Named pipes, sockets
+    /// and devices can't be archived and are skipped. Fails if the archive doesn't fit into
+    /// 4 GiB (zip64 is not supported).
+    ///
+    /// The modification times are clamped to 1980–2107 in MS-DOS format and to 1901–2038 in the
+    /// extended timestamp field.
+    ///
+    /// [`Manifest::encoded_name`]: crate::Manifest::encoded_name
+    pub fn write_zip<W: Write + Seek>(
+        &self,
+        mut writer: W,
+        method: ZipMethod,
+    ) -> Result<(), Error> {
+        let mut central_directory = Vec::new();
+        let mut num_entries: u16 = 0;
+        for entry in self.entries() {
+            let original = match entry.file_type {
+                FileType::HardLink => entry
+                    .target
+                    .as_deref()
+                    .and_then(|target| self.entries().iter().find(|e| e.path == target))
+                    .unwrap_or(entry),
+                _ => entry,
+            };
+            let (file_type, data): (libc::mode_t, &[u8]) = match original.file_type {
+                FileType::Regular => (
+                    libc::S_IFREG,
+                    self.contents(&original.path).unwrap_or_default(),
+                ),
+                FileType::Directory => (libc::S_IFDIR, &[]),
+                FileType::Symlink => (
+                    libc::S_IFLNK,
+                    original
+                        .target
+                        .as_deref()
+                        .map(|target| target.as_os_str().as_bytes())
+                        .unwrap_or_default(),
+                ),
+                _ => continue,
+            };
+            let mut name = self.manifest().encoded_name(entry);
+            if file_type == libc::S_IFDIR {
+                name.bytes.push(b'/');
+            }
+            let method = if data.is_empty() {
+                ZipMethod::Stored
+            } else {
+                method
+            };
+            let method_id: u16 = match method {
+                ZipMethod::Stored => 0,
+                ZipMethod::Deflated => 8,
+            };
+            let flags: u16 = if name.utf8_flag { 1 << 11 } else { 0 };
+            let secs = original.mtime.map(|t| unix_time(t).0).unwrap_or(0);
+            let (time, date) = dos_date_time(secs);
+            // the extended timestamp is a signed 32-bit number
+            let mtime = secs.clamp(i32::MIN.into(), i32::MAX.into()) as i32 as u32;
+            let name_len = to_u16(name.bytes.len())?;
+            let mut crc = Crc::new();
+            crc.update(data);
+            // the sizes are written after the data
+            let offset = writer.stream_position()?;
+            let mut header = Vec::with_capacity(LOCAL_HEADER_LEN + name.bytes.len() + 9);
+            put_u32(&mut header, LOCAL_HEADER_SIGNATURE);
+            put_u16(&mut header, VERSION_NEEDED);
+            put_u16(&mut header, flags);
+            put_u16(&mut header, method_id);
+            put_u16(&mut header, time);
+            put_u16(&mut header, date);
+            put_u32(&mut header, crc.sum());
+            put_u32(&mut header, 0);
+            put_u32(&mut header, 0);
+            put_u16(&mut header, name_len);
+            put_u16(&mut header, 9);
+            header.extend_from_slice(&name.bytes);
+            // extended timestamp
+            put_u16(&mut header, 0x5455);
+            put_u16(&mut header, 5);
+            header.push(1);
+            put_u32(&mut header, mtime);
+            writer.write_all(&header)?;
+            let data_offset = writer.stream_position()?;
+            match method {
+                ZipMethod::Stored => writer.write_all(data)?,
+                ZipMethod::Deflated => {
+                    let mut encoder = DeflateEncoder::new(&mut writer, Default::default());
+                    encoder.write_all(data)?;
+                    encoder.finish()?;
+                }
+            }
+            let end = writer.stream_position()?;
+            let compressed_size = to_u32(end - data_offset)?;
+            let size = to_u32(data.len() as u64)?;
+            writer.seek(SeekFrom::Start(offset + 18))?;
+            let mut sizes = Vec::with_capacity(8);
+            put_u32(&mut sizes, compressed_size);
+            put_u32(&mut sizes, size);
+            writer.write_all(&sizes)?;
+            writer.seek(SeekFrom::Start(end))?;
+            // `mode_t` is 16-bit on MacOS
+            #[allow(clippy::unnecessary_cast)]
+            let mut attributes = (file_type as u32 | (original.mode & 0o7777)) << 16;
+            if file_type == libc::S_IFDIR {
+                // MS-DOS directory attribute
+                attributes |= 0x10;
+            }
+            let cd = &mut central_directory;
+            put_u32(cd, CENTRAL_HEADER_SIGNATURE);
+            put_u16(cd, VERSION_MADE_BY);
+            put_u16(cd, VERSION_NEEDED);
+            put_u16(cd, flags);
+            put_u16(cd, method_id);
+            put_u16(cd, time);
+            put_u16(cd, date);
+            put_u32(cd, crc.sum());
+            put_u32(cd, compressed_size);
+            put_u32(cd, size);
+            put_u16(cd, name_len);
+            put_u16(cd, 9);
+            // comment length, disk number and internal attributes
+            put_u16(cd, 0);
+            put_u16(cd, 0);
+            put_u16(cd, 0);
+            put_u32(cd, attributes);
+            put_u32(cd, to_u32(offset)?);
+            cd.extend_from_slice(&name.bytes);
+            put_u16(cd, 0x5455);
+            put_u16(cd, 5);
+            cd.push(1);
+            put_u32(cd, mtime);
+            num_entries = num_entries.checked_add(1).ok_or_else(zip64_required)?;
+        }
+        let cd_offset = to_u32(writer.stream_position()?)?;
+        writer.write_all(&central_directory)?;
+        let mut end = Vec::with_capacity(END_LEN);
+        put_u32(&mut end, END_SIGNATURE);
+        // disk numbers
+        put_u16(&mut end, 0);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, num_entries);
+        put_u16(&mut end, num_entries);
+        put_u32(&mut end, to_u32(central_directory.len() as u64)?);
+        put_u32(&mut end, cd_offset);
+        // comment length
+        put_u16(&mut end, 0);
+        writer.write_all(&end)?;
+        Ok(())
+    }
+}
+
+/// Convert seconds since Unix epoch to MS-DOS time and date.
+///
+/// The time before 1980 and after 2107 is clamped.
+fn dos_date_time(secs: i64) -> (u16, u16) {
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    if year > 2107 {
+        return ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31);
+    }
+    let secs = secs.rem_euclid(SECS_PER_DAY);
+    let time = ((secs / 3600) << 11) | ((secs / 60 % 60) << 5) | (secs % 60 / 2);
+    let date = (((year - 1980) as u32) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn to_u16(value: usize) -> Result<u16, Error> {
+    value.try_into().map_err(|_| zip64_required())
+}
+
+fn to_u32(value: u64) -> Result<u32, Error> {
+    value.try_into().map_err(|_| zip64_required())
+}
+
+fn zip64_required() -> Error {
+    Error::new(ErrorKind::InvalidInput, "the archive requires zip64")
+}
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+const LOCAL_HEADER_LEN: usize = 30;
+const END_LEN: usize = 22;
+/// Unix, specification version 3.0.
+const VERSION_MADE_BY: u16 = (3 << 8) | 30;
+/// Deflate and directories.
+const VERSION_NEEDED: u16 = 20;
+const SECS_PER_DAY: i64 = 60 * 60 * 24;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dos_times() {
+        let min = (0, (1 << 5) | 1);
+        let max = ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31);
+        for (secs, expected) in [
+            (-1, min),
+            (0, min),
+            (315_532_800, min),
+            (
+                951_827_433,
+                ((12 << 11) | (30 << 5) | 16, (20 << 9) | (2 << 5) | 29),
+            ),
+            (
+                i64::from(i32::MAX),
+                ((3 << 11) | (14 << 5) | 3, (58 << 9) | (1 << 5) | 19),
+            ),
+            (4_354_819_200, max),
+        ] {
+            assert_eq!(expected, dos_date_time(secs), "{secs}");
+        }
+    }
+}